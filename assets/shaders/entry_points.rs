@@ -1,4 +1,20 @@
+pub mod comp {
+    pub mod reduce {
+        #[allow(non_upper_case_globals)]
+        pub const main: &str = "comp::reduce::main";
+        #[allow(non_upper_case_globals)]
+        pub const max_luminance: &str = "comp::reduce::max_luminance";
+    }
+}
 pub mod frag {
+    pub mod cutout {
+        #[allow(non_upper_case_globals)]
+        pub const main: &str = "frag::cutout::main";
+    }
+    pub mod fxaa {
+        #[allow(non_upper_case_globals)]
+        pub const main: &str = "frag::fxaa::main";
+    }
     pub mod textured_lit {
         #[allow(non_upper_case_globals)]
         pub const main: &str = "frag::textured_lit::main";
@@ -7,10 +23,26 @@ pub mod frag {
         #[allow(non_upper_case_globals)]
         pub const main: &str = "frag::unlit::main";
     }
+    pub mod uv_checker {
+        #[allow(non_upper_case_globals)]
+        pub const main: &str = "frag::uv_checker::main";
+    }
 }
 pub mod vert {
+    pub mod fullscreen {
+        #[allow(non_upper_case_globals)]
+        pub const main: &str = "vert::fullscreen::main";
+    }
+    pub mod immediate {
+        #[allow(non_upper_case_globals)]
+        pub const main: &str = "vert::immediate::main";
+    }
     pub mod mesh {
         #[allow(non_upper_case_globals)]
         pub const main: &str = "vert::mesh::main";
     }
+    pub mod mesh_normals {
+        #[allow(non_upper_case_globals)]
+        pub const main: &str = "vert::mesh_normals::main";
+    }
 }
@@ -20,7 +20,7 @@ fn main() {
         .add_plugins(DefaultPlugins)
         // .add_plugin(FrameTimeDiagnosticsPlugin::default())
         // .add_plugin(LogDiagnosticsPlugin::default())
-        .add_plugin(ObjPlugin)
+        .add_plugin(ObjPlugin::default())
         .add_plugin(RenderPlugin)
         .add_startup_system(load_assets)
         .run();
@@ -0,0 +1,173 @@
+use crate::device::Device;
+use crate::resource::buffer::{Buffer, BufferUsageFlags};
+
+use anyhow::{bail, Result};
+use ash::vk;
+
+use std::mem::size_of;
+
+
+
+/// Check dispatch group `counts` against the device's `maxComputeWorkGroupCount` in each dimension.
+fn validate_group_count(counts: [u32; 3], limits: &vk::PhysicalDeviceLimits) -> Result<()> {
+    for (dimension, (count, max_count)) in ["x", "y", "z"].into_iter().zip(counts.into_iter().zip(limits.max_compute_work_group_count)) {
+        if count > max_count {
+            bail!("Dispatch group count {} = {} exceeds device limit maxComputeWorkGroupCount[{}] = {}", dimension, count, dimension, max_count);
+        }
+    }
+
+    Ok(())
+}
+
+// Validated command recording
+impl Device {
+    /// Record a compute dispatch, checking the group counts against the device's `maxComputeWorkGroupCount`.
+    pub fn dispatch(
+        &self,
+        command_buffer: vk::CommandBuffer,
+        group_count_x: u32,
+        group_count_y: u32,
+        group_count_z: u32
+    ) -> Result<()> {
+        validate_group_count([group_count_x, group_count_y, group_count_z], &self.limits())?;
+
+        unsafe { self.cmd_dispatch(command_buffer, group_count_x, group_count_y, group_count_z) };
+
+        Ok(())
+    }
+
+    /// Record a compute dispatch reading its group counts from a `vk::DispatchIndirectCommand` at `offset` in `buffer`,
+    /// e.g. written by a previous dispatch for GPU-driven work.
+    /// 
+    /// `buffer` must have `INDIRECT_BUFFER` usage. Group counts are read at execution, so they aren't checked against device limits.
+    pub fn dispatch_indirect(
+        &self,
+        command_buffer: vk::CommandBuffer,
+        buffer: &Buffer,
+        offset: u64
+    ) -> Result<()> {
+        if !buffer.info.usage.contains(BufferUsageFlags::INDIRECT_BUFFER) {
+            bail!("Indirect dispatch buffer was not created with INDIRECT_BUFFER usage");
+        }
+        if offset % 4 != 0 {
+            bail!("Indirect dispatch offset {} is not a multiple of 4", offset);
+        }
+        let command_size = size_of::<vk::DispatchIndirectCommand>() as u64;
+        if offset + command_size > buffer.info.size as u64 {
+            bail!("Indirect dispatch command at offset {} exceeds buffer size {}", offset, buffer.info.size);
+        }
+
+        unsafe { self.cmd_dispatch_indirect(command_buffer, buffer.buffer, offset) };
+
+        Ok(())
+    }
+
+    /// Record `draw_count` indexed draws reading `vk::DrawIndexedIndirectCommand`s from `buffer`, starting at `offset`
+    /// and `stride` bytes apart, e.g. written by a culling dispatch for GPU-driven rendering.
+    /// 
+    /// `buffer` must have `INDIRECT_BUFFER` usage, and drawing more than once requires the `multiDrawIndirect` device feature.
+    pub fn draw_indexed_indirect(
+        &self,
+        command_buffer: vk::CommandBuffer,
+        buffer: &Buffer,
+        offset: u64,
+        draw_count: u32,
+        stride: u32
+    ) -> Result<()> {
+        if draw_count > 1 && !self.capabilities().multi_draw_indirect {
+            bail!("Indirect draw count {} requires the multiDrawIndirect device feature, which this device doesn't support", draw_count);
+        }
+        self.validate_indirect_draws(buffer, offset, draw_count, stride)?;
+
+        unsafe { self.cmd_draw_indexed_indirect(command_buffer, buffer.buffer, offset, draw_count, stride) };
+
+        Ok(())
+    }
+
+    /// Like [`Device::draw_indexed_indirect`], but the draw count is read from a `u32` at `count_offset` in `count_buffer`,
+    /// clamped to `max_draw_count`, so the GPU decides how many draws to record.
+    /// 
+    /// `count_buffer` must have `INDIRECT_BUFFER` usage, and the device must support the `drawIndirectCount` feature.
+    #[allow(clippy::too_many_arguments)]
+    pub fn draw_indexed_indirect_count(
+        &self,
+        command_buffer: vk::CommandBuffer,
+        buffer: &Buffer,
+        offset: u64,
+        count_buffer: &Buffer,
+        count_offset: u64,
+        max_draw_count: u32,
+        stride: u32
+    ) -> Result<()> {
+        if !self.capabilities().draw_indirect_count {
+            bail!("Indirect draw counts read from a buffer require the drawIndirectCount device feature, which this device doesn't support");
+        }
+        self.validate_indirect_draws(buffer, offset, max_draw_count, stride)?;
+        if !count_buffer.info.usage.contains(BufferUsageFlags::INDIRECT_BUFFER) {
+            bail!("Indirect draw count buffer was not created with INDIRECT_BUFFER usage");
+        }
+        if count_offset % 4 != 0 {
+            bail!("Indirect draw count offset {} is not a multiple of 4", count_offset);
+        }
+        if count_offset + size_of::<u32>() as u64 > count_buffer.info.size as u64 {
+            bail!("Indirect draw count at offset {} exceeds buffer size {}", count_offset, count_buffer.info.size);
+        }
+
+        unsafe {
+            self.cmd_draw_indexed_indirect_count(command_buffer, buffer.buffer, offset, count_buffer.buffer, count_offset, max_draw_count, stride)
+        };
+
+        Ok(())
+    }
+
+    fn validate_indirect_draws(&self, buffer: &Buffer, offset: u64, draw_count: u32, stride: u32) -> Result<()> {
+        if !buffer.info.usage.contains(BufferUsageFlags::INDIRECT_BUFFER) {
+            bail!("Indirect draw buffer was not created with INDIRECT_BUFFER usage");
+        }
+        if offset % 4 != 0 {
+            bail!("Indirect draw offset {} is not a multiple of 4", offset);
+        }
+        if draw_count == 0 {
+            return Ok(());
+        }
+
+        let max_draw_count = self.limits().max_draw_indirect_count;
+        if draw_count > max_draw_count {
+            bail!("Indirect draw count {} exceeds device limit maxDrawIndirectCount = {}", draw_count, max_draw_count);
+        }
+        let command_size = size_of::<vk::DrawIndexedIndirectCommand>() as u64;
+        if draw_count > 1 && (stride % 4 != 0 || (stride as u64) < command_size) {
+            bail!("Indirect draw stride {} must be a multiple of 4 and at least {}", stride, command_size);
+        }
+        let end = offset + (draw_count as u64 - 1) * stride as u64 + command_size;
+        if end > buffer.info.size as u64 {
+            bail!("Indirect draws end at byte {}, beyond buffer size {}", end, buffer.info.size);
+        }
+
+        Ok(())
+    }
+}
+
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn group_counts_within_limits() {
+        let limits = vk::PhysicalDeviceLimits {
+            max_compute_work_group_count: [65535, 1024, 64],
+            ..Default::default()
+        };
+
+        assert!(validate_group_count([65535, 1024, 64], &limits).is_ok());
+        assert!(validate_group_count([0, 0, 0], &limits).is_ok());
+        for counts in [[65536, 1, 1], [1, 1025, 1], [1, 1, 65]] {
+            assert!(validate_group_count(counts, &limits).is_err(), "{:?}", counts);
+        }
+
+        let error = validate_group_count([1, 1, 65], &limits).unwrap_err();
+        assert!(error.to_string().contains("maxComputeWorkGroupCount[z] = 64"), "{}", error);
+    }
+}
@@ -1,19 +1,22 @@
+use crate::error::GpuError;
 use crate::instance::Instance;
+use crate::sync::TimelineSemaphore;
 
 use crate::utils::vk_to_string;
 
-use anyhow::{Context, Result};
-use ash::extensions::khr;
-use ash::vk;
+use anyhow::{bail, Context, Result};
+use ash::extensions::{ext::DebugUtils, google::DisplayTiming, khr};
+use ash::{prelude::VkResult, vk};
 use bevy_ecs::system::Resource;
 use bevy_log::prelude::*;
 use bevy_window::RawHandleWrapper;
 use gpu_allocator::{vulkan::*, AllocatorDebugSettings};
-use std::{ops::Deref, os::raw::c_char, slice, sync::{Arc, Mutex}};
+use std::{ffi::{CStr, CString}, ops::Deref, os::raw::c_char, slice, sync::{Arc, Mutex, MutexGuard, PoisonError}};
 
 pub use ash::vk::Queue;
 
 
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
 pub enum QueueFamily {
     GRAPHICS,
     COMPUTE,
@@ -33,11 +36,142 @@ pub struct DeviceQueues {
     pub present_family: Option<u32>
 }
 
+/// Subgroup properties of a device, see [`Device::subgroup_properties`].
+#[derive(Copy, Clone, Debug)]
+pub struct SubgroupProperties {
+    /// Default subgroup size, used unless a shader stage requires another size
+    pub size: u32,
+    pub min_size: u32,
+    pub max_size: u32,
+    /// Shader stages supporting subgroup operations
+    pub supported_stages: vk::ShaderStageFlags,
+    pub supported_operations: vk::SubgroupFeatureFlags,
+    /// Shader stages that can be pinned to a subgroup size between `min_size` and `max_size`
+    pub required_size_stages: vk::ShaderStageFlags,
+}
+
+
+/// Optional device features and extensions, enabled when the physical device supports them, see [`Device::capabilities`].
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq)]
+pub struct DeviceCapabilities {
+    /// `TYPE_CUBE_ARRAY` image views, for cubemap arrays
+    pub image_cube_array: bool,
+    /// `LINE` and `POINT` polygon modes, for wireframes
+    pub fill_mode_non_solid: bool,
+    /// Logic ops instead of blending on color attachments
+    pub logic_op: bool,
+    /// Shading per sample instead of per pixel with multisampling
+    pub sample_rate_shading: bool,
+    /// Indirect draws with a draw count above 1
+    pub multi_draw_indirect: bool,
+    /// Indirect draws reading their draw count from a buffer
+    pub draw_indirect_count: bool,
+    /// Pinning shader stages to a required subgroup size
+    pub subgroup_size_control: bool,
+    /// Present timing feedback from the `VK_GOOGLE_display_timing` extension, see [`Surface::present_stats`](crate::surface::Surface::present_stats)
+    pub display_timing: bool,
+}
+
+impl DeviceCapabilities {
+    /// Every optional feature
+    pub fn all() -> Self {
+        Self {
+            image_cube_array: true,
+            fill_mode_non_solid: true,
+            logic_op: true,
+            sample_rate_shading: true,
+            multi_draw_indirect: true,
+            draw_indirect_count: true,
+            subgroup_size_control: true,
+            display_timing: true,
+        }
+    }
+
+    /// Query the optional features `physical_device` supports.
+    fn supported(instance: &Instance, physical_device: vk::PhysicalDevice) -> Self {
+        let mut vulkan_12_features = vk::PhysicalDeviceVulkan12Features::default();
+        let mut vulkan_13_features = vk::PhysicalDeviceVulkan13Features::default();
+        let mut features = vk::PhysicalDeviceFeatures2::builder()
+            .push_next(&mut vulkan_12_features)
+            .push_next(&mut vulkan_13_features);
+        unsafe { instance.get_physical_device_features2(physical_device, &mut features) };
+        let features = features.features;
+        let extensions = unsafe { instance.enumerate_device_extension_properties(physical_device) }
+            .unwrap_or_default();
+
+        Self {
+            image_cube_array: features.image_cube_array == vk::TRUE,
+            fill_mode_non_solid: features.fill_mode_non_solid == vk::TRUE,
+            logic_op: features.logic_op == vk::TRUE,
+            sample_rate_shading: features.sample_rate_shading == vk::TRUE,
+            multi_draw_indirect: features.multi_draw_indirect == vk::TRUE,
+            draw_indirect_count: vulkan_12_features.draw_indirect_count == vk::TRUE,
+            subgroup_size_control: vulkan_13_features.subgroup_size_control == vk::TRUE,
+            display_timing: supports_extension(&extensions, DisplayTiming::name()),
+        }
+    }
+
+    /// Features both `self` and `other` include
+    pub fn intersection(&self, other: &Self) -> Self {
+        Self {
+            image_cube_array: self.image_cube_array && other.image_cube_array,
+            fill_mode_non_solid: self.fill_mode_non_solid && other.fill_mode_non_solid,
+            logic_op: self.logic_op && other.logic_op,
+            sample_rate_shading: self.sample_rate_shading && other.sample_rate_shading,
+            multi_draw_indirect: self.multi_draw_indirect && other.multi_draw_indirect,
+            draw_indirect_count: self.draw_indirect_count && other.draw_indirect_count,
+            subgroup_size_control: self.subgroup_size_control && other.subgroup_size_control,
+            display_timing: self.display_timing && other.display_timing,
+        }
+    }
+
+    /// Names of the device extensions these capabilities require.
+    fn extension_names(&self) -> Vec<*const c_char> {
+        [(self.display_timing, DisplayTiming::name())]
+            .into_iter()
+            .filter_map(|(enabled, name)| enabled.then_some(name.as_ptr()))
+            .collect()
+    }
+
+    /// Enable these features in device creation feature structs.
+    fn enable(
+        &self,
+        features: &mut vk::PhysicalDeviceFeatures,
+        vulkan_12_features: &mut vk::PhysicalDeviceVulkan12Features,
+        vulkan_13_features: &mut vk::PhysicalDeviceVulkan13Features
+    ) {
+        let flag = |enabled: bool| match enabled {
+            true => vk::TRUE,
+            false => vk::FALSE
+        };
+
+        features.image_cube_array = flag(self.image_cube_array);
+        features.fill_mode_non_solid = flag(self.fill_mode_non_solid);
+        features.logic_op = flag(self.logic_op);
+        features.sample_rate_shading = flag(self.sample_rate_shading);
+        features.multi_draw_indirect = flag(self.multi_draw_indirect);
+        vulkan_12_features.draw_indirect_count = flag(self.draw_indirect_count);
+        vulkan_13_features.subgroup_size_control = flag(self.subgroup_size_control);
+    }
+}
+
+/// Whether `extensions`, as enumerated for a physical device, include the extension `name`
+fn supports_extension(extensions: &[vk::ExtensionProperties], name: &CStr) -> bool {
+    extensions
+        .iter()
+        .any(|properties| unsafe { CStr::from_ptr(properties.extension_name.as_ptr()) } == name)
+}
+
 
 pub struct DeviceOptions<'a> {
     raw_handle: Option<RawHandleWrapper>,
     extensions: &'a [*const c_char],
-    features: &'a mut vk::PhysicalDeviceFeatures2,
+    /// Required features
+    features: vk::PhysicalDeviceFeatures,
+    vulkan_12_features: vk::PhysicalDeviceVulkan12Features,
+    vulkan_13_features: vk::PhysicalDeviceVulkan13Features,
+    /// Features enabled if supported, in addition to the required features
+    optional_features: DeviceCapabilities,
     queues: [(QueueFamily, &'a [f32]); 3],
 }
 
@@ -53,8 +187,17 @@ pub struct DeviceInternal {
     pub(crate) queues: DeviceQueues,
     pub(crate) transfer_queue: Queue,
     pub(crate) transfer_pool: vk::CommandPool,
+    /// Held while submitting or presenting to any queue, or waiting for the device to idle.
+    /// Roles may alias one family and share its queues, and Vulkan requires queue access to be externally synchronized.
+    pub(crate) queue_lock: Mutex<()>,
 
     pub(crate) allocator: Option<Mutex<Allocator>>,
+    /// Debug utils loader for naming objects, present if the instance enabled `VK_EXT_debug_utils`
+    pub(crate) debug_utils: Option<DebugUtils>,
+    /// Optional features enabled on the device
+    pub(crate) capabilities: DeviceCapabilities,
+    /// Display timing loader, present if [`DeviceCapabilities::display_timing`] is enabled
+    pub(crate) display_timing: Option<DisplayTiming>,
 }
 
 impl Deref for DeviceInternal {
@@ -89,13 +232,12 @@ impl Device {
         instance: Instance,
         selector: fn(vk::PhysicalDeviceProperties2) -> bool,
         options: DeviceOptions
-    ) -> Result<Self> {
+    ) -> Result<Self, GpuError> {
         info!("Creating Vulkan device");
 
         // Get candidate physical devices filtered by selector
         let physical_devices: Vec<vk::PhysicalDevice> = unsafe {
-            instance.enumerate_physical_devices()
-                .context("Failed to enumerate physical devices")?
+            instance.enumerate_physical_devices()?
         }
         .iter()
         .filter_map(|&physical_device| {
@@ -120,47 +262,20 @@ impl Device {
             // Check for requested queues
             let available_queue_families = unsafe { instance.get_physical_device_queue_family_properties(physical_device) };
 
+            let [graphics_family, compute_family, transfer_family] = assign_queue_families(&available_queue_families)?;
             let mut queues = DeviceQueues {
-                graphics_family: u32::MAX,
-                compute_family: u32::MAX,
-                transfer_family: u32::MAX,
+                graphics_family,
+                compute_family,
+                transfer_family,
 
                 graphics_count: 0,
                 compute_count: 0,
                 transfer_count: 0,
 
-                present_family: Some(0)
-            };
-            available_queue_families
-                .iter()
-                .enumerate()
-                .for_each(|(index, properties)| {
-                    let index = index as u32;
-                    match (
-                        properties.queue_flags.contains(vk::QueueFlags::GRAPHICS),
-                        properties.queue_flags.contains(vk::QueueFlags::COMPUTE),
-                        properties.queue_flags.contains(vk::QueueFlags::TRANSFER),
-                    ) {
-                        (true, true, true) => queues.graphics_family = index,
-                        (false, true, true) => queues.compute_family = index,
-                        (false, false, true) => queues.transfer_family = index,
-                        _ => warn!("Encountered unsupported device queue family!"),
-                    }
-                });
-            // Handle missing queue families
-            match (
-                queues.graphics_family,
-                queues.compute_family,
-                queues.transfer_family,
-            ) {
-                (u32::MAX, _, _) => return None,
-                (_, u32::MAX, _) => return None,
-                (_, _, u32::MAX) => return None,
-                _ => (),
+                present_family: None
             };
 
             // Check for presentation support on window, if requested
-            // TODO: should consider checking all queue families
             match options.raw_handle.clone() {
                 Some(raw_handle) => {
                     let surface = khr::Surface::new(&instance.entry, &instance);
@@ -168,52 +283,74 @@ impl Device {
                         Ok(result) => result,
                         Err(_) => return None
                     };
-                    match unsafe { surface.get_physical_device_surface_support(physical_device, queues.graphics_family, surface_handle) } {
-                        Ok(_) => (),
-                        Err(_) => return None
-                    }
+                    let present_support = |index: u32| unsafe {
+                        surface.get_physical_device_surface_support(physical_device, index, surface_handle)
+                            .unwrap_or(false)
+                    };
+
+                    let present_family = select_present_family(queues.graphics_family, available_queue_families.len() as u32, present_support);
 
                     unsafe { surface.destroy_surface(surface_handle, None) };
 
-                    queues.present_family = Some(queues.graphics_family)
+                    match present_family {
+                        Some(_) => queues.present_family = present_family,
+                        None => return None
+                    }
                 },
                 None => ()
             };
 
             // Attempt logical device creation
-            let queue_create_infos: Vec<vk::DeviceQueueCreateInfo> = options.queues
+            // Aliased families share one queue create info, so their queues are shared as well
+            let (family_priorities, [graphics_count, compute_count, transfer_count]) = merge_queue_priorities(
+                &available_queue_families,
+                [queues.graphics_family, queues.compute_family, queues.transfer_family],
+                &options.queues
+            );
+            queues.graphics_count = graphics_count;
+            queues.compute_count = compute_count;
+            queues.transfer_count = transfer_count;
+            let mut queue_create_infos: Vec<vk::DeviceQueueCreateInfo> = family_priorities
                 .iter()
-                .filter_map(|(queue_family, priorities)| match priorities.len() > 0 {
-                    true => {
-                        let index = match queue_family {
-                            QueueFamily::GRAPHICS => {
-                                queues.graphics_count = priorities.len() as u32;
-                                queues.graphics_family
-                            },
-                            QueueFamily::COMPUTE => {
-                                queues.compute_count = priorities.len() as u32;
-                                queues.compute_family
-                            },
-                            QueueFamily::TRANSFER => {
-                                queues.transfer_count = priorities.len() as u32;
-                                queues.transfer_family
-                            },
-                        };
-                        Some(
-                            vk::DeviceQueueCreateInfo::builder()
-                                .queue_family_index(index as u32)
-                                .queue_priorities(priorities)
-                                .build(),
-                        )
-                    }
-                    false => None,
-                })
+                .map(|(index, priorities)| vk::DeviceQueueCreateInfo::builder()
+                    .queue_family_index(*index)
+                    .queue_priorities(priorities)
+                    .build()
+                )
                 .collect();
 
+            // Create a dedicated present queue if no requested queue family supports presentation
+            if let Some(present_family) = queues.present_family {
+                if !queue_create_infos.iter().any(|info| info.queue_family_index == present_family) {
+                    queue_create_infos.push(
+                        vk::DeviceQueueCreateInfo::builder()
+                            .queue_family_index(present_family)
+                            .queue_priorities(&[1.0])
+                            .build()
+                    );
+                }
+            }
+
+            // Enable the supported optional features along with the required features
+            let capabilities = options.optional_features.intersection(&DeviceCapabilities::supported(&instance, physical_device));
+            let mut features = options.features;
+            let mut vulkan_12_features = options.vulkan_12_features;
+            let mut vulkan_13_features = options.vulkan_13_features;
+            capabilities.enable(&mut features, &mut vulkan_12_features, &mut vulkan_13_features);
+            let extensions: Vec<*const c_char> = options.extensions
+                .iter()
+                .copied()
+                .chain(capabilities.extension_names())
+                .collect();
+            let mut features = vk::PhysicalDeviceFeatures2::builder()
+                .features(features)
+                .push_next(&mut vulkan_12_features)
+                .push_next(&mut vulkan_13_features);
+
             let create_info = vk::DeviceCreateInfo::builder()
                 .queue_create_infos(queue_create_infos.as_slice())
-                .enabled_extension_names(options.extensions)
-                .push_next(options.features);
+                .enabled_extension_names(&extensions)
+                .push_next(&mut features);
             //  Safety: vkCreateDevice
             //  In order for the created Device to be valid for the duration of its usage,
             //  the Instance this was called on must be dropped later than the resulting Device.
@@ -225,15 +362,16 @@ impl Device {
                     Err(_) => return None,
                 };
 
-            Some((physical_device, logical_device, queues))
+            Some((physical_device, logical_device, queues, capabilities))
         });
-        let (physical_device, logical_device, queues) = result.context("No suitable device found for requested parameters!")?;
+        let (physical_device, logical_device, queues, capabilities) = result.ok_or(GpuError::NoSuitableDevice)?;
+        info!("Enabled optional device features: {:?}", capabilities);
 
 
         // Get first transfer queue
         let transfer_queue = (0 < queues.transfer_count).then(|| {
             unsafe { logical_device.get_device_queue(queues.transfer_family, 0) }
-        }).context(format!("Queue index out of range; index {}, queue count {}", 0, queues.transfer_count))?;
+        }).ok_or(GpuError::NoSuitableDevice)?;
 
         // Create transfer command pool
         let create_info = vk::CommandPoolCreateInfo::builder()
@@ -258,8 +396,11 @@ impl Device {
                 },
                 buffer_device_address: true
             }
-        ).unwrap();
+        )?;
 
+        // Load debug utils for object naming
+        let debug_utils = instance.object_naming.then(|| DebugUtils::new(&instance.entry, &instance));
+        let display_timing = capabilities.display_timing.then(|| DisplayTiming::new(&instance, &logical_device));
 
 
         Ok(Self {
@@ -270,19 +411,22 @@ impl Device {
                 queues,
                 transfer_queue,
                 transfer_pool,
-                allocator: Some(Mutex::new(allocator))
+                queue_lock: Mutex::new(()),
+                allocator: Some(Mutex::new(allocator)),
+                debug_utils,
+                capabilities,
+                display_timing
             }),
         })
     }
 
-    pub fn primary(instance: Instance, raw_handle: Option<RawHandleWrapper>) -> Result<Self> {
-        let mut vulkan_memory_model_feature = vk::PhysicalDeviceVulkanMemoryModelFeatures::builder()
-            .vulkan_memory_model(true);
-        let mut dynamic_rendering_feature = vk::PhysicalDeviceDynamicRenderingFeatures::builder()
-            .dynamic_rendering(true);
-        let mut buffer_device_address_feature = vk::PhysicalDeviceBufferDeviceAddressFeatures::builder()
-            .buffer_device_address(true);
-        let mut descriptor_indexing_feature = vk::PhysicalDeviceDescriptorIndexingFeatures::builder()
+    pub fn primary(instance: Instance, raw_handle: Option<RawHandleWrapper>) -> Result<Self, GpuError> {
+        // Vulkan 1.2 and 1.3 features are enabled together, as chaining both these and the individual feature structs is invalid
+        let vulkan_12_features = vk::PhysicalDeviceVulkan12Features::builder()
+            .vulkan_memory_model(true)
+            .timeline_semaphore(true)
+            .buffer_device_address(true)
+            .separate_depth_stencil_layouts(true)
             .descriptor_binding_partially_bound(true)
             .descriptor_binding_sampled_image_update_after_bind(true)
             .descriptor_binding_storage_buffer_update_after_bind(true)
@@ -302,22 +446,23 @@ impl Device {
             .shader_storage_texel_buffer_array_non_uniform_indexing(true)
             .shader_uniform_buffer_array_non_uniform_indexing(true)
             .shader_uniform_texel_buffer_array_dynamic_indexing(true)
-            .shader_uniform_texel_buffer_array_non_uniform_indexing(true);
+            .shader_uniform_texel_buffer_array_non_uniform_indexing(true)
+            .build();
+        let vulkan_13_features = vk::PhysicalDeviceVulkan13Features::builder()
+            .dynamic_rendering(true)
+            .build();
 
         let options = DeviceOptions {
             raw_handle,
             extensions: &[
                 ash::extensions::khr::Swapchain::name().as_ptr(), //ash::extensions::khr::AccelerationStructure::name().as_ptr()
             ],
-            features: &mut vk::PhysicalDeviceFeatures2::builder()
-                .features(vk::PhysicalDeviceFeatures::builder()
-                    .sampler_anisotropy(true)
-                    .build()
-                )
-                .push_next(&mut vulkan_memory_model_feature)
-                .push_next(&mut dynamic_rendering_feature)
-                .push_next(&mut buffer_device_address_feature)
-                .push_next(&mut descriptor_indexing_feature),
+            features: vk::PhysicalDeviceFeatures::builder()
+                .sampler_anisotropy(true)
+                .build(),
+            vulkan_12_features,
+            vulkan_13_features,
+            optional_features: DeviceCapabilities::all(),
             queues: [
                 (QueueFamily::GRAPHICS, &[1.0]),
                 (QueueFamily::COMPUTE, &[1.0]),
@@ -344,6 +489,37 @@ impl Device {
         )
     }
 
+    /// Name a Vulkan object for validation messages and GPU captures.
+    /// 
+    /// Does nothing unless `VK_EXT_debug_utils` is enabled, see the `object_naming` feature for release builds.
+    pub fn set_object_name<T: vk::Handle>(&self, handle: T, name: &str) {
+        let Some(debug_utils) = &self.debug_utils else {
+            return;
+        };
+        let Ok(name) = CString::new(name) else {
+            return;
+        };
+
+        let name_info = vk::DebugUtilsObjectNameInfoEXT::builder()
+            .object_type(T::TYPE)
+            .object_handle(handle.as_raw())
+            .object_name(&name);
+        if let Err(error) = unsafe { debug_utils.set_debug_utils_object_name(self.handle(), &name_info) } {
+            warn!("Failed to name object \"{}\": {}", name.to_string_lossy(), error);
+        }
+    }
+
+    /// Index of the device queue family used for `queue_family`.
+    /// 
+    /// Families may alias each other on devices without dedicated compute or transfer families.
+    pub fn queue_family_index(&self, queue_family: QueueFamily) -> u32 {
+        match queue_family {
+            QueueFamily::GRAPHICS => self.queues.graphics_family,
+            QueueFamily::COMPUTE => self.queues.compute_family,
+            QueueFamily::TRANSFER => self.queues.transfer_family
+        }
+    }
+
     pub fn graphics_queue(&self, queue_index: u32) -> Result<Queue> {
         let queue = (queue_index < self.queues.graphics_count).then(|| {
             unsafe { self.get_device_queue(self.queues.graphics_family, queue_index) }
@@ -353,6 +529,23 @@ impl Device {
         Ok(queue)
     }
 
+    pub fn compute_queue(&self, queue_index: u32) -> Result<Queue> {
+        let queue = (queue_index < self.queues.compute_count).then(|| {
+            unsafe { self.get_device_queue(self.queues.compute_family, queue_index) }
+        })
+        .context(format!("Queue index out of range; index {}, queue count {}", queue_index, self.queues.compute_count))?;
+
+        Ok(queue)
+    }
+
+    pub fn present_queue(&self) -> Result<Queue, GpuError> {
+        let present_family = self.queues.present_family
+            .ok_or(GpuError::SurfaceUnsupported)?;
+
+        // The first queue of the present family is always created, see Device::new
+        Ok(unsafe { self.get_device_queue(present_family, 0) })
+    }
+
     pub fn transfer_queue(&self, queue_index: u32) -> Result<Queue> {
         if queue_index == 0 {
             return Ok(self.transfer_queue);
@@ -383,25 +576,236 @@ impl Device {
     }
 
     pub fn end_transfer_commands(&self, command_buffer: vk::CommandBuffer) -> Result<()> {
-        // Execute transfer command buffer
+        // Execute transfer command buffer, waiting on a fence rather than the queue, which other roles may share
+        let fence = match unsafe { self.create_fence(&vk::FenceCreateInfo::builder(), None) } {
+            Ok(result) => result,
+            Err(error) => {
+                unsafe { self.free_command_buffers(self.transfer_pool, &[command_buffer]) };
+                bail!("Device::end_transfer_commands: {}", error);
+            }
+        };
+        let result = unsafe {
+            self.end_command_buffer(command_buffer)
+                .and_then(|_| {
+                    let submit_info = vk::SubmitInfo::builder()
+                        .command_buffers(slice::from_ref(&command_buffer))
+                        .build();
+                    self.submit(self.transfer_queue, slice::from_ref(&submit_info), fence)
+                })
+                .and_then(|_| self.wait_for_fences(slice::from_ref(&fence), true, u64::MAX))
+        };
+
+        unsafe {
+            self.destroy_fence(fence, None);
+            self.free_command_buffers(self.transfer_pool, &[command_buffer]);
+        }
+
+        Ok(result?)
+    }
+
+    /// Submit transfer commands begun with [`Device::begin_transfer_commands`] without waiting for them,
+    /// signaling `signal` to `value` once they complete.
+    /// 
+    /// Lets large uploads run on the transfer queue while other queues render, with submissions using the data waiting on `signal`.
+    /// Exclusive images used on another queue family need an ownership transfer, see [`Sharing`](crate::resource::image::Sharing).
+    /// Free the command buffer with [`Device::free_transfer_commands`] once `signal` reaches `value`.
+    pub fn submit_transfer_commands(
+        &self,
+        command_buffer: vk::CommandBuffer,
+        signal: &TimelineSemaphore,
+        value: u64
+    ) -> Result<()> {
         unsafe {
             self.end_command_buffer(command_buffer)?;
+            let signal_semaphore = **signal;
+            let mut timeline_info = vk::TimelineSemaphoreSubmitInfo::builder()
+                .signal_semaphore_values(slice::from_ref(&value));
             let submit_info = vk::SubmitInfo::builder()
                 .command_buffers(slice::from_ref(&command_buffer))
+                .signal_semaphores(slice::from_ref(&signal_semaphore))
+                .push_next(&mut timeline_info)
                 .build();
-            self.queue_submit(self.transfer_queue, slice::from_ref(&submit_info), vk::Fence::null())?;
-            self.queue_wait_idle(self.transfer_queue)?;
-
-            self.free_command_buffers(self.transfer_pool, &[command_buffer]);
+            self.submit(self.transfer_queue, slice::from_ref(&submit_info), vk::Fence::null())?;
         }
-        
+
         Ok(())
     }
 
+    /// Free transfer commands submitted with [`Device::submit_transfer_commands`], which must have completed.
+    pub fn free_transfer_commands(&self, command_buffer: vk::CommandBuffer) {
+        unsafe { self.free_command_buffers(self.transfer_pool, &[command_buffer]) };
+    }
+
+    /// Record commands with `record`, submit them to the first queue of `queue_family`, and wait for completion.
+    /// 
+    /// Intended for one-off work outside of frame rendering, such as a compute job whose results are read back.
+    pub fn run_once<F>(&self, queue_family: QueueFamily, record: F) -> Result<()>
+    where
+        F: FnOnce(vk::CommandBuffer) -> Result<()>
+    {
+        self.run_once_with_fence(queue_family, |command_buffer, _| record(command_buffer))
+    }
+
+    /// Like [`Device::run_once`], also passing `record` the fence that signals once the commands complete,
+    /// e.g. to track descriptor sets bound by the commands. The fence is destroyed before returning.
+    pub fn run_once_with_fence<F>(&self, queue_family: QueueFamily, record: F) -> Result<()>
+    where
+        F: FnOnce(vk::CommandBuffer, vk::Fence) -> Result<()>
+    {
+        let (family_index, queue) = match queue_family {
+            QueueFamily::GRAPHICS => (self.queues.graphics_family, self.graphics_queue(0)?),
+            QueueFamily::COMPUTE => (self.queues.compute_family, self.compute_queue(0)?),
+            QueueFamily::TRANSFER => (self.queues.transfer_family, self.transfer_queue(0)?),
+        };
+
+        // Create temporary command pool and completion fence
+        let create_info = vk::CommandPoolCreateInfo::builder()
+            .queue_family_index(family_index)
+            .flags(vk::CommandPoolCreateFlags::TRANSIENT);
+        let command_pool = unsafe { self.create_command_pool(&create_info, None)? };
+
+        let create_info = vk::FenceCreateInfo::builder();
+        let fence = match unsafe { self.create_fence(&create_info, None) } {
+            Ok(result) => result,
+            Err(error) => {
+                unsafe { self.destroy_command_pool(command_pool, None) };
+                bail!("Device::run_once_with_fence: {}", error);
+            }
+        };
+
+        let result = (|| -> Result<()> {
+            let alloc_info = vk::CommandBufferAllocateInfo::builder()
+                .command_pool(command_pool)
+                .command_buffer_count(1)
+                .level(vk::CommandBufferLevel::PRIMARY);
+            let command_buffer = unsafe { self.allocate_command_buffers(&alloc_info)?[0] };
+
+            // Record commands
+            unsafe {
+                let begin_info = vk::CommandBufferBeginInfo::builder()
+                    .flags(vk::CommandBufferUsageFlags::ONE_TIME_SUBMIT);
+                self.begin_command_buffer(command_buffer, &begin_info)?;
+            }
+            record(command_buffer, fence)?;
+
+            // Submit and wait for completion
+            unsafe {
+                self.end_command_buffer(command_buffer)?;
+                let submit_info = vk::SubmitInfo::builder()
+                    .command_buffers(slice::from_ref(&command_buffer))
+                    .build();
+                self.submit(queue, slice::from_ref(&submit_info), fence)?;
+                self.wait_for_fences(slice::from_ref(&fence), true, u64::MAX)?;
+            }
+
+            Ok(())
+        })();
+
+        // Destroying the pool frees its command buffer
+        unsafe {
+            self.destroy_fence(fence, None);
+            self.destroy_command_pool(command_pool, None);
+        }
+
+        result
+    }
+
+    /// Submit `submits` to `queue`, holding the queue lock.
+    /// 
+    /// Compute and transfer queues alias the graphics queue on devices without dedicated families, so all submissions
+    /// go through here, or hold [`Device::lock_queues`], to keep threads from accessing a shared queue at once.
+    /// 
+    /// # Safety
+    /// Same as `vkQueueSubmit`
+    pub unsafe fn submit(&self, queue: Queue, submits: &[vk::SubmitInfo], fence: vk::Fence) -> VkResult<()> {
+        let _queues = self.lock_queues();
+        self.queue_submit(queue, submits, fence)
+    }
+
+    /// Lock all queues for direct access, e.g. to present, see [`Device::submit`].
+    pub fn lock_queues(&self) -> MutexGuard<'_, ()> {
+        self.queue_lock.lock().unwrap_or_else(PoisonError::into_inner)
+    }
+
+    /// Wait for the device to idle, holding the queue lock, as `vkDeviceWaitIdle` accesses every queue.
+    pub fn wait_idle(&self) -> VkResult<()> {
+        let _queues = self.lock_queues();
+        unsafe { self.device_wait_idle() }
+    }
+
     pub fn limits(&self) -> vk::PhysicalDeviceLimits {
         unsafe { self.instance.get_physical_device_properties(self.physical_device).limits }
     }
 
+    /// Optional features enabled on the device, where supported by the physical device.
+    /// 
+    /// Functionality relying on an optional feature should check it here, and fail gracefully without it.
+    pub fn capabilities(&self) -> DeviceCapabilities {
+        self.internal.capabilities
+    }
+
+    /// Features supported by the physical device.
+    pub fn supported_features(&self) -> vk::PhysicalDeviceFeatures {
+        unsafe { self.instance.get_physical_device_features(self.physical_device) }
+    }
+
+    /// Subgroup size and supported subgroup operations, for shaders using wave intrinsics.
+    pub fn subgroup_properties(&self) -> SubgroupProperties {
+        let mut subgroup_properties = vk::PhysicalDeviceSubgroupProperties::default();
+        let mut vulkan_13_properties = vk::PhysicalDeviceVulkan13Properties::default();
+        let mut properties = vk::PhysicalDeviceProperties2::builder()
+            .push_next(&mut subgroup_properties)
+            .push_next(&mut vulkan_13_properties);
+        unsafe { self.instance.get_physical_device_properties2(self.physical_device, &mut properties) };
+
+        SubgroupProperties {
+            size: subgroup_properties.subgroup_size,
+            min_size: vulkan_13_properties.min_subgroup_size,
+            max_size: vulkan_13_properties.max_subgroup_size,
+            supported_stages: subgroup_properties.supported_stages,
+            supported_operations: subgroup_properties.supported_operations,
+            required_size_stages: vulkan_13_properties.required_subgroup_size_stages
+        }
+    }
+
+    /// Default subgroup size of shaders on this device.
+    pub fn subgroup_size(&self) -> u32 {
+        self.subgroup_properties().size
+    }
+
+    /// Check that shaders in `stage` can be pinned to `subgroup_size`, see [`SubgroupProperties`].
+    pub fn validate_required_subgroup_size(&self, stage: vk::ShaderStageFlags, subgroup_size: u32) -> Result<()> {
+        if !self.capabilities().subgroup_size_control {
+            bail!("Required subgroup size {} needs the subgroupSizeControl device feature, which this device doesn't support", subgroup_size);
+        }
+        let properties = self.subgroup_properties();
+        if !subgroup_size.is_power_of_two() || subgroup_size < properties.min_size || subgroup_size > properties.max_size {
+            bail!(
+                "Required subgroup size {} must be a power of two between {} and {}",
+                subgroup_size, properties.min_size, properties.max_size
+            );
+        }
+        if !properties.required_size_stages.contains(stage) {
+            bail!("Shader stage {:?} does not support a required subgroup size, supported stages are {:?}", stage, properties.required_size_stages);
+        }
+
+        Ok(())
+    }
+
+    /// Size of the largest memory heap that is both device local and host visible, if any.
+    /// 
+    /// With resizable BAR this is usually all of video memory, otherwise a small 256MiB window or none.
+    pub fn device_local_host_visible_heap_size(&self) -> Option<u64> {
+        let memory_properties = unsafe { self.instance.get_physical_device_memory_properties(self.physical_device) };
+        let flags = vk::MemoryPropertyFlags::DEVICE_LOCAL | vk::MemoryPropertyFlags::HOST_VISIBLE | vk::MemoryPropertyFlags::HOST_COHERENT;
+
+        memory_properties.memory_types[..memory_properties.memory_type_count as usize]
+            .iter()
+            .filter(|memory_type| memory_type.property_flags.contains(flags))
+            .map(|memory_type| memory_properties.memory_heaps[memory_type.heap_index as usize].size)
+            .max()
+    }
+
     #[inline]
     pub fn strong_count(&self) -> usize {
         Arc::strong_count(&self.internal)
@@ -422,3 +826,202 @@ impl Deref for Device {
 //         info!("Dropping ref to Device!");
 //     }
 // }
+
+
+/// Assign queue families to roles by their flags, as `[graphics, compute, transfer]` family indices.
+/// 
+/// Missing dedicated compute families alias the graphics family, and missing dedicated transfer families alias
+/// the compute family, e.g. on integrated GPUs with a single family. Returns `None` without a graphics family.
+fn assign_queue_families(families: &[vk::QueueFamilyProperties]) -> Option<[u32; 3]> {
+    let (mut graphics_family, mut compute_family, mut transfer_family) = (None, None, None);
+    for (index, properties) in families.iter().enumerate() {
+        let index = Some(index as u32);
+        match (
+            properties.queue_flags.contains(vk::QueueFlags::GRAPHICS),
+            properties.queue_flags.contains(vk::QueueFlags::COMPUTE),
+            properties.queue_flags.contains(vk::QueueFlags::TRANSFER),
+        ) {
+            (true, true, true) => graphics_family = index,
+            (false, true, true) => compute_family = index,
+            (false, false, true) => transfer_family = index,
+            _ => warn!("Encountered unsupported device queue family!"),
+        }
+    }
+
+    let graphics_family = graphics_family?;
+    let compute_family = compute_family.unwrap_or(graphics_family);
+    let transfer_family = transfer_family.unwrap_or(compute_family);
+    Some([graphics_family, compute_family, transfer_family])
+}
+
+/// Merge the queue priorities requested per role into one list per family, as duplicate family indices are invalid.
+/// 
+/// Each family takes the longest list requested of it, capped at its queue count, so roles aliasing a family share its queues.
+/// Returns the priorities per family index, and the queue count of each role as `[graphics, compute, transfer]`.
+fn merge_queue_priorities<'a>(
+    families: &[vk::QueueFamilyProperties],
+    role_families: [u32; 3],
+    requested: &[(QueueFamily, &'a [f32])]
+) -> (Vec<(u32, &'a [f32])>, [u32; 3]) {
+    let mut family_priorities: Vec<(u32, &[f32])> = vec![];
+    let mut counts = [0; 3];
+    for &(queue_family, priorities) in requested {
+        if priorities.is_empty() {
+            continue;
+        }
+
+        let role = match queue_family {
+            QueueFamily::GRAPHICS => 0,
+            QueueFamily::COMPUTE => 1,
+            QueueFamily::TRANSFER => 2,
+        };
+        let index = role_families[role];
+        let available_count = families[index as usize].queue_count as usize;
+        let priorities = &priorities[..priorities.len().min(available_count)];
+        counts[role] = priorities.len() as u32;
+
+        match family_priorities.iter_mut().find(|(family, _)| *family == index) {
+            Some((_, family_priorities)) => if priorities.len() > family_priorities.len() {
+                *family_priorities = priorities;
+            },
+            None => family_priorities.push((index, priorities))
+        }
+    }
+
+    (family_priorities, counts)
+}
+
+/// Select the queue family to present from, given which families support presentation to a surface.
+/// 
+/// Prefers the graphics family, which avoids swapchain image ownership transfers.
+fn select_present_family(graphics_family: u32, family_count: u32, present_support: impl Fn(u32) -> bool) -> Option<u32> {
+    match present_support(graphics_family) {
+        true => Some(graphics_family),
+        false => (0..family_count).find(|&index| present_support(index))
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn family(queue_flags: vk::QueueFlags, queue_count: u32) -> vk::QueueFamilyProperties {
+        vk::QueueFamilyProperties {
+            queue_flags,
+            queue_count,
+            ..Default::default()
+        }
+    }
+
+    const GENERAL: vk::QueueFlags = vk::QueueFlags::from_raw(
+        vk::QueueFlags::GRAPHICS.as_raw() | vk::QueueFlags::COMPUTE.as_raw() | vk::QueueFlags::TRANSFER.as_raw()
+    );
+
+    #[test]
+    fn single_family_device_aliases_roles() {
+        let families = [family(GENERAL, 1)];
+        let role_families = assign_queue_families(&families).unwrap();
+        assert_eq!(role_families, [0, 0, 0]);
+
+        // Every role gets a queue, all sharing one create info capped at the family's single queue
+        let requested = [
+            (QueueFamily::GRAPHICS, &[1.0][..]),
+            (QueueFamily::COMPUTE, &[1.0][..]),
+            (QueueFamily::TRANSFER, &[0.5, 0.5][..])
+        ];
+        let (family_priorities, counts) = merge_queue_priorities(&families, role_families, &requested);
+        assert_eq!(family_priorities, vec![(0, &[0.5][..])]);
+        assert_eq!(counts, [1, 1, 1]);
+    }
+
+    #[test]
+    fn dedicated_families_get_own_queues() {
+        let families = [
+            family(GENERAL, 16),
+            family(vk::QueueFlags::TRANSFER, 2),
+            family(vk::QueueFlags::COMPUTE | vk::QueueFlags::TRANSFER, 8)
+        ];
+        let role_families = assign_queue_families(&families).unwrap();
+        assert_eq!(role_families, [0, 2, 1]);
+
+        let requested = [
+            (QueueFamily::GRAPHICS, &[1.0][..]),
+            (QueueFamily::COMPUTE, &[1.0, 1.0][..]),
+            (QueueFamily::TRANSFER, &[1.0, 1.0, 1.0][..])
+        ];
+        let (family_priorities, counts) = merge_queue_priorities(&families, role_families, &requested);
+        assert_eq!(family_priorities.iter().map(|&(index, priorities)| (index, priorities.len())).collect::<Vec<_>>(), vec![(0, 1), (2, 2), (1, 2)]);
+        assert_eq!(counts, [1, 2, 2]);
+    }
+
+    #[test]
+    fn transfer_aliases_compute_family() {
+        let families = [family(GENERAL, 1), family(vk::QueueFlags::COMPUTE | vk::QueueFlags::TRANSFER, 2)];
+        assert_eq!(assign_queue_families(&families), Some([0, 1, 1]));
+        assert_eq!(assign_queue_families(&[family(vk::QueueFlags::COMPUTE | vk::QueueFlags::TRANSFER, 2)]), None);
+    }
+
+    #[test]
+    fn present_family_prefers_graphics() {
+        assert_eq!(select_present_family(1, 3, |_| true), Some(1));
+    }
+
+    #[test]
+    fn present_family_falls_back_to_separate_family() {
+        assert_eq!(select_present_family(0, 3, |index| index == 2), Some(2));
+    }
+
+    #[test]
+    fn present_family_unsupported() {
+        assert_eq!(select_present_family(0, 3, |_| false), None);
+    }
+
+    #[test]
+    fn capabilities_enable_supported_features_only() {
+        let supported = DeviceCapabilities {
+            fill_mode_non_solid: true,
+            draw_indirect_count: true,
+            subgroup_size_control: true,
+            ..Default::default()
+        };
+        let enabled = DeviceCapabilities::all().intersection(&supported);
+        assert_eq!(enabled, supported);
+
+        let mut features = vk::PhysicalDeviceFeatures::builder()
+            .sampler_anisotropy(true)
+            .image_cube_array(true)
+            .build();
+        let mut vulkan_12_features = vk::PhysicalDeviceVulkan12Features::default();
+        let mut vulkan_13_features = vk::PhysicalDeviceVulkan13Features::builder()
+            .dynamic_rendering(true)
+            .build();
+        enabled.enable(&mut features, &mut vulkan_12_features, &mut vulkan_13_features);
+
+        // Required features are kept, and unsupported optional features disabled
+        assert_eq!(features.sampler_anisotropy, vk::TRUE);
+        assert_eq!(vulkan_13_features.dynamic_rendering, vk::TRUE);
+        assert_eq!(features.image_cube_array, vk::FALSE);
+        assert_eq!(features.logic_op, vk::FALSE);
+        assert_eq!(features.fill_mode_non_solid, vk::TRUE);
+        assert_eq!(vulkan_12_features.draw_indirect_count, vk::TRUE);
+        assert_eq!(vulkan_13_features.subgroup_size_control, vk::TRUE);
+    }
+
+    #[test]
+    fn display_timing_enables_its_extension() {
+        let mut properties = vk::ExtensionProperties::default();
+        for (name_char, &byte) in properties.extension_name.iter_mut().zip(DisplayTiming::name().to_bytes()) {
+            *name_char = byte as c_char;
+        }
+        assert!(supports_extension(&[properties], DisplayTiming::name()));
+        assert!(!supports_extension(&[properties], khr::Swapchain::name()));
+        assert!(!supports_extension(&[], DisplayTiming::name()));
+
+        let names = |capabilities: DeviceCapabilities| -> Vec<&CStr> {
+            capabilities.extension_names().into_iter().map(|name| unsafe { CStr::from_ptr(name) }).collect()
+        };
+        assert_eq!(names(DeviceCapabilities::all()), [DisplayTiming::name()]);
+        assert!(names(DeviceCapabilities::default()).is_empty());
+    }
+}
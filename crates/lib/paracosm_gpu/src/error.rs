@@ -0,0 +1,39 @@
+use ash::vk;
+use thiserror::Error;
+
+
+
+/// Failures of `paracosm_gpu` that callers may want to recover from, e.g. a device lacking required support.
+/// 
+/// Returned directly by device, surface, shader module and pipeline creation. Functions returning `anyhow::Result`
+/// wrap these where they apply, see [`anyhow::Error::downcast_ref`].
+#[derive(Error, Debug)]
+pub enum GpuError {
+    #[error("No suitable device found for requested parameters")]
+    NoSuitableDevice,
+    #[error("Device does not support presentation to the surface")]
+    SurfaceUnsupported,
+    #[error("Device feature {0} is required but not supported")]
+    MissingFeature(&'static str),
+    #[error("Invalid pipeline state: {0}")]
+    InvalidPipelineState(String),
+    #[error("Shader compilation failed: {0}")]
+    ShaderCompilation(String),
+    #[error("Pipeline creation failed: {0}")]
+    PipelineCreation(vk::Result),
+    #[error("Out of memory: {0}")]
+    OutOfMemory(vk::Result),
+    #[error("Memory allocation failed: {0}")]
+    Allocation(#[from] gpu_allocator::AllocationError),
+    #[error("Vulkan error: {0}")]
+    Vulkan(vk::Result),
+}
+
+impl From<vk::Result> for GpuError {
+    fn from(result: vk::Result) -> Self {
+        match result {
+            vk::Result::ERROR_OUT_OF_HOST_MEMORY | vk::Result::ERROR_OUT_OF_DEVICE_MEMORY => GpuError::OutOfMemory(result),
+            _ => GpuError::Vulkan(result)
+        }
+    }
+}
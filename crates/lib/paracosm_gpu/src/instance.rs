@@ -16,6 +16,8 @@ use std::{
 pub struct InstanceInternal {
     pub(crate) entry: ash::Entry,
     instance: ash::Instance,
+    /// Whether `VK_EXT_debug_utils` is enabled for naming objects
+    pub(crate) object_naming: bool,
 
     #[cfg(debug_assertions)]
     _debug_utils: DebugUtils,
@@ -81,9 +83,16 @@ impl Instance {
             "VK_LAYER_KHRONOS_validation\0".as_ptr() as *const c_char
         ]);
 
-        // Add DebugUtils to extensions to request
+        // Add DebugUtils to extensions to request, always in debug builds,
+        // and in release builds with the `object_naming` feature if the extension is available
         #[cfg(debug_assertions)]
-        {
+        let object_naming = true;
+        #[cfg(all(not(debug_assertions), feature = "object_naming"))]
+        let object_naming = debug_utils_available(&entry);
+        #[cfg(all(not(debug_assertions), not(feature = "object_naming")))]
+        let object_naming = false;
+
+        if object_naming {
             let debug_ext = DebugUtils::name().as_ptr();
             if !extensions.contains(&debug_ext) {
                 extensions.append(&mut vec![debug_ext]);
@@ -107,6 +116,7 @@ impl Instance {
             internal: Arc::new(InstanceInternal {
                 entry,
                 instance,
+                object_naming,
 
                 #[cfg(debug_assertions)]
                 _debug_utils,
@@ -137,6 +147,19 @@ impl Deref for Instance {
 //     }
 // }
 
+/// Check whether the Vulkan implementation supports `VK_EXT_debug_utils`
+#[cfg(all(not(debug_assertions), feature = "object_naming"))]
+fn debug_utils_available(entry: &ash::Entry) -> bool {
+    let Ok(properties) = entry.enumerate_instance_extension_properties(None) else {
+        return false;
+    };
+
+    properties.iter().any(|property| {
+        let name = unsafe { CStr::from_ptr(property.extension_name.as_ptr()) };
+        name == DebugUtils::name()
+    })
+}
+
 /// Vulkan Debug Utils callback function
 #[cfg(debug_assertions)]
 unsafe extern "system" fn vulkan_debug_utils_callback(
@@ -1,8 +1,11 @@
+pub mod commands;
 pub mod device;
+pub mod error;
 pub mod instance;
 //pub mod raster;
 pub mod resource;
 pub mod surface;
+pub mod sync;
 pub mod utils;
 
 use ash::vk;
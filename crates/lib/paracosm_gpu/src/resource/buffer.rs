@@ -1,12 +1,14 @@
 use crate::device::Device;
+use crate::sync::TimelineSemaphore;
 
-use anyhow::{Result, bail};
+use anyhow::{Context, Result, bail};
 use ash::vk;
 
 use bevy_log::prelude::*;
 
 use gpu_allocator::vulkan::*;
 
+use std::mem::size_of;
 use std::slice;
 use std::ptr::copy_nonoverlapping as memcpy;
 
@@ -17,11 +19,16 @@ pub use gpu_allocator::MemoryLocation;
 
 
 
+#[derive(Clone)]
 pub struct BufferInfo {
     pub size: usize,
+    /// Any combination of usages, e.g. `INDIRECT_BUFFER | STORAGE_BUFFER` for GPU-driven draws
     pub usage: vk::BufferUsageFlags,
     pub memory_location: MemoryLocation,
-    pub alignment: Option<u64>
+    /// Minimum alignment of the buffer's memory, a power of two, in addition to the device's requirements
+    pub alignment: Option<u64>,
+    /// Format of the buffer's texels, required with `UNIFORM_TEXEL_BUFFER` or `STORAGE_TEXEL_BUFFER` usage
+    pub texel_format: Option<vk::Format>
 }
 
 //#[derive(Debug)]
@@ -38,7 +45,8 @@ impl BufferInfo {
             size,
             usage,
             memory_location,
-            alignment: None
+            alignment: None,
+            texel_format: None
         }
     }
 }
@@ -56,13 +64,132 @@ impl Buffer {
 
         unsafe { memcpy(data.as_ptr(), memory.cast(), data.len()) };
     }
+
+    /// Get the device address of the buffer, for passing it to shaders by address.
+    /// 
+    /// The buffer must have been created with `SHADER_DEVICE_ADDRESS` usage.
+    pub fn device_address(&self) -> Result<vk::DeviceAddress> {
+        if !self.info.usage.contains(BufferUsageFlags::SHADER_DEVICE_ADDRESS) {
+            bail!("Buffer was not created with SHADER_DEVICE_ADDRESS usage");
+        }
+
+        let info = vk::BufferDeviceAddressInfo::builder()
+            .buffer(self.buffer);
+        Ok(unsafe { self.device.get_buffer_device_address(&info) })
+    }
+
+    /// Read the buffer's contents as `T`, e.g. results copied back from the GPU into a readback buffer.
+    /// 
+    /// Invalidates the mapped memory first if it isn't host coherent. GPU writes must be complete.
+    pub fn read_buffer<T: Copy>(&self) -> Result<Vec<T>> {
+        let count = element_count::<T>(self.info.size)?;
+        let allocation = self.allocation.as_ref()
+            .context("Buffer should have an allocation.")?;
+        let memory = allocation.mapped_ptr()
+            .context("Buffer allocation should be host visible.")?
+            .as_ptr();
+
+        // Make device writes visible to the host
+        if !allocation.memory_properties().contains(vk::MemoryPropertyFlags::HOST_COHERENT) {
+            let atom_size = self.device.limits().non_coherent_atom_size;
+            let memory_range = vk::MappedMemoryRange::builder()
+                .memory(unsafe { allocation.memory() })
+                .offset(allocation.offset() / atom_size * atom_size)
+                .size(vk::WHOLE_SIZE);
+            unsafe { self.device.invalidate_mapped_memory_ranges(slice::from_ref(&memory_range))? };
+        }
+
+        let mut data = Vec::with_capacity(count);
+        unsafe {
+            memcpy(memory.cast::<T>(), data.as_mut_ptr(), count);
+            data.set_len(count);
+        }
+
+        Ok(data)
+    }
+
+    /// Read the buffer's contents as `T` without blocking, if GPU writes to it have completed,
+    /// as signaled by `semaphore` reaching `value`. Returns `Ok(None)` while the writes are pending.
+    /// 
+    /// For continuous readback, e.g. GPU picking, cycle a readback buffer per frame in flight and read each once its frame completes.
+    pub fn try_read_buffer<T: Copy>(&self, semaphore: &TimelineSemaphore, value: u64) -> Result<Option<Vec<T>>> {
+        if semaphore.value()? < value {
+            return Ok(None);
+        }
+
+        self.read_buffer().map(Some)
+    }
+}
+
+/// Check that `info` describes a buffer Vulkan can create, whatever its usage.
+fn validate_buffer_info(name: &str, info: &BufferInfo) -> Result<()> {
+    if info.size == 0 {
+        bail!("Buffer \"{}\" has zero size; Vulkan buffers must have a non-zero size", name);
+    }
+    if let Some(alignment) = info.alignment {
+        if !alignment.is_power_of_two() {
+            bail!("Buffer \"{}\" alignment {} is not a power of two", name, alignment);
+        }
+    }
+
+    Ok(())
+}
+
+/// Check that every usage of `info` is valid for its size and texel format,
+/// given the device `limits` and the buffer features of each format.
+fn validate_buffer_usage(
+    name: &str,
+    info: &BufferInfo,
+    limits: &vk::PhysicalDeviceLimits,
+    buffer_features: impl Fn(vk::Format) -> vk::FormatFeatureFlags
+) -> Result<()> {
+    // Combined usages are valid, but bindless descriptors cover the whole buffer, which each usage's maximum range limits
+    let size = info.size as u64;
+    if info.usage.contains(BufferUsageFlags::UNIFORM_BUFFER) && size > limits.max_uniform_buffer_range as u64 {
+        bail!(
+            "Buffer \"{}\" size {} exceeds maxUniformBufferRange = {} of its UNIFORM_BUFFER usage",
+            name, size, limits.max_uniform_buffer_range
+        );
+    }
+    if info.usage.contains(BufferUsageFlags::STORAGE_BUFFER) && size > limits.max_storage_buffer_range as u64 {
+        bail!(
+            "Buffer \"{}\" size {} exceeds maxStorageBufferRange = {} of its STORAGE_BUFFER usage",
+            name, size, limits.max_storage_buffer_range
+        );
+    }
+
+    for (usage, feature) in [
+        (BufferUsageFlags::UNIFORM_TEXEL_BUFFER, vk::FormatFeatureFlags::UNIFORM_TEXEL_BUFFER),
+        (BufferUsageFlags::STORAGE_TEXEL_BUFFER, vk::FormatFeatureFlags::STORAGE_TEXEL_BUFFER)
+    ] {
+        if !info.usage.contains(usage) {
+            continue;
+        }
+        match info.texel_format {
+            None => bail!("Buffer \"{}\" has {:?} usage without a texel format", name, usage),
+            Some(format) if !buffer_features(format).contains(feature) => {
+                bail!("Buffer \"{}\" texel format {:?} doesn't support {:?} usage on this device", name, format, usage)
+            },
+            Some(_) => ()
+        }
+    }
+
+    Ok(())
+}
+
+/// Number of whole `T` values in a buffer of `size` bytes.
+fn element_count<T>(size: usize) -> Result<usize> {
+    match size_of::<T>() {
+        0 => bail!("Can't read a buffer as zero-sized values"),
+        element_size => Ok(size / element_size)
+    }
 }
 
 impl Drop for Buffer {
     fn drop(&mut self) {
         unsafe {
             // TODO: look into waiting on queue idle instead
-            self.device.device_wait_idle().unwrap();
+            self.device.wait_idle().unwrap();
 
             match self.allocation.take() {
                 Some(value) => {
@@ -91,7 +218,12 @@ impl Device {
         name: &str, 
         info: BufferInfo,
         data: Option<&[u8]>
-    ) -> Buffer {
+    ) -> Result<Buffer> {
+        validate_buffer_info(name, &info)?;
+        validate_buffer_usage(name, &info, &self.limits(), |format| unsafe {
+            self.instance.get_physical_device_format_properties(self.physical_device, format).buffer_features
+        })?;
+
         let create_info = &vk::BufferCreateInfo::builder()
             .size(info.size as u64)
             .usage(info.usage)
@@ -100,9 +232,12 @@ impl Device {
 
         let buffer = unsafe {
             self.logical_device.create_buffer(create_info, None)
-                .expect("Device should create a buffer.")
+                .context(format!("Device should create buffer \"{}\"", name))?
         };
-        let requirements = unsafe { self.get_buffer_memory_requirements(buffer) };
+        let mut requirements = unsafe { self.get_buffer_memory_requirements(buffer) };
+        if let Some(alignment) = info.alignment {
+            requirements.alignment = requirements.alignment.max(alignment);
+        }
 
         let allocation = self.allocator
             .as_ref()
@@ -122,12 +257,22 @@ impl Device {
                 .expect("Buffer memory should be bound on device.")
         };
 
-        Buffer {
+        self.set_object_name(buffer, name);
+
+        Ok(Buffer {
             device: self.clone(),
             info,
             buffer,
             allocation: Some(allocation),
-        }
+        })
+    }
+
+    /// Create a buffer for reading GPU results on the host, as a copy destination.
+    /// 
+    /// Readback buffers prefer host cached memory, which is much faster for the CPU to read than write-combined memory.
+    pub fn create_readback_buffer(&self, name: &str, size: usize) -> Result<Buffer> {
+        let info = BufferInfo::new(size, BufferUsageFlags::TRANSFER_DST, MemoryLocation::GpuToCpu);
+        self.create_buffer(name, info, None)
     }
 
     pub fn copy_buffer(
@@ -135,17 +280,95 @@ impl Device {
         source: &Buffer,
         destination: &Buffer,
         size: usize
+    ) {
+        self.copy_buffer_region(source, destination, 0, size);
+    }
+
+    /// Copy `size` bytes from the start of `source` to `destination_offset` in `destination`.
+    pub fn copy_buffer_region(
+        &self,
+        source: &Buffer,
+        destination: &Buffer,
+        destination_offset: usize,
+        size: usize
     ) {
         let command_buffer = self.begin_transfer_commands()
             .expect("Transfer command buffer should begin recording.");
 
         // Record commands for data transfer
         unsafe {
-            let regions = vk::BufferCopy::builder().size(size as u64);
+            let regions = vk::BufferCopy::builder()
+                .dst_offset(destination_offset as u64)
+                .size(size as u64);
             self.cmd_copy_buffer(command_buffer, source.buffer, destination.buffer, slice::from_ref(&regions));
         }
 
         self.end_transfer_commands(command_buffer)
             .expect("Transfer command buffer should end recording and submit to device.");
     }
-}
\ No newline at end of file
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn element_counts() {
+        assert_eq!(element_count::<u32>(16).unwrap(), 4);
+        assert_eq!(element_count::<[f32; 3]>(40).unwrap(), 3);
+        assert_eq!(element_count::<u64>(4).unwrap(), 0);
+        assert!(element_count::<()>(16).is_err());
+    }
+
+    #[test]
+    fn zero_size_buffers_are_rejected() {
+        let info = BufferInfo::new(0, BufferUsageFlags::VERTEX_BUFFER, MemoryLocation::GpuOnly);
+        let error = validate_buffer_info("empty", &info).unwrap_err();
+        assert!(error.to_string().contains("\"empty\" has zero size"), "{}", error);
+
+        assert!(validate_buffer_info("vertex", &BufferInfo { size: 4, ..info }).is_ok());
+    }
+
+    fn limits() -> vk::PhysicalDeviceLimits {
+        vk::PhysicalDeviceLimits {
+            max_uniform_buffer_range: 1 << 16,
+            max_storage_buffer_range: 1 << 27,
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn combined_usage_ranges() {
+        let no_features = |_| vk::FormatFeatureFlags::empty();
+        let usage = BufferUsageFlags::STORAGE_BUFFER | BufferUsageFlags::UNIFORM_BUFFER | BufferUsageFlags::VERTEX_BUFFER;
+        let info = BufferInfo::new(1 << 16, usage, MemoryLocation::GpuOnly);
+        assert!(validate_buffer_usage("combined", &info, &limits(), no_features).is_ok());
+
+        // Past the uniform range, the same size is only valid without uniform usage
+        let info = BufferInfo::new((1 << 16) + 4, usage, MemoryLocation::GpuOnly);
+        assert!(validate_buffer_usage("combined", &info, &limits(), no_features).is_err());
+        let info = BufferInfo { usage: usage - BufferUsageFlags::UNIFORM_BUFFER, ..info };
+        assert!(validate_buffer_usage("storage_vertex", &info, &limits(), no_features).is_ok());
+
+        let info = BufferInfo::new((1 << 27) + 4, BufferUsageFlags::STORAGE_BUFFER, MemoryLocation::GpuOnly);
+        assert!(validate_buffer_usage("storage", &info, &limits(), no_features).is_err());
+        let info = BufferInfo { usage: BufferUsageFlags::VERTEX_BUFFER, ..info };
+        assert!(validate_buffer_usage("vertex", &info, &limits(), no_features).is_ok());
+    }
+
+    #[test]
+    fn texel_usage_requires_supported_format() {
+        let buffer_features = |format| match format {
+            vk::Format::R32_UINT => vk::FormatFeatureFlags::UNIFORM_TEXEL_BUFFER | vk::FormatFeatureFlags::STORAGE_TEXEL_BUFFER,
+            vk::Format::R8G8B8_UNORM => vk::FormatFeatureFlags::UNIFORM_TEXEL_BUFFER,
+            _ => vk::FormatFeatureFlags::empty()
+        };
+        let usage = BufferUsageFlags::STORAGE_TEXEL_BUFFER | BufferUsageFlags::VERTEX_BUFFER;
+        let info = |texel_format| BufferInfo { texel_format, ..BufferInfo::new(256, usage, MemoryLocation::GpuOnly) };
+
+        assert!(validate_buffer_usage("texels", &info(Some(vk::Format::R32_UINT)), &limits(), buffer_features).is_ok());
+        assert!(validate_buffer_usage("texels", &info(Some(vk::Format::R8G8B8_UNORM)), &limits(), buffer_features).is_err());
+        assert!(validate_buffer_usage("texels", &info(None), &limits(), buffer_features).is_err());
+    }
+}
@@ -0,0 +1,321 @@
+use crate::device::Device;
+use super::buffer::*;
+
+use anyhow::{Context, Result, bail};
+use ash::vk;
+
+use bevy_log::prelude::*;
+
+use std::collections::HashMap;
+use std::mem::size_of_val;
+use std::ptr::copy_nonoverlapping as memcpy;
+
+
+
+/// A sub-range of a [`BufferArena`] allocation.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct BufferRange {
+    pub buffer: vk::Buffer,
+    pub offset: u64,
+    pub size: u64,
+    /// Usage of the arena the range was allocated from
+    pub usage: BufferUsageFlags,
+    block: usize,
+}
+
+struct ArenaBlock {
+    buffer: Buffer,
+    ranges: FreeList,
+}
+
+/// First fit allocator of ranges within one block, tracking live ranges to reject invalid frees.
+struct FreeList {
+    /// Free (offset, size) ranges, sorted by offset
+    free_ranges: Vec<(u64, u64)>,
+    /// Size of each live range by offset
+    live_ranges: HashMap<u64, u64>,
+}
+
+impl FreeList {
+    fn new(size: u64) -> Self {
+        Self {
+            free_ranges: vec![(0, size)],
+            live_ranges: HashMap::new(),
+        }
+    }
+
+    /// Allocate `size` bytes from the first free range that fits, returning the offset.
+    fn allocate(&mut self, size: u64) -> Option<u64> {
+        let range_index = self.free_ranges.iter().position(|(_, free_size)| *free_size >= size)?;
+
+        let (offset, free_size) = self.free_ranges[range_index];
+        match free_size == size {
+            true => { self.free_ranges.remove(range_index); },
+            false => self.free_ranges[range_index] = (offset + size, free_size - size)
+        }
+        self.live_ranges.insert(offset, size);
+
+        Some(offset)
+    }
+
+    /// Free a live range, merging it with adjacent free ranges.
+    fn free(&mut self, offset: u64, size: u64) -> Result<()> {
+        match self.live_ranges.get(&offset) {
+            Some(&live_size) if live_size == size => { self.live_ranges.remove(&offset); },
+            Some(&live_size) => bail!("Range at offset {} has size {}, but {} bytes were freed", offset, live_size, size),
+            None => bail!("Range at offset {} is not allocated, it may have been freed already", offset)
+        }
+
+        let free_ranges = &mut self.free_ranges;
+        let index = free_ranges.partition_point(|(free_offset, _)| *free_offset < offset);
+        free_ranges.insert(index, (offset, size));
+
+        // Merge with following range
+        if index + 1 < free_ranges.len() {
+            let (offset, size) = free_ranges[index];
+            let (next_offset, next_size) = free_ranges[index + 1];
+            if offset + size == next_offset {
+                free_ranges[index].1 = size + next_size;
+                free_ranges.remove(index + 1);
+            }
+        }
+
+        // Merge with preceding range
+        if index > 0 {
+            let (previous_offset, previous_size) = free_ranges[index - 1];
+            let (offset, size) = free_ranges[index];
+            if previous_offset + previous_size == offset {
+                free_ranges[index - 1].1 = previous_size + size;
+                free_ranges.remove(index);
+            }
+        }
+
+        Ok(())
+    }
+}
+
+/// Sub-allocates many small buffer ranges from a few large [`Buffer`]s.
+///
+/// Avoids one device allocation per small buffer, which wastes memory and can exceed `maxMemoryAllocationCount`.
+pub struct BufferArena {
+    device: Device,
+    name: String,
+    usage: BufferUsageFlags,
+    memory_location: MemoryLocation,
+    block_size: u64,
+    alignment: u64,
+    blocks: Vec<ArenaBlock>,
+}
+
+impl BufferArena {
+    /// Allocate a range of at least `size` bytes, creating a new block if no free range fits.
+    pub fn allocate(&mut self, size: u64) -> Result<BufferRange> {
+        if size == 0 {
+            bail!("BufferArena \"{}\" cannot allocate a zero size range", self.name);
+        }
+        let size = align_up(size, self.alignment);
+
+        // First fit search of existing blocks
+        for (block_index, block) in self.blocks.iter_mut().enumerate() {
+            let Some(offset) = block.ranges.allocate(size) else {
+                continue;
+            };
+
+            return Ok(BufferRange {
+                buffer: block.buffer.buffer,
+                offset,
+                size,
+                usage: self.usage,
+                block: block_index
+            });
+        }
+
+        // Create a new block, sized to fit oversized allocations
+        let block_size = self.block_size.max(size);
+        let info = BufferInfo::new(block_size as usize, self.usage, self.memory_location);
+        let name = format!("{} Block {}", self.name, self.blocks.len());
+        let buffer = self.device.create_buffer(&name, info, None)
+            .context(format!("BufferArena \"{}\" should create a new block", self.name))?;
+        debug!("Created BufferArena block \"{}\" of {} bytes", name, block_size);
+
+        let mut ranges = FreeList::new(block_size);
+        ranges.allocate(size);
+        self.blocks.push(ArenaBlock {
+            buffer,
+            ranges
+        });
+
+        Ok(BufferRange {
+            buffer: self.blocks.last().unwrap().buffer.buffer,
+            offset: 0,
+            size,
+            usage: self.usage,
+            block: self.blocks.len() - 1
+        })
+    }
+
+    /// Return a range to the arena for reuse, merging it with adjacent free ranges.
+    ///
+    /// The range must no longer be in use by the GPU. Fails for ranges of another arena, and ranges already freed.
+    pub fn free(&mut self, range: BufferRange) -> Result<()> {
+        let block = match self.blocks.get_mut(range.block) {
+            Some(block) if block.buffer.buffer == range.buffer => block,
+            _ => bail!("BufferArena \"{}\" cannot free a range from a block it doesn't own", self.name)
+        };
+
+        block.ranges.free(range.offset, range.size)
+            .context(format!("BufferArena \"{}\" should free range", self.name))
+    }
+
+    /// Write `data` to the start of `range`. The arena must be host visible.
+    pub fn write<T>(&self, range: &BufferRange, data: &[T]) -> Result<()> {
+        let data_size = size_of_val(data) as u64;
+        if data_size > range.size {
+            bail!("Data of {} bytes doesn't fit in BufferArena range of {} bytes", data_size, range.size);
+        }
+
+        let allocation = self.blocks.get(range.block)
+            .and_then(|block| block.buffer.allocation.as_ref())
+            .context("BufferRange should belong to an allocated block")?;
+        let memory = allocation.mapped_ptr()
+            .context("BufferArena allocation should be host visible")?
+            .as_ptr()
+            .cast::<u8>();
+
+        unsafe { memcpy(data.as_ptr().cast::<u8>(), memory.add(range.offset as usize), data_size as usize) };
+
+        Ok(())
+    }
+
+    /// Number of underlying device allocations
+    pub fn block_count(&self) -> usize {
+        self.blocks.len()
+    }
+}
+
+fn align_up(value: u64, alignment: u64) -> u64 {
+    (value + alignment - 1) / alignment * alignment
+}
+
+impl Device {
+    /// Create a [`BufferArena`] that allocates blocks of `block_size` bytes.
+    ///
+    /// Ranges are aligned to the device's minimum offset alignment for `usage`.
+    pub fn create_buffer_arena(
+        &self,
+        name: &str,
+        block_size: u64,
+        usage: BufferUsageFlags,
+        memory_location: MemoryLocation
+    ) -> Result<BufferArena> {
+        if block_size == 0 {
+            bail!("BufferArena \"{}\" has zero block size", name);
+        }
+
+        let limits = self.limits();
+        let mut alignment = 16;
+        if usage.contains(BufferUsageFlags::STORAGE_BUFFER) {
+            alignment = alignment.max(limits.min_storage_buffer_offset_alignment);
+        }
+        if usage.contains(BufferUsageFlags::UNIFORM_BUFFER) {
+            alignment = alignment.max(limits.min_uniform_buffer_offset_alignment);
+        }
+
+        Ok(BufferArena {
+            device: self.clone(),
+            name: name.to_string(),
+            usage,
+            memory_location,
+            block_size: align_up(block_size, alignment),
+            alignment,
+            blocks: vec![],
+        })
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn free_list_first_fit() {
+        let mut ranges = FreeList::new(256);
+        assert_eq!(ranges.allocate(64), Some(0));
+        assert_eq!(ranges.allocate(64), Some(64));
+        assert_eq!(ranges.allocate(64), Some(128));
+
+        // The freed first range is the first fit for a smaller allocation
+        ranges.free(0, 64).unwrap();
+        assert_eq!(ranges.allocate(32), Some(0));
+        assert_eq!(ranges.allocate(64), Some(192));
+        assert_eq!(ranges.allocate(64), None);
+    }
+
+    #[test]
+    fn free_list_merges_adjacent_ranges() {
+        let mut ranges = FreeList::new(192);
+        let offsets: Vec<u64> = (0..3).map(|_| ranges.allocate(64).unwrap()).collect();
+
+        // Free the outer ranges, then the middle one, which merges all three
+        ranges.free(offsets[0], 64).unwrap();
+        ranges.free(offsets[2], 64).unwrap();
+        assert_eq!(ranges.free_ranges, vec![(0, 64), (128, 64)]);
+        ranges.free(offsets[1], 64).unwrap();
+        assert_eq!(ranges.free_ranges, vec![(0, 192)]);
+    }
+
+    #[test]
+    fn free_list_rejects_invalid_frees() {
+        let mut ranges = FreeList::new(128);
+        let offset = ranges.allocate(64).unwrap();
+
+        assert!(ranges.free(offset, 32).is_err());
+        assert!(ranges.free(64, 64).is_err());
+        ranges.free(offset, 64).unwrap();
+        assert!(ranges.free(offset, 64).is_err());
+        assert_eq!(ranges.free_ranges, vec![(0, 128)]);
+    }
+
+    #[test]
+    fn free_list_stress() {
+        const SIZE: u64 = 1 << 16;
+        let mut ranges = FreeList::new(SIZE);
+        let mut live: Vec<(u64, u64)> = vec![];
+
+        // Deterministic pseudo random allocations and frees
+        let mut state = 0x2545_f491_u64;
+        let mut next = || {
+            state ^= state << 13;
+            state ^= state >> 7;
+            state ^= state << 17;
+            state
+        };
+        for _ in 0..10_000 {
+            match live.is_empty() || next() % 3 != 0 {
+                true => {
+                    let size = (next() % 16 + 1) * 16;
+                    if let Some(offset) = ranges.allocate(size) {
+                        live.push((offset, size));
+                    }
+                },
+                false => {
+                    let (offset, size) = live.swap_remove((next() % live.len() as u64) as usize);
+                    ranges.free(offset, size).unwrap();
+                }
+            }
+
+            // Live and free ranges never overlap and exactly cover the block
+            let mut covered: Vec<(u64, u64)> = live.iter().chain(ranges.free_ranges.iter()).copied().collect();
+            covered.sort_unstable();
+            let end = covered.iter().try_fold(0, |end, &(offset, size)| (offset == end).then_some(offset + size));
+            assert_eq!(end, Some(SIZE));
+        }
+
+        // Freeing everything merges back into a single range
+        for (offset, size) in live.drain(..) {
+            ranges.free(offset, size).unwrap();
+        }
+        assert_eq!(ranges.free_ranges, vec![(0, SIZE)]);
+    }
+}
@@ -1,13 +1,14 @@
-use crate::device::Device;
+use crate::device::{Device, QueueFamily};
 use crate::resource::buffer::Buffer;
 
-use anyhow::{Result, bail};
+use anyhow::{Context, Result, bail};
 use ash::vk;
 
 use bevy_log::prelude::*;
 
 use gpu_allocator::vulkan::*;
 
+use std::ops::Range;
 use std::slice;
 use std::ptr::copy_nonoverlapping as memcpy;
 
@@ -27,20 +28,169 @@ pub use gpu_allocator::MemoryLocation;
 
 
 
+/// Number of mip levels of an image
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum MipLevels {
+    Count(u32),
+    /// The complete chain down to a single texel, `floor(log2(max(width, height, depth))) + 1` levels
+    Full,
+}
+
+impl MipLevels {
+    /// Get the number of mip levels for an image of `extent`.
+    pub fn count(&self, extent: Extent3D) -> u32 {
+        match *self {
+            MipLevels::Count(count) => count,
+            MipLevels::Full => {
+                let largest = extent.width.max(extent.height).max(extent.depth).max(1);
+                u32::BITS - largest.leading_zeros()
+            }
+        }
+    }
+}
+
+#[derive(Clone)]
 pub struct ImageInfo {
     pub image_type: ImageType,
     pub image_format: Format,
     pub image_extent: Extent3D,
-    pub mip_levels: u32,
+    pub mip_levels: MipLevels,
     pub array_layers: u32,
     pub samples: SampleCountFlags,
+    /// `LINEAR` tiling allows host access to image memory with a `CpuToGpu` location, see [`Image::subresource_layout`]
     pub tiling: ImageTiling,
     pub usage: ImageUsageFlags,
     pub aspect: ImageAspectFlags,
     pub memory_location: MemoryLocation,
+    /// Attachment-only image whose contents never leave the GPU's tile memory, e.g. a multisampled color attachment
+    /// that is resolved. Uses lazily allocated memory where available, saving memory on tiled GPUs.
+    pub transient: bool,
+    /// Compatible formats views of the image may reinterpret it as, e.g. `R32_UINT` for `R8G8B8A8_UNORM` data,
+    /// see [`Image::create_format_view`]. Empty for views of the image format only.
+    pub view_formats: Vec<Format>,
+    /// How the image is shared between queue families, e.g. written by compute and sampled by graphics
+    pub sharing: Sharing,
     //pub alignment: Option<u64>
 }
 
+/// Queue family sharing of an image
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub enum Sharing {
+    /// Owned by one queue family at a time. Using the image on another family requires an ownership transfer,
+    /// see [`Device::release_image_ownership`] and [`Device::acquire_image_ownership`].
+    #[default]
+    Exclusive,
+    /// Accessible from all listed queue families without ownership transfers, possibly at lower performance
+    Concurrent(Vec<QueueFamily>),
+}
+
+impl ImageInfo {
+    pub fn mip_level_count(&self) -> u32 {
+        self.mip_levels.count(self.image_extent)
+    }
+
+    /// Extent of mip level `mip_level`, halved per level down to a single texel.
+    pub fn mip_extent(&self, mip_level: u32) -> Extent3D {
+        let extent = self.image_extent;
+        Extent3D {
+            width: (extent.width >> mip_level).max(1),
+            height: (extent.height >> mip_level).max(1),
+            depth: (extent.depth >> mip_level).max(1)
+        }
+    }
+}
+
+/// A region of one aspect of an [`Image`] copied to a buffer, see [`Device::copy_image_to_buffer`].
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct ImageCopyRegion {
+    /// A single aspect, e.g. `DEPTH` or `STENCIL` of a depth stencil image
+    pub aspect: ImageAspectFlags,
+    pub mip_level: u32,
+    pub base_array_layer: u32,
+    pub layer_count: u32,
+    pub offset: vk::Offset3D,
+    pub extent: Extent3D,
+    /// Byte offset of the region's data in the buffer
+    pub buffer_offset: u64,
+}
+
+impl ImageCopyRegion {
+    /// The whole of `aspect` of mip level `mip_level` and all array layers of `image`, at the start of the buffer.
+    pub fn mip_level(info: &ImageInfo, aspect: ImageAspectFlags, mip_level: u32) -> Self {
+        Self {
+            aspect,
+            mip_level,
+            base_array_layer: 0,
+            layer_count: info.array_layers,
+            offset: vk::Offset3D { x: 0, y: 0, z: 0 },
+            extent: info.mip_extent(mip_level),
+            buffer_offset: 0
+        }
+    }
+
+    /// Place the region's data at `buffer_offset` bytes into the buffer.
+    pub fn with_buffer_offset(mut self, buffer_offset: u64) -> Self {
+        self.buffer_offset = buffer_offset;
+        self
+    }
+
+    /// Size in bytes of the region's tightly packed data for an image of `format`.
+    pub fn data_size(&self, format: Format) -> Option<u64> {
+        let texel_size = aspect_texel_size(format, self.aspect)? as u64;
+        let extent = self.extent;
+        Some(texel_size * extent.width as u64 * extent.height as u64 * extent.depth as u64 * self.layer_count as u64)
+    }
+
+    /// Validate the region against the copied image and the size of the destination buffer.
+    pub fn validate(&self, info: &ImageInfo, buffer_size: u64) -> Result<()> {
+        if self.aspect.as_raw().count_ones() != 1 || !info.aspect.contains(self.aspect) {
+            bail!("Copy region aspect {:?} must be a single aspect of the image's {:?}", self.aspect, info.aspect);
+        }
+        let Some(data_size) = self.data_size(info.image_format) else {
+            bail!("Copying aspect {:?} of images with format {:?} to a buffer is unsupported", self.aspect, info.image_format);
+        };
+
+        if self.mip_level >= info.mip_level_count() {
+            bail!("Copy region mip level {} out of range for image with {} mip levels", self.mip_level, info.mip_level_count());
+        }
+        if self.layer_count == 0 || self.base_array_layer + self.layer_count > info.array_layers {
+            bail!(
+                "Copy region layers {}..{} out of range for image with {} array layers",
+                self.base_array_layer, self.base_array_layer + self.layer_count, info.array_layers
+            );
+        }
+
+        let mip_extent = info.mip_extent(self.mip_level);
+        let within = |offset: i32, size: u32, limit: u32| offset >= 0 && size > 0 && offset as u64 + size as u64 <= limit as u64;
+        if !within(self.offset.x, self.extent.width, mip_extent.width)
+            || !within(self.offset.y, self.extent.height, mip_extent.height)
+            || !within(self.offset.z, self.extent.depth, mip_extent.depth)
+        {
+            bail!(
+                "Copy region at {:?} of {:?} exceeds mip level {} extent {:?}",
+                self.offset, self.extent, self.mip_level, mip_extent
+            );
+        }
+
+        // Depth and stencil data must be 4 byte aligned, color data texel aligned
+        let alignment = match self.aspect {
+            ImageAspectFlags::COLOR => aspect_texel_size(info.image_format, self.aspect).unwrap_or(1) as u64,
+            _ => 4
+        };
+        if self.buffer_offset % alignment != 0 {
+            bail!("Copy region buffer offset {} must be a multiple of {} bytes", self.buffer_offset, alignment);
+        }
+        if self.buffer_offset + data_size > buffer_size {
+            bail!(
+                "Buffer of {} bytes is too small for {} bytes of image data at offset {}",
+                buffer_size, data_size, self.buffer_offset
+            );
+        }
+
+        Ok(())
+    }
+}
+
 //#[derive(Debug)]
 pub struct Image {
     device: Device,
@@ -49,6 +199,8 @@ pub struct Image {
     pub image: vk::Image,
     pub image_view: vk::ImageView,
     pub(crate) allocation: Option<Allocation>,
+    /// Lazily allocated memory of transient images, allocated outside the allocator
+    pub(crate) lazy_memory: Option<vk::DeviceMemory>,
 }
 
 impl Image {
@@ -61,7 +213,7 @@ impl Image {
                 vk::ImageSubresourceRange::builder()
                     .aspect_mask(info.aspect)
                     .base_mip_level(0)
-                    .level_count(info.mip_levels)
+                    .level_count(info.mip_level_count())
                     .base_array_layer(0)
                     .layer_count(info.array_layers)
                     .build()
@@ -76,10 +228,16 @@ impl Image {
             info,
             image,
             image_view,
-            allocation: None
+            allocation: None,
+            lazy_memory: None
         })
     }
 
+    /// Whether the image is backed by lazily allocated memory, see [`ImageInfo::transient`].
+    pub fn is_lazily_allocated(&self) -> bool {
+        self.lazy_memory.is_some()
+    }
+
     pub fn write_image<T>(
         &self,
         data: &Vec<T>
@@ -100,13 +258,124 @@ impl Image {
     pub fn extent(&self) -> Extent3D {
         self.info.image_extent
     }
+
+    /// Get the memory layout of the first mip level and layer of a linear tiled image, e.g. its row pitch for host writes.
+    pub fn subresource_layout(&self) -> Result<vk::SubresourceLayout> {
+        if self.info.tiling != ImageTiling::LINEAR {
+            bail!("Only linear tiled images have a defined subresource layout");
+        }
+
+        let subresource = vk::ImageSubresource::builder()
+            .aspect_mask(self.info.aspect)
+            .mip_level(0)
+            .array_layer(0)
+            .build();
+
+        Ok(unsafe { self.device.get_image_subresource_layout(self.image, subresource) })
+    }
+
+    /// Create a 2D view of a single array layer, e.g. to render into one face of a cubemap.
+    /// 
+    /// Cubemap array faces are laid out per cube, so face `face` of cube `cube` is layer `cube * 6 + face`.
+    pub fn create_layer_view(&self, layer: u32) -> Result<ImageLayerView> {
+        if layer >= self.info.array_layers {
+            bail!("Layer {} out of range for image with {} array layers", layer, self.info.array_layers);
+        }
+
+        let create_info = vk::ImageViewCreateInfo::builder()
+            .image(self.image)
+            .view_type(ImageType::TYPE_2D)
+            .format(self.info.image_format)
+            .subresource_range(
+                vk::ImageSubresourceRange::builder()
+                    .aspect_mask(self.info.aspect)
+                    .base_mip_level(0)
+                    .level_count(1)
+                    .base_array_layer(layer)
+                    .layer_count(1)
+                    .build()
+            );
+        let image_view = unsafe {
+            self.device.create_image_view(&create_info, None)
+                .context("Device should create an image layer view")?
+        };
+
+        Ok(ImageLayerView {
+            device: self.device.clone(),
+            layer,
+            image_view
+        })
+    }
+
+    /// Create a view of the whole image reinterpreted as `format`, which must be one of [`ImageInfo::view_formats`].
+    pub fn create_format_view(&self, format: Format) -> Result<ImageFormatView> {
+        if !self.info.view_formats.contains(&format) {
+            bail!("Format {:?} is not a view format of the image, view formats are {:?}", format, self.info.view_formats);
+        }
+
+        let create_info = vk::ImageViewCreateInfo::builder()
+            .image(self.image)
+            .view_type(self.info.image_type)
+            .format(format)
+            .subresource_range(
+                vk::ImageSubresourceRange::builder()
+                    .aspect_mask(self.info.aspect)
+                    .base_mip_level(0)
+                    .level_count(self.info.mip_level_count())
+                    .base_array_layer(0)
+                    .layer_count(self.info.array_layers)
+                    .build()
+            );
+        let image_view = unsafe {
+            self.device.create_image_view(&create_info, None)
+                .context("Device should create an image format view")?
+        };
+
+        Ok(ImageFormatView {
+            device: self.device.clone(),
+            format,
+            image_view
+        })
+    }
+}
+
+/// A view of a single array layer of an [`Image`], created by [`Image::create_layer_view`].
+/// 
+/// Must be dropped before its image.
+pub struct ImageLayerView {
+    device: Device,
+    pub layer: u32,
+    pub image_view: vk::ImageView,
+}
+
+impl Drop for ImageLayerView {
+    fn drop(&mut self) {
+        unsafe {
+            self.device.destroy_image_view(self.image_view, None);
+        }
+    }
+}
+
+/// A view of an [`Image`] reinterpreted as another compatible format, created by [`Image::create_format_view`].
+pub struct ImageFormatView {
+    device: Device,
+    pub format: Format,
+    pub image_view: vk::ImageView,
+}
+
+impl Drop for ImageFormatView {
+    fn drop(&mut self) {
+        unsafe {
+            self.device.destroy_image_view(self.image_view, None);
+        }
+    }
 }
 
 impl Drop for Image {
     fn drop(&mut self) {
         unsafe {
             // TODO: look into waiting on queue idle instead
-            self.device.device_wait_idle().unwrap();
+            self.device.wait_idle().unwrap();
 
             match self.allocation.take() {
                 Some(value) => {
@@ -128,6 +397,9 @@ impl Drop for Image {
             if self.cleanup {
                 self.device.destroy_image(self.image, None);
             }
+            if let Some(memory) = self.lazy_memory.take() {
+                self.device.free_memory(memory, None);
+            }
         }
     }
 }
@@ -137,32 +409,195 @@ impl Device {
     pub fn create_image(
         &self, 
         name: &str, 
-        info: ImageInfo,
+        mut info: ImageInfo,
         data: Option<&[u8]>
-    ) -> Image {
+    ) -> Result<Image> {
+        // Validate multisampling
+        if info.samples != SampleCountFlags::TYPE_1 {
+            let limits = self.limits();
+            let supported_samples = match info.aspect.contains(ImageAspectFlags::DEPTH) {
+                true => limits.framebuffer_depth_sample_counts,
+                false => limits.framebuffer_color_sample_counts
+            };
+            if !supported_samples.contains(info.samples) {
+                bail!("Image \"{}\" sample count {:?} not supported by device; supported {:?}", name, info.samples, supported_samples);
+            }
+            if info.mip_level_count() != 1 {
+                bail!("Image \"{}\" is multisampled and must have exactly one mip level", name);
+            }
+
+            // Multisampled images are attachment only, resolve to a single sampled image for shader access
+            let invalid_usage = info.usage & (ImageUsageFlags::SAMPLED | ImageUsageFlags::STORAGE);
+            if !invalid_usage.is_empty() {
+                bail!("Image \"{}\" is multisampled and can't have usage {:?}, resolve it to a single sampled image instead", name, invalid_usage);
+            }
+        }
+
+        // Validate full mip chains can be generated by blits of each level from the last, see [`Device::generate_mips`],
+        // as unsupported formats otherwise produce black mips
+        if info.mip_levels == MipLevels::Full {
+            match mip_generation_filter(self.format_features(info.image_format, info.tiling)) {
+                None => bail!("Image \"{}\" format {:?} does not support blits for generating a full mip chain", name, info.image_format),
+                Some(vk::Filter::NEAREST) => warn!(
+                    "Image \"{}\" format {:?} does not support linear filtering, its mip chain will be generated with nearest filtering",
+                    name, info.image_format
+                ),
+                Some(_) => ()
+            }
+        }
+        info.mip_levels = MipLevels::Count(info.mip_level_count());
+
+        // Validate transient images are only used as attachments
+        if info.transient {
+            let attachment_usage = ImageUsageFlags::COLOR_ATTACHMENT
+                | ImageUsageFlags::DEPTH_STENCIL_ATTACHMENT
+                | ImageUsageFlags::INPUT_ATTACHMENT
+                | ImageUsageFlags::TRANSIENT_ATTACHMENT;
+            if !attachment_usage.contains(info.usage) {
+                bail!("Transient image \"{}\" may only have attachment usage, got {:?}", name, info.usage);
+            }
+            if info.tiling != ImageTiling::OPTIMAL || info.memory_location != MemoryLocation::GpuOnly {
+                bail!("Transient image \"{}\" must use optimal tiling and GpuOnly memory", name);
+            }
+            info.usage |= ImageUsageFlags::TRANSIENT_ATTACHMENT;
+        }
+
+        // Validate extent, mip levels and layers against the device limits of the image type
+        let image_type = validate_image_extent(&info, &self.limits())
+            .with_context(|| format!("Image \"{}\" is invalid", name))?;
+
+        // Validate linear tiling, which is much more restricted than optimal tiling
+        if info.tiling == ImageTiling::LINEAR {
+            let properties = unsafe {
+                self.instance.get_physical_device_image_format_properties(
+                    self.physical_device,
+                    info.image_format,
+                    image_type,
+                    info.tiling,
+                    info.usage,
+                    vk::ImageCreateFlags::empty()
+                )
+            };
+            let properties = match properties {
+                Ok(properties) => properties,
+                Err(_) => bail!("Image \"{}\" format {:?} with usage {:?} does not support linear tiling", name, info.image_format, info.usage)
+            };
+            if info.mip_level_count() > properties.max_mip_levels || info.array_layers > properties.max_array_layers {
+                bail!(
+                    "Image \"{}\" with linear tiling supports up to {} mip levels and {} array layers, got {} and {}",
+                    name, properties.max_mip_levels, properties.max_array_layers, info.mip_level_count(), info.array_layers
+                );
+            }
+        }
+
+        // Validate cubemap layers, 6 faces per cube
+        let is_cube = matches!(info.image_type, ImageType::CUBE | ImageType::CUBE_ARRAY);
+        if is_cube {
+            if info.array_layers == 0 || info.array_layers % 6 != 0 {
+                bail!("Cubemap image \"{}\" has {} array layers; layer count must be a multiple of 6", name, info.array_layers);
+            }
+            if info.image_type == ImageType::CUBE_ARRAY && !self.capabilities().image_cube_array {
+                bail!("Cubemap array image \"{}\" requires the imageCubeArray feature, which this device doesn't support", name);
+            }
+            if info.image_type == ImageType::CUBE && info.array_layers != 6 {
+                bail!("Cubemap image \"{}\" has {} array layers; use CUBE_ARRAY for more than one cube", name, info.array_layers);
+            }
+            if info.image_extent.width != info.image_extent.height {
+                bail!("Cubemap image \"{}\" faces must be square, got {}x{}", name, info.image_extent.width, info.image_extent.height);
+            }
+        }
+
         // Create image
-        let image_type = match info.image_type {
-            vk::ImageViewType::TYPE_2D => vk::ImageType::TYPE_2D,
-            _ => panic!("Unsupported image type")
+        let mut flags = match is_cube {
+            true => vk::ImageCreateFlags::CUBE_COMPATIBLE,
+            false => vk::ImageCreateFlags::empty()
         };
-        let create_info = vk::ImageCreateInfo::builder()
+        // Mutable format images list every format views may use, including the image format for its default view
+        let mut view_formats = info.view_formats.clone();
+        if !view_formats.is_empty() {
+            flags |= vk::ImageCreateFlags::MUTABLE_FORMAT;
+            if !view_formats.contains(&info.image_format) {
+                view_formats.insert(0, info.image_format);
+            }
+        }
+        let (sharing_mode, queue_family_indices) = self.sharing_mode(&info.sharing);
+        let mut format_list_info = vk::ImageFormatListCreateInfo::builder()
+            .view_formats(&view_formats);
+        let mut create_info = vk::ImageCreateInfo::builder()
+            .flags(flags)
             .image_type(image_type)
             .format(info.image_format)
             .extent(info.image_extent)
-            .mip_levels(info.mip_levels)
+            .mip_levels(info.mip_level_count())
             .array_layers(info.array_layers)
             .samples(info.samples)
             .tiling(info.tiling)
             .usage(info.usage)
-            .sharing_mode(vk::SharingMode::EXCLUSIVE)
+            .sharing_mode(sharing_mode)
+            .queue_family_indices(&queue_family_indices)
             .initial_layout(vk::ImageLayout::UNDEFINED);
+        if !view_formats.is_empty() {
+            create_info = create_info.push_next(&mut format_list_info);
+        }
 
         let image = unsafe { 
             self.logical_device.create_image(&create_info, None)
-                .expect("Device should create an image.")
+                .context(format!("Device should create image \"{}\"", name))?
         };
+
+        // Destroy the image if binding its memory fails, so nothing leaks
+        match self.bind_new_image(name, image, info) {
+            Ok(image) => Ok(image),
+            Err(error) => {
+                unsafe { self.logical_device.destroy_image(image, None) };
+                Err(error)
+            }
+        }
+    }
+
+    /// Allocate and bind memory for a newly created `image`, and create its default view.
+    /// 
+    /// Memory is freed again on error, the image itself is left to the caller.
+    fn bind_new_image(&self, name: &str, image: vk::Image, info: ImageInfo) -> Result<Image> {
         let requirements = unsafe { self.logical_device.get_image_memory_requirements(image) };
 
+        // Bind transient images to lazily allocated memory if the device has it, which the allocator doesn't support
+        let lazy_memory_type = match info.transient {
+            true => self.lazily_allocated_memory_type(requirements.memory_type_bits),
+            false => None
+        };
+        if let Some(memory_type_index) = lazy_memory_type {
+            let allocate_info = vk::MemoryAllocateInfo::builder()
+                .allocation_size(requirements.size)
+                .memory_type_index(memory_type_index);
+            let memory = unsafe {
+                self.allocate_memory(&allocate_info, None)
+                    .context(format!("Device should allocate lazy memory for image \"{}\"", name))?
+            };
+            let image_view = unsafe { self.bind_image_memory(image, memory, 0) }
+                .map_err(anyhow::Error::from)
+                .and_then(|_| self.create_default_image_view(image, &info));
+            let image_view = match image_view {
+                Ok(image_view) => image_view,
+                Err(error) => {
+                    unsafe { self.free_memory(memory, None) };
+                    return Err(error.context(format!("Device should bind lazy memory for image \"{}\"", name)));
+                }
+            };
+            self.set_object_name(image, name);
+            self.set_object_name(image_view, format!("{} View", name).as_str());
+
+            return Ok(Image {
+                device: self.clone(),
+                cleanup: true,
+                info,
+                image,
+                image_view,
+                allocation: None,
+                lazy_memory: Some(memory)
+            });
+        }
+
         let allocation = self.allocator
             .as_ref()
             .unwrap()
@@ -172,41 +607,207 @@ impl Device {
                 name,
                 requirements,
                 location: info.memory_location,
-                linear: true
+                linear: info.tiling == ImageTiling::LINEAR
             })
-            .expect("Image memory should be allocated.");
+            .context(format!("Device should allocate memory for image \"{}\"", name))?;
 
-        unsafe {
-            self.bind_image_memory(image, allocation.memory(), allocation.offset())
-                .expect("Image memory should be bound on device.")
+        // Bind memory and create image view
+        let image_view = unsafe { self.bind_image_memory(image, allocation.memory(), allocation.offset()) }
+            .map_err(anyhow::Error::from)
+            .and_then(|_| self.create_default_image_view(image, &info));
+        let image_view = match image_view {
+            Ok(image_view) => image_view,
+            Err(error) => {
+                if let Err(free_error) = self.allocator.as_ref().unwrap().lock().unwrap().free(allocation) {
+                    debug!("{}", free_error.to_string());
+                }
+                return Err(error.context(format!("Device should bind memory for image \"{}\"", name)));
+            }
         };
 
-        // Create image view
+        self.set_object_name(image, name);
+        self.set_object_name(image_view, format!("{} View", name).as_str());
+
+        Ok(Image {
+            device: self.clone(),
+            cleanup: true,
+            info,
+            image,
+            image_view,
+            allocation: Some(allocation),
+            lazy_memory: None
+        })
+    }
+
+    fn create_default_image_view(&self, image: vk::Image, info: &ImageInfo) -> Result<vk::ImageView> {
         let create_info = vk::ImageViewCreateInfo::builder()
             .view_type(info.image_type)
             .image(image)
             .format(info.image_format)
             .subresource_range(vk::ImageSubresourceRange::builder()
                 .base_mip_level(0)
-                .level_count(info.mip_levels)
+                .level_count(info.mip_level_count())
                 .base_array_layer(0)
                 .layer_count(info.array_layers)
                 .aspect_mask(info.aspect)
                 .build()
             );
 
-        let image_view = unsafe {
+        Ok(unsafe {
             self.logical_device.create_image_view(&create_info, None)
-                .expect("Device should create an image view.")
+                .context("Device should create an image view.")?
+        })
+    }
+
+    /// Index of a lazily allocated memory type allowed by `memory_type_bits`, if the device has one.
+    fn lazily_allocated_memory_type(&self, memory_type_bits: u32) -> Option<u32> {
+        let memory_properties = unsafe { self.instance.get_physical_device_memory_properties(self.physical_device) };
+        memory_properties.memory_types[..memory_properties.memory_type_count as usize]
+            .iter()
+            .enumerate()
+            .find(|(index, memory_type)| {
+                memory_type_bits & (1 << index) != 0
+                    && memory_type.property_flags.contains(vk::MemoryPropertyFlags::DEVICE_LOCAL | vk::MemoryPropertyFlags::LAZILY_ALLOCATED)
+            })
+            .map(|(index, _)| index as u32)
+    }
+
+    /// Create a single sample image to resolve the multisampled `image` into.
+    pub fn create_resolve_image(
+        &self,
+        name: &str,
+        image: &Image
+    ) -> Result<Image> {
+        if image.info.samples == SampleCountFlags::TYPE_1 {
+            bail!("Image \"{}\" is not multisampled and cannot be resolved", name);
+        }
+
+        let info = ImageInfo {
+            image_type: image.info.image_type,
+            image_format: image.info.image_format,
+            image_extent: image.info.image_extent,
+            mip_levels: MipLevels::Count(1),
+            array_layers: image.info.array_layers,
+            samples: SampleCountFlags::TYPE_1,
+            tiling: ImageTiling::OPTIMAL,
+            usage: image.info.usage | ImageUsageFlags::SAMPLED | ImageUsageFlags::TRANSFER_SRC,
+            aspect: image.info.aspect,
+            memory_location: MemoryLocation::GpuOnly,
+            transient: false,
+            view_formats: vec![],
+            sharing: Sharing::Exclusive
         };
 
-        Image {
-            device: self.clone(),
-            cleanup: true,
-            info,
+        self.create_image(name, info, None)
+    }
+
+    /// Sharing mode and queue family indices for `sharing`.
+    /// 
+    /// Concurrent sharing needs at least two distinct families, so families aliasing a single family are exclusive.
+    fn sharing_mode(&self, sharing: &Sharing) -> (vk::SharingMode, Vec<u32>) {
+        let mut queue_family_indices: Vec<u32> = match sharing {
+            Sharing::Exclusive => vec![],
+            Sharing::Concurrent(families) => families
+                .iter()
+                .map(|&family| self.queue_family_index(family))
+                .collect()
+        };
+        queue_family_indices.sort_unstable();
+        queue_family_indices.dedup();
+
+        match queue_family_indices.len() > 1 {
+            true => (vk::SharingMode::CONCURRENT, queue_family_indices),
+            false => (vk::SharingMode::EXCLUSIVE, vec![])
+        }
+    }
+
+    /// Record the release half of an ownership transfer of an exclusive image from `source` to `destination`,
+    /// on a command buffer submitted to the `source` queue family. Transitions the layout to `new_layout`.
+    /// 
+    /// Does nothing if the image is shared concurrently or both families are the same.
+    pub fn release_image_ownership(
+        &self,
+        command_buffer: vk::CommandBuffer,
+        image: &Image,
+        source: QueueFamily,
+        destination: QueueFamily,
+        old_layout: ImageLayout,
+        new_layout: ImageLayout
+    ) {
+        self.record_ownership_transfer(
+            command_buffer,
             image,
-            image_view,
-            allocation: Some(allocation)
+            (source, destination),
+            (old_layout, new_layout),
+            (vk::AccessFlags::MEMORY_WRITE, vk::AccessFlags::empty()),
+            (vk::PipelineStageFlags::ALL_COMMANDS, vk::PipelineStageFlags::BOTTOM_OF_PIPE)
+        );
+    }
+
+    /// Record the acquire half of an ownership transfer of an exclusive image from `source` to `destination`,
+    /// on a command buffer submitted to the `destination` queue family after the release, e.g. waiting on a semaphore.
+    /// 
+    /// The layouts must match those of the release. Does nothing if the image is shared concurrently or both families are the same.
+    pub fn acquire_image_ownership(
+        &self,
+        command_buffer: vk::CommandBuffer,
+        image: &Image,
+        source: QueueFamily,
+        destination: QueueFamily,
+        old_layout: ImageLayout,
+        new_layout: ImageLayout
+    ) {
+        self.record_ownership_transfer(
+            command_buffer,
+            image,
+            (source, destination),
+            (old_layout, new_layout),
+            (vk::AccessFlags::empty(), vk::AccessFlags::MEMORY_READ | vk::AccessFlags::MEMORY_WRITE),
+            (vk::PipelineStageFlags::TOP_OF_PIPE, vk::PipelineStageFlags::ALL_COMMANDS)
+        );
+    }
+
+    fn record_ownership_transfer(
+        &self,
+        command_buffer: vk::CommandBuffer,
+        image: &Image,
+        (source, destination): (QueueFamily, QueueFamily),
+        (old_layout, new_layout): (ImageLayout, ImageLayout),
+        (src_access_mask, dst_access_mask): (vk::AccessFlags, vk::AccessFlags),
+        (src_stage_mask, dst_stage_mask): (vk::PipelineStageFlags, vk::PipelineStageFlags)
+    ) {
+        let source = self.queue_family_index(source);
+        let destination = self.queue_family_index(destination);
+        if source == destination || self.sharing_mode(&image.info.sharing).0 == vk::SharingMode::CONCURRENT {
+            return;
+        }
+
+        let image_barrier = vk::ImageMemoryBarrier::builder()
+            .old_layout(old_layout)
+            .new_layout(new_layout)
+            .src_queue_family_index(source)
+            .dst_queue_family_index(destination)
+            .image(image.image)
+            .subresource_range(ImageSubresourceRange::builder()
+                .aspect_mask(image.info.aspect)
+                .base_mip_level(0)
+                .level_count(image.info.mip_level_count())
+                .base_array_layer(0)
+                .layer_count(image.info.array_layers)
+                .build()
+            )
+            .src_access_mask(src_access_mask)
+            .dst_access_mask(dst_access_mask);
+        unsafe {
+            self.cmd_pipeline_barrier(
+                command_buffer,
+                src_stage_mask,
+                dst_stage_mask,
+                vk::DependencyFlags::empty(),
+                &[],
+                &[],
+                slice::from_ref(&image_barrier)
+            );
         }
     }
 
@@ -217,46 +818,26 @@ impl Device {
         old_layout: ImageLayout,
         new_layout: ImageLayout
     ) {
-        let (
+        self.transition_image_aspect_layout(command_buffer, image, image.info.aspect, old_layout, new_layout)
+    }
+
+    /// Transition the layout of only the `aspect` of an image, e.g. the depth of a depth stencil image for sampling
+    /// while its stencil stays attached. Uses the device's separate depth stencil layouts.
+    pub fn transition_image_aspect_layout(
+        &self,
+        command_buffer: vk::CommandBuffer,
+        image: &Image,
+        aspect: ImageAspectFlags,
+        old_layout: ImageLayout,
+        new_layout: ImageLayout
+    ) {
+        let Some((
             src_access_mask,
             dst_access_mask,
             src_stage_mask,
             dst_stage_mask,
-        ) = match (old_layout, new_layout) {
-            // Color attachment transitions
-            (vk::ImageLayout::UNDEFINED, vk::ImageLayout::COLOR_ATTACHMENT_OPTIMAL) => (
-                vk::AccessFlags::empty(),
-                vk::AccessFlags::COLOR_ATTACHMENT_WRITE,
-                vk::PipelineStageFlags::TOP_OF_PIPE,
-                vk::PipelineStageFlags::COLOR_ATTACHMENT_OUTPUT,
-            ),
-            (vk::ImageLayout::COLOR_ATTACHMENT_OPTIMAL, vk::ImageLayout::PRESENT_SRC_KHR) => (
-                vk::AccessFlags::COLOR_ATTACHMENT_WRITE,
-                vk::AccessFlags::empty(),
-                vk::PipelineStageFlags::COLOR_ATTACHMENT_OUTPUT,
-                vk::PipelineStageFlags::BOTTOM_OF_PIPE,
-            ),
-            // Depth attachment transitions
-            (vk::ImageLayout::UNDEFINED, vk::ImageLayout::DEPTH_STENCIL_ATTACHMENT_OPTIMAL) => (
-                vk::AccessFlags::empty(),
-                vk::AccessFlags::DEPTH_STENCIL_ATTACHMENT_READ | vk::AccessFlags::DEPTH_STENCIL_ATTACHMENT_WRITE,
-                vk::PipelineStageFlags::TOP_OF_PIPE,
-                vk::PipelineStageFlags::EARLY_FRAGMENT_TESTS,
-            ),
-            // Data transfer transitions
-            (vk::ImageLayout::UNDEFINED, vk::ImageLayout::TRANSFER_DST_OPTIMAL) => (
-                vk::AccessFlags::empty(),
-                vk::AccessFlags::TRANSFER_WRITE,
-                vk::PipelineStageFlags::TOP_OF_PIPE,
-                vk::PipelineStageFlags::TRANSFER,
-            ),
-            (vk::ImageLayout::TRANSFER_DST_OPTIMAL, vk::ImageLayout::SHADER_READ_ONLY_OPTIMAL) => (
-                vk::AccessFlags::TRANSFER_WRITE,
-                vk::AccessFlags::SHADER_READ,
-                vk::PipelineStageFlags::TRANSFER,
-                vk::PipelineStageFlags::FRAGMENT_SHADER,
-            ),
-            _ => panic!("Unsupported image layout transition!"),
+        )) = transition_masks(old_layout, new_layout) else {
+            panic!("Unsupported image layout transition from {:?} to {:?}", old_layout, new_layout);
         };
 
         let image_barrier = vk::ImageMemoryBarrier::builder()
@@ -266,9 +847,9 @@ impl Device {
             .dst_queue_family_index(vk::QUEUE_FAMILY_IGNORED)
             .image(image.image)
             .subresource_range(ImageSubresourceRange::builder()
-                .aspect_mask(image.info.aspect)
+                .aspect_mask(aspect)
                 .base_mip_level(0)
-                .level_count(image.info.mip_levels)
+                .level_count(image.info.mip_level_count())
                 .base_array_layer(0)
                 .layer_count(image.info.array_layers)
                 .build()
@@ -316,42 +897,736 @@ impl Device {
         buffer: &Buffer,
         image: &Image,
     ) {
-        let command_buffer = self.begin_transfer_commands()
-            .expect("Transfer command buffer should begin recording.");
+        self.copy_buffer_to_image_mips(buffer, image, &[0])
+            .expect("Transfer command buffer should copy the buffer to the image.");
+    }
 
-        self.transition_image_layout(
-            command_buffer,
-            &image,
-            ImageLayout::UNDEFINED,
-            ImageLayout::TRANSFER_DST_OPTIMAL
-        );
+    /// Copy the first `mip_offsets.len()` mip levels of `image` from `buffer`, each tightly packed at its offset,
+    /// e.g. the levels of a block compressed texture.
+    pub fn copy_buffer_to_image_mips(
+        &self,
+        buffer: &Buffer,
+        image: &Image,
+        mip_offsets: &[u64]
+    ) -> Result<()> {
+        if mip_offsets.len() as u32 > image.info.mip_level_count() {
+            bail!("Can't copy {} mip levels to an image with {}", mip_offsets.len(), image.info.mip_level_count());
+        }
 
-        unsafe {
-            let regions = vk::BufferImageCopy::builder()
-                .buffer_offset(0)
+        let regions: Vec<_> = mip_offsets
+            .iter()
+            .enumerate()
+            .map(|(mip_level, &offset)| vk::BufferImageCopy::builder()
+                .buffer_offset(offset)
                 .buffer_row_length(0)
                 .buffer_image_height(0)
                 .image_subresource(
                     vk::ImageSubresourceLayers::builder()
                         .aspect_mask(vk::ImageAspectFlags::COLOR)
-                        .mip_level(0)
+                        .mip_level(mip_level as u32)
                         .base_array_layer(0)
                         .layer_count(1)
                         .build()
                 )
                 .image_offset(vk::Offset3D { x: 0, y: 0, z: 0 })
-                .image_extent(image.extent());
+                .image_extent(image.info.mip_extent(mip_level as u32))
+                .build()
+            )
+            .collect();
+
+        let command_buffer = self.begin_transfer_commands()?;
+
+        self.transition_image_layout(
+            command_buffer,
+            image,
+            ImageLayout::UNDEFINED,
+            ImageLayout::TRANSFER_DST_OPTIMAL
+        );
 
+        unsafe {
             self.cmd_copy_buffer_to_image(
                 command_buffer,
                 buffer.buffer,
                 image.image,
                 vk::ImageLayout::TRANSFER_DST_OPTIMAL,
-                slice::from_ref(&regions),
+                &regions,
             );
         }
 
         self.end_transfer_commands(command_buffer)
-            .expect("Transfer command buffer should end recording and submit to device.");
+    }
+
+    /// Generate mip levels after the first of `image` by blitting each level from the last, on the graphics queue.
+    /// 
+    /// Level 0 must be written, with every level in `TRANSFER_DST_OPTIMAL`, as left by [`Device::copy_buffer_to_image_mips`].
+    /// Every level is left in `SHADER_READ_ONLY_OPTIMAL`. Images uploaded on a separate transfer family must first be
+    /// acquired by the graphics family, see [`Device::acquire_image_ownership`].
+    /// Formats without linear filter support fall back to nearest filtering, see [`mip_generation_filter`].
+    pub fn generate_mips(&self, image: &Image) -> Result<()> {
+        let mip_level_count = image.info.mip_level_count();
+        let filter = mip_generation_filter(self.format_features(image.info.image_format, image.info.tiling))
+            .context(format!("Image format {:?} does not support blits for generating mips", image.info.image_format))?;
+        if !image.info.usage.contains(ImageUsageFlags::TRANSFER_SRC | ImageUsageFlags::TRANSFER_DST) {
+            bail!("Generating mips requires TRANSFER_SRC and TRANSFER_DST image usage, got {:?}", image.info.usage);
+        }
+
+        let offsets = |extent: Extent3D| [
+            vk::Offset3D::default(),
+            vk::Offset3D { x: extent.width as i32, y: extent.height as i32, z: extent.depth as i32 }
+        ];
+        let subresource = |mip_level| vk::ImageSubresourceLayers::builder()
+            .aspect_mask(image.info.aspect)
+            .mip_level(mip_level)
+            .base_array_layer(0)
+            .layer_count(image.info.array_layers)
+            .build();
+
+        self.run_once(QueueFamily::GRAPHICS, |command_buffer| {
+            for mip_level in 1..mip_level_count {
+                // Read the last level once its blit, or copy, has completed
+                self.transition_mip_levels(command_buffer, image, mip_level - 1..mip_level, ImageLayout::TRANSFER_DST_OPTIMAL, ImageLayout::TRANSFER_SRC_OPTIMAL);
+
+                let region = vk::ImageBlit::builder()
+                    .src_subresource(subresource(mip_level - 1))
+                    .src_offsets(offsets(image.info.mip_extent(mip_level - 1)))
+                    .dst_subresource(subresource(mip_level))
+                    .dst_offsets(offsets(image.info.mip_extent(mip_level)));
+                unsafe {
+                    self.cmd_blit_image(
+                        command_buffer,
+                        image.image,
+                        ImageLayout::TRANSFER_SRC_OPTIMAL,
+                        image.image,
+                        ImageLayout::TRANSFER_DST_OPTIMAL,
+                        slice::from_ref(&region),
+                        filter
+                    );
+                }
+            }
+
+            // The last level was only written, every other level was read
+            self.transition_mip_levels(command_buffer, image, mip_level_count - 1..mip_level_count, ImageLayout::TRANSFER_DST_OPTIMAL, ImageLayout::SHADER_READ_ONLY_OPTIMAL);
+            if mip_level_count > 1 {
+                self.transition_mip_levels(command_buffer, image, 0..mip_level_count - 1, ImageLayout::TRANSFER_SRC_OPTIMAL, ImageLayout::SHADER_READ_ONLY_OPTIMAL);
+            }
+
+            Ok(())
+        })
+    }
+
+    /// Transition the layout of `mip_levels` of every layer of `image`
+    fn transition_mip_levels(
+        &self,
+        command_buffer: vk::CommandBuffer,
+        image: &Image,
+        mip_levels: Range<u32>,
+        old_layout: ImageLayout,
+        new_layout: ImageLayout
+    ) {
+        let (src_access_mask, dst_access_mask, src_stage_mask, dst_stage_mask) = transition_masks(old_layout, new_layout)
+            .unwrap_or_else(|| panic!("Unsupported image layout transition from {:?} to {:?}", old_layout, new_layout));
+
+        let image_barrier = vk::ImageMemoryBarrier::builder()
+            .old_layout(old_layout)
+            .new_layout(new_layout)
+            .src_queue_family_index(vk::QUEUE_FAMILY_IGNORED)
+            .dst_queue_family_index(vk::QUEUE_FAMILY_IGNORED)
+            .image(image.image)
+            .subresource_range(ImageSubresourceRange::builder()
+                .aspect_mask(image.info.aspect)
+                .base_mip_level(mip_levels.start)
+                .level_count(mip_levels.len() as u32)
+                .base_array_layer(0)
+                .layer_count(image.info.array_layers)
+                .build()
+            )
+            .src_access_mask(src_access_mask)
+            .dst_access_mask(dst_access_mask);
+        unsafe {
+            self.cmd_pipeline_barrier(
+                command_buffer,
+                src_stage_mask,
+                dst_stage_mask,
+                vk::DependencyFlags::empty(),
+                &[],
+                &[],
+                slice::from_ref(&image_barrier)
+            );
+        }
+    }
+
+    /// Format features of images of `format` with `tiling`
+    fn format_features(&self, format: Format, tiling: ImageTiling) -> vk::FormatFeatureFlags {
+        let properties = unsafe { self.instance.get_physical_device_format_properties(self.physical_device, format) };
+        match tiling {
+            ImageTiling::LINEAR => properties.linear_tiling_features,
+            _ => properties.optimal_tiling_features
+        }
+    }
+
+    /// Whether optimal tiling images of `format` can be sampled and copied to, e.g. to upload textures.
+    pub fn supports_sampled_format(&self, format: Format) -> bool {
+        let properties = unsafe { self.instance.get_physical_device_format_properties(self.physical_device, format) };
+        properties.optimal_tiling_features.contains(vk::FormatFeatureFlags::SAMPLED_IMAGE | vk::FormatFeatureFlags::TRANSFER_DST)
+    }
+
+    /// The preferred block compressed format this device samples, to transcode textures to, see [`select_compressed_format`].
+    pub fn preferred_compressed_format(&self, srgb: bool) -> Option<Format> {
+        select_compressed_format(srgb, |format| self.supports_sampled_format(format))
+    }
+
+    /// Record copies of `regions` of `image` to `buffer`, each tightly packed in row-major order at its buffer offset.
+    /// 
+    /// `image` must be in `TRANSFER_SRC_OPTIMAL` layout. Depth and stencil aspects are copied by separate regions.
+    pub fn record_copy_image_to_buffer(
+        &self,
+        command_buffer: vk::CommandBuffer,
+        image: &Image,
+        buffer: &Buffer,
+        regions: &[ImageCopyRegion]
+    ) -> Result<()> {
+        if regions.is_empty() {
+            bail!("Copying an image to a buffer needs at least one region");
+        }
+        for region in regions {
+            region.validate(&image.info, buffer.info.size as u64)?;
+        }
+
+        let regions: Vec<vk::BufferImageCopy2> = regions
+            .iter()
+            .map(|region| vk::BufferImageCopy2::builder()
+                .buffer_offset(region.buffer_offset)
+                .buffer_row_length(0)
+                .buffer_image_height(0)
+                .image_subresource(
+                    vk::ImageSubresourceLayers::builder()
+                        .aspect_mask(region.aspect)
+                        .mip_level(region.mip_level)
+                        .base_array_layer(region.base_array_layer)
+                        .layer_count(region.layer_count)
+                        .build()
+                )
+                .image_offset(region.offset)
+                .image_extent(region.extent)
+                .build()
+            )
+            .collect();
+        let copy_info = vk::CopyImageToBufferInfo2::builder()
+            .src_image(image.image)
+            .src_image_layout(vk::ImageLayout::TRANSFER_SRC_OPTIMAL)
+            .dst_buffer(buffer.buffer)
+            .regions(&regions);
+
+        unsafe { self.cmd_copy_image_to_buffer2(command_buffer, &copy_info) };
+
+        Ok(())
+    }
+
+    /// Copy `regions` of `image` to `buffer` on the graphics queue and wait for completion, e.g. for screenshots or readback.
+    /// Use a buffer from [`Device::create_readback_buffer`] and [`Buffer::read_buffer`] to read the result.
+    /// 
+    /// `image` is transitioned from `layout` for the copy and back afterwards, unless `layout` is `UNDEFINED`.
+    pub fn copy_image_to_buffer(
+        &self,
+        image: &Image,
+        layout: ImageLayout,
+        buffer: &Buffer,
+        regions: &[ImageCopyRegion]
+    ) -> Result<()> {
+        if !image.info.usage.contains(ImageUsageFlags::TRANSFER_SRC) {
+            bail!("Image must have TRANSFER_SRC usage to be copied to a buffer");
+        }
+        let transition = layout != ImageLayout::TRANSFER_SRC_OPTIMAL;
+        let transition_back = transition && layout != ImageLayout::UNDEFINED;
+        if (transition && transition_masks(layout, ImageLayout::TRANSFER_SRC_OPTIMAL).is_none())
+            || (transition_back && transition_masks(ImageLayout::TRANSFER_SRC_OPTIMAL, layout).is_none())
+        {
+            bail!("Copying an image in layout {:?} to a buffer is unsupported", layout);
+        }
+
+        self.run_once(QueueFamily::GRAPHICS, |command_buffer| {
+            if transition {
+                self.transition_image_layout(command_buffer, image, layout, ImageLayout::TRANSFER_SRC_OPTIMAL);
+            }
+            
+            self.record_copy_image_to_buffer(command_buffer, image, buffer, regions)?;
+
+            if transition_back {
+                self.transition_image_layout(command_buffer, image, ImageLayout::TRANSFER_SRC_OPTIMAL, layout);
+            }
+
+            Ok(())
+        })
+    }
+}
+
+/// Filter for generating mips by blitting each level from the last, given the `format_features` of the image,
+/// or `None` if the format can't be blitted. Formats without linear filter support fall back to nearest filtering.
+pub fn mip_generation_filter(format_features: vk::FormatFeatureFlags) -> Option<vk::Filter> {
+    if !format_features.contains(vk::FormatFeatureFlags::BLIT_SRC | vk::FormatFeatureFlags::BLIT_DST) {
+        return None;
+    }
+
+    match format_features.contains(vk::FormatFeatureFlags::SAMPLED_IMAGE_FILTER_LINEAR) {
+        true => Some(vk::Filter::LINEAR),
+        false => Some(vk::Filter::NEAREST)
+    }
+}
+
+/// Access and stage masks of a layout transition from `old_layout` to `new_layout`, if supported.
+/// 
+/// Returns `(src_access_mask, dst_access_mask, src_stage_mask, dst_stage_mask)`.
+fn transition_masks(
+    old_layout: ImageLayout,
+    new_layout: ImageLayout
+) -> Option<(vk::AccessFlags, vk::AccessFlags, vk::PipelineStageFlags, vk::PipelineStageFlags)> {
+    match (old_layout, new_layout) {
+        // Color attachment transitions
+        (vk::ImageLayout::UNDEFINED, vk::ImageLayout::COLOR_ATTACHMENT_OPTIMAL) => Some((
+            vk::AccessFlags::empty(),
+            vk::AccessFlags::COLOR_ATTACHMENT_WRITE,
+            vk::PipelineStageFlags::TOP_OF_PIPE,
+            vk::PipelineStageFlags::COLOR_ATTACHMENT_OUTPUT,
+        )),
+        (vk::ImageLayout::COLOR_ATTACHMENT_OPTIMAL, vk::ImageLayout::PRESENT_SRC_KHR) => Some((
+            vk::AccessFlags::COLOR_ATTACHMENT_WRITE,
+            vk::AccessFlags::empty(),
+            vk::PipelineStageFlags::COLOR_ATTACHMENT_OUTPUT,
+            vk::PipelineStageFlags::BOTTOM_OF_PIPE,
+        )),
+        (vk::ImageLayout::COLOR_ATTACHMENT_OPTIMAL, vk::ImageLayout::TRANSFER_SRC_OPTIMAL) => Some((
+            vk::AccessFlags::COLOR_ATTACHMENT_WRITE,
+            vk::AccessFlags::TRANSFER_READ,
+            vk::PipelineStageFlags::COLOR_ATTACHMENT_OUTPUT,
+            vk::PipelineStageFlags::TRANSFER,
+        )),
+        (vk::ImageLayout::COLOR_ATTACHMENT_OPTIMAL, vk::ImageLayout::SHADER_READ_ONLY_OPTIMAL) => Some((
+            vk::AccessFlags::COLOR_ATTACHMENT_WRITE,
+            vk::AccessFlags::SHADER_READ,
+            vk::PipelineStageFlags::COLOR_ATTACHMENT_OUTPUT,
+            vk::PipelineStageFlags::FRAGMENT_SHADER,
+        )),
+        (vk::ImageLayout::SHADER_READ_ONLY_OPTIMAL, vk::ImageLayout::COLOR_ATTACHMENT_OPTIMAL) => Some((
+            vk::AccessFlags::SHADER_READ,
+            vk::AccessFlags::COLOR_ATTACHMENT_WRITE,
+            vk::PipelineStageFlags::FRAGMENT_SHADER,
+            vk::PipelineStageFlags::COLOR_ATTACHMENT_OUTPUT,
+        )),
+        (vk::ImageLayout::SHADER_READ_ONLY_OPTIMAL, vk::ImageLayout::TRANSFER_SRC_OPTIMAL) => Some((
+            vk::AccessFlags::SHADER_READ,
+            vk::AccessFlags::TRANSFER_READ,
+            vk::PipelineStageFlags::FRAGMENT_SHADER,
+            vk::PipelineStageFlags::TRANSFER,
+        )),
+        (vk::ImageLayout::TRANSFER_SRC_OPTIMAL, vk::ImageLayout::SHADER_READ_ONLY_OPTIMAL) => Some((
+            vk::AccessFlags::TRANSFER_READ,
+            vk::AccessFlags::SHADER_READ,
+            vk::PipelineStageFlags::TRANSFER,
+            vk::PipelineStageFlags::FRAGMENT_SHADER,
+        )),
+        (vk::ImageLayout::TRANSFER_SRC_OPTIMAL, vk::ImageLayout::COLOR_ATTACHMENT_OPTIMAL) => Some((
+            vk::AccessFlags::TRANSFER_READ,
+            vk::AccessFlags::COLOR_ATTACHMENT_READ | vk::AccessFlags::COLOR_ATTACHMENT_WRITE,
+            vk::PipelineStageFlags::TRANSFER,
+            vk::PipelineStageFlags::COLOR_ATTACHMENT_OUTPUT,
+        )),
+        (vk::ImageLayout::TRANSFER_DST_OPTIMAL, vk::ImageLayout::COLOR_ATTACHMENT_OPTIMAL) => Some((
+            vk::AccessFlags::TRANSFER_WRITE,
+            vk::AccessFlags::COLOR_ATTACHMENT_READ | vk::AccessFlags::COLOR_ATTACHMENT_WRITE,
+            vk::PipelineStageFlags::TRANSFER,
+            vk::PipelineStageFlags::COLOR_ATTACHMENT_OUTPUT,
+        )),
+        // Depth attachment transitions
+        (vk::ImageLayout::UNDEFINED, vk::ImageLayout::DEPTH_STENCIL_ATTACHMENT_OPTIMAL) => Some((
+            vk::AccessFlags::empty(),
+            vk::AccessFlags::DEPTH_STENCIL_ATTACHMENT_READ | vk::AccessFlags::DEPTH_STENCIL_ATTACHMENT_WRITE,
+            vk::PipelineStageFlags::TOP_OF_PIPE,
+            vk::PipelineStageFlags::EARLY_FRAGMENT_TESTS,
+        )),
+        (vk::ImageLayout::UNDEFINED, vk::ImageLayout::DEPTH_ATTACHMENT_OPTIMAL)
+        | (vk::ImageLayout::UNDEFINED, vk::ImageLayout::STENCIL_ATTACHMENT_OPTIMAL) => Some((
+            vk::AccessFlags::empty(),
+            vk::AccessFlags::DEPTH_STENCIL_ATTACHMENT_READ | vk::AccessFlags::DEPTH_STENCIL_ATTACHMENT_WRITE,
+            vk::PipelineStageFlags::TOP_OF_PIPE,
+            vk::PipelineStageFlags::EARLY_FRAGMENT_TESTS,
+        )),
+        (vk::ImageLayout::DEPTH_ATTACHMENT_OPTIMAL, vk::ImageLayout::DEPTH_READ_ONLY_OPTIMAL) => Some((
+            vk::AccessFlags::DEPTH_STENCIL_ATTACHMENT_WRITE,
+            vk::AccessFlags::SHADER_READ,
+            vk::PipelineStageFlags::LATE_FRAGMENT_TESTS,
+            vk::PipelineStageFlags::FRAGMENT_SHADER,
+        )),
+        (vk::ImageLayout::DEPTH_READ_ONLY_OPTIMAL, vk::ImageLayout::DEPTH_ATTACHMENT_OPTIMAL) => Some((
+            vk::AccessFlags::SHADER_READ,
+            vk::AccessFlags::DEPTH_STENCIL_ATTACHMENT_READ | vk::AccessFlags::DEPTH_STENCIL_ATTACHMENT_WRITE,
+            vk::PipelineStageFlags::FRAGMENT_SHADER,
+            vk::PipelineStageFlags::EARLY_FRAGMENT_TESTS,
+        )),
+        // Data transfer transitions
+        (vk::ImageLayout::UNDEFINED, vk::ImageLayout::TRANSFER_DST_OPTIMAL) => Some((
+            vk::AccessFlags::empty(),
+            vk::AccessFlags::TRANSFER_WRITE,
+            vk::PipelineStageFlags::TOP_OF_PIPE,
+            vk::PipelineStageFlags::TRANSFER,
+        )),
+        (vk::ImageLayout::TRANSFER_DST_OPTIMAL, vk::ImageLayout::SHADER_READ_ONLY_OPTIMAL) => Some((
+            vk::AccessFlags::TRANSFER_WRITE,
+            vk::AccessFlags::SHADER_READ,
+            vk::PipelineStageFlags::TRANSFER,
+            vk::PipelineStageFlags::FRAGMENT_SHADER,
+        )),
+        (vk::ImageLayout::TRANSFER_DST_OPTIMAL, vk::ImageLayout::TRANSFER_SRC_OPTIMAL) => Some((
+            vk::AccessFlags::TRANSFER_WRITE,
+            vk::AccessFlags::TRANSFER_READ,
+            vk::PipelineStageFlags::TRANSFER,
+            vk::PipelineStageFlags::TRANSFER,
+        )),
+        // Readback transitions
+        (vk::ImageLayout::UNDEFINED, vk::ImageLayout::TRANSFER_SRC_OPTIMAL) => Some((
+            vk::AccessFlags::empty(),
+            vk::AccessFlags::TRANSFER_READ,
+            vk::PipelineStageFlags::TOP_OF_PIPE,
+            vk::PipelineStageFlags::TRANSFER,
+        )),
+        (vk::ImageLayout::GENERAL, vk::ImageLayout::TRANSFER_SRC_OPTIMAL) => Some((
+            vk::AccessFlags::MEMORY_WRITE,
+            vk::AccessFlags::TRANSFER_READ,
+            vk::PipelineStageFlags::ALL_COMMANDS,
+            vk::PipelineStageFlags::TRANSFER,
+        )),
+        (vk::ImageLayout::TRANSFER_SRC_OPTIMAL, vk::ImageLayout::GENERAL) => Some((
+            vk::AccessFlags::TRANSFER_READ,
+            vk::AccessFlags::MEMORY_READ | vk::AccessFlags::MEMORY_WRITE,
+            vk::PipelineStageFlags::TRANSFER,
+            vk::PipelineStageFlags::ALL_COMMANDS,
+        )),
+        (vk::ImageLayout::PRESENT_SRC_KHR, vk::ImageLayout::TRANSFER_SRC_OPTIMAL) => Some((
+            vk::AccessFlags::empty(),
+            vk::AccessFlags::TRANSFER_READ,
+            vk::PipelineStageFlags::TOP_OF_PIPE,
+            vk::PipelineStageFlags::TRANSFER,
+        )),
+        (vk::ImageLayout::TRANSFER_SRC_OPTIMAL, vk::ImageLayout::PRESENT_SRC_KHR) => Some((
+            vk::AccessFlags::TRANSFER_READ,
+            vk::AccessFlags::empty(),
+            vk::PipelineStageFlags::TRANSFER,
+            vk::PipelineStageFlags::BOTTOM_OF_PIPE,
+        )),
+        (vk::ImageLayout::DEPTH_STENCIL_ATTACHMENT_OPTIMAL, vk::ImageLayout::TRANSFER_SRC_OPTIMAL)
+        | (vk::ImageLayout::DEPTH_ATTACHMENT_OPTIMAL, vk::ImageLayout::TRANSFER_SRC_OPTIMAL)
+        | (vk::ImageLayout::STENCIL_ATTACHMENT_OPTIMAL, vk::ImageLayout::TRANSFER_SRC_OPTIMAL) => Some((
+            vk::AccessFlags::DEPTH_STENCIL_ATTACHMENT_WRITE,
+            vk::AccessFlags::TRANSFER_READ,
+            vk::PipelineStageFlags::LATE_FRAGMENT_TESTS,
+            vk::PipelineStageFlags::TRANSFER,
+        )),
+        (vk::ImageLayout::TRANSFER_SRC_OPTIMAL, vk::ImageLayout::DEPTH_STENCIL_ATTACHMENT_OPTIMAL)
+        | (vk::ImageLayout::TRANSFER_SRC_OPTIMAL, vk::ImageLayout::DEPTH_ATTACHMENT_OPTIMAL)
+        | (vk::ImageLayout::TRANSFER_SRC_OPTIMAL, vk::ImageLayout::STENCIL_ATTACHMENT_OPTIMAL) => Some((
+            vk::AccessFlags::TRANSFER_READ,
+            vk::AccessFlags::DEPTH_STENCIL_ATTACHMENT_READ | vk::AccessFlags::DEPTH_STENCIL_ATTACHMENT_WRITE,
+            vk::PipelineStageFlags::TRANSFER,
+            vk::PipelineStageFlags::EARLY_FRAGMENT_TESTS,
+        )),
+        _ => None
+    }
+}
+
+/// Validate the extent, mip level count and array layers of `info` against `limits` for its image type,
+/// returning the type of image its views are created from.
+/// 
+/// 1D images must be one texel high and deep, 2D and cube images one texel deep, and 3D images one layer.
+fn validate_image_extent(info: &ImageInfo, limits: &vk::PhysicalDeviceLimits) -> Result<vk::ImageType> {
+    let extent = info.image_extent;
+    let (image_type, max_dimension, limit_name) = match info.image_type {
+        ImageType::TYPE_1D | ImageType::TYPE_1D_ARRAY => (vk::ImageType::TYPE_1D, limits.max_image_dimension1_d, "maxImageDimension1D"),
+        ImageType::TYPE_2D | ImageType::TYPE_2D_ARRAY => (vk::ImageType::TYPE_2D, limits.max_image_dimension2_d, "maxImageDimension2D"),
+        ImageType::CUBE | ImageType::CUBE_ARRAY => (vk::ImageType::TYPE_2D, limits.max_image_dimension_cube, "maxImageDimensionCube"),
+        ImageType::TYPE_3D => (vk::ImageType::TYPE_3D, limits.max_image_dimension3_d, "maxImageDimension3D"),
+        image_type => bail!("Unsupported image type {:?}", image_type)
+    };
+
+    let extent_valid = match image_type {
+        vk::ImageType::TYPE_1D => extent.width > 0 && extent.height == 1 && extent.depth == 1,
+        vk::ImageType::TYPE_2D => extent.width > 0 && extent.height > 0 && extent.depth == 1,
+        _ => extent.width > 0 && extent.height > 0 && extent.depth > 0
+    };
+    if !extent_valid {
+        bail!("Extent {}x{}x{} is invalid for a {:?} image", extent.width, extent.height, extent.depth, info.image_type);
+    }
+    if extent.width > max_dimension || extent.height > max_dimension || extent.depth > max_dimension {
+        bail!("Extent {}x{}x{} exceeds device limit {} = {}", extent.width, extent.height, extent.depth, limit_name, max_dimension);
+    }
+
+    let max_mip_levels = MipLevels::Full.count(extent);
+    if info.mip_level_count() > max_mip_levels {
+        bail!(
+            "{} mip levels requested, but a {}x{}x{} extent allows at most {}",
+            info.mip_level_count(), extent.width, extent.height, extent.depth, max_mip_levels
+        );
+    }
+    if image_type == vk::ImageType::TYPE_3D && info.array_layers != 1 {
+        bail!("3D images must have exactly one array layer, got {}", info.array_layers);
+    }
+    if info.array_layers > limits.max_image_array_layers {
+        bail!("{} array layers exceed device limit maxImageArrayLayers = {}", info.array_layers, limits.max_image_array_layers);
+    }
+
+    Ok(image_type)
+}
+
+/// Size in bytes of a single texel of `format`, for uncompressed color formats.
+pub fn format_texel_size(format: Format) -> Option<usize> {
+    match format {
+        Format::R8_UNORM | Format::R8_SRGB | Format::R8_UINT => Some(1),
+        Format::R8G8_UNORM | Format::R8G8_SRGB | Format::R16_SFLOAT | Format::R16_UINT => Some(2),
+        Format::R8G8B8A8_UNORM
+        | Format::R8G8B8A8_SRGB
+        | Format::B8G8R8A8_UNORM
+        | Format::B8G8R8A8_SRGB
+        | Format::A2B10G10R10_UNORM_PACK32
+        | Format::R16G16_SFLOAT
+        | Format::R32_SFLOAT
+        | Format::R32_UINT => Some(4),
+        Format::R16G16B16A16_SFLOAT | Format::R32G32_SFLOAT => Some(8),
+        Format::R32G32B32A32_SFLOAT => Some(16),
+        _ => None
+    }
+}
+
+/// Size in bytes of a single texel of one `aspect` of `format` when copied to a buffer.
+/// 
+/// Depth aspects of 24 bit formats are copied as 32 bit texels, and stencil aspects as 8 bit texels.
+pub fn aspect_texel_size(format: Format, aspect: ImageAspectFlags) -> Option<usize> {
+    match aspect {
+        ImageAspectFlags::COLOR => format_texel_size(format),
+        ImageAspectFlags::DEPTH => match format {
+            Format::D16_UNORM | Format::D16_UNORM_S8_UINT => Some(2),
+            Format::X8_D24_UNORM_PACK32
+            | Format::D24_UNORM_S8_UINT
+            | Format::D32_SFLOAT
+            | Format::D32_SFLOAT_S8_UINT => Some(4),
+            _ => None
+        },
+        ImageAspectFlags::STENCIL => format_has_stencil(format).then_some(1),
+        _ => None
+    }
+}
+
+/// Size in bytes of a 4x4 texel block of `format`, for block compressed formats.
+pub fn compressed_block_size(format: Format) -> Option<usize> {
+    match format {
+        Format::BC1_RGB_UNORM_BLOCK
+        | Format::BC1_RGB_SRGB_BLOCK
+        | Format::BC1_RGBA_UNORM_BLOCK
+        | Format::BC1_RGBA_SRGB_BLOCK
+        | Format::ETC2_R8G8B8_UNORM_BLOCK
+        | Format::ETC2_R8G8B8_SRGB_BLOCK => Some(8),
+        Format::BC3_UNORM_BLOCK
+        | Format::BC3_SRGB_BLOCK
+        | Format::BC7_UNORM_BLOCK
+        | Format::BC7_SRGB_BLOCK
+        | Format::ETC2_R8G8B8A8_UNORM_BLOCK
+        | Format::ETC2_R8G8B8A8_SRGB_BLOCK
+        | Format::ASTC_4X4_UNORM_BLOCK
+        | Format::ASTC_4X4_SRGB_BLOCK => Some(16),
+        _ => None
+    }
+}
+
+/// Size in bytes of block compressed data of `format` covering `extent`, with partial blocks at the edges rounded up.
+pub fn compressed_data_size(format: Format, extent: Extent3D) -> Option<u64> {
+    let block_size = compressed_block_size(format)? as u64;
+    let blocks = |texels: u32| (texels as u64 + 3) / 4;
+    Some(blocks(extent.width) * blocks(extent.height) * extent.depth as u64 * block_size)
+}
+
+/// Select the format to transcode compressed textures to from those `supported`, preferring BC7, then ASTC 4x4, then ETC2,
+/// so desktop GPUs get BC7 and mobile GPUs ASTC or ETC2.
+pub fn select_compressed_format(srgb: bool, supported: impl Fn(Format) -> bool) -> Option<Format> {
+    let candidates = match srgb {
+        true => [Format::BC7_SRGB_BLOCK, Format::ASTC_4X4_SRGB_BLOCK, Format::ETC2_R8G8B8A8_SRGB_BLOCK],
+        false => [Format::BC7_UNORM_BLOCK, Format::ASTC_4X4_UNORM_BLOCK, Format::ETC2_R8G8B8A8_UNORM_BLOCK]
+    };
+
+    candidates.into_iter().find(|&format| supported(format))
+}
+
+/// Whether `format` has a stencil aspect.
+pub fn format_has_stencil(format: Format) -> bool {
+    matches!(format, Format::S8_UINT | Format::D16_UNORM_S8_UINT | Format::D24_UNORM_S8_UINT | Format::D32_SFLOAT_S8_UINT)
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn image_info(format: Format, aspect: ImageAspectFlags, extent: Extent3D, mip_levels: MipLevels, array_layers: u32) -> ImageInfo {
+        ImageInfo {
+            image_type: match array_layers {
+                1 => ImageType::TYPE_2D,
+                _ => ImageType::TYPE_2D_ARRAY
+            },
+            image_format: format,
+            image_extent: extent,
+            mip_levels,
+            array_layers,
+            samples: SampleCountFlags::TYPE_1,
+            tiling: ImageTiling::OPTIMAL,
+            usage: ImageUsageFlags::TRANSFER_SRC,
+            aspect,
+            memory_location: MemoryLocation::GpuOnly,
+            transient: false,
+            view_formats: vec![],
+            sharing: Sharing::Exclusive,
+        }
+    }
+
+    const EXTENT: Extent3D = Extent3D { width: 64, height: 32, depth: 1 };
+
+    fn limits() -> vk::PhysicalDeviceLimits {
+        vk::PhysicalDeviceLimits {
+            max_image_dimension1_d: 4096,
+            max_image_dimension2_d: 4096,
+            max_image_dimension3_d: 256,
+            max_image_dimension_cube: 1024,
+            max_image_array_layers: 256,
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn extent_limits_per_image_type() {
+        let info = |image_type, width, height, depth| ImageInfo {
+            image_type,
+            ..image_info(Format::R8G8B8A8_UNORM, ImageAspectFlags::COLOR, Extent3D { width, height, depth }, MipLevels::Count(1), 1)
+        };
+
+        assert_eq!(validate_image_extent(&info(ImageType::TYPE_2D, 4096, 4096, 1), &limits()).unwrap(), vk::ImageType::TYPE_2D);
+        assert!(validate_image_extent(&info(ImageType::TYPE_2D, 4097, 1, 1), &limits()).is_err());
+        assert!(validate_image_extent(&info(ImageType::TYPE_2D, 16, 16, 2), &limits()).is_err());
+
+        // 3D images may be deep, but only up to the smaller 3D limit
+        assert_eq!(validate_image_extent(&info(ImageType::TYPE_3D, 256, 256, 256), &limits()).unwrap(), vk::ImageType::TYPE_3D);
+        assert!(validate_image_extent(&info(ImageType::TYPE_3D, 512, 16, 16), &limits()).is_err());
+        assert!(validate_image_extent(&info(ImageType::TYPE_3D, 16, 16, 0), &limits()).is_err());
+        let layered = ImageInfo { array_layers: 2, ..info(ImageType::TYPE_3D, 16, 16, 16) };
+        assert!(validate_image_extent(&layered, &limits()).is_err());
+
+        assert!(validate_image_extent(&info(ImageType::CUBE, 2048, 2048, 1), &limits()).is_err());
+        assert_eq!(validate_image_extent(&info(ImageType::TYPE_1D, 4096, 1, 1), &limits()).unwrap(), vk::ImageType::TYPE_1D);
+        assert!(validate_image_extent(&info(ImageType::TYPE_1D, 16, 2, 1), &limits()).is_err());
+    }
+
+    #[test]
+    fn mip_levels_bounded_by_largest_dimension() {
+        let info = ImageInfo {
+            image_type: ImageType::TYPE_3D,
+            ..image_info(Format::R8_UNORM, ImageAspectFlags::COLOR, Extent3D { width: 4, height: 4, depth: 64 }, MipLevels::Count(7), 1)
+        };
+        assert!(validate_image_extent(&info, &limits()).is_ok());
+        assert!(validate_image_extent(&ImageInfo { mip_levels: MipLevels::Count(8), ..info }, &limits()).is_err());
+    }
+
+    #[test]
+    fn compressed_format_preference() {
+        assert_eq!(select_compressed_format(true, |_| true), Some(Format::BC7_SRGB_BLOCK));
+        assert_eq!(
+            select_compressed_format(false, |format| format != Format::BC7_UNORM_BLOCK),
+            Some(Format::ASTC_4X4_UNORM_BLOCK)
+        );
+        assert_eq!(
+            select_compressed_format(true, |format| format == Format::ETC2_R8G8B8A8_SRGB_BLOCK),
+            Some(Format::ETC2_R8G8B8A8_SRGB_BLOCK)
+        );
+        assert_eq!(select_compressed_format(true, |_| false), None);
+    }
+
+    #[test]
+    fn compressed_sizes_round_up_to_blocks() {
+        let extent = |width, height| Extent3D { width, height, depth: 1 };
+        assert_eq!(compressed_data_size(Format::BC7_SRGB_BLOCK, extent(64, 32)), Some(16 * 8 * 16));
+        assert_eq!(compressed_data_size(Format::BC7_SRGB_BLOCK, extent(5, 1)), Some(2 * 16));
+        assert_eq!(compressed_data_size(Format::BC1_RGB_UNORM_BLOCK, extent(1, 1)), Some(8));
+        assert_eq!(compressed_data_size(Format::R8G8B8A8_UNORM, extent(4, 4)), None);
+    }
+
+    #[test]
+    fn full_mip_chain_counts() {
+        let extent = |width, height, depth| Extent3D { width, height, depth };
+        assert_eq!(MipLevels::Full.count(extent(512, 256, 1)), 10);
+        assert_eq!(MipLevels::Full.count(extent(256, 512, 1)), 10);
+        assert_eq!(MipLevels::Full.count(extent(513, 1, 1)), 10);
+        assert_eq!(MipLevels::Full.count(extent(1, 1, 1)), 1);
+        assert_eq!(MipLevels::Full.count(extent(16, 16, 64)), 7);
+        assert_eq!(MipLevels::Count(3).count(extent(512, 256, 1)), 3);
+    }
+
+    #[test]
+    fn color_region_size() {
+        let info = image_info(Format::R8G8B8A8_UNORM, ImageAspectFlags::COLOR, EXTENT, MipLevels::Count(1), 2);
+        let region = ImageCopyRegion::mip_level(&info, ImageAspectFlags::COLOR, 0);
+
+        assert_eq!(region.data_size(info.image_format), Some(64 * 32 * 4 * 2));
+        assert!(region.validate(&info, 64 * 32 * 4 * 2).is_ok());
+        assert!(region.validate(&info, 64 * 32 * 4).is_err());
+    }
+
+    #[test]
+    fn depth_stencil_regions_copy_one_aspect() {
+        let aspect = ImageAspectFlags::DEPTH | ImageAspectFlags::STENCIL;
+        let info = image_info(Format::D24_UNORM_S8_UINT, aspect, EXTENT, MipLevels::Count(1), 1);
+        let buffer_size = 64 * 32 * 5;
+
+        let depth = ImageCopyRegion::mip_level(&info, ImageAspectFlags::DEPTH, 0);
+        let stencil = ImageCopyRegion::mip_level(&info, ImageAspectFlags::STENCIL, 0).with_buffer_offset(64 * 32 * 4);
+        assert_eq!(depth.data_size(info.image_format), Some(64 * 32 * 4));
+        assert_eq!(stencil.data_size(info.image_format), Some(64 * 32));
+        assert!(depth.validate(&info, buffer_size).is_ok());
+        assert!(stencil.validate(&info, buffer_size).is_ok());
+
+        // Both aspects at once, or an aspect the image lacks, are rejected
+        assert!(ImageCopyRegion::mip_level(&info, aspect, 0).validate(&info, buffer_size).is_err());
+        assert!(ImageCopyRegion::mip_level(&info, ImageAspectFlags::COLOR, 0).validate(&info, buffer_size).is_err());
+        // Depth and stencil offsets must be 4 byte aligned
+        assert!(stencil.with_buffer_offset(2).validate(&info, buffer_size).is_err());
+    }
+
+    #[test]
+    fn regions_within_mip_levels_and_layers() {
+        let info = image_info(Format::R32_SFLOAT, ImageAspectFlags::COLOR, EXTENT, MipLevels::Full, 4);
+        assert_eq!(info.mip_extent(2), Extent3D { width: 16, height: 8, depth: 1 });
+        assert_eq!(info.mip_extent(6), Extent3D { width: 1, height: 1, depth: 1 });
+
+        let region = ImageCopyRegion::mip_level(&info, ImageAspectFlags::COLOR, 2);
+        assert!(region.validate(&info, u64::MAX / 2).is_ok());
+        assert!(ImageCopyRegion::mip_level(&info, ImageAspectFlags::COLOR, 7).validate(&info, u64::MAX / 2).is_err());
+
+        let layers = ImageCopyRegion { base_array_layer: 3, layer_count: 2, ..region };
+        assert!(layers.validate(&info, u64::MAX / 2).is_err());
+        let outside = ImageCopyRegion { offset: vk::Offset3D { x: 8, y: 0, z: 0 }, ..region };
+        assert!(outside.validate(&info, u64::MAX / 2).is_err());
+    }
+
+    #[test]
+    fn mip_generation_falls_back_to_nearest_filtering() {
+        let blit = vk::FormatFeatureFlags::BLIT_SRC | vk::FormatFeatureFlags::BLIT_DST;
+        assert_eq!(mip_generation_filter(blit | vk::FormatFeatureFlags::SAMPLED_IMAGE_FILTER_LINEAR), Some(vk::Filter::LINEAR));
+        // e.g. R32G32B32A32_SFLOAT, which is commonly blittable without linear filtering
+        assert_eq!(mip_generation_filter(blit | vk::FormatFeatureFlags::SAMPLED_IMAGE), Some(vk::Filter::NEAREST));
+        assert_eq!(mip_generation_filter(vk::FormatFeatureFlags::BLIT_SRC | vk::FormatFeatureFlags::SAMPLED_IMAGE_FILTER_LINEAR), None);
+        assert_eq!(mip_generation_filter(vk::FormatFeatureFlags::empty()), None);
+    }
+
+    #[test]
+    fn mip_generation_transitions_are_supported() {
+        for (old_layout, new_layout) in [
+            (ImageLayout::TRANSFER_DST_OPTIMAL, ImageLayout::TRANSFER_SRC_OPTIMAL),
+            (ImageLayout::TRANSFER_DST_OPTIMAL, ImageLayout::SHADER_READ_ONLY_OPTIMAL),
+            (ImageLayout::TRANSFER_SRC_OPTIMAL, ImageLayout::SHADER_READ_ONLY_OPTIMAL)
+        ] {
+            assert!(transition_masks(old_layout, new_layout).is_some(), "{:?} -> {:?}", old_layout, new_layout);
+        }
     }
 }
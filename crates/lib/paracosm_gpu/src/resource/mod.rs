@@ -1,7 +1,9 @@
 pub mod buffer;
+pub mod buffer_arena;
 pub mod image;
 pub mod pipeline;
 pub mod sampler;
 pub mod shader_module;
+pub mod staging_pool;
 
 pub use gpu_allocator::MemoryLocation;
@@ -1,5 +1,6 @@
 use crate::device::Device;
-use crate::resource::shader_module::ShaderModule;
+use crate::error::GpuError;
+use crate::resource::{image::format_has_stencil, shader_module::{FragmentExecution, ShaderModule}};
 
 use anyhow::{bail, Result};
 use ash::vk;
@@ -25,10 +26,15 @@ pub use vk::{
     PolygonMode,
     CullModeFlags,
     FrontFace,
+    LogicOp,
     PipelineDepthStencilStateCreateInfo,
     CompareOp,
+    DynamicState,
     PipelineMultisampleStateCreateInfo,
     SampleCountFlags,
+    StencilFaceFlags,
+    StencilOp,
+    StencilOpState,
     PipelineLayout
 };
 
@@ -41,23 +47,55 @@ pub use vk::{
 pub struct GraphicsPipeline {
     device: Device,
     pub pipeline: vk::Pipeline,
+    /// Color attachment formats the pipeline renders to
+    pub color_formats: Vec<Format>,
+    /// Depth attachment format the pipeline renders to, `UNDEFINED` without a depth attachment
+    pub depth_format: Format,
+}
+
+impl GraphicsPipeline {
+    /// Check that the attachments of a rendering pass match the formats this pipeline was created for.
+    pub fn validate_attachments(&self, color_formats: &[Format], depth_format: Format) -> Result<()> {
+        if self.color_formats != color_formats {
+            bail!("Pipeline color formats {:?} do not match rendering attachment formats {:?}", self.color_formats, color_formats);
+        }
+        if self.depth_format != depth_format {
+            bail!("Pipeline depth format {:?} does not match rendering attachment format {:?}", self.depth_format, depth_format);
+        }
+
+        Ok(())
+    }
 }
 
 impl Drop for GraphicsPipeline {
     fn drop(&mut self) {
         info!("Dropping GraphicsPipeline");
         unsafe {
-            self.device.device_wait_idle().unwrap();
+            self.device.wait_idle().unwrap();
             
             self.device.destroy_pipeline(self.pipeline, None);
         }
     }
 }
 
-// TODO: implement pipeline for Compute shaders
+/// A [`ComputePipeline`] containing a compute shader stage and resource bindings.
+/// 
+/// Created by calling [`Device::create_compute_pipeline`].
 #[derive(Clone, Resource)]
 pub struct ComputePipeline {
-    device: Device
+    device: Device,
+    pub pipeline: vk::Pipeline,
+}
+
+impl Drop for ComputePipeline {
+    fn drop(&mut self) {
+        info!("Dropping ComputePipeline");
+        unsafe {
+            self.device.wait_idle().unwrap();
+            
+            self.device.destroy_pipeline(self.pipeline, None);
+        }
+    }
 }
 
 
@@ -65,15 +103,30 @@ pub struct ComputePipeline {
 /// Describes the shader stages, resource bindings, vertex input, and fixed function state of a graphics pipeline.
 pub struct GraphicsPipelineInfo {
     pub vertex_stage_info: VertexStageInfo,
-    pub fragment_stage_info: FragmentStageInfo,
+    /// Fragment stage and color targets, or `None` for a depth-only pipeline
+    pub fragment_stage_info: Option<FragmentStageInfo>,
     // TODO: Refactor to hide ash::vk
     pub input_assembly_state: vk::PipelineInputAssemblyStateCreateInfo,
     pub rasterization_state: vk::PipelineRasterizationStateCreateInfo,
     pub depth_stencil_state: Option<vk::PipelineDepthStencilStateCreateInfo>,
+    /// Depth attachment format, used only with a `depth_stencil_state`
+    pub depth_format: vk::Format,
+    /// Logic op applied to all color attachments instead of blending, requires the `logicOp` device feature
+    pub logic_op: Option<vk::LogicOp>,
     pub multisample_state: vk::PipelineMultisampleStateCreateInfo,
+    /// Dynamic states in addition to the viewport and scissor, e.g. `DEPTH_BIAS` to set depth bias per draw
+    pub dynamic_states: Vec<vk::DynamicState>,
+}
+
+/// Describes the shader stage of a compute pipeline.
+#[derive(Clone)]
+pub struct ComputePipelineInfo {
+    pub shader: ShaderModule,
+    pub entry_point: Cow<'static, str>,
 }
 
 // TODO: Refactor to hide ash::vk
+#[derive(Clone)]
 pub struct VertexStageInfo {
     pub shader: ShaderModule,
     pub entry_point: Cow<'static, str>,
@@ -81,12 +134,14 @@ pub struct VertexStageInfo {
 }
 
 // TODO: Refactor to hide ash::vk
+#[derive(Clone)]
 pub struct VertexInputDescription {
     pub binding_description: vk::VertexInputBindingDescription,
     pub attribute_descriptions: Vec<vk::VertexInputAttributeDescription>
 }
 
 // TODO: Refactor to hide ash::vk
+#[derive(Clone)]
 pub struct FragmentStageInfo {
     pub shader: ShaderModule,
     pub entry_point: Cow<'static, str>,
@@ -96,6 +151,28 @@ pub struct FragmentStageInfo {
 
 
 
+/// Check a fragment stage's reflected execution against the pipeline depth state.
+/// 
+/// Returns a warning for combinations that are valid but rarely intended.
+fn check_fragment_execution(
+    execution: FragmentExecution,
+    depth_stencil_state: Option<&vk::PipelineDepthStencilStateCreateInfo>
+) -> Result<Option<&'static str>, GpuError> {
+    if !execution.early_fragment_tests {
+        return Ok(None);
+    }
+    if execution.writes_depth {
+        return Err(GpuError::InvalidPipelineState("Fragment shaders with the EarlyFragmentTests execution mode can't write FragDepth, depth is tested before the shader runs".to_string()));
+    }
+
+    // Early tests write depth before the shader runs, so discarded fragments still occlude
+    let writes_depth = depth_stencil_state.map_or(false, |state| state.depth_test_enable == vk::TRUE && state.depth_write_enable == vk::TRUE);
+    match execution.discards && writes_depth {
+        true => Ok(Some("Fragment shader may discard under forced early fragment tests, discarded fragments will still write depth")),
+        false => Ok(None)
+    }
+}
+
 // Implement pipeline creation
 impl Device {
     /// Create a new [`GraphicsPipeline`] from [`GraphicsPipelineInfo`]
@@ -103,20 +180,28 @@ impl Device {
         &self,
         info: GraphicsPipelineInfo,
         layout: vk::PipelineLayout
-    ) -> Result<GraphicsPipeline> {
+    ) -> Result<GraphicsPipeline, GpuError> {
         // Create shader stage infos
-        let shader_stage_create_infos = [
+        let mut shader_stage_create_infos = vec![
             vk::PipelineShaderStageCreateInfo::builder()
                 .stage(vk::ShaderStageFlags::VERTEX)
                 .module(info.vertex_stage_info.shader.module)
                 .name(unsafe { CStr::from_bytes_with_nul_unchecked(info.vertex_stage_info.entry_point.as_bytes()) })
-                .build(),
-            vk::PipelineShaderStageCreateInfo::builder()
-                .stage(vk::ShaderStageFlags::FRAGMENT)
-                .module(info.fragment_stage_info.shader.module)
-                .name(unsafe { CStr::from_bytes_with_nul_unchecked(info.fragment_stage_info.entry_point.as_bytes()) })
                 .build()
         ];
+        if let Some(fragment_stage_info) = &info.fragment_stage_info {
+            shader_stage_create_infos.push(vk::PipelineShaderStageCreateInfo::builder()
+                .stage(vk::ShaderStageFlags::FRAGMENT)
+                .module(fragment_stage_info.shader.module)
+                .name(unsafe { CStr::from_bytes_with_nul_unchecked(fragment_stage_info.entry_point.as_bytes()) })
+                .build()
+            );
+
+            let execution = fragment_stage_info.shader.fragment_execution(&fragment_stage_info.entry_point);
+            if let Some(warning) = check_fragment_execution(execution, info.depth_stencil_state.as_ref())? {
+                warn!("{} ({:?} in {:?})", warning, fragment_stage_info.entry_point.trim_end_matches('\0'), fragment_stage_info.shader.path);
+            }
+        }
 
         // Create vertex input state info
         let vertex_input_state_create_info = vk::PipelineVertexInputStateCreateInfo::builder()
@@ -127,10 +212,15 @@ impl Device {
         let viewport_state_create_info = vk::PipelineViewportStateCreateInfo::builder()
             .scissor_count(1)
             .viewport_count(1);
-        let dynamic_states = [
+        let mut dynamic_states = vec![
             vk::DynamicState::VIEWPORT,
             vk::DynamicState::SCISSOR
         ];
+        for state in info.dynamic_states.iter() {
+            if !dynamic_states.contains(state) {
+                dynamic_states.push(*state);
+            }
+        }
         let dynamic_state_create_info = vk::PipelineDynamicStateCreateInfo::builder()
             .dynamic_states(&dynamic_states);
 
@@ -138,16 +228,48 @@ impl Device {
         let input_assembly_state_create_info = info.input_assembly_state;
         let rasterization_state_create_info = info.rasterization_state;
         let multisample_state_create_info = info.multisample_state;
+        if rasterization_state_create_info.polygon_mode != vk::PolygonMode::FILL && !self.capabilities().fill_mode_non_solid {
+            return Err(GpuError::MissingFeature("fillModeNonSolid"));
+        }
+        if multisample_state_create_info.sample_shading_enable == vk::TRUE {
+            if !self.capabilities().sample_rate_shading {
+                return Err(GpuError::MissingFeature("sampleRateShading"));
+            }
+            if !(0.0..=1.0).contains(&multisample_state_create_info.min_sample_shading) {
+                return Err(GpuError::InvalidPipelineState(format!("Minimum sample shading {} is outside of [0, 1]", multisample_state_create_info.min_sample_shading)));
+            }
+        }
 
         // Create attachment state infos
-        let color_blend_attachment_states = info.fragment_stage_info.color_blend_states.as_slice();
+        let (color_blend_attachment_states, color_attachment_formats) = match &info.fragment_stage_info {
+            Some(fragment_stage_info) => (fragment_stage_info.color_blend_states.as_slice(), fragment_stage_info.target_states.as_slice()),
+            None => (&[][..], &[][..])
+        };
+        if info.logic_op.is_some() && !self.capabilities().logic_op {
+            return Err(GpuError::MissingFeature("logicOp"));
+        }
+        if info.logic_op.is_some() && color_blend_attachment_states.iter().any(|state| state.blend_enable == vk::TRUE) {
+            return Err(GpuError::InvalidPipelineState(format!("Logic op {:?} can't be combined with color attachment blending", info.logic_op)));
+        }
         let color_blend_state_create_info = vk::PipelineColorBlendStateCreateInfo::builder()
-            .logic_op(vk::LogicOp::CLEAR)
+            .logic_op_enable(info.logic_op.is_some())
+            .logic_op(info.logic_op.unwrap_or(vk::LogicOp::CLEAR))
             .attachments(color_blend_attachment_states);
-        let depth_stencil_state_create_info = info.depth_stencil_state.unwrap();
+        // Pipelines without depth stencil state render without a depth attachment
+        let depth_attachment_format = match info.depth_stencil_state {
+            Some(_) => info.depth_format,
+            None => vk::Format::UNDEFINED
+        };
+        let depth_stencil_state_create_info = info.depth_stencil_state.unwrap_or_default();
+        // Depth stencil formats are attached as both the depth and stencil attachment
+        let stencil_attachment_format = match format_has_stencil(depth_attachment_format) {
+            true => depth_attachment_format,
+            false => vk::Format::UNDEFINED
+        };
         let mut pipeline_rendering_create_info = vk::PipelineRenderingCreateInfo::builder()
-            .color_attachment_formats(info.fragment_stage_info.target_states.as_slice())
-            .depth_attachment_format(vk::Format::D24_UNORM_S8_UINT);
+            .color_attachment_formats(color_attachment_formats)
+            .depth_attachment_format(depth_attachment_format)
+            .stencil_attachment_format(stencil_attachment_format);
 
 
 
@@ -167,13 +289,85 @@ impl Device {
         let pipeline = unsafe {
             match self.create_graphics_pipelines(vk::PipelineCache::null(), slice::from_ref(&create_info), None) {
                 Ok(result) => result,
-                Err(_) => bail!("Failed to create pipeline!".to_string())
+                Err((_, error)) => return Err(GpuError::PipelineCreation(error))
             }
         }[0];
 
         Ok(GraphicsPipeline {
             device: self.clone(),
             pipeline,
+            color_formats: color_attachment_formats.to_vec(),
+            depth_format: depth_attachment_format,
         })
     }
-}
\ No newline at end of file
+    /// Create a new [`ComputePipeline`] from [`ComputePipelineInfo`]
+    pub fn create_compute_pipeline(
+        &self,
+        info: ComputePipelineInfo,
+        layout: vk::PipelineLayout
+    ) -> Result<ComputePipeline, GpuError> {
+        let shader_stage_create_info = vk::PipelineShaderStageCreateInfo::builder()
+            .stage(vk::ShaderStageFlags::COMPUTE)
+            .module(info.shader.module)
+            .name(unsafe { CStr::from_bytes_with_nul_unchecked(info.entry_point.as_bytes()) })
+            .build();
+
+        let create_info = vk::ComputePipelineCreateInfo::builder()
+            .stage(shader_stage_create_info)
+            .layout(layout);
+        let pipeline = unsafe {
+            match self.create_compute_pipelines(vk::PipelineCache::null(), slice::from_ref(&create_info), None) {
+                Ok(result) => result,
+                Err((_, error)) => return Err(GpuError::PipelineCreation(error))
+            }
+        }[0];
+
+        Ok(ComputePipeline {
+            device: self.clone(),
+            pipeline,
+        })
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn depth_state(depth_write_enable: bool) -> vk::PipelineDepthStencilStateCreateInfo {
+        vk::PipelineDepthStencilStateCreateInfo::builder()
+            .depth_test_enable(true)
+            .depth_write_enable(depth_write_enable)
+            .depth_compare_op(vk::CompareOp::LESS)
+            .build()
+    }
+
+    #[test]
+    fn discard_under_early_fragment_tests_still_writes_depth() {
+        let cutout = FragmentExecution {
+            early_fragment_tests: true,
+            discards: true,
+            writes_depth: false
+        };
+
+        // Depth is written before the shader can discard, which is worth a warning
+        assert!(check_fragment_execution(cutout, Some(&depth_state(true))).unwrap().is_some());
+        // Without depth writes, or a depth attachment, discarded fragments leave depth untouched
+        assert_eq!(check_fragment_execution(cutout, Some(&depth_state(false))).unwrap(), None);
+        assert_eq!(check_fragment_execution(cutout, None).unwrap(), None);
+        // Depth is only written for fragments that pass late tests
+        let late_cutout = FragmentExecution { early_fragment_tests: false, ..cutout };
+        assert_eq!(check_fragment_execution(late_cutout, Some(&depth_state(true))).unwrap(), None);
+    }
+
+    #[test]
+    fn early_fragment_tests_exclude_shader_depth_writes() {
+        let execution = FragmentExecution {
+            early_fragment_tests: true,
+            discards: false,
+            writes_depth: true
+        };
+        assert!(matches!(check_fragment_execution(execution, Some(&depth_state(true))), Err(GpuError::InvalidPipelineState(_))));
+        assert!(check_fragment_execution(FragmentExecution { early_fragment_tests: false, ..execution }, Some(&depth_state(true))).is_ok());
+    }
+}
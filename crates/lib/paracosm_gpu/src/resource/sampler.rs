@@ -1,6 +1,6 @@
 use crate::device::Device;
 
-use anyhow::Result;
+use anyhow::{bail, Context, Result};
 use ash::vk;
 
 use std::ops::Deref;
@@ -11,7 +11,8 @@ pub use vk::{
     SamplerAddressMode,
     BorderColor,
     CompareOp,
-    SamplerMipmapMode
+    SamplerMipmapMode,
+    SamplerReductionMode
 };
 
 
@@ -24,7 +25,9 @@ pub struct SamplerInfo {
     pub unnormalized_coordinates: bool,
     pub compare_op: Option<CompareOp>,
     pub mipmap_mode: SamplerMipmapMode,
-    pub mipmap_lod: (f32, f32, f32)
+    pub mipmap_lod: (f32, f32, f32),
+    /// Min or max reduction of filtered texels instead of a weighted average, e.g. for depth pyramids
+    pub reduction_mode: Option<SamplerReductionMode>
 }
 
 #[derive(Clone)]
@@ -45,7 +48,7 @@ impl Drop for Sampler {
     fn drop(&mut self) {
         unsafe {
             // TODO: look into waiting on queue idle instead
-            self.device.device_wait_idle().unwrap();
+            self.device.wait_idle().unwrap();
 
             self.device.destroy_sampler(self.sampler, None);
         }
@@ -56,8 +59,14 @@ impl Device {
     pub fn create_sampler(
         &self,
         info: &SamplerInfo
-    ) -> Sampler {
-        let create_info = vk::SamplerCreateInfo::builder()
+    ) -> Result<Sampler> {
+        let mut reduction_mode_info = vk::SamplerReductionModeCreateInfo::builder()
+            .reduction_mode(info.reduction_mode.unwrap_or(SamplerReductionMode::WEIGHTED_AVERAGE));
+        if info.reduction_mode.is_some() && !self.supports_sampler_filter_minmax() {
+            bail!("Sampler reduction mode {:?} requires samplerFilterMinmax support", info.reduction_mode);
+        }
+
+        let mut create_info = vk::SamplerCreateInfo::builder()
             .mag_filter(info.filter.0)
             .min_filter(info.filter.1)
             .address_mode_u(info.address_mode.0)
@@ -73,15 +82,28 @@ impl Device {
             .mip_lod_bias(info.mipmap_lod.0)
             .min_lod(info.mipmap_lod.1)
             .max_lod(info.mipmap_lod.2);
+        if info.reduction_mode.is_some() {
+            create_info = create_info.push_next(&mut reduction_mode_info);
+        }
 
         let sampler = unsafe {
             self.logical_device.create_sampler(&create_info, None)
-                .expect("Device should create a sampler.")
+                .context("Device should create a sampler.")?
         };
 
-        Sampler {
+        Ok(Sampler {
             device: self.clone(),
             sampler
-        }
+        })
+    }
+
+    /// Whether samplers can use min and max reduction modes, see [`SamplerInfo::reduction_mode`].
+    pub fn supports_sampler_filter_minmax(&self) -> bool {
+        let mut vulkan_12_properties = vk::PhysicalDeviceVulkan12Properties::default();
+        let mut properties = vk::PhysicalDeviceProperties2::builder()
+            .push_next(&mut vulkan_12_properties);
+        unsafe { self.instance.get_physical_device_properties2(self.physical_device, &mut properties) };
+
+        vulkan_12_properties.filter_minmax_single_component_formats == vk::TRUE
     }
 }
\ No newline at end of file
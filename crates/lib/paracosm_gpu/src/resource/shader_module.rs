@@ -1,12 +1,13 @@
 use crate::device::Device;
+use crate::error::GpuError;
 
-use anyhow::Context;
 use anyhow::Result;
 use ash::vk;
 use ash::util;
 
 use std::{
     borrow::Cow,
+    collections::{HashMap, HashSet},
     fs::File,
     ops::Deref,
     path::Path,
@@ -19,7 +20,19 @@ pub struct ShaderModuleInternal {
     device: Device,
     pub path: Cow<'static, Path>,
     //pub entry_points: Vec<String>,
-    pub module: vk::ShaderModule
+    pub module: vk::ShaderModule,
+    /// Execution of each fragment entry point, reflected from the SPIR-V
+    fragment_entry_points: HashMap<String, FragmentExecution>
+}
+
+impl ShaderModuleInternal {
+    /// Execution of fragment `entry_point`, with or without its nul terminator, default if it isn't a fragment entry point
+    pub fn fragment_execution(&self, entry_point: &str) -> FragmentExecution {
+        self.fragment_entry_points
+            .get(entry_point.trim_end_matches('\0'))
+            .copied()
+            .unwrap_or_default()
+    }
 }
 
 impl Drop for ShaderModuleInternal {
@@ -47,25 +60,239 @@ impl Deref for ShaderModule {
 
 
 
+/// How a fragment entry point interacts with depth testing, reflected from its SPIR-V
+#[derive(Copy, Clone, Default, Eq, PartialEq, Debug)]
+pub struct FragmentExecution {
+    /// Declares the `EarlyFragmentTests` execution mode, e.g. `#[spirv(fragment(early_fragment_tests))]`
+    pub early_fragment_tests: bool,
+    /// May discard fragments, in the entry point or any function it calls
+    pub discards: bool,
+    /// Writes the `FragDepth` builtin
+    pub writes_depth: bool
+}
+
+// SPIR-V opcodes and operands used by reflection
+const OP_ENTRY_POINT: u32 = 15;
+const OP_EXECUTION_MODE: u32 = 16;
+const OP_FUNCTION: u32 = 54;
+const OP_FUNCTION_END: u32 = 56;
+const OP_FUNCTION_CALL: u32 = 57;
+const OP_DECORATE: u32 = 71;
+const OP_KILL: u32 = 252;
+const OP_TERMINATE_INVOCATION: u32 = 4416;
+const OP_DEMOTE_TO_HELPER_INVOCATION: u32 = 5380;
+const EXECUTION_MODEL_FRAGMENT: u32 = 4;
+const EXECUTION_MODE_EARLY_FRAGMENT_TESTS: u32 = 9;
+const DECORATION_BUILT_IN: u32 = 11;
+const BUILT_IN_FRAG_DEPTH: u32 = 22;
+
+/// Reflect the [`FragmentExecution`] of each fragment entry point in SPIR-V `code`, by name
+pub fn reflect_fragment_entry_points(code: &[u32]) -> HashMap<String, FragmentExecution> {
+    // (name, interface ids) of each fragment entry point id
+    let mut entry_points: HashMap<u32, (String, &[u32])> = HashMap::new();
+    let mut early_fragment_tests = vec![];
+    let mut frag_depth_ids = vec![];
+    // Callees of each function, and the functions that discard directly
+    let mut calls: HashMap<u32, Vec<u32>> = HashMap::new();
+    let mut discarding_functions = HashSet::new();
+    let mut function = None;
+
+    // Instructions follow the five word header
+    let mut words = code.get(5..).unwrap_or_default();
+    while let Some(&first_word) = words.first() {
+        let word_count = (first_word >> 16) as usize;
+        if word_count == 0 || word_count > words.len() {
+            break;
+        }
+        let (instruction, rest) = words.split_at(word_count);
+        words = rest;
+
+        match (first_word & 0xFFFF, &instruction[1..]) {
+            (OP_ENTRY_POINT, [EXECUTION_MODEL_FRAGMENT, id, operands @ ..]) => {
+                // Nul terminated name packed into little endian words, followed by interface ids
+                let name_words = operands.iter().position(|word| word.to_le_bytes().contains(&0)).map_or(operands.len(), |index| index + 1);
+                let name_bytes: Vec<u8> = operands[..name_words].iter().flat_map(|word| word.to_le_bytes()).take_while(|&byte| byte != 0).collect();
+                entry_points.insert(*id, (String::from_utf8_lossy(&name_bytes).into_owned(), &operands[name_words..]));
+            },
+            (OP_EXECUTION_MODE, [id, EXECUTION_MODE_EARLY_FRAGMENT_TESTS, ..]) => early_fragment_tests.push(*id),
+            (OP_DECORATE, [id, DECORATION_BUILT_IN, BUILT_IN_FRAG_DEPTH, ..]) => frag_depth_ids.push(*id),
+            (OP_FUNCTION, [_, id, ..]) => function = Some(*id),
+            (OP_FUNCTION_END, _) => function = None,
+            (OP_FUNCTION_CALL, [_, _, callee, ..]) => if let Some(caller) = function {
+                calls.entry(caller).or_default().push(*callee);
+            },
+            (OP_KILL | OP_TERMINATE_INVOCATION | OP_DEMOTE_TO_HELPER_INVOCATION, _) => if let Some(function) = function {
+                discarding_functions.insert(function);
+            },
+            _ => ()
+        }
+    }
+
+    entry_points
+        .into_iter()
+        .map(|(id, (name, interface))| (name, FragmentExecution {
+            early_fragment_tests: early_fragment_tests.contains(&id),
+            discards: reaches_any(id, &calls, &discarding_functions),
+            writes_depth: interface.iter().any(|id| frag_depth_ids.contains(id))
+        }))
+        .collect()
+}
+
+
+/// Whether `function`, or any function it calls directly or indirectly, is in `targets`
+fn reaches_any(function: u32, calls: &HashMap<u32, Vec<u32>>, targets: &HashSet<u32>) -> bool {
+    let mut visited = HashSet::from([function]);
+    let mut pending = vec![function];
+    while let Some(function) = pending.pop() {
+        if targets.contains(&function) {
+            return true;
+        }
+        let callees = calls.get(&function).map_or(&[][..], Vec::as_slice);
+        pending.extend(callees.iter().filter(|&&callee| visited.insert(callee)));
+    }
+
+    false
+}
+
+
+
 impl Device {
     pub fn create_shader_module(
         &self,
         path: &Path
-    ) -> Result<ShaderModule> {
-        let mut file = File::open(&path).context(format!("Failed to open shader file {:?}", &path))?;
-        let code = util::read_spv(&mut file).context(format!("Failed to read shader file {:?}", &path))?;
+    ) -> Result<ShaderModule, GpuError> {
+        let mut file = File::open(&path)
+            .map_err(|error| GpuError::ShaderCompilation(format!("Failed to open shader file {:?}: {}", &path, error)))?;
+        let code = util::read_spv(&mut file)
+            .map_err(|error| GpuError::ShaderCompilation(format!("Failed to read SPIR-V from {:?}: {}", &path, error)))?;
 
         let create_info = vk::ShaderModuleCreateInfo::builder()
             .code(&code);
         let shader_module = unsafe {
             self.logical_device.create_shader_module(&create_info, None)
-                .context(format!("Failed to create shader module from file {:?}", &path))?
+                .map_err(|error| GpuError::ShaderCompilation(format!("Failed to create shader module from {:?}: {}", &path, error)))?
         };
 
         Ok(ShaderModule(Arc::new(ShaderModuleInternal {
             device: self.clone(),
             path: Cow::from(path.to_path_buf()),
-            module: shader_module
+            module: shader_module,
+            fragment_entry_points: reflect_fragment_entry_points(&code)
         })))
     }
 }
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Pack a nul terminated SPIR-V literal string into words
+    fn literal(name: &str) -> Vec<u32> {
+        let mut bytes = name.as_bytes().to_vec();
+        bytes.resize((bytes.len() / 4 + 1) * 4, 0);
+        bytes.chunks(4).map(|chunk| u32::from_le_bytes(chunk.try_into().unwrap())).collect()
+    }
+
+    fn instruction(opcode: u32, operands: &[u32]) -> Vec<u32> {
+        let mut words = vec![((operands.len() as u32 + 1) << 16) | opcode];
+        words.extend_from_slice(operands);
+        words
+    }
+
+    /// Ids of the void type and void function type used by test functions
+    const VOID: u32 = 20;
+    const VOID_FUNCTION: u32 = 21;
+
+    fn header() -> Vec<u32> {
+        vec![0x07230203, 0x00010300, 0, 32, 0]
+    }
+
+    fn fragment_entry_point(id: u32, name: &str, interface: &[u32]) -> Vec<u32> {
+        let mut operands = vec![EXECUTION_MODEL_FRAGMENT, id];
+        operands.extend(literal(name));
+        operands.extend_from_slice(interface);
+        instruction(OP_ENTRY_POINT, &operands)
+    }
+
+    /// Function `id` calling each of `callees`, and discarding if `kills`
+    fn function(id: u32, callees: &[u32], kills: bool) -> Vec<u32> {
+        let mut code = instruction(OP_FUNCTION, &[VOID, id, 0, VOID_FUNCTION]);
+        for (index, &callee) in callees.iter().enumerate() {
+            code.extend(instruction(OP_FUNCTION_CALL, &[VOID, 30 + index as u32, callee]));
+        }
+        if kills {
+            code.extend(instruction(OP_KILL, &[]));
+        }
+        code.extend(instruction(OP_FUNCTION_END, &[]));
+        code
+    }
+
+    /// A module with a fragment entry point `name`, id 1, which writes `FragDepth` through the output variable id 2,
+    /// and discards in the function id 3 it calls
+    fn fragment_module(name: &str, early_fragment_tests: bool, discards: bool, writes_depth: bool) -> Vec<u32> {
+        let mut code = header();
+        code.extend(fragment_entry_point(1, name, &[2]));
+        if early_fragment_tests {
+            code.extend(instruction(OP_EXECUTION_MODE, &[1, EXECUTION_MODE_EARLY_FRAGMENT_TESTS]));
+        }
+        if writes_depth {
+            code.extend(instruction(OP_DECORATE, &[2, DECORATION_BUILT_IN, BUILT_IN_FRAG_DEPTH]));
+        }
+        code.extend(function(1, &[3], false));
+        code.extend(function(3, &[], discards));
+        code
+    }
+
+    #[test]
+    fn reflects_early_fragment_tests_and_discard() {
+        let entry_points = reflect_fragment_entry_points(&fragment_module("frag::cutout::main", true, true, false));
+        assert_eq!(entry_points.len(), 1);
+        assert_eq!(entry_points["frag::cutout::main"], FragmentExecution {
+            early_fragment_tests: true,
+            discards: true,
+            writes_depth: false
+        });
+
+        let entry_points = reflect_fragment_entry_points(&fragment_module("main", false, false, true));
+        assert_eq!(entry_points["main"], FragmentExecution {
+            early_fragment_tests: false,
+            discards: false,
+            writes_depth: true
+        });
+    }
+
+    #[test]
+    fn ignores_non_fragment_entry_points_and_truncated_code() {
+        let mut code = header();
+        let mut entry_point = vec![0, 1];
+        entry_point.extend(literal("vert::main"));
+        code.extend(instruction(OP_ENTRY_POINT, &entry_point));
+        code.extend(instruction(OP_EXECUTION_MODE, &[1, EXECUTION_MODE_EARLY_FRAGMENT_TESTS]));
+        assert!(reflect_fragment_entry_points(&code).is_empty());
+
+        // An instruction running past the end of the code stops reflection
+        let mut code = header();
+        code.extend(fragment_entry_point(1, "main", &[]));
+        code.extend(instruction(OP_EXECUTION_MODE, &[1, EXECUTION_MODE_EARLY_FRAGMENT_TESTS]));
+        code.truncate(code.len() - 1);
+        assert_eq!(reflect_fragment_entry_points(&code)["main"], FragmentExecution::default());
+        assert!(reflect_fragment_entry_points(&[]).is_empty());
+    }
+    #[test]
+    fn discards_are_reflected_per_entry_point() {
+        // The cutout reaches a kill through two calls, while the opaque entry point's recursive calls never discard
+        let mut code = header();
+        code.extend(fragment_entry_point(1, "cutout", &[]));
+        code.extend(fragment_entry_point(2, "opaque", &[]));
+        code.extend(function(1, &[3], false));
+        code.extend(function(2, &[5], false));
+        code.extend(function(3, &[4], false));
+        code.extend(function(4, &[], true));
+        code.extend(function(5, &[2, 5], false));
+
+        let entry_points = reflect_fragment_entry_points(&code);
+        assert!(entry_points["cutout"].discards);
+        assert!(!entry_points["opaque"].discards);
+    }
+}
@@ -0,0 +1,164 @@
+use crate::device::Device;
+use super::buffer::*;
+
+use anyhow::{bail, Result};
+use ash::vk;
+
+use std::{
+    mem::size_of,
+    ops::Deref,
+    sync::Mutex
+};
+
+
+
+/// Smallest staging buffer created by a [`StagingPool`], so small uploads share buffers
+const MIN_STAGING_SIZE: usize = 64 * 1024;
+/// Most idle buffers kept by a [`StagingPool`], larger surpluses are freed
+const MAX_IDLE_BUFFERS: usize = 8;
+/// Largest share of a device local, host visible heap a single buffer may take for a direct upload,
+/// so small 256MiB BAR windows aren't exhausted
+const DIRECT_UPLOAD_HEAP_FRACTION: u64 = 4;
+
+/// Recycles host visible staging buffers between uploads, instead of allocating a new buffer for each.
+pub struct StagingPool {
+    device: Device,
+    idle_buffers: Mutex<Vec<Buffer>>,
+}
+
+impl StagingPool {
+    /// Get an idle staging buffer of at least `size` bytes, or create one.
+    /// 
+    /// The buffer returns to the pool when the [`StagingBuffer`] drops, so the upload must complete first.
+    pub fn acquire(&self, size: usize) -> Result<StagingBuffer> {
+        let mut idle_buffers = self.idle_buffers.lock().unwrap();
+
+        // Use the smallest idle buffer that fits
+        let best_fit = idle_buffers
+            .iter()
+            .enumerate()
+            .filter(|(_, buffer)| buffer.info.size >= size)
+            .min_by_key(|(_, buffer)| buffer.info.size)
+            .map(|(index, _)| index);
+        let buffer = match best_fit {
+            Some(index) => idle_buffers.swap_remove(index),
+            None => {
+                let size = size.max(MIN_STAGING_SIZE).next_power_of_two();
+                let info = BufferInfo::new(size, BufferUsageFlags::TRANSFER_SRC, MemoryLocation::CpuToGpu);
+                self.device.create_buffer("Staging Buffer", info, None)?
+            }
+        };
+
+        Ok(StagingBuffer {
+            pool: self,
+            buffer: Some(buffer)
+        })
+    }
+
+    /// Create a `GpuOnly` buffer holding `data`.
+    /// 
+    /// If the device has memory that is both device local and host visible, e.g. with resizable BAR, and the buffer fits,
+    /// `data` is written to the buffer directly. Otherwise it is copied through a staging buffer, which needs `TRANSFER_DST` usage.
+    pub fn upload_buffer<T>(&self, name: &str, info: BufferInfo, data: &Vec<T>) -> Result<Buffer> {
+        if info.memory_location == MemoryLocation::GpuOnly && self.fits_direct_upload(info.size) {
+            let direct_info = BufferInfo {
+                memory_location: MemoryLocation::CpuToGpu,
+                ..info.clone()
+            };
+            let buffer = self.device.create_buffer(name, direct_info, None)?;
+
+            // The allocator falls back to host memory if no device local memory type is free
+            let device_local = buffer.allocation.as_ref()
+                .map_or(false, |allocation| allocation.memory_properties().contains(vk::MemoryPropertyFlags::DEVICE_LOCAL));
+            if device_local {
+                buffer.write_buffer(data);
+                return Ok(buffer);
+            }
+        }
+
+        self.staged_upload(name, info, data)
+    }
+
+    /// Write `data` to `destination` starting at byte `offset`, through a staging buffer, 
+    /// e.g. to stream a region of a large `GpuOnly` instance buffer each frame.
+    /// 
+    /// `destination` needs `TRANSFER_DST` usage. The copy is complete on return, so following submissions see the data,
+    /// but the region must not be read by frames still in flight.
+    pub fn update_buffer<T>(&self, destination: &Buffer, offset: usize, data: &Vec<T>) -> Result<()> {
+        let size = size_of::<T>() * data.len();
+        if size == 0 {
+            return Ok(());
+        }
+        if !destination.info.usage.contains(BufferUsageFlags::TRANSFER_DST) {
+            bail!("Buffer updates require TRANSFER_DST usage, buffer has {:?}", destination.info.usage);
+        }
+        if offset + size > destination.info.size {
+            bail!("Buffer update of {} bytes at offset {} exceeds buffer size {}", size, offset, destination.info.size);
+        }
+
+        let staging_buffer = self.acquire(size)?;
+        staging_buffer.write_buffer(data);
+        self.device.copy_buffer_region(&staging_buffer, destination, offset, size);
+
+        Ok(())
+    }
+
+    fn staged_upload<T>(&self, name: &str, info: BufferInfo, data: &Vec<T>) -> Result<Buffer> {
+        let size = info.size;
+        let staging_buffer = self.acquire(size)?;
+        staging_buffer.write_buffer(data);
+
+        let buffer = self.device.create_buffer(name, info, None)?;
+        self.device.copy_buffer(&staging_buffer, &buffer, size);
+
+        Ok(buffer)
+    }
+
+    fn fits_direct_upload(&self, size: usize) -> bool {
+        self.device.device_local_host_visible_heap_size()
+            .map_or(false, |heap_size| size as u64 <= heap_size / DIRECT_UPLOAD_HEAP_FRACTION)
+    }
+
+    /// Number of idle buffers held for reuse
+    pub fn idle_count(&self) -> usize {
+        self.idle_buffers.lock().unwrap().len()
+    }
+
+    fn release(&self, buffer: Buffer) {
+        let mut idle_buffers = self.idle_buffers.lock().unwrap();
+        if idle_buffers.len() < MAX_IDLE_BUFFERS {
+            idle_buffers.push(buffer);
+        }
+    }
+}
+
+/// A staging buffer borrowed from a [`StagingPool`], returned to the pool on drop.
+pub struct StagingBuffer<'a> {
+    pool: &'a StagingPool,
+    buffer: Option<Buffer>,
+}
+
+impl Deref for StagingBuffer<'_> {
+    type Target = Buffer;
+
+    fn deref(&self) -> &Self::Target {
+        self.buffer.as_ref().unwrap()
+    }
+}
+
+impl Drop for StagingBuffer<'_> {
+    fn drop(&mut self) {
+        if let Some(buffer) = self.buffer.take() {
+            self.pool.release(buffer);
+        }
+    }
+}
+
+impl Device {
+    pub fn create_staging_pool(&self) -> StagingPool {
+        StagingPool {
+            device: self.clone(),
+            idle_buffers: Mutex::new(vec![])
+        }
+    }
+}
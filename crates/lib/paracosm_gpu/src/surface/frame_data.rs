@@ -4,6 +4,13 @@ use anyhow::{Context, Result};
 use ash::vk;
 
 
+/// Per-frame resources for acquiring swapchain image ownership on a dedicated present queue family.
+pub(crate) struct PresentTransfer {
+    pub(crate) semaphore: vk::Semaphore,
+    command_pool: vk::CommandPool,
+    pub(crate) command_buffer: vk::CommandBuffer,
+}
+
 pub struct FrameData {
     device: Device,
     // Frame sync
@@ -12,6 +19,8 @@ pub struct FrameData {
     // Frame commands
     command_pool: vk::CommandPool,
     pub command_buffer: vk::CommandBuffer,
+    // Present queue ownership transfer, if present family differs from graphics family
+    pub(crate) present_transfer: Option<PresentTransfer>,
 }
 
 impl FrameData {
@@ -49,23 +58,72 @@ impl FrameData {
                 .context("FrameData::new: ")?[0]
         };
 
+        // Create present ownership transfer resources if needed
+        let present_transfer = match device.queues.present_family {
+            Some(present_family) if present_family != device.queues.graphics_family => {
+                let create_info = vk::SemaphoreCreateInfo::builder();
+                let semaphore = unsafe {
+                    device.create_semaphore(&create_info, None)
+                        .context("FrameData::new: ")?
+                };
+
+                let create_info = vk::CommandPoolCreateInfo::builder()
+                    .queue_family_index(present_family)
+                    .flags(vk::CommandPoolCreateFlags::RESET_COMMAND_BUFFER);
+                let command_pool = unsafe {
+                    device.create_command_pool(&create_info, None)
+                        .context("FrameData::new: ")?
+                };
+
+                let alloc_info = vk::CommandBufferAllocateInfo::builder()
+                    .command_pool(command_pool)
+                    .command_buffer_count(1)
+                    .level(vk::CommandBufferLevel::PRIMARY);
+                let command_buffer = unsafe {
+                    device.allocate_command_buffers(&alloc_info)
+                        .context("FrameData::new: ")?[0]
+                };
+
+                Some(PresentTransfer {
+                    semaphore,
+                    command_pool,
+                    command_buffer
+                })
+            },
+            _ => None
+        };
+
         Ok(Self {
             device,
             render_semaphore,
             in_flight_fence,
             command_pool,
             command_buffer,
+            present_transfer,
         })
     }
+
+    /// The semaphore presentation must wait on for this frame.
+    pub(crate) fn present_semaphore(&self) -> vk::Semaphore {
+        match &self.present_transfer {
+            Some(present_transfer) => present_transfer.semaphore,
+            None => self.render_semaphore
+        }
+    }
 }
 
 impl Drop for FrameData {
     fn drop(&mut self) {
         unsafe {
-            self.device.device_wait_idle().unwrap();
+            self.device.wait_idle().unwrap();
 
             self.device.destroy_command_pool(self.command_pool, None);
 
+            if let Some(present_transfer) = self.present_transfer.take() {
+                self.device.destroy_command_pool(present_transfer.command_pool, None);
+                self.device.destroy_semaphore(present_transfer.semaphore, None);
+            }
+
             self.device.destroy_semaphore(self.render_semaphore, None);
             self.device.destroy_fence(self.in_flight_fence, None);
         }
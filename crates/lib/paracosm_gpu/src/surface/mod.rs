@@ -5,8 +5,10 @@ use swapchain::Swapchain;
 use frame_data::FrameData;
 
 use crate::device::Device;
+use crate::error::GpuError;
+use crate::resource::image::Image;
 
-use anyhow::{Result, bail};
+use anyhow::{anyhow, bail, Context, Result};
 use ash::extensions::khr;
 use ash::vk;
 
@@ -19,16 +21,54 @@ use std::{
 };
 
 
+/// Swapchain formats in order of preference, used when no preferred format is set or available.
+pub const DEFAULT_FORMATS: [vk::Format; 2] = [
+    vk::Format::B8G8R8A8_SRGB,
+    vk::Format::R8G8B8A8_SRGB,
+];
+
+/// Scores a surface format for selection, `None` to reject it, see [`Surface::set_format_selector`]
+pub type FormatSelector = Box<dyn Fn(&vk::SurfaceFormatKHR) -> Option<u32> + Send + Sync>;
+
+/// Whether a swapchain still matches its surface, returned by [`Surface::acquire_next_image`] and [`Surface::queue_present`]
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum SwapchainStatus {
+    Optimal,
+    /// Usable, but the surface changed, e.g. on a DPI change. The surface should be reconfigured.
+    Suboptimal,
+    /// Unusable until the surface is reconfigured, e.g. after a display mode change. No image was acquired or presented.
+    OutOfDate,
+}
+
+/// Present timing of the most recent frame reported by the presentation engine, see [`Surface::present_stats`].
+/// 
+/// Times are in nanoseconds of the presentation engine's monotonic clock.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct PresentStats {
+    /// When the frame's image was displayed
+    pub actual_present_time: u64,
+    /// When the image could have been displayed at the earliest, before `actual_present_time` if it missed refreshes
+    pub earliest_present_time: u64,
+}
+
 /// Public API for interacting with the Vulkan surface.
 pub struct Surface {
     device: Device,
     graphics_queue: vk::Queue,
+    present_queue: vk::Queue,
 
     surface: khr::Surface,
     surface_handle: vk::SurfaceKHR,
 
     swapchain: Option<RefCell<Swapchain>>,
     pub swapchain_semaphore: vk::Semaphore,
+    preferred_format: Option<vk::Format>,
+    format_selector: Option<FormatSelector>,
+    /// Present mode requested by the last [`Surface::configure`] or [`Surface::set_vsync`], kept when resizing
+    requested_present_mode: PresentMode,
+    /// Id of the next present, reported back in present timings with display timing enabled
+    next_present_id: u32,
+    present_stats: Option<PresentStats>,
 
     frame_index: usize,
     frame_data: Vec<FrameData>,
@@ -38,12 +78,13 @@ impl Surface {
     pub fn new(
         device: Device,
         raw_handle: &RawHandleWrapper
-    ) -> Self {
+    ) -> Result<Self, GpuError> {
         let instance = &device.instance;
         
-        // Get first Graphics queue
-        let graphics_queue = device.graphics_queue(0)
-            .expect("Device should provide a standard graphics queue");
+        // Get first Graphics queue, and the present queue, which requires a device created for a window
+        //  The first graphics queue is always created, see Device::new
+        let graphics_queue = unsafe { device.get_device_queue(device.queues.graphics_family, 0) };
+        let present_queue = device.present_queue()?;
 
         // Create surface from window
         let surface = khr::Surface::new(&instance.entry, &instance);
@@ -53,74 +94,68 @@ impl Surface {
         //
         //  Guaranteed by Surface retaining a reference to this Instance
         let surface_handle = unsafe { 
-            ash_window::create_surface(&instance.entry, &instance, raw_handle.display_handle, raw_handle.window_handle, None)
-                .expect("Surface::new: Surface creation failed")
+            ash_window::create_surface(&instance.entry, &instance, raw_handle.display_handle, raw_handle.window_handle, None)?
         };
 
         // Create semaphore to sync swapchain image acquisition
         let create_info = vk::SemaphoreCreateInfo::builder();
         let swapchain_semaphore = match unsafe { device.create_semaphore(&create_info, None) } {
             Ok(result) => result,
-            Err(error) => panic!("Surface::new: {}", error.to_string())
+            Err(error) => {
+                unsafe { surface.destroy_surface(surface_handle, None) };
+                return Err(error.into());
+            }
         };
 
         let frame_data: Vec<FrameData> = vec![];
 
-        Self {
+        Ok(Self {
             device,
             graphics_queue,
+            present_queue,
             surface,
             surface_handle,
             swapchain: None,
             swapchain_semaphore,
+            preferred_format: None,
+            format_selector: None,
+            requested_present_mode: PresentMode::Fifo,
+            next_present_id: 0,
+            present_stats: None,
             frame_index: 0,
             frame_data
-        }
+        })
     }
 
-    // TODO: refactor to more elegantly handle errors
-    pub fn configure(&mut self, present_mode: PresentMode, extent: vk::Extent2D) {
-        // Drop any existing swapchain
-        self.swapchain = None;
+    /// Create the surface's swapchain, retiring any existing one, for `present_mode` and `extent`.
+    /// 
+    /// On failure the surface is left without a swapchain until configured successfully.
+    pub fn configure(&mut self, present_mode: PresentMode, extent: vk::Extent2D) -> Result<()> {
+        // Retire any existing swapchain into the new one, letting the presentation engine reuse its resources
+        let old_swapchain = self.swapchain.take();
+        let old_swapchain_handle = old_swapchain.as_ref().map_or(vk::SwapchainKHR::null(), |swapchain| swapchain.borrow().handle);
 
         // Check swapchain support
-        let capabilities = match unsafe { self.surface.get_physical_device_surface_capabilities(self.device.physical_device, self.surface_handle) } {
-            Ok(result) => result,
-            Err(error) => panic!("Surface::configure: {}", error.to_string())
-        };
-        let formats = match unsafe { self.surface.get_physical_device_surface_formats(self.device.physical_device, self.surface_handle) } {
-            Ok(result) => result,
-            Err(error) => panic!("Surface::configure: {}", error.to_string())
-        };
-        let present_modes = match unsafe { self.surface.get_physical_device_surface_present_modes(self.device.physical_device, self.surface_handle) } {
-            Ok(result) => result,
-            Err(error) => panic!("Surface::configure: {}", error.to_string())
-        };
+        let capabilities = unsafe { self.surface.get_physical_device_surface_capabilities(self.device.physical_device, self.surface_handle) }
+            .context("Surface::configure: ")?;
+        let present_modes = unsafe { self.surface.get_physical_device_surface_present_modes(self.device.physical_device, self.surface_handle) }
+            .context("Surface::configure: ")?;
 
-        if formats.is_empty() || present_modes.is_empty() {
-            panic!("Surface::configure: {}", "Presentation to this window not supported by this device".to_string())
+        if present_modes.is_empty() {
+            bail!("Surface::configure: Presentation to this window not supported by this device");
         }
         
         // Get swapchain parameters
-        let selected_format = *formats.iter().find(|format| {
-            match (format.format, format.color_space) {
-                (vk::Format::R8G8B8A8_SRGB, vk::ColorSpaceKHR::SRGB_NONLINEAR) => true,
-                _ => false
-            }
-        })
-        .or_else(|| {
-            Some(&formats[0])
-        })
-        .unwrap();
-        let present_mode = match present_mode {
-            PresentMode::Fifo => vk::PresentModeKHR::FIFO,
-            PresentMode::Mailbox => vk::PresentModeKHR::MAILBOX,
-            PresentMode::Immediate => vk::PresentModeKHR::IMMEDIATE,
-            PresentMode::AutoVsync => vk::PresentModeKHR::FIFO,
-            PresentMode::AutoNoVsync => vk::PresentModeKHR::MAILBOX,
-        };
+        let selected_format = self.select_format().context("Surface::configure: ")?;
+        self.requested_present_mode = present_mode;
+        let present_mode = select_present_mode(present_mode, &present_modes);
+        // The surface decides the extent unless it reports the sentinel, then `extent` is used within supported bounds.
+        // Fixed render resolutions independent of the window are rendered offscreen and scaled to the swapchain instead.
         let surface_extent = match capabilities.current_extent.width {
-            u32::MAX => extent,
+            u32::MAX => vk::Extent2D {
+                width: extent.width.clamp(capabilities.min_image_extent.width, capabilities.max_image_extent.width),
+                height: extent.height.clamp(capabilities.min_image_extent.height, capabilities.max_image_extent.height)
+            },
             _ => capabilities.current_extent
         };
         let image_count = match capabilities.max_image_count > 0 && capabilities.max_image_count < capabilities.min_image_count + 1 {
@@ -129,32 +164,55 @@ impl Surface {
         };
 
         // Create swapchain
-        let swapchain = match Swapchain::new(self.device.clone(), self.surface_handle, selected_format, present_mode, surface_extent, capabilities.current_transform, image_count) {
-            Ok(result) => result,
-            Err(error) => panic!("Surface::configure: {}", error.to_string())
-        };
+        let swapchain = Swapchain::new(self.device.clone(), self.surface_handle, selected_format, present_mode, surface_extent, capabilities.current_transform, image_count, old_swapchain_handle)
+            .context("Surface::configure: ")?;
+        // Dropping the old swapchain waits for in-flight frames still using its images
+        drop(old_swapchain);
 
         // Create frame data for frame-in-flight pipelining
         self.frame_data.clear();
         for _ in 0..swapchain.image_count() {
-            self.frame_data.push(FrameData::new(self.device.clone()).expect("Surface::new: FrameData creation failed"));
+            self.frame_data.push(FrameData::new(self.device.clone()).context("Surface::configure: FrameData creation failed")?);
         }
 
         self.swapchain = Some(RefCell::new(swapchain));
+
+        Ok(())
     }
 
 
 
-    pub fn begin_rendering(&mut self) -> Result<vk::CommandBuffer> {
-        let Some(swapchain) = &self.swapchain else {
+    /// Set the swapchain format to use when available, e.g. to match the color target format of existing pipelines.
+    /// 
+    /// Takes effect on the next [`Surface::configure`].
+    pub fn set_preferred_format(&mut self, format: vk::Format) {
+        self.preferred_format = Some(format);
+    }
+
+    /// Select swapchain formats with `selector` instead of the preferred and [`DEFAULT_FORMATS`], e.g. for HDR color spaces.
+    /// 
+    /// The highest scoring format is selected, and configuration fails if it rejects every format. Takes effect on the next [`Surface::configure`].
+    pub fn set_format_selector(&mut self, selector: FormatSelector) {
+        self.format_selector = Some(selector);
+    }
+
+    /// Select a swapchain format supported by this surface, with the format selector if set, see [`select_surface_format`].
+    pub fn select_format(&self) -> Result<vk::SurfaceFormatKHR> {
+        let formats = unsafe { self.surface.get_physical_device_surface_formats(self.device.physical_device, self.surface_handle)? };
+        match &self.format_selector {
+            Some(selector) => select_surface_format_with(&formats, |format| selector(format)),
+            None => select_surface_format(&formats, self.preferred_format)
+        }
+    }
+
+    /// Begin recording the current frame's command buffer, without rendering to the swapchain image.
+    /// 
+    /// Used to render offscreen before [`Surface::blit_to_swapchain`], ending with [`Surface::end_frame`].
+    pub fn begin_frame(&mut self) -> Result<vk::CommandBuffer> {
+        if self.swapchain.is_none() {
             bail!("Surface has no swapchain!");
-        };
-        let swapchain = swapchain.borrow();
+        }
 
-        let extent = swapchain.image_extent;
-        let render_target = &swapchain.images[self.frame_index];
-        let depth_target = &swapchain.depth_images[self.frame_index];
-        
         // Get current frame data
         let frame_data = &self.frame_data[self.frame_index];
 
@@ -170,19 +228,47 @@ impl Surface {
             let begin_info = vk::CommandBufferBeginInfo::builder()
                 .flags(vk::CommandBufferUsageFlags::ONE_TIME_SUBMIT);
             self.device.begin_command_buffer(frame_data.command_buffer, &begin_info)?;
+        }
+
+        Ok(frame_data.command_buffer)
+    }
+
+    pub fn begin_rendering(&mut self) -> Result<vk::CommandBuffer> {
+        self.begin_frame()?;
+
+        let Some(swapchain) = &self.swapchain else {
+            bail!("Surface has no swapchain!");
+        };
+        let swapchain = swapchain.borrow();
+
+        let extent = swapchain.image_extent;
+        let render_target = &swapchain.images[self.frame_index];
+        let depth_target = &swapchain.depth_images[self.frame_index];
+        
+        // Get current frame data
+        let frame_data = &self.frame_data[self.frame_index];
 
-            // Transition attachments layouts to optimal
+        unsafe {
+            // Transition attachments layouts to optimal, with separate depth and stencil layouts
             self.device.transition_image_layout(
                 frame_data.command_buffer, 
                 render_target, 
                 vk::ImageLayout::UNDEFINED, 
                 vk::ImageLayout::COLOR_ATTACHMENT_OPTIMAL
             );
-            self.device.transition_image_layout(
+            self.device.transition_image_aspect_layout(
+                frame_data.command_buffer,
+                depth_target,
+                vk::ImageAspectFlags::DEPTH,
+                vk::ImageLayout::UNDEFINED,
+                vk::ImageLayout::DEPTH_ATTACHMENT_OPTIMAL
+            );
+            self.device.transition_image_aspect_layout(
                 frame_data.command_buffer,
                 depth_target,
+                vk::ImageAspectFlags::STENCIL,
                 vk::ImageLayout::UNDEFINED,
-                vk::ImageLayout::DEPTH_STENCIL_ATTACHMENT_OPTIMAL
+                vk::ImageLayout::STENCIL_ATTACHMENT_OPTIMAL
             );
 
             // Begin rendering
@@ -196,12 +282,20 @@ impl Surface {
                 });
             let depth_attachment_info = vk::RenderingAttachmentInfo::builder()
                 .image_view(depth_target.image_view)
-                .image_layout(vk::ImageLayout::DEPTH_STENCIL_ATTACHMENT_OPTIMAL)
+                .image_layout(vk::ImageLayout::DEPTH_ATTACHMENT_OPTIMAL)
                 .load_op(vk::AttachmentLoadOp::CLEAR)
                 .store_op(vk::AttachmentStoreOp::STORE)
                 .clear_value(vk::ClearValue {
                     depth_stencil: vk::ClearDepthStencilValue { depth: 0.0, stencil: 0 }
                 });
+            let stencil_attachment_info = vk::RenderingAttachmentInfo::builder()
+                .image_view(depth_target.image_view)
+                .image_layout(vk::ImageLayout::STENCIL_ATTACHMENT_OPTIMAL)
+                .load_op(vk::AttachmentLoadOp::CLEAR)
+                .store_op(vk::AttachmentStoreOp::DONT_CARE)
+                .clear_value(vk::ClearValue {
+                    depth_stencil: vk::ClearDepthStencilValue { depth: 0.0, stencil: 0 }
+                });
             let rendering_info = vk::RenderingInfo::builder()
                 .render_area(vk::Rect2D::builder()
                     // Leave offset default
@@ -210,7 +304,8 @@ impl Surface {
                 )
                 .layer_count(1)
                 .color_attachments(slice::from_ref(&color_attachment_info))
-                .depth_attachment(&depth_attachment_info);
+                .depth_attachment(&depth_attachment_info)
+                .stencil_attachment(&stencil_attachment_info);
                 
             self.device.cmd_begin_rendering(frame_data.command_buffer, &rendering_info);
         }
@@ -218,7 +313,155 @@ impl Surface {
         Ok(frame_data.command_buffer)
     }
 
+    /// Blit `source` to the current swapchain image, scaling it to the swapchain extent.
+    /// 
+    /// `source` must be in `TRANSFER_SRC_OPTIMAL` layout. The swapchain image is left in `COLOR_ATTACHMENT_OPTIMAL`
+    /// layout, allowing further rendering on top before [`Surface::end_frame`].
+    pub fn blit_to_swapchain(&self, command_buffer: vk::CommandBuffer, source: &Image) -> Result<()> {
+        let Some(swapchain) = &self.swapchain else {
+            bail!("Surface has no swapchain!");
+        };
+        let swapchain = swapchain.borrow();
+
+        let render_target = &swapchain.images[self.frame_index];
+        let source_extent = source.extent();
+        let target_extent = swapchain.image_extent;
+
+        let subresource = vk::ImageSubresourceLayers::builder()
+            .aspect_mask(vk::ImageAspectFlags::COLOR)
+            .mip_level(0)
+            .base_array_layer(0)
+            .layer_count(1)
+            .build();
+        let region = vk::ImageBlit::builder()
+            .src_subresource(subresource)
+            .src_offsets([
+                vk::Offset3D::default(),
+                vk::Offset3D { x: source_extent.width as i32, y: source_extent.height as i32, z: 1 }
+            ])
+            .dst_subresource(subresource)
+            .dst_offsets([
+                vk::Offset3D::default(),
+                vk::Offset3D { x: target_extent.width as i32, y: target_extent.height as i32, z: 1 }
+            ]);
+
+        unsafe {
+            self.device.transition_image_layout(
+                command_buffer,
+                render_target,
+                vk::ImageLayout::UNDEFINED,
+                vk::ImageLayout::TRANSFER_DST_OPTIMAL
+            );
+            self.device.cmd_blit_image(
+                command_buffer,
+                source.image,
+                vk::ImageLayout::TRANSFER_SRC_OPTIMAL,
+                render_target.image,
+                vk::ImageLayout::TRANSFER_DST_OPTIMAL,
+                slice::from_ref(&region),
+                vk::Filter::LINEAR
+            );
+            self.device.transition_image_layout(
+                command_buffer,
+                render_target,
+                vk::ImageLayout::TRANSFER_DST_OPTIMAL,
+                vk::ImageLayout::COLOR_ATTACHMENT_OPTIMAL
+            );
+        }
+
+        Ok(())
+    }
+
+    /// Copy the current swapchain image to `destination`, e.g. a history image for temporal effects, scaling it to fit.
+    /// 
+    /// Record after rendering has ended and before [`Surface::end_frame`], so the frame is complete. The swapchain image
+    /// must be in `COLOR_ATTACHMENT_OPTIMAL` layout and stays in it. `destination` is left in `SHADER_READ_ONLY_OPTIMAL`
+    /// layout, ready for sampling by following frames on the graphics queue.
+    pub fn copy_to_image(&self, command_buffer: vk::CommandBuffer, destination: &Image) -> Result<()> {
+        let Some(swapchain) = &self.swapchain else {
+            bail!("Surface has no swapchain!");
+        };
+        let swapchain = swapchain.borrow();
+
+        let render_target = &swapchain.images[self.frame_index];
+        let source_extent = swapchain.image_extent;
+        let target_extent = destination.extent();
+
+        let subresource = vk::ImageSubresourceLayers::builder()
+            .aspect_mask(vk::ImageAspectFlags::COLOR)
+            .mip_level(0)
+            .base_array_layer(0)
+            .layer_count(1)
+            .build();
+        let region = vk::ImageBlit::builder()
+            .src_subresource(subresource)
+            .src_offsets([
+                vk::Offset3D::default(),
+                vk::Offset3D { x: source_extent.width as i32, y: source_extent.height as i32, z: 1 }
+            ])
+            .dst_subresource(subresource)
+            .dst_offsets([
+                vk::Offset3D::default(),
+                vk::Offset3D { x: target_extent.width as i32, y: target_extent.height as i32, z: 1 }
+            ]);
+
+        unsafe {
+            self.device.transition_image_layout(
+                command_buffer,
+                render_target,
+                vk::ImageLayout::COLOR_ATTACHMENT_OPTIMAL,
+                vk::ImageLayout::TRANSFER_SRC_OPTIMAL
+            );
+            self.device.transition_image_layout(
+                command_buffer,
+                destination,
+                vk::ImageLayout::UNDEFINED,
+                vk::ImageLayout::TRANSFER_DST_OPTIMAL
+            );
+            self.device.cmd_blit_image(
+                command_buffer,
+                render_target.image,
+                vk::ImageLayout::TRANSFER_SRC_OPTIMAL,
+                destination.image,
+                vk::ImageLayout::TRANSFER_DST_OPTIMAL,
+                slice::from_ref(&region),
+                vk::Filter::LINEAR
+            );
+            self.device.transition_image_layout(
+                command_buffer,
+                destination,
+                vk::ImageLayout::TRANSFER_DST_OPTIMAL,
+                vk::ImageLayout::SHADER_READ_ONLY_OPTIMAL
+            );
+            self.device.transition_image_layout(
+                command_buffer,
+                render_target,
+                vk::ImageLayout::TRANSFER_SRC_OPTIMAL,
+                vk::ImageLayout::COLOR_ATTACHMENT_OPTIMAL
+            );
+        }
+
+        Ok(())
+    }
+
     pub fn end_rendering(&self) -> Result<()> {
+        // End rendering
+        unsafe { self.device.cmd_end_rendering(self.frame_data[self.frame_index].command_buffer) };
+
+        self.end_frame()
+    }
+
+    /// End rendering, copy the frame to `history` with [`Surface::copy_to_image`], then end the frame.
+    pub fn end_rendering_with_history(&self, history: &Image) -> Result<()> {
+        let command_buffer = self.frame_data[self.frame_index].command_buffer;
+        unsafe { self.device.cmd_end_rendering(command_buffer) };
+
+        self.copy_to_image(command_buffer, history)?;
+        self.end_frame()
+    }
+
+    /// Prepare the current swapchain image for presentation, then end recording and submit the frame.
+    pub fn end_frame(&self) -> Result<()> {
         let Some(swapchain) = &self.swapchain else {
             bail!("Surface has no swapchain!");
         };
@@ -230,35 +473,99 @@ impl Surface {
         let frame_data = &self.frame_data[self.frame_index];
 
         unsafe {
-            // End rendering
-            self.device.cmd_end_rendering(frame_data.command_buffer);
-
-            // Transition attachments layouts to optimal
-            self.device.transition_image_layout(
-                frame_data.command_buffer, 
-                render_target, 
-                vk::ImageLayout::COLOR_ATTACHMENT_OPTIMAL, 
-                vk::ImageLayout::PRESENT_SRC_KHR
-            );
+            match &frame_data.present_transfer {
+                // Transition attachments layouts to optimal
+                None => self.device.transition_image_layout(
+                    frame_data.command_buffer, 
+                    render_target, 
+                    vk::ImageLayout::COLOR_ATTACHMENT_OPTIMAL, 
+                    vk::ImageLayout::PRESENT_SRC_KHR
+                ),
+                // Release render target to present queue family
+                Some(_) => self.present_ownership_barrier(frame_data.command_buffer, render_target, true)
+            };
 
             // End command recording
             self.device.end_command_buffer(frame_data.command_buffer)?;
 
             // Submit command buffer
+            let graphics_fence = match frame_data.present_transfer {
+                Some(_) => vk::Fence::null(),
+                None => frame_data.in_flight_fence
+            };
             let submit_infos = &[
                 vk::SubmitInfo::builder()
-                    .wait_dst_stage_mask(slice::from_ref(&vk::PipelineStageFlags::COLOR_ATTACHMENT_OUTPUT))
+                    .wait_dst_stage_mask(slice::from_ref(&(vk::PipelineStageFlags::COLOR_ATTACHMENT_OUTPUT | vk::PipelineStageFlags::TRANSFER)))
                     .wait_semaphores(slice::from_ref(&self.swapchain_semaphore))
                     .signal_semaphores(slice::from_ref(&frame_data.render_semaphore))
                     .command_buffers(slice::from_ref(&frame_data.command_buffer))
                     .build()
             ];
-            self.device.queue_submit(self.graphics_queue, submit_infos, frame_data.in_flight_fence)?
+            self.device.submit(self.graphics_queue, submit_infos, graphics_fence)?;
+
+            // Acquire render target on present queue family
+            if let Some(present_transfer) = &frame_data.present_transfer {
+                let command_buffer = present_transfer.command_buffer;
+                self.device.reset_command_buffer(command_buffer, vk::CommandBufferResetFlags::empty())?;
+
+                let begin_info = vk::CommandBufferBeginInfo::builder()
+                    .flags(vk::CommandBufferUsageFlags::ONE_TIME_SUBMIT);
+                self.device.begin_command_buffer(command_buffer, &begin_info)?;
+                self.present_ownership_barrier(command_buffer, render_target, false);
+                self.device.end_command_buffer(command_buffer)?;
+
+                // The in-flight fence is signaled here, as this submission completes after the graphics submission
+                let submit_infos = &[
+                    vk::SubmitInfo::builder()
+                        .wait_dst_stage_mask(slice::from_ref(&vk::PipelineStageFlags::ALL_COMMANDS))
+                        .wait_semaphores(slice::from_ref(&frame_data.render_semaphore))
+                        .signal_semaphores(slice::from_ref(&present_transfer.semaphore))
+                        .command_buffers(slice::from_ref(&command_buffer))
+                        .build()
+                ];
+                self.device.submit(self.present_queue, submit_infos, frame_data.in_flight_fence)?;
+            }
         }
 
         Ok(())
     }
 
+    /// Record the release (graphics) or acquire (present) half of a render target queue family ownership transfer.
+    fn present_ownership_barrier(&self, command_buffer: vk::CommandBuffer, render_target: &Image, release: bool) {
+        let (src_access_mask, src_stage_mask) = match release {
+            true => (vk::AccessFlags::COLOR_ATTACHMENT_WRITE, vk::PipelineStageFlags::COLOR_ATTACHMENT_OUTPUT),
+            false => (vk::AccessFlags::empty(), vk::PipelineStageFlags::TOP_OF_PIPE)
+        };
+
+        let image_barrier = vk::ImageMemoryBarrier::builder()
+            .old_layout(vk::ImageLayout::COLOR_ATTACHMENT_OPTIMAL)
+            .new_layout(vk::ImageLayout::PRESENT_SRC_KHR)
+            .src_queue_family_index(self.device.queues.graphics_family)
+            .dst_queue_family_index(self.device.queues.present_family.unwrap_or(vk::QUEUE_FAMILY_IGNORED))
+            .image(render_target.image)
+            .subresource_range(vk::ImageSubresourceRange::builder()
+                .aspect_mask(vk::ImageAspectFlags::COLOR)
+                .base_mip_level(0)
+                .level_count(1)
+                .base_array_layer(0)
+                .layer_count(1)
+                .build()
+            )
+            .src_access_mask(src_access_mask)
+            .dst_access_mask(vk::AccessFlags::empty());
+        unsafe {
+            self.device.cmd_pipeline_barrier(
+                command_buffer,
+                src_stage_mask,
+                vk::PipelineStageFlags::BOTTOM_OF_PIPE,
+                vk::DependencyFlags::empty(),
+                &[],
+                &[],
+                slice::from_ref(&image_barrier)
+            );
+        }
+    }
+
     pub fn extent(&self) -> Result<vk::Extent2D> {
         let Some(swapchain) = &self.swapchain else {
             bail!("Surface has no swapchain!");
@@ -268,31 +575,114 @@ impl Surface {
         Ok(swapchain.image_extent)
     }
 
+    /// Present mode selected by the last [`Surface::configure`], which may be a fallback from the requested mode.
+    pub fn present_mode(&self) -> Result<vk::PresentModeKHR> {
+        let Some(swapchain) = &self.swapchain else {
+            bail!("Surface has no swapchain!");
+        };
+        let swapchain = swapchain.borrow();
+
+        Ok(swapchain.present_mode)
+    }
+
+    /// Present mode requested by the last [`Surface::configure`] or [`Surface::set_vsync`], before any fallback.
+    pub fn requested_present_mode(&self) -> PresentMode {
+        self.requested_present_mode
+    }
+
+    /// Reconfigure the surface with vsync on, presenting with FIFO, or off, presenting with MAILBOX or IMMEDIATE if available.
+    /// 
+    /// The setting is kept when the surface is resized, until its window's present mode changes.
+    pub fn set_vsync(&mut self, vsync: bool) -> Result<()> {
+        let extent = self.extent()?;
+        self.configure(vsync_present_mode(vsync), extent)
+    }
+
+    /// Reconfigure the surface for a new `extent`, e.g. after its window was resized, keeping the requested present mode.
+    /// 
+    /// The old swapchain is retired into the new one, and destroyed once frames in flight are complete.
+    /// Zero extents, e.g. of a minimized window, keep the current swapchain, see [`is_presentable_extent`].
+    pub fn resize(&mut self, extent: vk::Extent2D) -> Result<()> {
+        if !is_presentable_extent(extent) {
+            return Ok(());
+        }
+
+        self.configure(self.requested_present_mode, extent)
+    }
+
+    pub fn format(&self) -> Result<vk::Format> {
+        let Some(swapchain) = &self.swapchain else {
+            bail!("Surface has no swapchain!");
+        };
+        let swapchain = swapchain.borrow();
+
+        Ok(swapchain.image_format)
+    }
+
+    /// Present timing of the most recently displayed frame, or `None` without the
+    /// [`display_timing`](crate::device::DeviceCapabilities::display_timing) capability or before any frame was displayed.
+    /// 
+    /// The presentation engine reports timings some frames after presenting, so these lag the current frame.
+    pub fn present_stats(&mut self) -> Result<Option<PresentStats>> {
+        let (Some(display_timing), Some(swapchain)) = (self.device.display_timing.as_ref(), &self.swapchain) else {
+            return Ok(None);
+        };
+
+        // Each timing is reported once, so the last stats are kept until a newer frame is reported
+        let timings = unsafe { display_timing.get_past_presentation_timing(swapchain.borrow().handle) }
+            .context("Surface::present_stats: ")?;
+        if let Some(stats) = latest_present_stats(&timings) {
+            self.present_stats = Some(stats);
+        }
+
+        Ok(self.present_stats)
+    }
+
     pub fn frame_count(&self) -> usize {
         self.frame_data.len()
     }
 
+    pub fn frame_index(&self) -> usize {
+        self.frame_index
+    }
+
+    /// Fence signaled when the current frame's submitted commands complete.
+    pub fn frame_fence(&self) -> vk::Fence {
+        self.frame_data[self.frame_index].in_flight_fence
+    }
+
+    /// Fences of all frames, which are destroyed when the surface is reconfigured or dropped.
+    pub fn frame_fences(&self) -> Vec<vk::Fence> {
+        self.frame_data.iter().map(|frame_data| frame_data.in_flight_fence).collect()
+    }
+
     pub fn frame_data(&self) -> &FrameData {
         &self.frame_data[self.frame_index]
     }
 
     // Wrap Vulkan methods
 
-    pub fn acquire_next_image(&mut self, timeout: u64) -> Result<bool> {
+    pub fn acquire_next_image(&mut self, timeout: u64) -> Result<SwapchainStatus> {
         let Some(swapchain) = &self.swapchain else {
             bail!("Surface has no swapchain!");
         };
         let swapchain = swapchain.borrow();
         
-        unsafe {
-            let (index, suboptimal) = swapchain.acquire_next_image(swapchain.handle, timeout, self.swapchain_semaphore, vk::Fence::null())?;
-            self.frame_index = index as usize;
-
-            Ok(suboptimal)
+        match unsafe { swapchain.acquire_next_image(swapchain.handle, timeout, self.swapchain_semaphore, vk::Fence::null()) } {
+            Ok((index, suboptimal)) => {
+                self.frame_index = index as usize;
+
+                match suboptimal {
+                    true => Ok(SwapchainStatus::Suboptimal),
+                    false => Ok(SwapchainStatus::Optimal)
+                }
+            },
+            Err(vk::Result::ERROR_OUT_OF_DATE_KHR) => Ok(SwapchainStatus::OutOfDate),
+            Err(error) => Err(error.into())
         }
     }
 
-    pub fn queue_present(&mut self) -> Result<bool> {
+    pub fn queue_present(&mut self) -> Result<SwapchainStatus> {
         let frame_data = &self.frame_data[self.frame_index];
 
         let Some(swapchain) = &self.swapchain else {
@@ -301,16 +691,35 @@ impl Surface {
         let swapchain = swapchain.borrow();
 
         let indices = &[self.frame_index as u32];
-        let present_info = &vk::PresentInfoKHR::builder()
+        let present_semaphore = frame_data.present_semaphore();
+        let mut present_info = vk::PresentInfoKHR::builder()
             .swapchains(slice::from_ref(&swapchain.handle))
-            .wait_semaphores(slice::from_ref(&frame_data.render_semaphore))
+            .wait_semaphores(slice::from_ref(&present_semaphore))
             .image_indices(indices);
 
-        unsafe {
-            swapchain.queue_present(self.graphics_queue, present_info)?;
+        // Timings are only reported for presents with an id, displayed as soon as possible
+        let present_times = [vk::PresentTimeGOOGLE {
+            present_id: self.next_present_id,
+            desired_present_time: 0
+        }];
+        let mut present_times_info = vk::PresentTimesInfoGOOGLE::builder()
+            .times(&present_times);
+        if self.device.display_timing.is_some() {
+            present_info = present_info.push_next(&mut present_times_info);
+            self.next_present_id = self.next_present_id.wrapping_add(1);
         }
 
-        Ok(false)
+        // The present queue may be shared with other roles, see Device::submit
+        let queues = self.device.lock_queues();
+        let result = unsafe { swapchain.queue_present(self.present_queue, &present_info) };
+        drop(queues);
+
+        match result {
+            Ok(false) => Ok(SwapchainStatus::Optimal),
+            Ok(true) => Ok(SwapchainStatus::Suboptimal),
+            Err(vk::Result::ERROR_OUT_OF_DATE_KHR) => Ok(SwapchainStatus::OutOfDate),
+            Err(error) => Err(error.into())
+        }
     }
 }
 
@@ -321,7 +730,7 @@ impl Drop for Surface {
 
         info!("Dropping Surface!");
         unsafe {
-            self.device.device_wait_idle().unwrap();
+            self.device.wait_idle().unwrap();
             
             self.device.destroy_semaphore(self.swapchain_semaphore, None);
 
@@ -331,3 +740,189 @@ impl Drop for Surface {
 }
 
 
+/// Stats of the most recently presented frame among past presentation `timings`, by present id.
+fn latest_present_stats(timings: &[vk::PastPresentationTimingGOOGLE]) -> Option<PresentStats> {
+    timings
+        .iter()
+        .max_by_key(|timing| timing.present_id)
+        .map(|timing| PresentStats {
+            actual_present_time: timing.actual_present_time,
+            earliest_present_time: timing.earliest_present_time
+        })
+}
+
+/// Whether a swapchain can be created for `extent`, which is zero sized for minimized windows.
+pub fn is_presentable_extent(extent: vk::Extent2D) -> bool {
+    extent.width > 0 && extent.height > 0
+}
+
+/// Present mode requested for vsync on, or off.
+pub fn vsync_present_mode(vsync: bool) -> PresentMode {
+    match vsync {
+        true => PresentMode::AutoVsync,
+        false => PresentMode::AutoNoVsync
+    }
+}
+
+/// Select the first of the Vulkan present modes matching `present_mode` in the surface's `available` modes,
+/// falling back on FIFO, which all devices support.
+fn select_present_mode(present_mode: PresentMode, available: &[vk::PresentModeKHR]) -> vk::PresentModeKHR {
+    let requested_modes: &[vk::PresentModeKHR] = match present_mode {
+        PresentMode::Fifo | PresentMode::AutoVsync => &[vk::PresentModeKHR::FIFO],
+        PresentMode::Mailbox => &[vk::PresentModeKHR::MAILBOX],
+        PresentMode::Immediate => &[vk::PresentModeKHR::IMMEDIATE],
+        PresentMode::AutoNoVsync => &[vk::PresentModeKHR::MAILBOX, vk::PresentModeKHR::IMMEDIATE],
+    };
+    match requested_modes.iter().find(|mode| available.contains(mode)) {
+        Some(&mode) => mode,
+        None => {
+            warn!("Surface::configure: Present modes {:?} unavailable, falling back to FIFO", requested_modes);
+            vk::PresentModeKHR::FIFO
+        }
+    }
+}
+
+/// Select a swapchain format from the surface's `formats`.
+/// 
+/// Selects the `preferred` format if available, otherwise the first available of [`DEFAULT_FORMATS`], in the SRGB_NONLINEAR color space.
+/// Fails listing the available formats if neither is available, rather than pick one the pipelines may not be compatible with.
+pub fn select_surface_format(formats: &[vk::SurfaceFormatKHR], preferred: Option<vk::Format>) -> Result<vk::SurfaceFormatKHR> {
+    let selected = select_surface_format_with(formats, |format| {
+        if format.color_space != vk::ColorSpaceKHR::SRGB_NONLINEAR {
+            return None;
+        }
+        match Some(format.format) == preferred {
+            true => Some(u32::MAX),
+            false => DEFAULT_FORMATS.iter().position(|&default| default == format.format).map(|index| (DEFAULT_FORMATS.len() - index) as u32)
+        }
+    })?;
+
+    if let Some(preferred) = preferred.filter(|&preferred| preferred != selected.format) {
+        warn!("Preferred surface format {:?} is unavailable, selected {:?}", preferred, selected.format);
+    }
+
+    Ok(selected)
+}
+
+/// Select the highest scoring of the surface's `formats` by `selector`, the first of equally scored formats.
+/// 
+/// Fails listing the available formats if `selector` rejects them all.
+pub fn select_surface_format_with(
+    formats: &[vk::SurfaceFormatKHR],
+    selector: impl Fn(&vk::SurfaceFormatKHR) -> Option<u32>
+) -> Result<vk::SurfaceFormatKHR> {
+    if formats.is_empty() {
+        bail!("Presentation to this window not supported by this device");
+    }
+
+    formats.iter()
+        .rev()
+        .filter_map(|format| selector(format).map(|score| (score, *format)))
+        .max_by_key(|(score, _)| *score)
+        .map(|(_, format)| format)
+        .ok_or_else(|| {
+            let available: Vec<String> = formats
+                .iter()
+                .map(|format| format!("{:?} ({:?})", format.format, format.color_space))
+                .collect();
+            anyhow!("No suitable surface format, available: {}", available.join(", "))
+        })
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn format(format: vk::Format, color_space: vk::ColorSpaceKHR) -> vk::SurfaceFormatKHR {
+        vk::SurfaceFormatKHR { format, color_space }
+    }
+
+    const FORMATS: [vk::SurfaceFormatKHR; 3] = [
+        vk::SurfaceFormatKHR { format: vk::Format::R8G8B8A8_SRGB, color_space: vk::ColorSpaceKHR::SRGB_NONLINEAR },
+        vk::SurfaceFormatKHR { format: vk::Format::A2B10G10R10_UNORM_PACK32, color_space: vk::ColorSpaceKHR::HDR10_ST2084_EXT },
+        vk::SurfaceFormatKHR { format: vk::Format::B8G8R8A8_SRGB, color_space: vk::ColorSpaceKHR::SRGB_NONLINEAR },
+    ];
+
+    #[test]
+    fn minimized_extents_are_not_presentable() {
+        assert!(is_presentable_extent(vk::Extent2D { width: 1, height: 1 }));
+        assert!(!is_presentable_extent(vk::Extent2D { width: 0, height: 720 }));
+        assert!(!is_presentable_extent(vk::Extent2D { width: 1280, height: 0 }));
+    }
+
+    #[test]
+    fn vsync_toggles_present_mode() {
+        let all = [vk::PresentModeKHR::FIFO, vk::PresentModeKHR::MAILBOX, vk::PresentModeKHR::IMMEDIATE];
+        assert_eq!(select_present_mode(vsync_present_mode(true), &all), vk::PresentModeKHR::FIFO);
+        assert_eq!(select_present_mode(vsync_present_mode(false), &all), vk::PresentModeKHR::MAILBOX);
+
+        // Vsync off falls back from MAILBOX to IMMEDIATE, then FIFO
+        let no_mailbox = [vk::PresentModeKHR::FIFO, vk::PresentModeKHR::IMMEDIATE];
+        assert_eq!(select_present_mode(vsync_present_mode(false), &no_mailbox), vk::PresentModeKHR::IMMEDIATE);
+        assert_eq!(select_present_mode(vsync_present_mode(false), &[vk::PresentModeKHR::FIFO]), vk::PresentModeKHR::FIFO);
+    }
+
+    #[test]
+    fn explicit_present_modes_fall_back_to_fifo() {
+        let fifo_only = [vk::PresentModeKHR::FIFO];
+        for present_mode in [PresentMode::Fifo, PresentMode::Mailbox, PresentMode::Immediate] {
+            assert_eq!(select_present_mode(present_mode, &fifo_only), vk::PresentModeKHR::FIFO);
+        }
+        let available = [vk::PresentModeKHR::FIFO, vk::PresentModeKHR::IMMEDIATE];
+        assert_eq!(select_present_mode(PresentMode::Immediate, &available), vk::PresentModeKHR::IMMEDIATE);
+        assert_eq!(select_present_mode(PresentMode::Mailbox, &available), vk::PresentModeKHR::FIFO);
+    }
+
+    #[test]
+    fn preferred_then_default_formats() {
+        assert_eq!(select_surface_format(&FORMATS, None).unwrap(), FORMATS[2]);
+        assert_eq!(select_surface_format(&FORMATS, Some(vk::Format::R8G8B8A8_SRGB)).unwrap(), FORMATS[0]);
+        // Unavailable preferred formats fall back to the defaults
+        assert_eq!(select_surface_format(&FORMATS, Some(vk::Format::R16G16B16A16_SFLOAT)).unwrap(), FORMATS[2]);
+        // Preferred formats must also be in the SRGB_NONLINEAR color space
+        assert_eq!(select_surface_format(&FORMATS, Some(vk::Format::A2B10G10R10_UNORM_PACK32)).unwrap(), FORMATS[2]);
+    }
+
+    #[test]
+    fn rejected_formats_are_listed() {
+        let error = select_surface_format_with(&FORMATS, |_| None).unwrap_err().to_string();
+        for candidate in ["R8G8B8A8_SRGB (SRGB_NONLINEAR)", "A2B10G10R10_UNORM_PACK32 (HDR10_ST2084_EXT)", "B8G8R8A8_SRGB (SRGB_NONLINEAR)"] {
+            assert!(error.contains(candidate), "{} missing from {}", candidate, error);
+        }
+
+        let unorm_only = [format(vk::Format::B8G8R8A8_UNORM, vk::ColorSpaceKHR::SRGB_NONLINEAR)];
+        let error = select_surface_format(&unorm_only, None).unwrap_err().to_string();
+        assert!(error.contains("B8G8R8A8_UNORM (SRGB_NONLINEAR)"), "{}", error);
+        assert!(select_surface_format(&[], None).is_err());
+    }
+
+    #[test]
+    fn selector_picks_highest_score() {
+        let hdr = |format: &vk::SurfaceFormatKHR| match format.color_space {
+            vk::ColorSpaceKHR::HDR10_ST2084_EXT => Some(2),
+            _ => Some(1)
+        };
+        assert_eq!(select_surface_format_with(&FORMATS, hdr).unwrap(), FORMATS[1]);
+        // Ties select the first format
+        assert_eq!(select_surface_format_with(&FORMATS, |_| Some(0)).unwrap(), FORMATS[0]);
+    }
+    #[test]
+    fn present_stats_report_the_latest_frame() {
+        let timing = |present_id: u32, actual_present_time: u64| vk::PastPresentationTimingGOOGLE {
+            present_id,
+            desired_present_time: 0,
+            actual_present_time,
+            earliest_present_time: actual_present_time - 16_666_667,
+            present_margin: 0
+        };
+
+        // Timings may be reported out of order
+        let timings = [timing(7, 200_000_000), timing(9, 233_333_333), timing(8, 216_666_667)];
+        assert_eq!(latest_present_stats(&timings), Some(PresentStats {
+            actual_present_time: 233_333_333,
+            earliest_present_time: 216_666_666
+        }));
+        assert_eq!(latest_present_stats(&[]), None);
+    }
+}
@@ -20,11 +20,13 @@ pub(super) struct Swapchain {
 
     pub image_format: vk::Format,
     pub image_extent: vk::Extent2D,
+    pub present_mode: vk::PresentModeKHR,
     pub images: Vec<Image>,
     pub depth_images: Vec<Image>
 }
 
 impl Swapchain {
+    #[allow(clippy::too_many_arguments)]
     pub fn new(
         device: Device,
         surface_handle: vk::SurfaceKHR,
@@ -32,7 +34,8 @@ impl Swapchain {
         present_mode: vk::PresentModeKHR,
         surface_extent: vk::Extent2D,
         surface_transform: vk::SurfaceTransformFlagsKHR,
-        image_count: u32
+        image_count: u32,
+        old_swapchain: vk::SwapchainKHR
     ) -> Result<Self> {
         let create_info = &vk::SwapchainCreateInfoKHR::builder()
             .surface(surface_handle)
@@ -41,12 +44,13 @@ impl Swapchain {
             .image_color_space(selected_format.color_space)
             .image_extent(surface_extent)
             .image_array_layers(1)
-            .image_usage(vk::ImageUsageFlags::COLOR_ATTACHMENT)
+            .image_usage(vk::ImageUsageFlags::COLOR_ATTACHMENT | vk::ImageUsageFlags::TRANSFER_SRC | vk::ImageUsageFlags::TRANSFER_DST)
             .image_sharing_mode(vk::SharingMode::EXCLUSIVE)
             .pre_transform(surface_transform)
             .composite_alpha(vk::CompositeAlphaFlagsKHR::OPAQUE)
             .present_mode(present_mode)
-            .clipped(true);
+            .clipped(true)
+            .old_swapchain(old_swapchain);
 
         let swapchain = khr::Swapchain::new(&device.instance, &device);
         let swapchain_handle = match unsafe { swapchain.create_swapchain(create_info, None) } {
@@ -64,13 +68,16 @@ impl Swapchain {
                 image_type: ImageType::TYPE_2D,
                 image_format: selected_format.format,
                 image_extent: Extent3D { width: surface_extent.width, height: surface_extent.height, depth: 1 },
-                mip_levels: 1,
+                mip_levels: MipLevels::Count(1),
                 array_layers: 1,
                 samples: SampleCountFlags::TYPE_1, // unused
                 tiling: ImageTiling::OPTIMAL,  // unused
                 usage: ImageUsageFlags::COLOR_ATTACHMENT, // unused
                 aspect: ImageAspectFlags::COLOR,
-                memory_location: MemoryLocation::Unknown  // unused
+                memory_location: MemoryLocation::Unknown, // unused
+                transient: false,
+                view_formats: vec![],
+                sharing: Sharing::Exclusive
             };
 
             match Image::from_vk(&device, image, image_info) {
@@ -87,15 +94,18 @@ impl Swapchain {
                 image_type: ImageType::TYPE_2D,
                 image_format: Format::D24_UNORM_S8_UINT,
                 image_extent: Extent3D { width: surface_extent.width, height: surface_extent.height, depth: 1 },
-                mip_levels: 1,
+                mip_levels: MipLevels::Count(1),
                 array_layers: 1,
                 samples: SampleCountFlags::TYPE_1,
                 tiling: ImageTiling::OPTIMAL,
                 usage: ImageUsageFlags::DEPTH_STENCIL_ATTACHMENT,
                 aspect: ImageAspectFlags::DEPTH | ImageAspectFlags::STENCIL,
-                memory_location: MemoryLocation::GpuOnly
+                memory_location: MemoryLocation::GpuOnly,
+                transient: false,
+                view_formats: vec![],
+                sharing: Sharing::Exclusive
             };
-            depth_images.push(device.create_image(format!("Depth Buffer {}", i).as_str(), create_info, None));
+            depth_images.push(device.create_image(format!("Depth Buffer {}", i).as_str(), create_info, None)?);
         }
 
 
@@ -105,6 +115,7 @@ impl Swapchain {
             handle: swapchain_handle,
             image_format: selected_format.format,
             image_extent: surface_extent,
+            present_mode,
             images,
             // image_views,
             depth_images
@@ -129,7 +140,7 @@ impl Drop for Swapchain {
         info!("Dropping Swapchain!");
 
         unsafe {
-            let _ = self.device.device_wait_idle();
+            let _ = self.device.wait_idle();
 
             self.images.clear();
             self.destroy_swapchain(self.handle, None);
@@ -0,0 +1,242 @@
+use crate::device::Device;
+
+use anyhow::{Context, Result};
+use ash::{prelude::VkResult, vk::{self, Handle}};
+use bevy_log::prelude::*;
+
+use std::{
+    ops::Deref,
+    time::Duration
+};
+
+
+/// Semaphore wait semantics for [`Device::wait_for`].
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum WaitMode {
+    /// Wait until all semaphores reach their values
+    All,
+    /// Wait until any semaphore reaches its value
+    Any,
+}
+
+/// A timeline semaphore for user-driven synchronization.
+/// 
+/// Created by calling [`Device::create_timeline_semaphore`].
+pub struct TimelineSemaphore {
+    device: Device,
+    semaphore: vk::Semaphore
+}
+
+impl TimelineSemaphore {
+    /// Current counter value of the semaphore.
+    pub fn value(&self) -> Result<u64> {
+        unsafe {
+            self.device.get_semaphore_counter_value(self.semaphore)
+                .context("TimelineSemaphore::value: ")
+        }
+    }
+
+    /// Signal the semaphore to `value` from the host.
+    pub fn signal(&self, value: u64) -> Result<()> {
+        let signal_info = vk::SemaphoreSignalInfo::builder()
+            .semaphore(self.semaphore)
+            .value(value);
+
+        unsafe {
+            self.device.signal_semaphore(&signal_info)
+                .context("TimelineSemaphore::signal: ")
+        }
+    }
+}
+
+impl Deref for TimelineSemaphore {
+    type Target = vk::Semaphore;
+
+    fn deref(&self) -> &Self::Target {
+        &self.semaphore
+    }
+}
+
+impl Drop for TimelineSemaphore {
+    fn drop(&mut self) {
+        unsafe {
+            // TODO: look into waiting on queue idle instead
+            self.device.wait_idle().unwrap();
+
+            self.device.destroy_semaphore(self.semaphore, None);
+        }
+    }
+}
+
+impl Device {
+    pub fn create_timeline_semaphore(
+        &self,
+        initial_value: u64
+    ) -> Result<TimelineSemaphore> {
+        let mut type_info = vk::SemaphoreTypeCreateInfo::builder()
+            .semaphore_type(vk::SemaphoreType::TIMELINE)
+            .initial_value(initial_value);
+        let create_info = vk::SemaphoreCreateInfo::builder()
+            .push_next(&mut type_info);
+
+        let semaphore = unsafe {
+            self.logical_device.create_semaphore(&create_info, None)
+                .context("Device should create a timeline semaphore")?
+        };
+
+        Ok(TimelineSemaphore {
+            device: self.clone(),
+            semaphore
+        })
+    }
+
+    /// Wait on the host for timeline semaphores to reach their paired values.
+    /// 
+    /// Returns `Ok(false)` if `timeout` elapses first. Waiting for no semaphores returns `Ok(true)` immediately.
+    pub fn wait_for(
+        &self,
+        semaphores: &[(&TimelineSemaphore, u64)],
+        mode: WaitMode,
+        timeout: Duration
+    ) -> Result<bool> {
+        let pairs = semaphores.iter().map(|(semaphore, value)| (semaphore.semaphore, *value));
+        let Some((flags, handles, values)) = semaphore_wait_parameters(pairs, mode) else {
+            return Ok(true);
+        };
+        let wait_info = vk::SemaphoreWaitInfo::builder()
+            .flags(flags)
+            .semaphores(&handles)
+            .values(&values);
+
+        match unsafe { self.wait_semaphores(&wait_info, timeout_nanos(timeout)) } {
+            Ok(_) => Ok(true),
+            Err(vk::Result::TIMEOUT) => Ok(false),
+            Err(error) => Err(error).context("Device::wait_for: ")
+        }
+    }
+
+    /// Wait on the host for all device queues to finish their submitted work, like `device_wait_idle` but bounded.
+    /// 
+    /// Returns `Ok(false)` if `timeout` elapses first, e.g. on a hung GPU, so the app can force exit instead of hanging.
+    /// Fences still pending after a timeout or error are leaked, as they can't be destroyed while in use, see [`idle_wait_outcome`].
+    pub fn wait_idle_timeout(&self, timeout: Duration) -> Result<bool> {
+        let mut queues: Vec<vk::Queue> = vec![];
+        for (family, count) in [
+            (self.queues.graphics_family, self.queues.graphics_count),
+            (self.queues.compute_family, self.queues.compute_count),
+            (self.queues.transfer_family, self.queues.transfer_count),
+        ] {
+            queues.extend((0..count).map(|index| unsafe { self.get_device_queue(family, index) }));
+        }
+        if let Some(present_family) = self.queues.present_family {
+            queues.push(unsafe { self.get_device_queue(present_family, 0) });
+        }
+        queues.sort_unstable_by_key(|queue| queue.as_raw());
+        queues.dedup();
+
+        // An empty submission's fence signals once all previously submitted work on its queue completes
+        let mut fences = Vec::with_capacity(queues.len());
+        let mut submitted = 0;
+        let submission = queues.into_iter().try_for_each(|queue| unsafe {
+            let fence = self.create_fence(&vk::FenceCreateInfo::builder(), None)?;
+            fences.push(fence);
+            self.submit(queue, &[], fence)?;
+            submitted += 1;
+            Ok::<_, vk::Result>(())
+        });
+
+        // Wait on the submitted fences even after a failed submission, so they aren't destroyed while pending
+        let wait = match submitted {
+            0 => Ok(()),
+            _ => unsafe { self.wait_for_fences(&fences[..submitted], true, timeout_nanos(timeout)) }
+        };
+        let (idle, destroy_submitted) = idle_wait_outcome(wait);
+
+        // Fences after the submitted ones were never submitted, so are never pending
+        let pending = match destroy_submitted {
+            true => 0,
+            false => submitted
+        };
+        if pending > 0 {
+            warn!("Leaking {} fences still pending after Device::wait_idle_timeout", pending);
+        }
+        fences[pending..].iter().for_each(|&fence| unsafe { self.destroy_fence(fence, None) });
+
+        submission.context("Device::wait_idle_timeout: ")?;
+        idle.context("Device::wait_idle_timeout: ")
+    }
+}
+
+/// Flags, semaphore handles and paired values of a wait in `mode`, or `None` for a wait on no semaphores,
+/// which completes immediately. Vulkan requires at least one semaphore, and with `Any` an empty wait would never complete.
+fn semaphore_wait_parameters(
+    semaphores: impl IntoIterator<Item = (vk::Semaphore, u64)>,
+    mode: WaitMode
+) -> Option<(vk::SemaphoreWaitFlags, Vec<vk::Semaphore>, Vec<u64>)> {
+    let (handles, values): (Vec<vk::Semaphore>, Vec<u64>) = semaphores.into_iter().unzip();
+    if handles.is_empty() {
+        return None;
+    }
+
+    let flags = match mode {
+        WaitMode::All => vk::SemaphoreWaitFlags::empty(),
+        WaitMode::Any => vk::SemaphoreWaitFlags::ANY
+    };
+    Some((flags, handles, values))
+}
+
+/// Timeout in nanoseconds for Vulkan waits, saturating at `u64::MAX`, which waits indefinitely.
+fn timeout_nanos(timeout: Duration) -> u64 {
+    u64::try_from(timeout.as_nanos()).unwrap_or(u64::MAX)
+}
+
+/// Whether a fence wait that ended with `wait` completed, and whether its fences may be destroyed.
+/// 
+/// Fences are still pending after a timeout, or an error other than device loss, so must be leaked instead.
+fn idle_wait_outcome(wait: VkResult<()>) -> (VkResult<bool>, bool) {
+    match wait {
+        Ok(()) => (Ok(true), true),
+        Err(vk::Result::TIMEOUT) => (Ok(false), false),
+        // Lost devices complete all pending work
+        Err(vk::Result::ERROR_DEVICE_LOST) => (Err(vk::Result::ERROR_DEVICE_LOST), true),
+        Err(error) => (Err(error), false)
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn semaphore_waits_pair_handles_with_values() {
+        let semaphores = [(vk::Semaphore::from_raw(1), 10), (vk::Semaphore::from_raw(2), 20)];
+
+        let (flags, handles, values) = semaphore_wait_parameters(semaphores, WaitMode::All).unwrap();
+        assert_eq!(flags, vk::SemaphoreWaitFlags::empty());
+        assert_eq!(handles, [vk::Semaphore::from_raw(1), vk::Semaphore::from_raw(2)]);
+        assert_eq!(values, [10, 20]);
+
+        let (flags, ..) = semaphore_wait_parameters(semaphores, WaitMode::Any).unwrap();
+        assert_eq!(flags, vk::SemaphoreWaitFlags::ANY);
+    }
+
+    #[test]
+    fn empty_semaphore_waits_complete_immediately() {
+        let semaphores: [(vk::Semaphore, u64); 0] = [];
+        assert!(semaphore_wait_parameters(semaphores, WaitMode::All).is_none());
+        assert!(semaphore_wait_parameters(semaphores, WaitMode::Any).is_none());
+    }
+
+    #[test]
+    fn idle_wait_timeout_leaks_pending_fences() {
+        // A tiny timeout against pending work returns instead of hanging, leaving its fences pending
+        assert_eq!(timeout_nanos(Duration::from_nanos(1)), 1);
+        assert_eq!(idle_wait_outcome(Err(vk::Result::TIMEOUT)), (Ok(false), false));
+
+        assert_eq!(idle_wait_outcome(Ok(())), (Ok(true), true));
+        assert_eq!(idle_wait_outcome(Err(vk::Result::ERROR_DEVICE_LOST)), (Err(vk::Result::ERROR_DEVICE_LOST), true));
+        assert_eq!(idle_wait_outcome(Err(vk::Result::ERROR_OUT_OF_HOST_MEMORY)), (Err(vk::Result::ERROR_OUT_OF_HOST_MEMORY), false));
+        assert_eq!(timeout_nanos(Duration::MAX), u64::MAX);
+    }
+}
@@ -7,10 +7,15 @@ use bevy_asset::{AddAsset};
 
 /// Adds support for Obj file loading to Apps
 #[derive(Default)]
-pub struct ObjPlugin;
+pub struct ObjPlugin {
+    /// Weld loaded mesh vertices within this epsilon, see [`Mesh::weld`](paracosm_render::mesh::Mesh::weld)
+    pub weld_epsilon: Option<f32>,
+}
 
 impl Plugin for ObjPlugin {
     fn build(&self, app: &mut App) {
-        app.init_asset_loader::<ObjLoader>();
+        app.add_asset_loader(ObjLoader {
+            weld_epsilon: self.weld_epsilon
+        });
     }
 }
\ No newline at end of file
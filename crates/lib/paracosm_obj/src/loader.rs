@@ -5,13 +5,16 @@ use bevy_math::prelude::*;
 use bevy_utils::{BoxedFuture, HashMap};
 use obj::raw::{object::Polygon, RawObj};
 use paracosm_render::{
-    mesh::{Mesh, Vertex},
+    mesh::{IndexFormat, Mesh, Submesh, Vertex},
     //render_resource::PrimitiveTopology,
 };
 use thiserror::Error;
 
 #[derive(Default)]
-pub struct ObjLoader;
+pub struct ObjLoader {
+    /// Weld loaded mesh vertices within this epsilon
+    pub weld_epsilon: Option<f32>,
+}
 
 impl AssetLoader for ObjLoader {
     fn load<'a>(
@@ -19,7 +22,7 @@ impl AssetLoader for ObjLoader {
         bytes: &'a [u8],
         load_context: &'a mut bevy_asset::LoadContext,
     ) -> BoxedFuture<'a, Result<(), anyhow::Error>> {
-        Box::pin(async move { Ok(load_obj(bytes, load_context).await?) })
+        Box::pin(async move { Ok(load_obj(bytes, load_context, self.weld_epsilon).await?) })
     }
 
     fn extensions(&self) -> &[&str] {
@@ -39,13 +42,28 @@ pub enum ObjError {
 async fn load_obj<'a, 'b>(
     bytes: &'a [u8],
     load_context: &'a mut LoadContext<'b>,
+    weld_epsilon: Option<f32>,
 ) -> Result<(), ObjError> {
-    let mesh = load_obj_from_bytes(bytes)?;
+    let mut mesh = load_obj_from_bytes(bytes)?;
+    finish_mesh(&mut mesh, weld_epsilon);
+    if let Some(name) = load_context.path().file_stem() {
+        mesh.set_name(&name.to_string_lossy());
+    }
     load_context.set_default_asset(LoadedAsset::new(mesh));
     
     Ok(())
 }
 
+/// Weld `mesh` within `weld_epsilon` if set, then choose its index format
+fn finish_mesh(mesh: &mut Mesh, weld_epsilon: Option<f32>) {
+    if let Some(epsilon) = weld_epsilon {
+        let removed = mesh.weld(epsilon);
+        debug!("Welded {} duplicate vertices, {} remaining", removed, mesh.vertex_count());
+    }
+    // Use 16 bit indices whenever the mesh is small enough
+    mesh.set_index_format(IndexFormat::for_vertex_count(mesh.vertex_count()));
+}
+
 type VertexKey = (usize, usize, usize);
 
 struct MeshIndices {
@@ -94,7 +112,36 @@ pub fn load_obj_from_bytes(bytes: &[u8]) -> Result<Mesh, ObjError> {
 
     let mut vertices = Vec::with_capacity(vertcount);
 
-    for polygon in &raw.polygons {
+    // Group polygons by material, so each material's triangles form one contiguous range of indices
+    let mut polygon_materials: Vec<Option<&str>> = vec![None; raw.polygons.len()];
+    for (material, group) in raw.meshes.iter() {
+        for range in group.polygons.iter() {
+            polygon_materials[range.start..range.end]
+                .iter_mut()
+                .for_each(|polygon_material| *polygon_material = Some(material.as_str()));
+        }
+    }
+    let mut material_order: Vec<Option<&str>> = vec![];
+    for material in polygon_materials.iter() {
+        if !material_order.contains(material) {
+            material_order.push(*material);
+        }
+    }
+    let mut polygon_order: Vec<usize> = (0..raw.polygons.len()).collect();
+    polygon_order.sort_by_key(|&index| material_order.iter().position(|material| *material == polygon_materials[index]));
+
+    let mut submeshes: Vec<Submesh> = vec![];
+    for polygon_index in polygon_order {
+        let polygon = &raw.polygons[polygon_index];
+        let material = polygon_materials[polygon_index];
+        if submeshes.last().map(|submesh| submesh.material.as_deref()) != Some(material) {
+            submeshes.push(Submesh {
+                first_index: indices.indices.len() as u32,
+                index_count: 0,
+                material: material.map(str::to_string)
+            });
+        }
+
         match polygon {
             Polygon::P(poly) if poly.len() == 3 => {
                 let normal = calculate_normal(&raw, poly);
@@ -158,10 +205,19 @@ pub fn load_obj_from_bytes(bytes: &[u8]) -> Result<Mesh, ObjError> {
             }
             _ => return Err(ObjError::NonTriangulatedMesh),
         }
+
+        if let Some(submesh) = submeshes.last_mut() {
+            submesh.index_count += 3;
+        }
     }
 
     debug!("\npoly count: {} \nvertex count: {} \nindex count: {}", raw.polygons.len(), vertices.len(), indices.indices.len());
-    let mesh = Mesh::with_geometry(vertices, indices.indices);
+    let mut mesh = Mesh::with_geometry(vertices, indices.indices);
+    // Meshes without materials are drawn whole
+    if submeshes.iter().any(|submesh| submesh.material.is_some()) {
+        mesh.set_submeshes(submeshes)
+            .expect("Submeshes should cover the loaded indices");
+    }
 
     Ok(mesh)
 }
@@ -196,4 +252,59 @@ fn calculate_normal(raw: &RawObj, polygon: &[usize]) -> Vec3 {
     let n = v1.cross(v2);
 
     n
-}
\ No newline at end of file
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A quad split into two materials, with `red` reopened after `blue`
+    const TWO_MATERIAL_QUAD: &[u8] = b"\
+v 0 0 0
+v 1 0 0
+v 0 1 0
+v 1 1 0
+usemtl red
+f 1 2 3
+usemtl blue
+f 2 4 3
+usemtl red
+f 1 3 4
+";
+
+    #[test]
+    fn two_materials_load_as_two_submeshes() {
+        let mesh = load_obj_from_bytes(TWO_MATERIAL_QUAD).unwrap();
+
+        // Both red triangles are grouped before the blue one
+        assert_eq!(mesh.submeshes(), vec![
+            Submesh { first_index: 0, index_count: 6, material: Some("red".to_string()) },
+            Submesh { first_index: 6, index_count: 3, material: Some("blue".to_string()) }
+        ]);
+        assert_eq!(mesh.index_count(), 9);
+        assert_eq!(mesh.vertex_count(), 4);
+        assert!(mesh.indices().iter().all(|&index| (index as usize) < mesh.vertex_count()));
+
+        // The blue range indexes the blue face's corners, positions 2, 4 and 3
+        assert_eq!(&mesh.indices()[6..9], &[1, 3, 2]);
+    }
+
+    #[test]
+    fn small_meshes_use_16_bit_indices() {
+        let mut mesh = load_obj_from_bytes(TWO_MATERIAL_QUAD).unwrap();
+        finish_mesh(&mut mesh, None);
+        assert_eq!(mesh.index_format(), IndexFormat::U16);
+
+        let vertex = Vertex::new(Vec3::ZERO, Vec3::Z, Vec3::ONE, Vec2::ZERO);
+        let mut mesh = Mesh::with_geometry(vec![vertex; 65536], vec![0, 1, 65535]);
+        finish_mesh(&mut mesh, None);
+        assert_eq!(mesh.index_format(), IndexFormat::U32);
+    }
+
+    #[test]
+    fn meshes_without_materials_are_one_submesh() {
+        let mesh = load_obj_from_bytes(b"v 0 0 0\nv 1 0 0\nv 0 1 0\nf 1 2 3\n").unwrap();
+        assert_eq!(mesh.submeshes(), vec![Submesh { first_index: 0, index_count: 3, material: None }]);
+    }
+}
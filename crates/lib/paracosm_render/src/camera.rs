@@ -0,0 +1,143 @@
+use ash::vk;
+
+use bevy_ecs::prelude::*;
+
+use rust_shaders_shared::glam::{Mat4, Vec3};
+
+
+
+/// Projection of a [`Camera`], mapping view space to Vulkan clip space.
+///
+/// All projections produce depth in `[0, 1]` from near to far. The renderer flips the viewport depth range,
+/// so rendered depth is reversed, and compared with [`Camera::DEPTH_COMPARE_OP`].
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Projection {
+    /// Perspective projection with no far plane
+    PerspectiveInfinite {
+        fov_y: f32,
+        near: f32
+    },
+    /// Perspective projection with a finite far plane
+    PerspectiveFinite {
+        fov_y: f32,
+        near: f32,
+        far: f32
+    },
+    /// Orthographic projection of a view `height` units tall, centered on the view direction
+    Orthographic {
+        height: f32,
+        near: f32,
+        far: f32
+    }
+}
+
+impl Default for Projection {
+    fn default() -> Self {
+        Projection::PerspectiveInfinite {
+            fov_y: 45_f32.to_radians(),
+            near: 0.1
+        }
+    }
+}
+
+impl Projection {
+    /// Get the projection matrix for a view of `aspect_ratio` (width / height).
+    pub fn matrix(&self, aspect_ratio: f32) -> Mat4 {
+        let mut matrix = match *self {
+            Projection::PerspectiveInfinite { fov_y, near } => Mat4::perspective_infinite_rh(fov_y, aspect_ratio, near),
+            Projection::PerspectiveFinite { fov_y, near, far } => Mat4::perspective_rh(fov_y, aspect_ratio, near, far),
+            Projection::Orthographic { height, near, far } => {
+                let half_height = height / 2.0;
+                let half_width = half_height * aspect_ratio;
+                Mat4::orthographic_rh(-half_width, half_width, -half_height, half_height, near, far)
+            }
+        };
+
+        // Flip Y for Vulkan clip space
+        matrix.y_axis *= -1.0;
+
+        matrix
+    }
+}
+
+/// A [`Camera`] component places a view into the scene. The renderer uses the first camera found.
+#[derive(Clone, Component, Debug)]
+pub struct Camera {
+    pub eye: Vec3,
+    pub target: Vec3,
+    pub up: Vec3,
+    pub projection: Projection,
+}
+
+impl Default for Camera {
+    fn default() -> Self {
+        Self {
+            eye: Vec3::new(-5.0, 2.0, -5.0),
+            target: Vec3::ZERO,
+            up: Vec3::Y,
+            projection: Projection::default(),
+        }
+    }
+}
+
+impl Camera {
+    /// Depth compare op matching the renderer's reversed depth range
+    pub const DEPTH_COMPARE_OP: vk::CompareOp = vk::CompareOp::GREATER_OR_EQUAL;
+    /// Depth clear value matching the renderer's reversed depth range
+    pub const DEPTH_CLEAR_VALUE: f32 = 0.0;
+
+    pub fn view_matrix(&self) -> Mat4 {
+        Mat4::look_at_rh(self.eye, self.target, self.up)
+    }
+
+    /// Get the combined view projection matrix for a view of `aspect_ratio` (width / height).
+    pub fn view_projection(&self, aspect_ratio: f32) -> Mat4 {
+        self.projection.matrix(aspect_ratio) * self.view_matrix()
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Project view space `point` to normalized device coordinates.
+    fn project(matrix: Mat4, point: Vec3) -> Vec3 {
+        let clip = matrix * point.extend(1.0);
+        clip.truncate() / clip.w
+    }
+
+    fn assert_near(actual: Vec3, expected: Vec3) {
+        assert!((actual - expected).abs().max_element() < 1e-5, "Expected {:?}, got {:?}", expected, actual);
+    }
+
+    #[test]
+    fn perspective_infinite() {
+        let fov_y = 90_f32.to_radians();
+        let matrix = Projection::PerspectiveInfinite { fov_y, near: 0.5 }.matrix(2.0);
+
+        // The top edge of the frustum maps to Vulkan's -Y, the right edge to +X
+        assert_near(project(matrix, Vec3::new(0.0, 0.5, -0.5)), Vec3::new(0.0, -1.0, 0.0));
+        assert_near(project(matrix, Vec3::new(1.0, 0.0, -0.5)), Vec3::new(1.0, 0.0, 0.0));
+        // Depth approaches 1 with distance
+        let far = project(matrix, Vec3::new(0.0, 0.0, -1.0e6));
+        assert!(far.z > 0.99 && far.z <= 1.0);
+    }
+
+    #[test]
+    fn perspective_finite() {
+        let matrix = Projection::PerspectiveFinite { fov_y: 90_f32.to_radians(), near: 1.0, far: 10.0 }.matrix(1.0);
+
+        assert_near(project(matrix, Vec3::new(0.0, 1.0, -1.0)), Vec3::new(0.0, -1.0, 0.0));
+        assert_near(project(matrix, Vec3::new(-10.0, 0.0, -10.0)), Vec3::new(-1.0, 0.0, 1.0));
+    }
+
+    #[test]
+    fn orthographic() {
+        let matrix = Projection::Orthographic { height: 4.0, near: 1.0, far: 3.0 }.matrix(1.5);
+
+        assert_near(project(matrix, Vec3::new(3.0, 2.0, -1.0)), Vec3::new(1.0, -1.0, 0.0));
+        assert_near(project(matrix, Vec3::new(0.0, -1.0, -2.0)), Vec3::new(0.0, 0.5, 0.5));
+        assert_near(project(matrix, Vec3::new(-3.0, 0.0, -3.0)), Vec3::new(-1.0, 0.0, 1.0));
+    }
+}
@@ -0,0 +1,334 @@
+use crate::{camera::Camera, DescriptorCounts, DescriptorStats, RenderContext};
+
+use bevy_app::{App, Plugin};
+use bevy_ecs::prelude::*;
+use bevy_math::{Mat4, Vec2, Vec3};
+use bevy_utils::HashMap;
+use bevy_window::{WindowId, Windows};
+
+use paracosm_gpu::{
+    device::Device,
+    resource::buffer::*,
+};
+pub use rust_shaders_shared::Vertex;
+
+use ash::vk;
+
+use std::mem::size_of;
+
+
+
+/// Adds the [`DebugDraw`] resource for immediate mode geometry
+pub struct DebugDrawPlugin;
+
+impl Plugin for DebugDrawPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<DebugDraw>()
+            .init_resource::<DescriptorOverlay>()
+            .add_system(draw_descriptor_overlay);
+    }
+}
+
+/// Normalized device depth of screen space geometry, just beyond the near plane
+const SCREEN_DEPTH: f32 = 1.0e-3;
+
+/// Accumulates immediate mode geometry, such as debug lines and UI quads, to be drawn at the end of the frame.
+/// 
+/// Vertices are in world space, and are cleared after each rendered frame.
+#[derive(Default, Resource)]
+pub struct DebugDraw {
+    line_vertices: Vec<Vertex>,
+    triangle_vertices: Vec<Vertex>,
+    /// Vertex buffer per window and frame in flight, as each window's frames complete independently
+    frame_buffers: HashMap<(WindowId, usize), Buffer>,
+}
+
+impl DebugDraw {
+    /// Draw a line from `start` to `end`.
+    pub fn line(&mut self, start: Vec3, end: Vec3, color: Vec3) {
+        self.line_vertices.push(Vertex::new(start, Vec3::ZERO, color, Vec2::ZERO));
+        self.line_vertices.push(Vertex::new(end, Vec3::ZERO, color, Vec2::ZERO));
+    }
+
+    /// Draw a quad from `corners` in counter-clockwise order, with UVs covering the full texture.
+    pub fn quad(&mut self, corners: [Vec3; 4], color: Vec3) {
+        let normal = (corners[1] - corners[0]).cross(corners[2] - corners[0]).normalize_or_zero();
+        let uvs = [
+            Vec2::new(0.0, 1.0),
+            Vec2::new(1.0, 1.0),
+            Vec2::new(1.0, 0.0),
+            Vec2::new(0.0, 0.0),
+        ];
+
+        for index in [0, 1, 2, 2, 3, 0] {
+            self.triangle_vertices.push(Vertex::new(corners[index], normal, color, uvs[index]));
+        }
+    }
+
+    /// Draw a screen space quad from `min` to `max` in normalized device coordinates, `(-1, -1)` being the top left,
+    /// in front of the scene. `view_projection` is the matrix of the rendered frame, see [`Camera::view_projection`].
+    pub fn screen_quad(&mut self, view_projection: Mat4, min: Vec2, max: Vec2, color: Vec3) {
+        let inverse_view_projection = view_projection.inverse();
+        let corners = [
+            Vec2::new(min.x, max.y),
+            max,
+            Vec2::new(max.x, min.y),
+            min,
+        ]
+            .map(|corner| inverse_view_projection.project_point3(corner.extend(SCREEN_DEPTH)));
+
+        self.quad(corners, color);
+    }
+
+    pub fn line_vertices(&self) -> &[Vertex] {
+        &self.line_vertices
+    }
+
+    pub fn triangle_vertices(&self) -> &[Vertex] {
+        &self.triangle_vertices
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.line_vertices.is_empty() && self.triangle_vertices.is_empty()
+    }
+
+    pub fn clear(&mut self) {
+        self.line_vertices.clear();
+        self.triangle_vertices.clear();
+    }
+
+    /// Vertices of a frame's vertex buffer, lines followed by triangles starting at [`DebugDraw::first_triangle_vertex`].
+    fn frame_vertices(&self) -> Vec<Vertex> {
+        self.line_vertices
+            .iter()
+            .chain(self.triangle_vertices.iter())
+            .copied()
+            .collect()
+    }
+
+    /// First vertex of the triangles in a frame's vertex buffer.
+    fn first_triangle_vertex(&self) -> u32 {
+        self.line_vertices.len() as u32
+    }
+
+    /// Drop the vertex buffers of a closed window.
+    pub(crate) fn remove_window(&mut self, window_id: WindowId) {
+        self.frame_buffers.retain(|(id, _), _| *id != window_id);
+    }
+
+    /// Write accumulated vertices into the vertex buffer for `frame_index` of `window_id`, lines followed by triangles.
+    /// 
+    /// The buffer is recreated when it is too small to hold this frame's vertices.
+    pub(crate) fn write_frame_buffer(&mut self, device: &Device, window_id: WindowId, frame_index: usize) -> Option<&Buffer> {
+        if self.is_empty() {
+            return None;
+        }
+
+        let vertices = self.frame_vertices();
+        let size = size_of::<Vertex>() * vertices.len();
+
+        let key = (window_id, frame_index);
+        if self.frame_buffers.get(&key).map_or(true, |buffer| buffer.info.size < size) {
+            // Grow to the next power of two to avoid recreating the buffer every frame
+            let info = BufferInfo::new(
+                size.next_power_of_two(),
+                BufferUsageFlags::VERTEX_BUFFER,
+                MemoryLocation::CpuToGpu
+            );
+            let name = format!("Debug Draw Buffer (Window {:?}, Frame {})", window_id, frame_index);
+            self.frame_buffers.insert(key, device.create_buffer(name.as_str(), info, None)
+                .expect("Device should create a buffer."));
+        }
+
+        let buffer = &self.frame_buffers[&key];
+        buffer.write_buffer(&vertices);
+
+        Some(buffer)
+    }
+}
+
+/// The [`DescriptorOverlay`] resource draws bindless descriptor usage of each binding as a bar in the top left
+/// of the primary window, to make descriptor leaks visible during development.
+/// 
+/// Bars fill on a log scale of the binding's capacity, so the few descriptors most scenes use stay visible.
+#[derive(Clone, Copy, Debug, Default, Resource)]
+pub struct DescriptorOverlay {
+    pub enabled: bool,
+}
+
+/// Fraction of a usage bar filled by `counts`, on a log scale
+fn usage_fraction(counts: DescriptorCounts) -> f32 {
+    let capacity = counts.used + counts.free;
+    match capacity {
+        0 => 0.0,
+        _ => (counts.used as f32).ln_1p() / (capacity as f32).ln_1p()
+    }
+}
+
+/// Quads of the descriptor usage bars in normalized device coordinates, as `(min, max, color)`.
+/// Each binding has a gray background bar, followed by its usage bar.
+fn descriptor_overlay_bars(stats: &DescriptorStats) -> Vec<(Vec2, Vec2, Vec3)> {
+    const ORIGIN: Vec2 = Vec2::new(-0.95, -0.95);
+    const SIZE: Vec2 = Vec2::new(0.5, 0.04);
+    const SPACING: f32 = 0.06;
+
+    [
+        (stats.storage_buffers, Vec3::new(0.9, 0.3, 0.2)),
+        (stats.storage_images, Vec3::new(0.9, 0.8, 0.2)),
+        (stats.sampled_images, Vec3::new(0.2, 0.8, 0.3)),
+        (stats.samplers, Vec3::new(0.2, 0.5, 0.9)),
+    ]
+        .into_iter()
+        .enumerate()
+        .flat_map(|(row, (counts, color))| {
+            let min = ORIGIN + Vec2::new(0.0, row as f32 * SPACING);
+            let fill = Vec2::new(SIZE.x * usage_fraction(counts), SIZE.y);
+            [
+                (min, min + SIZE, Vec3::splat(0.15)),
+                (min, min + fill, color),
+            ]
+        })
+        .collect()
+}
+
+/// Draw the [`DescriptorOverlay`] with [`DebugDraw`] when enabled
+fn draw_descriptor_overlay(
+    overlay: Res<DescriptorOverlay>,
+    render_context: Option<Res<RenderContext>>,
+    windows: Res<Windows>,
+    cameras: Query<&Camera>,
+    mut debug_draw: ResMut<DebugDraw>
+) {
+    if !overlay.enabled {
+        return;
+    }
+    let (Some(render_context), Some(window)) = (render_context, windows.get_primary()) else {
+        return;
+    };
+    if window.width() <= 0.0 || window.height() <= 0.0 {
+        return;
+    }
+
+    let camera = cameras.iter().next().cloned().unwrap_or_default();
+    let view_projection = camera.view_projection(window.width() / window.height());
+    let stats = render_context.resource_manager.descriptor_stats();
+    for (min, max, color) in descriptor_overlay_bars(&stats) {
+        debug_draw.screen_quad(view_projection, min, max, color);
+    }
+}
+
+/// Record draws for the accumulated [`DebugDraw`] geometry.
+/// 
+/// Expects viewport, scissor, and push constants to already be set on `command_buffer`.
+pub(crate) fn draw_debug_geometry(
+    device: &Device,
+    command_buffer: vk::CommandBuffer,
+    debug_draw: &mut DebugDraw,
+    window_id: WindowId,
+    frame_index: usize,
+    line_pipeline: Option<vk::Pipeline>,
+    triangle_pipeline: Option<vk::Pipeline>
+) {
+    let line_count = debug_draw.line_vertices.len() as u32;
+    let first_triangle_vertex = debug_draw.first_triangle_vertex();
+    let triangle_count = debug_draw.triangle_vertices.len() as u32;
+
+    let Some(buffer) = debug_draw.write_frame_buffer(device, window_id, frame_index) else {
+        return;
+    };
+
+    unsafe {
+        device.cmd_bind_vertex_buffers(command_buffer, 0, &[buffer.buffer], &[0]);
+
+        if let Some(pipeline) = line_pipeline.filter(|_| line_count > 0) {
+            device.cmd_bind_pipeline(command_buffer, vk::PipelineBindPoint::GRAPHICS, pipeline);
+            device.cmd_draw(command_buffer, line_count, 1, 0, 0);
+        }
+        if let Some(pipeline) = triangle_pipeline.filter(|_| triangle_count > 0) {
+            device.cmd_bind_pipeline(command_buffer, vk::PipelineBindPoint::GRAPHICS, pipeline);
+            device.cmd_draw(command_buffer, triangle_count, 1, first_triangle_vertex, 0);
+        }
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn usage_fraction_log_scale() {
+        assert_eq!(usage_fraction(DescriptorCounts { used: 0, free: 1000 }), 0.0);
+        assert_eq!(usage_fraction(DescriptorCounts { used: 1000, free: 0 }), 1.0);
+        assert_eq!(usage_fraction(DescriptorCounts::default()), 0.0);
+
+        let few = usage_fraction(DescriptorCounts { used: 10, free: 999_990 });
+        let more = usage_fraction(DescriptorCounts { used: 100, free: 999_900 });
+        assert!(few > 0.1 && few < more && more < 1.0);
+    }
+
+    #[test]
+    fn overlay_bars_fill_backgrounds() {
+        let stats = DescriptorStats {
+            storage_buffers: DescriptorCounts { used: 4, free: 4 },
+            ..Default::default()
+        };
+        let bars = descriptor_overlay_bars(&stats);
+        assert_eq!(bars.len(), 8);
+
+        for pair in bars.chunks_exact(2) {
+            let ((background_min, background_max, _), (min, max, _)) = (pair[0], pair[1]);
+            assert_eq!(min, background_min);
+            assert!(max.x <= background_max.x && max.y == background_max.y);
+        }
+        // Only storage buffers are in use
+        assert!(bars[1].1.x > bars[1].0.x);
+        assert!(bars[3..].iter().step_by(2).all(|(min, max, _)| min.x == max.x));
+    }
+
+    fn positions(vertices: &[Vertex]) -> Vec<Vec3> {
+        vertices.iter().map(|vertex| vertex.position).collect()
+    }
+
+    #[test]
+    fn lines_and_quads_fill_their_vertex_lists() {
+        let corners = [Vec3::ZERO, Vec3::X, Vec3::X + Vec3::Y, Vec3::Y];
+        let mut debug_draw = DebugDraw::default();
+        debug_draw.quad(corners, Vec3::ONE);
+        debug_draw.line(Vec3::ZERO, Vec3::Z, Vec3::X);
+        assert!(!debug_draw.is_empty());
+
+        assert_eq!(positions(debug_draw.line_vertices()), [Vec3::ZERO, Vec3::Z]);
+        // Two counter-clockwise triangles sharing the diagonal, facing +Z
+        let triangles = debug_draw.triangle_vertices();
+        assert_eq!(positions(triangles), [0, 1, 2, 2, 3, 0].map(|index| corners[index]));
+        assert!(triangles.iter().all(|vertex| vertex.normal == Vec3::Z));
+
+        // Lines and triangles are drawn from one buffer, lines first
+        let vertices = debug_draw.frame_vertices();
+        let first_triangle_vertex = debug_draw.first_triangle_vertex() as usize;
+        assert_eq!(first_triangle_vertex, 2);
+        assert_eq!(positions(&vertices[..first_triangle_vertex]), positions(debug_draw.line_vertices()));
+        assert_eq!(positions(&vertices[first_triangle_vertex..]), positions(triangles));
+
+        debug_draw.clear();
+        assert!(debug_draw.is_empty());
+    }
+
+    #[test]
+    fn screen_quad_covers_screen_rect() {
+        let view_projection = Camera::default().view_projection(16.0 / 9.0);
+        let (min, max) = (Vec2::new(-0.5, -0.75), Vec2::new(0.25, 0.5));
+
+        let mut debug_draw = DebugDraw::default();
+        debug_draw.screen_quad(view_projection, min, max, Vec3::ONE);
+
+        for vertex in debug_draw.triangle_vertices() {
+            let ndc = view_projection.project_point3(vertex.position);
+            assert!((ndc.z - SCREEN_DEPTH).abs() < 1.0e-4);
+            let corner_x = (ndc.x - min.x).abs() < 1.0e-4 || (ndc.x - max.x).abs() < 1.0e-4;
+            let corner_y = (ndc.y - min.y).abs() < 1.0e-4 || (ndc.y - max.y).abs() < 1.0e-4;
+            assert!(corner_x && corner_y, "Vertex at {:?} is not a corner of the rect", ndc);
+        }
+    }
+}
@@ -0,0 +1,43 @@
+use bevy_ecs::prelude::*;
+
+
+
+/// Label of the built-in wireframe overlay pipeline
+pub const WIREFRAME: &str = "debug_wireframe";
+/// Label of the built-in normal-direction coloring pipeline
+pub const NORMALS: &str = "debug_normals";
+/// Label of the built-in UV checkerboard pipeline
+pub const UV_CHECKER: &str = "debug_uv_checker";
+
+/// The [`DebugView`] resource selects how meshes are visualized by the renderer, to help debug mesh data.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Resource)]
+pub enum DebugView {
+    /// Normal shading
+    #[default]
+    Shaded,
+    /// Normal shading with a wireframe overlay, or without one if the device doesn't support `fillModeNonSolid`
+    Wireframe,
+    /// World space normals as color, replacing shading
+    Normals,
+    /// Texture coordinate checkerboard, replacing shading
+    UvChecker,
+}
+
+impl DebugView {
+    /// Label of the pipeline drawn instead of the mesh's shading pipeline, if any.
+    pub fn replacement_pipeline(&self) -> Option<&'static str> {
+        match self {
+            DebugView::Normals => Some(NORMALS),
+            DebugView::UvChecker => Some(UV_CHECKER),
+            _ => None
+        }
+    }
+
+    /// Label of the pipeline drawn over the mesh's shading, if any.
+    pub fn overlay_pipeline(&self) -> Option<&'static str> {
+        match self {
+            DebugView::Wireframe => Some(WIREFRAME),
+            _ => None
+        }
+    }
+}
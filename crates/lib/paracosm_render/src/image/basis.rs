@@ -0,0 +1,124 @@
+use super::{Image, ImageUpload};
+
+use anyhow::{anyhow, bail, Context, Result};
+use basis_universal::{
+    transcoder_init,
+    TranscodeParameters,
+    Transcoder,
+    TranscoderTextureFormat
+};
+use bevy_asset::{AssetLoader, LoadContext, LoadedAsset};
+use bevy_utils::BoxedFuture;
+
+use paracosm_gpu::{
+    device::Device,
+    resource::image::{Extent3D, Format}
+};
+
+
+
+/// An [`AssetLoader`] for Basis Universal `.basis` textures.
+///
+/// Textures are transcoded on upload to the compressed format the device prefers, BC7, ASTC 4x4 or ETC2,
+/// so one asset serves every platform, see [`Device::preferred_compressed_format`].
+#[derive(Default)]
+pub struct BasisLoader;
+
+impl AssetLoader for BasisLoader {
+    fn load<'a>(
+        &'a self,
+        bytes: &'a [u8],
+        load_context: &'a mut LoadContext,
+    ) -> BoxedFuture<'a, anyhow::Result<(), anyhow::Error>> {
+        Box::pin(async move {
+            transcoder_init();
+            if !Transcoder::new().validate_header(bytes) {
+                bail!("Failed to load Basis Universal texture {:?}: invalid header", load_context.path());
+            }
+
+            let mut image = Image::basis(bytes.to_vec());
+            if let Some(name) = load_context.path().file_stem() {
+                image.set_name(&name.to_string_lossy());
+            }
+            let asset = LoadedAsset::new(image);
+
+            load_context.set_default_asset(asset);
+            Ok(())
+        })
+    }
+
+    fn extensions(&self) -> &[&str] {
+        &["basis"]
+    }
+}
+
+/// Transcoder target producing the blocks of compressed `format`
+fn transcoder_format(format: Format) -> Option<TranscoderTextureFormat> {
+    match format {
+        Format::BC7_SRGB_BLOCK | Format::BC7_UNORM_BLOCK => Some(TranscoderTextureFormat::BC7_RGBA),
+        Format::ASTC_4X4_SRGB_BLOCK | Format::ASTC_4X4_UNORM_BLOCK => Some(TranscoderTextureFormat::ASTC_4x4_RGBA),
+        Format::ETC2_R8G8B8A8_SRGB_BLOCK | Format::ETC2_R8G8B8A8_UNORM_BLOCK => Some(TranscoderTextureFormat::ETC2_RGBA),
+        _ => None
+    }
+}
+
+/// Transcode every mip level of the first image in Basis Universal `data` to the compressed format `device` prefers.
+pub(super) fn transcode(device: &Device, data: &[u8]) -> Result<ImageUpload> {
+    let format = device.preferred_compressed_format(true)
+        .context("Device samples none of the BC7, ASTC 4x4 or ETC2 formats Basis Universal textures transcode to")?;
+    let target = transcoder_format(format)
+        .context(format!("No Basis Universal transcoder target for {:?}", format))?;
+
+    transcoder_init();
+    let mut transcoder = Transcoder::new();
+    let description = transcoder.image_level_description(data, 0, 0)
+        .context("Basis Universal texture has no images")?;
+    let level_count = transcoder.image_level_count(data, 0);
+
+    transcoder.prepare_transcoding(data)
+        .map_err(|_| anyhow!("Failed to prepare Basis Universal texture for transcoding"))?;
+    let mip_levels: Result<Vec<_>> = (0..level_count)
+        .map(|level_index| {
+            let parameters = TranscodeParameters {
+                image_index: 0,
+                level_index,
+                ..Default::default()
+            };
+            transcoder.transcode_image_level(data, target, parameters)
+                .map_err(|error| anyhow!("Failed to transcode Basis Universal mip level {} to {:?}: {:?}", level_index, format, error))
+        })
+        .collect();
+    transcoder.end_transcoding();
+
+    Ok(ImageUpload {
+        format,
+        extent: Extent3D { width: description.original_width, height: description.original_height, depth: 1 },
+        mip_levels: mip_levels?
+    })
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use paracosm_gpu::resource::image::select_compressed_format;
+
+    #[test]
+    fn every_selectable_format_has_a_transcoder_target() {
+        for srgb in [true, false] {
+            let mut excluded = vec![];
+            while let Some(format) = select_compressed_format(srgb, |format| !excluded.contains(&format)) {
+                assert!(transcoder_format(format).is_some(), "{:?}", format);
+                excluded.push(format);
+            }
+            assert_eq!(excluded.len(), 3);
+        }
+    }
+
+    #[test]
+    fn bc7_devices_transcode_to_bc7() {
+        let format = select_compressed_format(true, |format| format == Format::BC7_SRGB_BLOCK || format == Format::ETC2_R8G8B8A8_SRGB_BLOCK);
+        assert_eq!(format, Some(Format::BC7_SRGB_BLOCK));
+        assert!(matches!(transcoder_format(Format::BC7_SRGB_BLOCK), Some(TranscoderTextureFormat::BC7_RGBA)));
+    }
+}
@@ -1,3 +1,9 @@
+#[cfg(feature = "basis")]
+mod basis;
+
+#[cfg(feature = "basis")]
+pub use basis::BasisLoader;
+
 use crate::{
     render_asset::*,
     RenderContext
@@ -13,6 +19,7 @@ use bevy_ecs::{
     }
 };
 
+use bevy_log::prelude::*;
 use bevy_reflect::{TypeUuid};
 use bevy_utils::BoxedFuture;
 
@@ -22,16 +29,17 @@ use image::{
     io::Reader as ImageReader
 };
 
-use paracosm_gpu::resource::{
-    buffer as gpu_buffer, 
-    image as gpu_image, 
-    sampler as gpu_sampler
+use paracosm_gpu::{
+    device::Device,
+    resource::{
+        image as gpu_image, 
+        sampler as gpu_sampler
+    }
 };
 use rust_shaders_shared::ResourceHandle;
 
 use std::io::Cursor;
 use std::collections::HashMap;
-use std::mem::size_of;
 use std::ops::Deref;
 
 
@@ -62,13 +70,33 @@ impl Plugin for ImagePlugin {
 
         #[cfg(any(
             feature = "png",
+            feature = "jpeg",
+            feature = "tga",
+            feature = "bmp",
+            feature = "hdr",
+            feature = "exr",
         ))]
         {
             app.init_asset_loader::<ImageLoader>();
         }
+        #[cfg(feature = "basis")]
+        {
+            app.init_asset_loader::<BasisLoader>();
+        }
     }
 }
 
+/// File extensions of the image formats enabled by features
+const IMAGE_EXTENSIONS: &[&str] = &[
+    #[cfg(feature = "png")] "png",
+    #[cfg(feature = "jpeg")] "jpg",
+    #[cfg(feature = "jpeg")] "jpeg",
+    #[cfg(feature = "tga")] "tga",
+    #[cfg(feature = "bmp")] "bmp",
+    #[cfg(feature = "hdr")] "hdr",
+    #[cfg(feature = "exr")] "exr",
+];
+
 /// An [`AssetLoader`] for image assets.
 #[derive(Default)]
 pub struct ImageLoader;
@@ -80,12 +108,25 @@ impl AssetLoader for ImageLoader {
         load_context: &'a mut LoadContext,
     ) -> BoxedFuture<'a, anyhow::Result<(), anyhow::Error>> {
         Box::pin(async move {
-            let image = match ImageReader::with_format(Cursor::new(bytes), ImageFormat::Png).decode() {
+            // Detect format by extension, falling back to the file contents for unknown extensions
+            let format = load_context.path()
+                .extension()
+                .and_then(ImageFormat::from_extension);
+            let reader = match format {
+                Some(format) => ImageReader::with_format(Cursor::new(bytes), format),
+                None => ImageReader::new(Cursor::new(bytes)).with_guessed_format()?
+            };
+
+            let image = match reader.decode() {
                 Ok(result) => result,
-                Err(error) => bail!("Failed to load png: {}", error.to_string())
+                Err(error) => bail!("Failed to load image {:?}: {}", load_context.path(), error.to_string())
             };
 
-            let asset = LoadedAsset::new(Image(image));
+            let mut image = Image::new(image);
+            if let Some(name) = load_context.path().file_stem() {
+                image.set_name(&name.to_string_lossy());
+            }
+            let asset = LoadedAsset::new(image);
 
             load_context.set_default_asset(asset);
             Ok(())
@@ -93,22 +134,122 @@ impl AssetLoader for ImageLoader {
     }
 
     fn extensions(&self) -> &[&str] {
-        &["png"]
+        IMAGE_EXTENSIONS
     }
 }
 
 
 
+/// Pixel data of an [`Image`]
+pub enum ImageData {
+    /// Decoded pixels, converted to a GPU format on upload, see [`Image::gpu_format_and_data`]
+    Decoded(DynamicImage),
+    /// A Basis Universal texture, transcoded on upload to a compressed format the device samples
+    #[cfg(feature = "basis")]
+    Basis(Vec<u8>),
+}
+
 #[derive(TypeUuid)]
 #[uuid = "258d6fb5-6314-4816-9771-c24eb249abfe"]
-#[repr(transparent)]
-pub struct Image(DynamicImage);
+pub struct Image {
+    data: ImageData,
+    name: Option<String>,
+}
 
-impl Deref for Image {
-    type Target = DynamicImage;
+/// Image data in a GPU format ready for upload, with the data of each mip level
+struct ImageUpload {
+    format: gpu_image::Format,
+    extent: gpu_image::Extent3D,
+    mip_levels: Vec<Vec<u8>>,
+}
 
-    fn deref(&self) -> &Self::Target {
-        &self.0
+impl Image {
+    pub fn new(image: DynamicImage) -> Self {
+        Self {
+            data: ImageData::Decoded(image),
+            name: None
+        }
+    }
+
+    /// Create an image from the contents of a Basis Universal `.basis` file, see [`BasisLoader`].
+    #[cfg(feature = "basis")]
+    pub fn basis(data: Vec<u8>) -> Self {
+        Self {
+            data: ImageData::Basis(data),
+            name: None
+        }
+    }
+
+    pub fn data(&self) -> &ImageData {
+        &self.data
+    }
+
+    /// Decoded pixels of the image, or `None` for compressed images.
+    pub fn decoded(&self) -> Option<&DynamicImage> {
+        match &self.data {
+            ImageData::Decoded(image) => Some(image),
+            #[cfg(feature = "basis")]
+            ImageData::Basis(_) => None
+        }
+    }
+
+    /// Name GPU resources of this image after `name`, e.g. its file name, to identify them in debugging tools.
+    pub fn set_name(&mut self, name: &str) {
+        self.name = Some(name.to_string());
+    }
+
+    pub fn name(&self) -> Option<&str> {
+        self.name.as_deref()
+    }
+
+    /// Get the GPU format for this image's channel layout, and its pixel data converted to that format,
+    /// or `None` for compressed images, which are transcoded for the device on upload.
+    /// 
+    /// Float images use `R32G32B32A32_SFLOAT` and 16-bit images `R16G16B16A16_UNORM`, with others using `R8G8B8A8_SRGB`.
+    /// Channels are expanded to RGBA, as three channel formats are rarely supported for sampling.
+    pub fn gpu_format_and_data(&self) -> Option<(gpu_image::Format, Vec<u8>)> {
+        let image = self.decoded()?;
+        let format_and_data = match image {
+            DynamicImage::ImageRgb32F(_) | DynamicImage::ImageRgba32F(_) => {
+                let data = image.to_rgba32f()
+                    .into_raw()
+                    .iter()
+                    .flat_map(|value| value.to_ne_bytes())
+                    .collect();
+                (gpu_image::Format::R32G32B32A32_SFLOAT, data)
+            },
+            DynamicImage::ImageLuma16(_)
+            | DynamicImage::ImageLumaA16(_)
+            | DynamicImage::ImageRgb16(_)
+            | DynamicImage::ImageRgba16(_) => {
+                let data = image.to_rgba16()
+                    .into_raw()
+                    .iter()
+                    .flat_map(|value| value.to_ne_bytes())
+                    .collect();
+                (gpu_image::Format::R16G16B16A16_UNORM, data)
+            },
+            _ => (gpu_image::Format::R8G8B8A8_SRGB, image.to_rgba8().into_raw())
+        };
+
+        Some(format_and_data)
+    }
+
+    /// Get the image's data in a GPU format `device` samples.
+    fn upload(&self, #[cfg_attr(not(feature = "basis"), allow(unused_variables))] device: &Device) -> Result<ImageUpload> {
+        match &self.data {
+            ImageData::Decoded(image) => {
+                let (format, data) = self.gpu_format_and_data()
+                    .expect("Decoded images should have a GPU format.");
+                Ok(ImageUpload {
+                    format,
+                    extent: gpu_image::Extent3D { width: image.width(), height: image.height(), depth: 1 },
+                    mip_levels: vec![data]
+                })
+            },
+            #[cfg(feature = "basis")]
+            ImageData::Basis(data) => basis::transcode(device, data)
+        }
     }
 }
 
@@ -130,32 +271,52 @@ impl RenderAsset for Image {
         let device = &param.device;
         let resource_manager = &param.resource_manager;
 
-        // Create staging buffer
-        let size = (source_asset.width() * source_asset.height()) as usize * size_of::<u32>();
-        let info = gpu_buffer::BufferInfo::new(size, gpu_buffer::BufferUsageFlags::TRANSFER_SRC, gpu_buffer::MemoryLocation::CpuToGpu);
-        let staging_buffer = device.create_buffer("Image Staging Buffer", info, None);
+        let upload = match source_asset.upload(device) {
+            Ok(upload) => upload,
+            Err(error) => {
+                error!("Failed to prepare image {:?}: {}", source_asset.name(), error);
+                return Err(PrepareAssetError::Skip);
+            }
+        };
+
+        // Pack mip levels into one buffer, block compressed levels keeping block size alignment
+        let mut data = Vec::with_capacity(upload.mip_levels.iter().map(Vec::len).sum());
+        let mut mip_offsets = Vec::with_capacity(upload.mip_levels.len());
+        for mip_level in upload.mip_levels.iter() {
+            mip_offsets.push(data.len() as u64);
+            data.extend_from_slice(mip_level);
+        }
+
+        // Get staging buffer, returned to the pool once the upload below completes
+        let staging_buffer = param.staging_pool.acquire(data.len())
+            .expect("Staging pool should provide a buffer.");
 
         // Copy data to staging buffer
-        staging_buffer.write_buffer(&source_asset.as_bytes().to_vec());
+        staging_buffer.write_buffer(&data);
 
         // Create GPU image
         let create_info = gpu_image::ImageInfo {
             image_type: gpu_image::ImageType::TYPE_2D,
-            image_format: gpu_image::Format::R8G8B8A8_SRGB,
-            image_extent: gpu_image::Extent3D { width: source_asset.width(), height: source_asset.height(), depth: 1 },
-            mip_levels: 1,
+            image_format: upload.format,
+            image_extent: upload.extent,
+            mip_levels: gpu_image::MipLevels::Count(mip_offsets.len() as u32),
             array_layers: 1,
             samples: gpu_image::SampleCountFlags::TYPE_1,
             tiling: gpu_image::ImageTiling::OPTIMAL,
             usage: gpu_image::ImageUsageFlags::SAMPLED | gpu_image::ImageUsageFlags::TRANSFER_DST,
             aspect: gpu_image::ImageAspectFlags::COLOR,
-            memory_location: gpu_image::MemoryLocation::GpuOnly
+            memory_location: gpu_image::MemoryLocation::GpuOnly,
+            transient: false,
+            view_formats: vec![],
+            sharing: gpu_image::Sharing::Exclusive
         };
-        let image = device.create_image("Image", create_info, None);
+        let image = device.create_image(&asset_debug_name("Image", source_asset.name()), create_info, None)
+            .expect("Device should create an image.");
         let handle = resource_manager.new_sampled_image_handle(&image);
 
         // Copy from staging buffer to GPU image
-        device.copy_buffer_to_image(&staging_buffer, &image);
+        device.copy_buffer_to_image_mips(&staging_buffer, &image, &mip_offsets)
+            .expect("Device should copy the staging buffer to the image.");
 
         Ok(GpuImage {
             image,
@@ -198,9 +359,28 @@ impl Sampler {
             unnormalized_coordinates,
             compare_op,
             mipmap_mode,
-            mipmap_lod
+            mipmap_lod,
+            reduction_mode: None
         })
     }
+
+    /// Filter with up to `max_anisotropy` samples, or without anisotropic filtering for `None`.
+    pub fn with_anisotropy(mut self, max_anisotropy: Option<f32>) -> Self {
+        self.0.anisotropy = max_anisotropy;
+        self
+    }
+
+    /// Offset the selected mip level by `bias`, positive values blurring and negative values sharpening.
+    pub fn with_lod_bias(mut self, bias: f32) -> Self {
+        self.0.mipmap_lod.0 = bias;
+        self
+    }
+
+    /// Use a min or max reduction instead of a weighted average when filtering.
+    pub fn with_reduction_mode(mut self, reduction_mode: gpu_sampler::SamplerReductionMode) -> Self {
+        self.0.reduction_mode = Some(reduction_mode);
+        self
+    }
 }
 
 pub struct GpuSampler {
@@ -221,7 +401,13 @@ impl RenderAsset for Sampler {
 
         let sampler_info = &source_asset.0;
 
-        let sampler = device.create_sampler(sampler_info);
+        let sampler = match device.create_sampler(sampler_info) {
+            Ok(sampler) => sampler,
+            Err(error) => {
+                error!("Failed to prepare sampler: {}", error);
+                return Err(PrepareAssetError::Skip);
+            }
+        };
         let handle = resource_manager.new_sampler_handle(&sampler);
 
         Ok(GpuSampler {
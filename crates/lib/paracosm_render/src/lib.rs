@@ -1,21 +1,40 @@
+pub mod camera;
+pub mod debug_draw;
+pub mod debug_view;
 pub mod image;
 pub mod mesh;
+pub mod post_process;
+pub mod profiler;
+pub mod reduce;
 mod render_asset;
+pub mod render_phase;
 mod render_resource;
 mod renderer;
+pub mod view;
 mod window;
 
 use crate::image::*;
+use debug_draw::DebugDrawPlugin;
+pub use debug_draw::DescriptorOverlay;
+pub use profiler::FrameProfiler;
+use debug_view::DebugView;
 use mesh::*;
 use renderer::*;
-pub use renderer::RenderContext;
+pub use renderer::{FrameTimings, RenderContext, SceneData};
 pub use render_resource::{
+    frame_ring_buffer::FrameRingBuffer,
     pipeline::*,
-    shader::*
+    shader::*,
+    DescriptorCounts,
+    DescriptorStats,
+    HandleInfo,
+    HandleOccupant,
+    ResourceType
 };
+pub use window::Presentation;
 use window::WindowRenderPlugin;
 
-use bevy_app::{App, Plugin};
+use bevy_app::{App, CoreStage, Plugin};
 use bevy_ecs::prelude::*;
 
 use paracosm_gpu::GpuPlugin;
@@ -34,14 +53,20 @@ impl Plugin for RenderPlugin {
         }
 
         // Add renderer systems
-        app.add_startup_system(initialize_renderer.at_start())
-            .add_system(render_system.at_end());
+        app.init_resource::<DebugView>()
+            .init_resource::<FrameTimings>()
+            .init_resource::<FrameProfiler>()
+            .add_startup_system(initialize_renderer.at_start())
+            .add_system(render_system.at_end())
+            .add_system_to_stage(CoreStage::PostUpdate, profiler::profile_frame_timings)
+            .add_system_to_stage(CoreStage::Last, shutdown_renderer);
 
         // Add supporting plugins
         app.add_plugin(WindowRenderPlugin)
             .add_plugin(ShaderPlugin)
             .add_plugin(PipelineManagerPlugin)
             .add_plugin(MeshPlugin)
+            .add_plugin(DebugDrawPlugin)
             .add_plugin(ImagePlugin);
     }
 }
\ No newline at end of file
@@ -1,9 +1,12 @@
+mod shape;
+
 use crate::{
     render_asset::*,
     RenderContext
 };
 
 use anyhow::{Result, bail};
+use ash::vk;
 
 use bevy_app::{App, Plugin};
 use bevy_asset::{AddAsset, Handle};
@@ -21,7 +24,9 @@ use bevy_utils::{HashMap};
 use paracosm_gpu::{
     resource:: buffer::*, 
 };
+use rust_shaders_shared::glam::{Vec3, Vec4};
 pub use rust_shaders_shared::{
+    PackedVertex,
     ResourceHandle,
     Vertex,
 };
@@ -50,6 +55,77 @@ impl Plugin for MeshPlugin {
 }
 
 
+/// Vertex layout a [`Mesh`] is uploaded with
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum VertexFormat {
+    /// [`Vertex`], with 32 bit float color
+    #[default]
+    Full,
+    /// [`PackedVertex`], with 8 bit RGBA color
+    PackedColor,
+}
+
+impl VertexFormat {
+    /// Label of the variant of pipeline `label` matching this vertex layout.
+    pub fn pipeline_label(&self, label: &str) -> String {
+        match self {
+            VertexFormat::Full => label.to_string(),
+            VertexFormat::PackedColor => format!("{}_packed_color", label)
+        }
+    }
+
+    pub fn vertex_size(&self) -> usize {
+        match self {
+            VertexFormat::Full => size_of::<Vertex>(),
+            VertexFormat::PackedColor => size_of::<PackedVertex>()
+        }
+    }
+}
+
+/// Index type a [`Mesh`] is uploaded with
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum IndexFormat {
+    /// 16 bit indices, halving index buffer size for meshes of at most 65535 vertices.
+    /// Index `0xFFFF` is left unused, as it restarts strip topologies with primitive restart enabled.
+    U16,
+    #[default]
+    U32,
+}
+
+impl IndexFormat {
+    /// Smallest format able to index `vertex_count` vertices.
+    pub fn for_vertex_count(vertex_count: usize) -> Self {
+        match vertex_count <= u16::MAX as usize {
+            true => IndexFormat::U16,
+            false => IndexFormat::U32
+        }
+    }
+
+    pub fn index_size(&self) -> usize {
+        match self {
+            IndexFormat::U16 => size_of::<u16>(),
+            IndexFormat::U32 => size_of::<u32>()
+        }
+    }
+
+    pub fn index_type(&self) -> vk::IndexType {
+        match self {
+            IndexFormat::U16 => vk::IndexType::UINT16,
+            IndexFormat::U32 => vk::IndexType::UINT32
+        }
+    }
+}
+
+/// Range of a [`Mesh`]'s indices drawn with one material, e.g. an OBJ `usemtl` group
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Submesh {
+    pub first_index: u32,
+    pub index_count: u32,
+    /// Name of the material the submesh is drawn with, `None` for the default material
+    pub material: Option<String>,
+}
+
+
 // TODO: Split Asset and GPU resource, convert in prepare phase
 #[derive(TypeUuid)]
 #[uuid = "c6b21835-2c1b-431e-bf23-806a01591a7c"]
@@ -57,6 +133,10 @@ impl Plugin for MeshPlugin {
 pub struct Mesh {
     vertices: Vec<Vertex>,
     indices: Vec<u32>,
+    vertex_format: VertexFormat,
+    index_format: IndexFormat,
+    submeshes: Vec<Submesh>,
+    name: Option<String>,
 }
 
 impl Mesh {
@@ -64,6 +144,10 @@ impl Mesh {
         Self {
             vertices: vec![],
             indices: vec![],
+            vertex_format: VertexFormat::default(),
+            index_format: IndexFormat::default(),
+            submeshes: vec![],
+            name: None,
         }
     }
 
@@ -74,9 +158,23 @@ impl Mesh {
         Self {
             vertices,
             indices,
+            vertex_format: VertexFormat::default(),
+            index_format: IndexFormat::default(),
+            submeshes: vec![],
+            name: None,
         }
     }
 
+    /// Create a mesh uploaded with [`IndexFormat::U16`] indices.
+    pub fn with_geometry_u16(
+        vertices: Vec<Vertex>,
+        indices: Vec<u16>
+    ) -> Self {
+        let mut mesh = Self::with_geometry(vertices, indices.into_iter().map(u32::from).collect());
+        mesh.index_format = IndexFormat::U16;
+        mesh
+    }
+
     pub fn insert_vertex(&mut self, vertex: Vertex) {
         self.vertices.push(vertex);
     }
@@ -85,9 +183,209 @@ impl Mesh {
         self.indices = indices;
     }
 
+    /// Upload with `vertex_format`, e.g. [`VertexFormat::PackedColor`] to cut vertex size for meshes that only need 8 bit color.
+    pub fn set_vertex_format(&mut self, vertex_format: VertexFormat) {
+        self.vertex_format = vertex_format;
+    }
+
+    pub fn vertex_format(&self) -> VertexFormat {
+        self.vertex_format
+    }
+
+    /// Upload with `index_format`, e.g. [`IndexFormat::U16`] to halve index buffer size for small meshes.
+    /// 
+    /// Indices are kept as `u32` until upload, so editing operations like [`Mesh::weld`] work with either format.
+    pub fn set_index_format(&mut self, index_format: IndexFormat) {
+        self.index_format = index_format;
+    }
+
+    pub fn index_format(&self) -> IndexFormat {
+        self.index_format
+    }
+
+    /// Split the mesh into `submeshes` drawn with separate materials. Ranges must lie within the mesh's indices.
+    pub fn set_submeshes(&mut self, submeshes: Vec<Submesh>) -> Result<()> {
+        let index_count = self.indices.len() as u64;
+        if let Some(submesh) = submeshes.iter().find(|submesh| submesh.first_index as u64 + submesh.index_count as u64 > index_count) {
+            bail!(
+                "Submesh indices {}..{} exceed mesh index count {}",
+                submesh.first_index, submesh.first_index + submesh.index_count, index_count
+            );
+        }
+        self.submeshes = submeshes;
+
+        Ok(())
+    }
+
+    /// Submeshes the mesh is drawn as, a single submesh with the default material for meshes that weren't split.
+    pub fn submeshes(&self) -> Vec<Submesh> {
+        match self.submeshes.is_empty() {
+            true => vec![Submesh {
+                first_index: 0,
+                index_count: self.indices.len() as u32,
+                material: None
+            }],
+            false => self.submeshes.clone()
+        }
+    }
+
+    /// Name GPU resources of this mesh after `name`, e.g. its file name, to identify them in debugging tools.
+    pub fn set_name(&mut self, name: &str) {
+        self.name = Some(name.to_string());
+    }
+
+    pub fn name(&self) -> Option<&str> {
+        self.name.as_deref()
+    }
+
     pub fn index_count(&self) -> usize {
         self.indices.len()
     }
+
+    pub fn indices(&self) -> &[u32] {
+        &self.indices
+    }
+
+    pub fn vertex_count(&self) -> usize {
+        self.vertices.len()
+    }
+
+    /// Merge vertices whose attributes are all within `epsilon` of each other, and remap indices to the merged vertices.
+    /// An `epsilon` of `0.0` merges only exactly equal vertices.
+    /// 
+    /// Triangles are unchanged apart from which vertex they index. Returns the number of vertices removed.
+    pub fn weld(&mut self, epsilon: f32) -> usize {
+        let (welded, remap) = match epsilon > 0.0 {
+            true => weld_within(&self.vertices, epsilon),
+            false => weld_exact(&self.vertices)
+        };
+
+        let removed = self.vertices.len() - welded.len();
+        self.indices
+            .iter_mut()
+            .for_each(|index| *index = remap[*index as usize]);
+        self.vertices = welded;
+
+        removed
+    }
+
+    /// Compute a tangent for each vertex from its triangles' texture coordinates, for normal mapping.
+    /// 
+    /// Tangents are orthonormalized against the vertex normal. The `w` component is the handedness of the tangent space,
+    /// `1.0` or `-1.0` for mirrored UVs, so the bitangent is `cross(normal, tangent.xyz) * tangent.w`.
+    pub fn compute_tangents(&self) -> Vec<Vec4> {
+        let mut tangents = vec![Vec3::ZERO; self.vertices.len()];
+        let mut bitangents = vec![Vec3::ZERO; self.vertices.len()];
+        for triangle in self.indices.chunks_exact(3) {
+            let [a, b, c] = [triangle[0] as usize, triangle[1] as usize, triangle[2] as usize];
+            let (v0, v1, v2) = (&self.vertices[a], &self.vertices[b], &self.vertices[c]);
+
+            let edge1 = v1.position - v0.position;
+            let edge2 = v2.position - v0.position;
+            let delta_uv1 = v1.uv - v0.uv;
+            let delta_uv2 = v2.uv - v0.uv;
+
+            // Skip triangles with degenerate texture coordinates
+            let determinant = delta_uv1.perp_dot(delta_uv2);
+            if determinant.abs() <= f32::EPSILON {
+                continue;
+            }
+            let r = 1.0 / determinant;
+            let tangent = (edge1 * delta_uv2.y - edge2 * delta_uv1.y) * r;
+            let bitangent = (edge2 * delta_uv1.x - edge1 * delta_uv2.x) * r;
+
+            for index in [a, b, c] {
+                tangents[index] += tangent;
+                bitangents[index] += bitangent;
+            }
+        }
+
+        self.vertices
+            .iter()
+            .zip(tangents.iter().zip(bitangents.iter()))
+            .map(|(vertex, (&tangent, &bitangent))| {
+                let normal = vertex.normal.normalize_or_zero();
+
+                // Gram-Schmidt orthonormalize, falling back to any perpendicular for vertices without usable UVs
+                let tangent = (tangent - normal * normal.dot(tangent)).try_normalize()
+                    .unwrap_or_else(|| normal.any_orthonormal_vector());
+                let handedness = match normal.cross(tangent).dot(bitangent) < 0.0 {
+                    true => -1.0,
+                    false => 1.0
+                };
+
+                tangent.extend(handedness)
+            })
+            .collect()
+    }
+}
+
+/// Welded vertices, and the welded index of each vertex
+type WeldResult = (Vec<Vertex>, Vec<u32>);
+
+/// Weld vertices within `epsilon`, bucketing them by position on a grid of epsilon sized cells,
+/// so matches are only searched in neighboring cells.
+fn weld_within(vertices: &[Vertex], epsilon: f32) -> WeldResult {
+    let cell = |vertex: &Vertex| {
+        let cell = (vertex.position / epsilon).floor();
+        (cell.x as i32, cell.y as i32, cell.z as i32)
+    };
+
+    let mut cells: HashMap<(i32, i32, i32), Vec<u32>> = HashMap::new();
+    let mut welded: Vec<Vertex> = Vec::with_capacity(vertices.len());
+    let mut remap = Vec::with_capacity(vertices.len());
+    for vertex in vertices.iter() {
+        let (x, y, z) = cell(vertex);
+        let existing = (-1..=1)
+            .flat_map(|dx| (-1..=1).flat_map(move |dy| (-1..=1).map(move |dz| (x + dx, y + dy, z + dz))))
+            .filter_map(|key| cells.get(&key))
+            .flatten()
+            .copied()
+            .find(|&index| vertices_within(&welded[index as usize], vertex, epsilon));
+
+        let index = existing.unwrap_or_else(|| {
+            let index = welded.len() as u32;
+            welded.push(*vertex);
+            cells.entry((x, y, z)).or_default().push(index);
+            index
+        });
+        remap.push(index);
+    }
+
+    (welded, remap)
+}
+
+/// Weld exactly equal vertices, hashing their attribute bits. `-0.0` and `0.0` are treated as equal.
+fn weld_exact(vertices: &[Vertex]) -> WeldResult {
+    let key = |vertex: &Vertex| -> [u32; 11] {
+        let Vertex { position, normal, color, uv } = *vertex;
+        [
+            position.x, position.y, position.z,
+            normal.x, normal.y, normal.z,
+            color.x, color.y, color.z,
+            uv.x, uv.y
+        ]
+            .map(|value| (value + 0.0).to_bits())
+    };
+
+    let mut indices: HashMap<[u32; 11], u32> = HashMap::new();
+    let mut welded: Vec<Vertex> = Vec::with_capacity(vertices.len());
+    let remap = vertices
+        .iter()
+        .map(|vertex| *indices.entry(key(vertex)).or_insert_with(|| {
+            welded.push(*vertex);
+            welded.len() as u32 - 1
+        }))
+        .collect();
+
+    (welded, remap)
+}
+
+fn vertices_within(a: &Vertex, b: &Vertex, epsilon: f32) -> bool {
+    a.position.abs_diff_eq(b.position, epsilon)
+        && a.normal.abs_diff_eq(b.normal, epsilon)
+        && a.color.abs_diff_eq(b.color, epsilon)
+        && a.uv.abs_diff_eq(b.uv, epsilon)
 }
 
 impl Drop for Mesh {
@@ -103,7 +401,23 @@ pub struct GpuMesh {
     pub index_buffer: Buffer,
     pub vertex_buffer_handle: ResourceHandle,
     pub index_buffer_handle: ResourceHandle,
-    pub index_count: u32
+    pub index_count: u32,
+    pub vertex_format: VertexFormat,
+    pub index_format: IndexFormat,
+    pub submeshes: Vec<Submesh>
+}
+
+/// Whether `mesh` has geometry to upload. Empty meshes have nothing to draw, and Vulkan doesn't allow empty buffers.
+fn is_preparable(mesh: &Mesh) -> bool {
+    !mesh.vertices.is_empty() && !mesh.indices.is_empty()
+}
+
+/// Index format `mesh` is uploaded with, falling back to 32 bit indices for meshes that grew too large for 16 bit ones.
+fn upload_index_format(mesh: &Mesh) -> IndexFormat {
+    match mesh.index_format {
+        IndexFormat::U16 if IndexFormat::for_vertex_count(mesh.vertices.len()) != IndexFormat::U16 => IndexFormat::U32,
+        index_format => index_format
+    }
 }
 
 impl RenderAsset for Mesh {
@@ -113,42 +427,49 @@ impl RenderAsset for Mesh {
     fn prepare_asset(
         source_asset: &Self,
         param: &mut SystemParamItem<Self::Param>,
-    ) -> Result<Self::PreparedAsset, crate::render_asset::PrepareAssetError> {
-        let device = &param.device;
+    ) -> Result<Self::PreparedAsset, PrepareAssetError> {
         let resource_manager = &param.resource_manager;
 
-        let vertices_size = size_of::<Vertex>() * source_asset.vertices.len();
-        let indices_size = size_of::<u32>() * source_asset.indices.len();
-
-        // Create staging buffers
-        let info = BufferInfo::new(vertices_size, BufferUsageFlags::TRANSFER_SRC, MemoryLocation::CpuToGpu);
-        let vertex_staging_buffer = device.create_buffer("Vertex Staging Buffer", info, None);
-
-        let info = BufferInfo::new(indices_size, BufferUsageFlags::TRANSFER_SRC, MemoryLocation::CpuToGpu);
-        let index_staging_buffer = device.create_buffer("Index Staging Buffer", info, None);
+        if !is_preparable(source_asset) {
+            warn!("Skipping preparation of empty mesh");
+            return Err(PrepareAssetError::Skip);
+        }
 
-        // Copy data to staging buffers
-        vertex_staging_buffer.write_buffer(&source_asset.vertices);
-        index_staging_buffer.write_buffer(&source_asset.indices);
+        let vertices_size = source_asset.vertex_format.vertex_size() * source_asset.vertices.len();
+        let index_format = upload_index_format(source_asset);
+        if index_format != source_asset.index_format {
+            warn!("Mesh has {} vertices, too many for 16 bit indices; uploading 32 bit indices", source_asset.vertices.len());
+        }
+        let indices_size = index_format.index_size() * source_asset.indices.len();
 
-        // Create GPU buffers
+        // Create GPU buffers, uploaded directly where device local memory is host visible
         let info = BufferInfo::new(
             vertices_size,
             BufferUsageFlags::TRANSFER_DST | BufferUsageFlags::VERTEX_BUFFER | BufferUsageFlags::STORAGE_BUFFER,
             MemoryLocation::GpuOnly
         );
-        let vertex_buffer = device.create_buffer("Vertex Buffer", info, None);
+        let vertex_buffer_name = asset_debug_name("Vertex Buffer", source_asset.name());
+        let vertex_buffer = match source_asset.vertex_format {
+            VertexFormat::Full => param.staging_pool.upload_buffer(&vertex_buffer_name, info, &source_asset.vertices),
+            VertexFormat::PackedColor => {
+                let vertices: Vec<PackedVertex> = source_asset.vertices.iter().map(PackedVertex::from_vertex).collect();
+                param.staging_pool.upload_buffer(&vertex_buffer_name, info, &vertices)
+            }
+        }.expect("Device should create a buffer.");
 
         let info = BufferInfo::new(
             indices_size,
             BufferUsageFlags::TRANSFER_DST  | BufferUsageFlags::INDEX_BUFFER | BufferUsageFlags::STORAGE_BUFFER,
             MemoryLocation::GpuOnly
         );
-        let index_buffer = device.create_buffer("Index Buffer", info, None);
-
-        // Copy from staging buffers to GPU buffers
-        device.copy_buffer(&vertex_staging_buffer, &vertex_buffer, vertices_size);
-        device.copy_buffer(&index_staging_buffer, &index_buffer, indices_size);
+        let index_buffer_name = asset_debug_name("Index Buffer", source_asset.name());
+        let index_buffer = match index_format {
+            IndexFormat::U32 => param.staging_pool.upload_buffer(&index_buffer_name, info, &source_asset.indices),
+            IndexFormat::U16 => {
+                let indices: Vec<u16> = source_asset.indices.iter().map(|&index| index as u16).collect();
+                param.staging_pool.upload_buffer(&index_buffer_name, info, &indices)
+            }
+        }.expect("Device should create a buffer.");
 
         // Add buffer to resource manager
         let vertex_buffer_handle = resource_manager.new_buffer_handle(&vertex_buffer);
@@ -159,7 +480,172 @@ impl RenderAsset for Mesh {
             index_buffer,
             vertex_buffer_handle,
             index_buffer_handle,
-            index_count: source_asset.index_count() as u32
+            index_count: source_asset.index_count() as u32,
+            vertex_format: source_asset.vertex_format,
+            index_format,
+            submeshes: source_asset.submeshes()
         })
     }
-}
\ No newline at end of file
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rust_shaders_shared::glam::Vec2;
+
+    /// Triangles of `mesh` as vertex positions, in a canonical order
+    fn triangles(mesh: &Mesh) -> Vec<[[u32; 3]; 3]> {
+        let mut triangles: Vec<_> = mesh.indices
+            .chunks_exact(3)
+            .map(|triangle| [0, 1, 2].map(|corner| mesh.vertices[triangle[corner] as usize].position.to_array().map(f32::to_bits)))
+            .collect();
+        triangles.sort_unstable();
+        triangles
+    }
+
+    /// A cube with a vertex per corner of each face, differing only in position between faces
+    fn unwelded_cube() -> Mesh {
+        let cube = Mesh::cube(2.0);
+        let vertices = cube.vertices
+            .iter()
+            .map(|vertex| Vertex::new(vertex.position, Vec3::Y, Vec3::ONE, Vec2::ZERO))
+            .collect();
+        Mesh::with_geometry(vertices, cube.indices.clone())
+    }
+
+    #[test]
+    fn index_format_for_vertex_count() {
+        assert_eq!(IndexFormat::for_vertex_count(0), IndexFormat::U16);
+        assert_eq!(IndexFormat::for_vertex_count(65535), IndexFormat::U16);
+        assert_eq!(IndexFormat::for_vertex_count(65536), IndexFormat::U32);
+        assert_eq!(IndexFormat::for_vertex_count(65537), IndexFormat::U32);
+    }
+
+    #[test]
+    fn oversized_u16_meshes_upload_u32_indices() {
+        let vertices = |count: usize| vec![Vertex::new(Vec3::ZERO, Vec3::Z, Vec3::ONE, Vec2::ZERO); count];
+
+        let mesh = Mesh::with_geometry_u16(vertices(65535), vec![0, 1, 65534]);
+        assert_eq!(upload_index_format(&mesh), IndexFormat::U16);
+        // e.g. after inserting vertices into a mesh created with 16 bit indices
+        let mesh = Mesh::with_geometry_u16(vertices(65536), vec![0, 1, 65535]);
+        assert_eq!(upload_index_format(&mesh), IndexFormat::U32);
+        // Meshes chosen to use 32 bit indices keep them
+        let mesh = Mesh::with_geometry(vertices(3), vec![0, 1, 2]);
+        assert_eq!(upload_index_format(&mesh), IndexFormat::U32);
+    }
+
+    #[test]
+    fn empty_meshes_are_skipped() {
+        assert!(!is_preparable(&Mesh::new()));
+
+        let cube = Mesh::cube(2.0);
+        assert!(!is_preparable(&Mesh::with_geometry(cube.vertices.clone(), vec![])));
+        assert!(is_preparable(&cube));
+    }
+
+    #[test]
+    fn weld_cube_to_corners() {
+        for epsilon in [0.0, 1.0e-4] {
+            let mut mesh = unwelded_cube();
+            let expected = triangles(&mesh);
+
+            assert_eq!(mesh.weld(epsilon), 16);
+            assert_eq!(mesh.vertex_count(), 8);
+            assert_eq!(mesh.index_count(), 36);
+            assert_eq!(triangles(&mesh), expected);
+        }
+    }
+
+    #[test]
+    fn weld_keeps_distinct_attributes() {
+        // Per-face normals keep the cube's faces separate
+        let mut mesh = Mesh::cube(2.0);
+        assert_eq!(mesh.weld(1.0e-4), 0);
+        assert_eq!(mesh.vertex_count(), 24);
+    }
+
+    #[test]
+    fn exact_weld_merges_signed_zeros_only() {
+        let vertex = |x: f32| Vertex::new(Vec3::new(x, 0.0, 0.0), Vec3::Z, Vec3::ONE, Vec2::ZERO);
+        let mut mesh = Mesh::with_geometry(
+            vec![vertex(0.0), vertex(-0.0), vertex(1.0e-7), vertex(1.0e6), vertex(1.0e6)],
+            vec![0, 1, 2, 2, 3, 4]
+        );
+
+        assert_eq!(mesh.weld(0.0), 2);
+        assert_eq!(mesh.vertex_count(), 3);
+        assert_eq!(mesh.indices, vec![0, 0, 1, 1, 2, 2]);
+    }
+
+    /// A quad in the XY plane facing `normal`, with U along `u_sign * X` and V along Y
+    fn quad(normal: Vec3, u_sign: f32) -> Mesh {
+        let vertex = |x: f32, y: f32| Vertex::new(Vec3::new(x, y, 0.0), normal, Vec3::ONE, Vec2::new(u_sign * x, y));
+        Mesh::with_geometry(
+            vec![vertex(0.0, 0.0), vertex(1.0, 0.0), vertex(1.0, 1.0), vertex(0.0, 1.0)],
+            vec![0, 1, 2, 0, 2, 3]
+        )
+    }
+
+    #[test]
+    fn tangent_handedness() {
+        for tangent in quad(Vec3::Z, 1.0).compute_tangents() {
+            assert!(tangent.abs_diff_eq(Vec4::new(1.0, 0.0, 0.0, 1.0), 1.0e-6), "{:?}", tangent);
+        }
+
+        // Mirrored UVs flip the tangent and the handedness, keeping the bitangent along V
+        for tangent in quad(Vec3::Z, -1.0).compute_tangents() {
+            assert!(tangent.abs_diff_eq(Vec4::new(-1.0, 0.0, 0.0, -1.0), 1.0e-6), "{:?}", tangent);
+            let bitangent = Vec3::Z.cross(tangent.truncate()) * tangent.w;
+            assert!(bitangent.abs_diff_eq(Vec3::Y, 1.0e-6), "{:?}", bitangent);
+        }
+    }
+
+    #[test]
+    fn tangents_orthonormal_to_normals() {
+        // Normals tilted away from the face, and unnormalized, still get perpendicular unit tangents
+        let meshes = [quad(Vec3::new(1.0, 0.0, 2.0), 1.0), quad(Vec3::new(0.0, 0.0, 3.0), 1.0), Mesh::cube(2.0)];
+        for mesh in meshes {
+            for (vertex, tangent) in mesh.vertices.iter().zip(mesh.compute_tangents()) {
+                let normal = vertex.normal.normalize();
+                let tangent = tangent.truncate();
+                assert!((tangent.length() - 1.0).abs() < 1.0e-5, "{:?}", tangent);
+                assert!(normal.dot(tangent).abs() < 1.0e-5, "{:?} {:?}", normal, tangent);
+            }
+        }
+    }
+
+    #[test]
+    fn tangents_without_uvs_are_perpendicular() {
+        let mut mesh = quad(Vec3::Z, 1.0);
+        mesh.vertices.iter_mut().for_each(|vertex| vertex.uv = Vec2::ZERO);
+
+        for tangent in mesh.compute_tangents() {
+            assert!(Vec3::Z.dot(tangent.truncate()).abs() < 1.0e-6);
+            assert!((tangent.truncate().length() - 1.0).abs() < 1.0e-6);
+            assert_eq!(tangent.w, 1.0);
+        }
+    }
+
+    #[test]
+    fn submeshes_within_index_bounds() {
+        let mut mesh = Mesh::cube(1.0);
+        let index_count = mesh.index_count() as u32;
+        assert_eq!(mesh.submeshes(), vec![Submesh { first_index: 0, index_count, material: None }]);
+
+        let split = vec![
+            Submesh { first_index: 0, index_count: 6, material: Some("front".to_string()) },
+            Submesh { first_index: 6, index_count: index_count - 6, material: None }
+        ];
+        mesh.set_submeshes(split.clone()).unwrap();
+        assert_eq!(mesh.submeshes(), split);
+
+        // Ranges past the last index are rejected, keeping the previous submeshes
+        let past_end = Submesh { first_index: index_count - 3, index_count: 6, material: None };
+        assert!(mesh.set_submeshes(vec![past_end]).is_err());
+        let overflowing = Submesh { first_index: u32::MAX, index_count: u32::MAX, material: None };
+        assert!(mesh.set_submeshes(vec![overflowing]).is_err());
+        assert_eq!(mesh.submeshes(), split);
+    }
+}
@@ -0,0 +1,239 @@
+use super::Mesh;
+
+use bevy_math::{Vec2, Vec3};
+
+use rust_shaders_shared::Vertex;
+
+use std::f32::consts::PI;
+
+
+
+/// Generators for common primitive shapes.
+///
+/// Shapes are centered on the origin with Y up, and faces wound counter-clockwise when viewed from outside.
+impl Mesh {
+    /// Create an axis-aligned cube with edge length `size`, with separate vertices per face for flat normals.
+    pub fn cube(size: f32) -> Self {
+        let half = size / 2.0;
+
+        // (normal, u, v) per face, with u x v = normal
+        let faces = [
+            (Vec3::X, Vec3::NEG_Z, Vec3::Y),
+            (Vec3::NEG_X, Vec3::Z, Vec3::Y),
+            (Vec3::Y, Vec3::X, Vec3::NEG_Z),
+            (Vec3::NEG_Y, Vec3::X, Vec3::Z),
+            (Vec3::Z, Vec3::X, Vec3::Y),
+            (Vec3::NEG_Z, Vec3::NEG_X, Vec3::Y),
+        ];
+        let corners = [
+            (Vec2::new(-1.0, -1.0), Vec2::new(0.0, 1.0)),
+            (Vec2::new(1.0, -1.0), Vec2::new(1.0, 1.0)),
+            (Vec2::new(1.0, 1.0), Vec2::new(1.0, 0.0)),
+            (Vec2::new(-1.0, 1.0), Vec2::new(0.0, 0.0)),
+        ];
+
+        let mut vertices = Vec::with_capacity(faces.len() * 4);
+        let mut indices = Vec::with_capacity(faces.len() * 6);
+        for (normal, u, v) in faces {
+            let base = vertices.len() as u32;
+            for (corner, uv) in corners {
+                let position = (normal + u * corner.x + v * corner.y) * half;
+                vertices.push(Vertex::new(position, normal, Vec3::ONE, uv));
+            }
+            indices.extend_from_slice(&[base, base + 1, base + 2, base, base + 2, base + 3]);
+        }
+
+        Self::with_geometry(vertices, indices)
+    }
+
+    /// Create a UV sphere with `rings` latitude bands and `sectors` longitude bands.
+    ///
+    /// `rings` is clamped to at least 2, and `sectors` to at least 3.
+    pub fn uv_sphere(radius: f32, rings: u32, sectors: u32) -> Self {
+        let rings = rings.max(2);
+        let sectors = sectors.max(3);
+
+        let mut vertices = Vec::with_capacity(((rings + 1) * (sectors + 1)) as usize);
+        for ring in 0..=rings {
+            let phi = PI * ring as f32 / rings as f32;
+            for sector in 0..=sectors {
+                let theta = 2.0 * PI * sector as f32 / sectors as f32;
+                let normal = Vec3::new(phi.sin() * theta.cos(), phi.cos(), phi.sin() * theta.sin());
+                let uv = Vec2::new(sector as f32 / sectors as f32, ring as f32 / rings as f32);
+                vertices.push(Vertex::new(normal * radius, normal, Vec3::ONE, uv));
+            }
+        }
+
+        // Skip the degenerate triangles touching each pole
+        let mut indices = Vec::with_capacity((6 * sectors * (rings - 1)) as usize);
+        let stride = sectors + 1;
+        for ring in 0..rings {
+            for sector in 0..sectors {
+                let a = ring * stride + sector;
+                let b = a + 1;
+                let c = b + stride;
+                let d = a + stride;
+                if ring != 0 {
+                    indices.extend_from_slice(&[a, b, c]);
+                }
+                if ring != rings - 1 {
+                    indices.extend_from_slice(&[a, c, d]);
+                }
+            }
+        }
+
+        Self::with_geometry(vertices, indices)
+    }
+
+    /// Create a square plane on the XZ axes with edge length `size`, facing +Y.
+    ///
+    /// Each edge is split into `subdivisions` segments, clamped to at least 1.
+    pub fn plane(size: f32, subdivisions: u32) -> Self {
+        let subdivisions = subdivisions.max(1);
+        let half = size / 2.0;
+        let step = size / subdivisions as f32;
+
+        let mut vertices = Vec::with_capacity(((subdivisions + 1) * (subdivisions + 1)) as usize);
+        for row in 0..=subdivisions {
+            for column in 0..=subdivisions {
+                let position = Vec3::new(-half + column as f32 * step, 0.0, half - row as f32 * step);
+                let uv = Vec2::new(column as f32 / subdivisions as f32, 1.0 - row as f32 / subdivisions as f32);
+                vertices.push(Vertex::new(position, Vec3::Y, Vec3::ONE, uv));
+            }
+        }
+
+        let mut indices = Vec::with_capacity((6 * subdivisions * subdivisions) as usize);
+        let stride = subdivisions + 1;
+        for row in 0..subdivisions {
+            for column in 0..subdivisions {
+                let a = row * stride + column;
+                let b = a + 1;
+                let c = b + stride;
+                let d = a + stride;
+                indices.extend_from_slice(&[a, b, c, a, c, d]);
+            }
+        }
+
+        Self::with_geometry(vertices, indices)
+    }
+
+    /// Create a capped cylinder along the Y axis with `sectors` sides, clamped to at least 3.
+    pub fn cylinder(radius: f32, height: f32, sectors: u32) -> Self {
+        let sectors = sectors.max(3);
+        let half = height / 2.0;
+
+        let mut vertices = vec![];
+        let mut indices = vec![];
+
+        // Create side
+        for (ring, y) in [half, -half].into_iter().enumerate() {
+            for sector in 0..=sectors {
+                let theta = 2.0 * PI * sector as f32 / sectors as f32;
+                let normal = Vec3::new(theta.cos(), 0.0, theta.sin());
+                let position = Vec3::new(normal.x * radius, y, normal.z * radius);
+                let uv = Vec2::new(sector as f32 / sectors as f32, ring as f32);
+                vertices.push(Vertex::new(position, normal, Vec3::ONE, uv));
+            }
+        }
+        let stride = sectors + 1;
+        for sector in 0..sectors {
+            let a = sector;
+            let b = a + 1;
+            let c = b + stride;
+            let d = a + stride;
+            indices.extend_from_slice(&[a, b, c, a, c, d]);
+        }
+
+        // Create caps
+        for (normal, y) in [(Vec3::Y, half), (Vec3::NEG_Y, -half)] {
+            let center = vertices.len() as u32;
+            vertices.push(Vertex::new(Vec3::new(0.0, y, 0.0), normal, Vec3::ONE, Vec2::splat(0.5)));
+            for sector in 0..=sectors {
+                let theta = 2.0 * PI * sector as f32 / sectors as f32;
+                let position = Vec3::new(theta.cos() * radius, y, theta.sin() * radius);
+                let uv = Vec2::new(0.5 + theta.cos() * 0.5, 0.5 + theta.sin() * 0.5);
+                vertices.push(Vertex::new(position, normal, Vec3::ONE, uv));
+            }
+            for sector in 0..sectors {
+                let current = center + 1 + sector;
+                match normal.y > 0.0 {
+                    true => indices.extend_from_slice(&[center, current + 1, current]),
+                    false => indices.extend_from_slice(&[center, current, current + 1])
+                }
+            }
+        }
+
+        Self::with_geometry(vertices, indices)
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Assert every triangle of `mesh` faces away from the inside, i.e. along `outward` at its centroid.
+    fn assert_outward_winding(mesh: &Mesh, outward: impl Fn(Vec3) -> Vec3) {
+        for triangle in mesh.indices.chunks_exact(3) {
+            let [a, b, c] = [0, 1, 2].map(|corner| mesh.vertices[triangle[corner] as usize].position);
+            let normal = (b - a).cross(c - a);
+            let centroid = (a + b + c) / 3.0;
+            assert!(normal.dot(outward(centroid)) > 0.0, "Triangle {:?} is wound clockwise from outside", triangle);
+        }
+    }
+
+    fn assert_indices_in_bounds(mesh: &Mesh) {
+        assert_eq!(mesh.index_count() % 3, 0);
+        assert!(mesh.indices.iter().all(|&index| (index as usize) < mesh.vertex_count()));
+    }
+
+    #[test]
+    fn cube_counts() {
+        let mesh = Mesh::cube(2.0);
+        assert_eq!(mesh.vertex_count(), 24);
+        assert_eq!(mesh.index_count(), 36);
+        assert_indices_in_bounds(&mesh);
+        assert_outward_winding(&mesh, |centroid| centroid);
+    }
+
+    #[test]
+    fn uv_sphere_counts() {
+        let mesh = Mesh::uv_sphere(1.0, 4, 8);
+        assert_eq!(mesh.vertex_count(), 5 * 9);
+        // Pole bands have one triangle per sector, other bands two
+        assert_eq!(mesh.index_count(), 3 * (2 * 8 + 2 * 8 * 2));
+        assert_indices_in_bounds(&mesh);
+        assert_outward_winding(&mesh, |centroid| centroid);
+
+        // Degenerate parameters are clamped
+        let mesh = Mesh::uv_sphere(1.0, 0, 0);
+        assert_eq!(mesh.vertex_count(), 3 * 4);
+        assert_eq!(mesh.index_count(), 3 * 2 * 3);
+    }
+
+    #[test]
+    fn plane_counts() {
+        let mesh = Mesh::plane(2.0, 3);
+        assert_eq!(mesh.vertex_count(), 16);
+        assert_eq!(mesh.index_count(), 54);
+        assert_indices_in_bounds(&mesh);
+        assert_outward_winding(&mesh, |_| Vec3::Y);
+
+        let mesh = Mesh::plane(2.0, 0);
+        assert_eq!(mesh.vertex_count(), 4);
+        assert_eq!(mesh.index_count(), 6);
+    }
+
+    #[test]
+    fn cylinder_counts() {
+        let mesh = Mesh::cylinder(1.0, 2.0, 8);
+        // Side rings and caps with a center vertex, each with a seam vertex
+        assert_eq!(mesh.vertex_count(), 2 * 9 + 2 * (1 + 9));
+        assert_eq!(mesh.index_count(), 6 * 8 + 2 * 3 * 8);
+        assert_indices_in_bounds(&mesh);
+        assert_outward_winding(&mesh, |centroid| match (centroid.y.abs() - 1.0).abs() < 1e-5 {
+            true => Vec3::new(0.0, centroid.y, 0.0),
+            false => centroid
+        });
+    }
+}
@@ -0,0 +1,318 @@
+use crate::render_resource::{ResourceManager, ResourceType};
+
+use anyhow::Result;
+use ash::vk;
+
+use bevy_log::prelude::*;
+use bevy_window::WindowId;
+
+use paracosm_gpu::{
+    device::Device,
+    resource::image::*,
+};
+
+use rust_shaders_shared::{
+    PostProcessConstants,
+    ResourceHandle,
+};
+
+use std::{
+    collections::HashMap,
+    slice
+};
+
+
+
+/// Label of the built-in FXAA post-process pipeline
+pub const FXAA: &str = "fxaa";
+
+struct PostProcessTarget {
+    image: Image,
+    handle: ResourceHandle,
+}
+
+/// Ping-pong targets for a single frame in flight, and the frame's view target color image.
+struct PostProcessFrame {
+    source: vk::Image,
+    source_handle: ResourceHandle,
+    targets: [PostProcessTarget; 2],
+}
+
+/// Image a post-process pass reads or writes
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum PassImage {
+    /// The view target color image the chain starts from
+    ViewColor,
+    /// One of the frame's ping-pong targets
+    Target(usize)
+}
+
+/// A scheduled post-process pass, see [`schedule_passes`]
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+struct PostProcessPass<'a> {
+    label: &'a str,
+    source: PassImage,
+    target: usize,
+    /// Whether the target is written for the first time this frame, so its previous contents are undefined
+    first_write: bool,
+}
+
+/// Assign each effect with a pipeline a ping-pong target, reading the previous pass's output.
+fn schedule_passes<'a>(effects: &'a [String], has_pipeline: impl Fn(&str) -> bool) -> Vec<PostProcessPass<'a>> {
+    let mut source = PassImage::ViewColor;
+    let mut written = [false; 2];
+
+    effects
+        .iter()
+        .filter(|label| match has_pipeline(label) {
+            true => true,
+            false => {
+                warn!("Skipping post-process effect \"{}\" with no pipeline", label);
+                false
+            }
+        })
+        .enumerate()
+        .map(|(pass, label)| {
+            let target = pass % 2;
+            let scheduled = PostProcessPass {
+                label,
+                source,
+                target,
+                first_write: !written[target]
+            };
+            written[target] = true;
+            source = PassImage::Target(target);
+
+            scheduled
+        })
+        .collect()
+}
+
+/// An ordered chain of full-screen post-process passes, run on a [`ViewTarget`](crate::view::ViewTarget).
+///
+/// Each effect is the label of a full-screen pipeline in the [`PipelineManager`](crate::PipelineManager),
+/// which reads the previous pass's output through [`PostProcessConstants`] and writes the next.
+#[derive(Default)]
+pub struct PostProcessChain {
+    effects: Vec<String>,
+    frames: HashMap<WindowId, Vec<PostProcessFrame>>,
+}
+
+impl PostProcessChain {
+    /// Append an effect to the end of the chain.
+    pub fn push_effect(&mut self, label: &str) {
+        self.effects.push(label.to_string());
+    }
+
+    pub fn effects(&self) -> &[String] {
+        &self.effects
+    }
+
+    pub fn clear_effects(&mut self) {
+        self.effects.clear();
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.effects.is_empty()
+    }
+
+    /// Create ping-pong targets for `window_id` matching its view target color images,
+    /// replacing any existing targets.
+    pub(crate) fn prepare(
+        &mut self,
+        device: &Device,
+        resource_manager: &ResourceManager,
+        window_id: WindowId,
+        view_colors: &[&Image]
+    ) -> Result<()> {
+        self.remove(resource_manager, window_id);
+
+        let mut frames = Vec::with_capacity(view_colors.len());
+        for (frame, view_color) in view_colors.iter().enumerate() {
+            let mut info = view_color.info.clone();
+            info.usage = ImageUsageFlags::COLOR_ATTACHMENT | ImageUsageFlags::SAMPLED | ImageUsageFlags::TRANSFER_SRC;
+
+            let create_target = |index: usize| -> Result<PostProcessTarget> {
+                let name = format!("Post Process Target {} (Frame {})", index, frame);
+                let image = device.create_image(name.as_str(), info.clone(), None)?;
+                let handle = resource_manager.new_sampled_image_handle(&image);
+                Ok(PostProcessTarget { image, handle })
+            };
+
+            frames.push(PostProcessFrame {
+                source: view_color.image,
+                source_handle: resource_manager.new_sampled_image_handle(view_color),
+                targets: [create_target(0)?, create_target(1)?],
+            });
+        }
+        self.frames.insert(window_id, frames);
+
+        Ok(())
+    }
+
+    pub(crate) fn is_prepared(&self, window_id: WindowId) -> bool {
+        self.frames.contains_key(&window_id)
+    }
+
+    /// Remove the targets for `window_id`, releasing their sampled image handles.
+    pub(crate) fn remove(&mut self, resource_manager: &ResourceManager, window_id: WindowId) {
+        let Some(frames) = self.frames.remove(&window_id) else {
+            return;
+        };
+
+        for frame in frames {
+            resource_manager.recycle_handle(ResourceType::SampledImage, frame.source_handle);
+            for target in frame.targets.iter() {
+                resource_manager.recycle_handle(ResourceType::SampledImage, target.handle);
+            }
+        }
+    }
+
+    /// Record the chain's passes, starting from a prepared view target color image in `COLOR_ATTACHMENT_OPTIMAL` layout.
+    ///
+    /// Returns the final output in `SHADER_READ_ONLY_OPTIMAL` layout, or `None` without recording anything
+    /// if the chain is empty or unprepared. Effects without a pipeline are skipped.
+    pub(crate) fn run<'a>(
+        &'a self,
+        resource_manager: &ResourceManager,
+        command_buffer: vk::CommandBuffer,
+        view_color: &'a Image,
+        sampler_handle: ResourceHandle,
+        pipeline: impl Fn(&str) -> Option<vk::Pipeline>
+    ) -> Option<&'a Image> {
+        if self.effects.is_empty() {
+            return None;
+        }
+        let frame = self.frames
+            .values()
+            .flatten()
+            .find(|frame| frame.source == view_color.image)?;
+
+        let device = &resource_manager.device;
+        let pipeline_layout = resource_manager.pipeline_layouts[0];
+
+        let extent = view_color.extent();
+        let mut source = view_color;
+
+        device.transition_image_layout(
+            command_buffer,
+            view_color,
+            vk::ImageLayout::COLOR_ATTACHMENT_OPTIMAL,
+            vk::ImageLayout::SHADER_READ_ONLY_OPTIMAL
+        );
+
+        for pass in schedule_passes(&self.effects, |label| pipeline(label).is_some()) {
+            let Some(pipeline) = pipeline(pass.label) else {
+                continue;
+            };
+
+            let target = &frame.targets[pass.target];
+            let source_handle = match pass.source {
+                PassImage::ViewColor => frame.source_handle,
+                PassImage::Target(index) => frame.targets[index].handle
+            };
+
+            let old_layout = match pass.first_write {
+                true => vk::ImageLayout::UNDEFINED,
+                false => vk::ImageLayout::SHADER_READ_ONLY_OPTIMAL
+            };
+            device.transition_image_layout(command_buffer, &target.image, old_layout, vk::ImageLayout::COLOR_ATTACHMENT_OPTIMAL);
+
+            unsafe {
+                // Begin rendering
+                let color_attachment_info = vk::RenderingAttachmentInfo::builder()
+                    .image_view(target.image.image_view)
+                    .image_layout(vk::ImageLayout::ATTACHMENT_OPTIMAL)
+                    .load_op(vk::AttachmentLoadOp::DONT_CARE)
+                    .store_op(vk::AttachmentStoreOp::STORE);
+                let render_area = vk::Rect2D::builder()
+                    .extent(vk::Extent2D { width: extent.width, height: extent.height })
+                    .build();
+                let rendering_info = vk::RenderingInfo::builder()
+                    .render_area(render_area)
+                    .layer_count(1)
+                    .color_attachments(slice::from_ref(&color_attachment_info));
+                device.cmd_begin_rendering(command_buffer, &rendering_info);
+
+                let viewports = [
+                    vk::Viewport::builder()
+                        .width(extent.width as f32)
+                        .height(extent.height as f32)
+                        .min_depth(0.0)
+                        .max_depth(1.0)
+                        .build()
+                ];
+                device.cmd_set_viewport(command_buffer, 0, &viewports);
+                device.cmd_set_scissor(command_buffer, 0, slice::from_ref(&render_area));
+
+                // Draw full-screen triangle
+                let push_constant = [PostProcessConstants {
+                    source_image_handle: source_handle,
+                    sampler_handle,
+                    texel_size: rust_shaders_shared::glam::Vec2::new(1.0 / extent.width as f32, 1.0 / extent.height as f32),
+                }];
+                let (_, push_constant_bytes, _) = push_constant.align_to::<u8>();
+                device.cmd_bind_pipeline(command_buffer, vk::PipelineBindPoint::GRAPHICS, pipeline);
+                device.cmd_push_constants(command_buffer, pipeline_layout, vk::ShaderStageFlags::ALL, 0, push_constant_bytes);
+                device.cmd_draw(command_buffer, 3, 1, 0, 0);
+
+                device.cmd_end_rendering(command_buffer);
+            }
+
+            device.transition_image_layout(
+                command_buffer,
+                &target.image,
+                vk::ImageLayout::COLOR_ATTACHMENT_OPTIMAL,
+                vk::ImageLayout::SHADER_READ_ONLY_OPTIMAL
+            );
+
+            source = &target.image;
+        }
+
+        Some(source)
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn effects(labels: &[&str]) -> Vec<String> {
+        labels.iter().map(|label| label.to_string()).collect()
+    }
+
+    #[test]
+    fn single_identity_pass_reads_view_color() {
+        let effects = effects(&["identity"]);
+        let passes = schedule_passes(&effects, |_| true);
+
+        assert_eq!(passes, vec![
+            PostProcessPass { label: "identity", source: PassImage::ViewColor, target: 0, first_write: true }
+        ]);
+    }
+
+    #[test]
+    fn identity_and_blur_passes_ping_pong() {
+        let effects = effects(&["identity", "blur", "blur"]);
+        let passes = schedule_passes(&effects, |_| true);
+
+        assert_eq!(passes, vec![
+            PostProcessPass { label: "identity", source: PassImage::ViewColor, target: 0, first_write: true },
+            PostProcessPass { label: "blur", source: PassImage::Target(0), target: 1, first_write: true },
+            PostProcessPass { label: "blur", source: PassImage::Target(1), target: 0, first_write: false },
+        ]);
+    }
+
+    #[test]
+    fn effects_without_pipelines_are_skipped() {
+        let effects = effects(&["identity", "missing", "blur"]);
+        let passes = schedule_passes(&effects, |label| label != "missing");
+
+        assert_eq!(passes, vec![
+            PostProcessPass { label: "identity", source: PassImage::ViewColor, target: 0, first_write: true },
+            PostProcessPass { label: "blur", source: PassImage::Target(0), target: 1, first_write: true },
+        ]);
+        assert!(schedule_passes(&effects, |_| false).is_empty());
+    }
+}
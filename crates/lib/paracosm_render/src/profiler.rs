@@ -0,0 +1,186 @@
+use crate::FrameTimings;
+
+use bevy_ecs::prelude::*;
+use bevy_window::WindowId;
+
+use std::{
+    collections::{HashMap, VecDeque},
+    time::Duration
+};
+
+
+
+/// Frames of timings kept by a default [`FrameProfiler`], about two seconds at 60 frames per second
+pub const DEFAULT_WINDOW: usize = 120;
+
+/// Label of a window's recording time in the [`FrameProfiler`]
+pub fn recording_label(window_id: WindowId) -> String {
+    format!("Recording ({:?})", window_id)
+}
+
+/// Label of the total recording time of all windows in the [`FrameProfiler`]
+pub const TOTAL_RECORDING: &str = "Recording (total)";
+
+/// Statistics of a labelled timing over a [`FrameProfiler`]'s window
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct TimingStats {
+    /// Number of samples in the window, at most the window size
+    pub samples: usize,
+    pub mean: Duration,
+    /// Median sample
+    pub p50: Duration,
+    /// Sample that 99% of samples are at or below, the slowest frames apart from outliers
+    pub p99: Duration,
+}
+
+/// The [`FrameProfiler`] resource keeps the last frames of labelled timings, e.g. each window's
+/// [`FrameTimings::recording`], and computes their statistics over that sliding window.
+#[derive(Resource)]
+pub struct FrameProfiler {
+    window: usize,
+    samples: HashMap<String, VecDeque<Duration>>,
+}
+
+impl Default for FrameProfiler {
+    fn default() -> Self {
+        Self::new(DEFAULT_WINDOW)
+    }
+}
+
+impl FrameProfiler {
+    /// Create a profiler keeping the last `window` samples of each label, at least one.
+    pub fn new(window: usize) -> Self {
+        Self {
+            window: window.max(1),
+            samples: HashMap::new(),
+        }
+    }
+
+    /// Add a sample of `label`, dropping its oldest sample once the window is full.
+    pub fn record(&mut self, label: &str, duration: Duration) {
+        let samples = self.samples.entry(label.to_string()).or_default();
+        if samples.len() == self.window {
+            samples.pop_front();
+        }
+        samples.push_back(duration);
+    }
+
+    /// Add the recording time of each window in `timings`, and their total.
+    pub fn record_frame(&mut self, timings: &FrameTimings) {
+        for (&window_id, &duration) in timings.recording.iter() {
+            self.record(&recording_label(window_id), duration);
+        }
+        if !timings.recording.is_empty() {
+            self.record(TOTAL_RECORDING, timings.total_recording());
+        }
+    }
+
+    /// Statistics of `label` over the window, `None` if it has no samples.
+    pub fn stats(&self, label: &str) -> Option<TimingStats> {
+        let mut samples: Vec<Duration> = self.samples.get(label)?.iter().copied().collect();
+        if samples.is_empty() {
+            return None;
+        }
+        samples.sort_unstable();
+
+        Some(TimingStats {
+            samples: samples.len(),
+            mean: samples.iter().sum::<Duration>() / samples.len() as u32,
+            p50: percentile(&samples, 50),
+            p99: percentile(&samples, 99),
+        })
+    }
+
+    /// Labels with samples in the window.
+    pub fn labels(&self) -> impl Iterator<Item = &str> {
+        self.samples.keys().map(String::as_str)
+    }
+
+    /// Drop every sample, e.g. after a change that invalidates earlier timings.
+    pub fn clear(&mut self) {
+        self.samples.clear();
+    }
+}
+
+/// Nearest rank `percent`th percentile of non-empty `sorted` samples
+fn percentile(sorted: &[Duration], percent: usize) -> Duration {
+    let rank = (sorted.len() * percent + 99) / 100;
+    sorted[rank.clamp(1, sorted.len()) - 1]
+}
+
+/// Add the last frame's [`FrameTimings`] to the [`FrameProfiler`]
+pub(crate) fn profile_frame_timings(
+    frame_timings: Res<FrameTimings>,
+    mut profiler: ResMut<FrameProfiler>
+) {
+    profiler.record_frame(&frame_timings);
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn percentiles_of_synthetic_timings() {
+        let mut profiler = FrameProfiler::new(100);
+        // Shuffled 1 to 100 ms, as frames don't arrive sorted
+        for millis in (1..=100).map(|index| (index * 37) % 100 + 1) {
+            profiler.record("frame", Duration::from_millis(millis));
+        }
+
+        let stats = profiler.stats("frame").unwrap();
+        assert_eq!(stats.samples, 100);
+        assert_eq!(stats.p50, Duration::from_millis(50));
+        assert_eq!(stats.p99, Duration::from_millis(99));
+        assert_eq!(stats.mean, Duration::from_micros(50_500));
+        assert_eq!(profiler.stats("missing"), None);
+    }
+
+    #[test]
+    fn window_drops_oldest_samples() {
+        let mut profiler = FrameProfiler::new(10);
+        for millis in 1..=20 {
+            profiler.record("frame", Duration::from_millis(millis));
+        }
+
+        // Only 11 to 20 ms remain
+        let stats = profiler.stats("frame").unwrap();
+        assert_eq!(stats.samples, 10);
+        assert_eq!(stats.p50, Duration::from_millis(15));
+        assert_eq!(stats.p99, Duration::from_millis(20));
+
+        // A single slow frame shows in p99 but not the median
+        profiler.record("frame", Duration::from_millis(500));
+        let stats = profiler.stats("frame").unwrap();
+        assert_eq!(stats.p50, Duration::from_millis(16));
+        assert_eq!(stats.p99, Duration::from_millis(500));
+    }
+
+    #[test]
+    fn frame_timings_are_labelled_per_window() {
+        let window_id = WindowId::new();
+        let mut timings = FrameTimings::default();
+        timings.recording.insert(WindowId::primary(), Duration::from_millis(2));
+        timings.recording.insert(window_id, Duration::from_millis(3));
+
+        let mut profiler = FrameProfiler::default();
+        profiler.record_frame(&timings);
+        profiler.record_frame(&FrameTimings::default());
+
+        let mut labels: Vec<_> = profiler.labels().collect();
+        labels.sort_unstable();
+        let mut expected = vec![recording_label(WindowId::primary()), recording_label(window_id), TOTAL_RECORDING.to_string()];
+        expected.sort_unstable();
+        assert_eq!(labels, expected);
+
+        assert_eq!(profiler.stats(&recording_label(window_id)).unwrap().p50, Duration::from_millis(3));
+        // Frames without rendered windows add no samples
+        assert_eq!(profiler.stats(TOTAL_RECORDING).unwrap(), TimingStats {
+            samples: 1,
+            mean: Duration::from_millis(5),
+            p50: Duration::from_millis(5),
+            p99: Duration::from_millis(5),
+        });
+    }
+}
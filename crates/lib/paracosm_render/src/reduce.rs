@@ -0,0 +1,305 @@
+use crate::{image::GpuImage, RenderContext, ResourceType};
+
+use anyhow::{bail, Result};
+use ash::vk;
+
+use bevy_ecs::system::Resource;
+
+use paracosm_gpu::{
+    device::{Device, QueueFamily},
+    resource::{
+        buffer::*,
+        image::ImageUsageFlags,
+        pipeline::*,
+        shader_module::ShaderModule,
+    }
+};
+
+use rust_shaders_shared::{
+    ImageReduceConstants,
+    ReduceConstants,
+    ResourceHandle,
+    REDUCE_IMAGE_WORKGROUP_SIZE,
+    REDUCE_OP_MAX,
+    REDUCE_OP_MIN,
+    REDUCE_OP_SUM,
+    REDUCE_WORKGROUP_SIZE
+};
+
+use std::{
+    borrow::Cow,
+    mem::size_of,
+    slice
+};
+
+
+
+/// Operation combining the values of a [`Reducer::reduce_buffer`] reduction
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ReduceOp {
+    /// Wrapping sum of all values
+    Sum,
+    Min,
+    Max
+}
+
+impl ReduceOp {
+    /// Result of reducing no values
+    pub fn identity(&self) -> u32 {
+        match self {
+            ReduceOp::Sum | ReduceOp::Max => 0,
+            ReduceOp::Min => u32::MAX
+        }
+    }
+
+    pub fn combine(&self, a: u32, b: u32) -> u32 {
+        match self {
+            ReduceOp::Sum => a.wrapping_add(b),
+            ReduceOp::Min => a.min(b),
+            ReduceOp::Max => a.max(b)
+        }
+    }
+
+    /// Reduce `values` on the CPU, matching the result of [`Reducer::reduce_buffer`].
+    pub fn reduce(&self, values: &[u32]) -> u32 {
+        values.iter().fold(self.identity(), |result, &value| self.combine(result, value))
+    }
+
+    fn shader_op(&self) -> u32 {
+        match self {
+            ReduceOp::Sum => REDUCE_OP_SUM,
+            ReduceOp::Min => REDUCE_OP_MIN,
+            ReduceOp::Max => REDUCE_OP_MAX
+        }
+    }
+}
+
+/// Exposure scale mapping `max_luminance`, e.g. from [`Reducer::max_luminance`], to 1.0 for exposure adaptation.
+pub fn exposure_for_max_luminance(max_luminance: f32) -> f32 {
+    1.0 / max_luminance.max(1e-4)
+}
+
+/// Workgroups dispatched to reduce `count` values, one invocation per value up to `max_group_count` workgroups.
+/// Invocations stride over the remaining values.
+fn reduce_group_count(count: u32, max_group_count: u32) -> u32 {
+    let group_count = count / REDUCE_WORKGROUP_SIZE + (count % REDUCE_WORKGROUP_SIZE != 0) as u32;
+    group_count.clamp(1, max_group_count.max(1))
+}
+
+/// Workgroups dispatched to cover `size` texels with image reduction workgroups.
+fn image_group_count(size: u32) -> u32 {
+    (size + REDUCE_IMAGE_WORKGROUP_SIZE - 1) / REDUCE_IMAGE_WORKGROUP_SIZE
+}
+
+/// The [`Reducer`] resource runs the built-in parallel reduction compute pipelines, e.g. for histograms
+/// or the min/max statistics auto-exposure needs.
+///
+/// Reductions combine 32-bit values with storage buffer atomics, which compute shaders support on every Vulkan device,
+/// so only the workgroup size limits are validated.
+#[derive(Resource)]
+pub struct Reducer {
+    reduce_pipeline: ComputePipeline,
+    max_luminance_pipeline: ComputePipeline,
+}
+
+impl Reducer {
+    pub(crate) fn new(device: &Device, module: &ShaderModule, pipeline_layout: PipelineLayout) -> Result<Self> {
+        let limits = device.limits();
+        let image_invocations = REDUCE_IMAGE_WORKGROUP_SIZE * REDUCE_IMAGE_WORKGROUP_SIZE;
+        if limits.max_compute_work_group_size[0] < REDUCE_WORKGROUP_SIZE
+            || limits.max_compute_work_group_size[1] < REDUCE_IMAGE_WORKGROUP_SIZE
+            || limits.max_compute_work_group_invocations < REDUCE_WORKGROUP_SIZE.max(image_invocations)
+        {
+            bail!(
+                "Reduction workgroups exceed device limits maxComputeWorkGroupSize = {:?}, maxComputeWorkGroupInvocations = {}",
+                limits.max_compute_work_group_size, limits.max_compute_work_group_invocations
+            );
+        }
+
+        let pipeline = |entry_point: &'static str| device.create_compute_pipeline(ComputePipelineInfo {
+            shader: module.clone(),
+            entry_point: Cow::from(entry_point)
+        }, pipeline_layout);
+
+        Ok(Self {
+            reduce_pipeline: pipeline("comp::reduce::main\0")?,
+            max_luminance_pipeline: pipeline("comp::reduce::max_luminance\0")?,
+        })
+    }
+
+    /// Reduce the first `count` `u32` values of `source` with `op` on the GPU, and wait for the result.
+    ///
+    /// `source` must have `STORAGE_BUFFER` usage, and its writes must be complete.
+    pub fn reduce_buffer(&self, render_context: &RenderContext, source: &Buffer, count: u32, op: ReduceOp) -> Result<u32> {
+        if !source.info.usage.contains(BufferUsageFlags::STORAGE_BUFFER) {
+            bail!("Reduced buffer with usage {:?} must include STORAGE_BUFFER usage", source.info.usage);
+        }
+        if count as u64 * size_of::<u32>() as u64 > source.info.size as u64 {
+            bail!("Reducing {} values overruns buffer of {} bytes", count, source.info.size);
+        }
+        if count == 0 {
+            return Ok(op.identity());
+        }
+
+        let device = &render_context.device;
+        let resource_manager = &render_context.resource_manager;
+        let group_count = reduce_group_count(count, device.limits().max_compute_work_group_count[0]);
+
+        let source_handle = resource_manager.new_buffer_handle(source);
+        let result = self.run(render_context, op.identity(), self.reduce_pipeline.pipeline, |command_buffer, result_handle| {
+            let push_constant = [ReduceConstants {
+                source_buffer_handle: source_handle,
+                result_buffer_handle: result_handle,
+                count,
+                op: op.shader_op()
+            }];
+            let (_, push_constant_bytes, _) = unsafe { push_constant.align_to::<u8>() };
+            unsafe { device.cmd_push_constants(command_buffer, resource_manager.pipeline_layouts[0], vk::ShaderStageFlags::ALL, 0, push_constant_bytes) };
+            device.dispatch(command_buffer, group_count, 1, 1)
+        });
+        resource_manager.recycle_handle(ResourceType::StorageBuffer, source_handle);
+
+        result
+    }
+
+    /// Find the largest luminance of the texels of `image` on the GPU, and wait for the result.
+    /// Pass it to [`exposure_for_max_luminance`] to adapt exposure to the image.
+    ///
+    /// Runs on the graphics queue, where images are sampled, so exclusive images need no ownership transfer.
+    pub fn max_luminance(&self, render_context: &RenderContext, image: &GpuImage) -> Result<f32> {
+        let info = &image.image.info;
+        if !info.usage.contains(ImageUsageFlags::SAMPLED) {
+            bail!("Image with usage {:?} must include SAMPLED usage to reduce its luminance", info.usage);
+        }
+
+        let device = &render_context.device;
+        let resource_manager = &render_context.resource_manager;
+        let (width, height) = (info.image_extent.width, info.image_extent.height);
+
+        let result = self.run(render_context, 0, self.max_luminance_pipeline.pipeline, |command_buffer, result_handle| {
+            let push_constant = [ImageReduceConstants {
+                source_image_handle: image.handle,
+                result_buffer_handle: result_handle,
+                width,
+                height
+            }];
+            let (_, push_constant_bytes, _) = unsafe { push_constant.align_to::<u8>() };
+            unsafe { device.cmd_push_constants(command_buffer, resource_manager.pipeline_layouts[0], vk::ShaderStageFlags::ALL, 0, push_constant_bytes) };
+            device.dispatch(command_buffer, image_group_count(width), image_group_count(height), 1)
+        })?;
+
+        Ok(f32::from_bits(result))
+    }
+
+    /// Run a reduction with `pipeline` into a result buffer initialized to `identity`, recording its dispatch with `dispatch`.
+    fn run<F>(&self, render_context: &RenderContext, identity: u32, pipeline: vk::Pipeline, dispatch: F) -> Result<u32>
+    where
+        F: FnOnce(vk::CommandBuffer, ResourceHandle) -> Result<()>
+    {
+        let device = &render_context.device;
+        let resource_manager = &render_context.resource_manager;
+
+        let info = BufferInfo::new(
+            size_of::<u32>(),
+            BufferUsageFlags::STORAGE_BUFFER | BufferUsageFlags::TRANSFER_DST,
+            MemoryLocation::GpuToCpu
+        );
+        let result_buffer = device.create_buffer("Reduction Result", info, None)?;
+        let result_handle = resource_manager.new_buffer_handle(&result_buffer);
+
+        let mut submitted_fence = None;
+        let result = device.run_once_with_fence(QueueFamily::GRAPHICS, |command_buffer, fence| {
+            submitted_fence = Some(fence);
+            resource_manager.bind_compute(command_buffer, fence);
+
+            // Initialize the result before the dispatch combines into it, and make it visible to the host after
+            unsafe {
+                device.cmd_fill_buffer(command_buffer, result_buffer.buffer, 0, vk::WHOLE_SIZE, identity);
+                let barrier = vk::MemoryBarrier::builder()
+                    .src_access_mask(vk::AccessFlags::TRANSFER_WRITE)
+                    .dst_access_mask(vk::AccessFlags::SHADER_READ | vk::AccessFlags::SHADER_WRITE);
+                device.cmd_pipeline_barrier(
+                    command_buffer,
+                    vk::PipelineStageFlags::TRANSFER,
+                    vk::PipelineStageFlags::COMPUTE_SHADER,
+                    vk::DependencyFlags::empty(),
+                    slice::from_ref(&barrier),
+                    &[],
+                    &[]
+                );
+                device.cmd_bind_pipeline(command_buffer, vk::PipelineBindPoint::COMPUTE, pipeline);
+            }
+
+            dispatch(command_buffer, result_handle)?;
+
+            unsafe {
+                let barrier = vk::MemoryBarrier::builder()
+                    .src_access_mask(vk::AccessFlags::SHADER_WRITE)
+                    .dst_access_mask(vk::AccessFlags::HOST_READ);
+                device.cmd_pipeline_barrier(
+                    command_buffer,
+                    vk::PipelineStageFlags::COMPUTE_SHADER,
+                    vk::PipelineStageFlags::HOST,
+                    vk::DependencyFlags::empty(),
+                    slice::from_ref(&barrier),
+                    &[],
+                    &[]
+                );
+            }
+
+            Ok(())
+        });
+
+        // The fence is destroyed by run_once_with_fence
+        if let Some(fence) = submitted_fence {
+            resource_manager.forget_fences(slice::from_ref(&fence));
+        }
+        resource_manager.recycle_handle(ResourceType::StorageBuffer, result_handle);
+        result?;
+
+        Ok(result_buffer.read_buffer::<u32>()?[0])
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const VALUES: [u32; 6] = [7, 3, 12, 0, 5, 9];
+
+    #[test]
+    fn reduce_known_values() {
+        assert_eq!(ReduceOp::Sum.reduce(&VALUES), 36);
+        assert_eq!(ReduceOp::Min.reduce(&VALUES), 0);
+        assert_eq!(ReduceOp::Max.reduce(&VALUES), 12);
+    }
+
+    #[test]
+    fn reduce_empty_and_wrapping() {
+        assert_eq!(ReduceOp::Min.reduce(&[]), u32::MAX);
+        assert_eq!(ReduceOp::Max.reduce(&[]), 0);
+        assert_eq!(ReduceOp::Sum.reduce(&[u32::MAX, 2]), 1);
+    }
+
+    #[test]
+    fn group_counts() {
+        assert_eq!(reduce_group_count(1, 65535), 1);
+        assert_eq!(reduce_group_count(64, 65535), 1);
+        assert_eq!(reduce_group_count(65, 65535), 2);
+        // Large reductions stride over the values instead of exceeding the limit
+        assert_eq!(reduce_group_count(u32::MAX, 65535), 65535);
+        assert_eq!(image_group_count(1920), 240);
+        assert_eq!(image_group_count(1081), 136);
+    }
+
+    #[test]
+    fn luminance_bits_order_like_floats() {
+        let luminances = [0.0_f32, 1.0e-8, 0.25, 1.0, 16.5, 1.0e4];
+        let bits: Vec<u32> = luminances.iter().map(|luminance| luminance.to_bits()).collect();
+
+        assert!(bits.windows(2).all(|pair| pair[0] < pair[1]));
+        assert_eq!(f32::from_bits(ReduceOp::Max.reduce(&bits)), 1.0e4);
+        assert_eq!(exposure_for_max_luminance(4.0), 0.25);
+    }
+}
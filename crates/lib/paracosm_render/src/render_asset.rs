@@ -18,6 +18,8 @@ use std::{
 
 pub enum PrepareAssetError {
     RetryNextUpdate,
+    /// The asset has nothing to prepare, e.g. an empty mesh, and should not be retried
+    Skip,
 }
 
 /// Describes how an asset gets prepared for rendering.
@@ -39,6 +41,16 @@ pub trait RenderAsset: Asset {
 
 
 
+/// Name for a GPU resource of an asset, e.g. "Vertex Buffer (monkey)", or just `resource` for unnamed assets.
+pub(crate) fn asset_debug_name(resource: &str, asset_name: Option<&str>) -> String {
+    match asset_name {
+        Some(asset_name) => format!("{} ({})", resource, asset_name),
+        None => resource.to_string()
+    }
+}
+
+
+
 #[derive(Clone, Hash, Debug, Default, PartialEq, Eq, SystemLabel)]
 pub enum PrepareAssetLabel {
     PreAssetPrepare,
@@ -49,6 +61,7 @@ pub enum PrepareAssetLabel {
 
 pub struct RenderAssetPlugin<A: RenderAsset> {
     prepare_asset_label: PrepareAssetLabel,
+    prepare_budget: Option<usize>,
     phantom: PhantomData<fn() -> A>,
 }
 
@@ -56,15 +69,24 @@ impl<A: RenderAsset> RenderAssetPlugin<A> {
     pub fn with_prepare_asset_label(prepare_asset_label: PrepareAssetLabel) -> Self {
         Self {
             prepare_asset_label,
+            prepare_budget: None,
             phantom: PhantomData,
         }
     }
+
+    /// Limit preparation to `assets_per_update` assets each update, spreading uploads of many assets across frames.
+    /// A budget of 0 is treated as 1, so queued assets are always prepared eventually.
+    pub fn with_prepare_budget(mut self, assets_per_update: usize) -> Self {
+        self.prepare_budget = Some(assets_per_update);
+        self
+    }
 }
 
 impl<A: RenderAsset> Default for RenderAssetPlugin<A> {
     fn default() -> Self {
         Self {
             prepare_asset_label: Default::default(),
+            prepare_budget: None,
             phantom: PhantomData,
         }
     }
@@ -85,7 +107,10 @@ impl<A: RenderAsset> Plugin for RenderAssetPlugin<A> {
         };
 
         app.init_resource::<RenderAssets<A>>()
-            .init_resource::<PrepareAssetsQueue<A>>()
+            .insert_resource(PrepareAssetsQueue::<A> {
+                budget: self.prepare_budget,
+                ..Default::default()
+            })
             .add_system(prepare_asset_system.at_start());
     }
 }
@@ -106,19 +131,41 @@ impl<A: RenderAsset> Default for RenderAssets<A> {
 
 // TODO: consider storing inside system?
 /// All assets that should be prepared next frame.
+/// 
+/// With a `budget`, at most that many assets are prepared each update, highest priority first.
+/// A budget of 0 prepares one asset each update.
 #[derive(Resource)]
 pub struct PrepareAssetsQueue<A: RenderAsset> {
     assets: Vec<Handle<A>>,
+    pub budget: Option<usize>,
+    priorities: HashMap<Handle<A>, i32>,
 }
 
 impl<A: RenderAsset> Default for PrepareAssetsQueue<A> {
     fn default() -> Self {
         Self {
             assets: Default::default(),
+            budget: None,
+            priorities: Default::default(),
         }
     }
 }
 
+impl<A: RenderAsset> PrepareAssetsQueue<A> {
+    /// Set the preparation priority of an asset, higher first. Assets default to priority 0.
+    pub fn set_priority(&mut self, handle: &Handle<A>, priority: i32) {
+        self.priorities.insert(handle.clone_weak(), priority);
+    }
+
+    pub fn len(&self) -> usize {
+        self.assets.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.assets.is_empty()
+    }
+}
+
 
 
 fn prepare_assets<A: RenderAsset>(
@@ -133,27 +180,135 @@ fn prepare_assets<A: RenderAsset>(
             AssetEvent::Created { handle } |
             AssetEvent::Modified { handle } => {
                 info!("Mesh Asset created or modified!");
-                prepare_queue.assets.push(handle.clone_weak());
+                if !prepare_queue.assets.contains(handle) {
+                    prepare_queue.assets.push(handle.clone_weak());
+                }
             },
             AssetEvent::Removed { handle } => {
                 render_assets.remove(&handle);
+                prepare_queue.assets.retain(|queued| queued != handle);
+                prepare_queue.priorities.remove(handle);
             }
         }
     }
 
+    // Take the highest priority assets within budget, leaving the rest queued for following updates
+    let mut queued_assets = std::mem::take(&mut prepare_queue.assets);
+    let priorities = &prepare_queue.priorities;
+    queued_assets.sort_by_key(|handle| std::cmp::Reverse(priorities.get(handle).copied().unwrap_or_default()));
+    if let Some(budget) = prepare_queue.budget.map(|budget| budget.max(1)) {
+        if queued_assets.len() > budget {
+            prepare_queue.assets = queued_assets.split_off(budget);
+        }
+    }
+
     let mut param = param.into_inner();
-    let queued_assets = std::mem::take(&mut prepare_queue.assets);
     for handle in queued_assets {
         let asset = assets.get(&handle)
             .expect("Failed to get asset from handle");
         match A::prepare_asset(asset, &mut param) {
             Ok(prepared_asset) => {
+                prepare_queue.priorities.remove(&handle);
                 render_assets.insert(handle, prepared_asset);
             },
             Err(PrepareAssetError::RetryNextUpdate) => {
                 error!("PrepareAssetError");
                 prepare_queue.assets.push(handle);
+            },
+            Err(PrepareAssetError::Skip) => {
+                // Drop any earlier preparation, e.g. of a mesh modified to be empty
+                render_assets.remove(&handle);
+                prepare_queue.priorities.remove(&handle);
+                debug!("Skipped preparing asset {:?}", handle);
             }
         }
     }
-}
\ No newline at end of file
+}
+
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use bevy_asset::{AddAsset, AssetPlugin};
+    use bevy_reflect::TypeUuid;
+
+    #[derive(TypeUuid)]
+    #[uuid = "5c4f3a1e-8d2b-4e7a-9f61-0b3d2c8e7a15"]
+    struct TestAsset {
+        empty: bool
+    }
+
+    impl RenderAsset for TestAsset {
+        type PreparedAsset = ();
+        type Param = ();
+
+        fn prepare_asset(
+            source_asset: &Self,
+            _param: &mut SystemParamItem<Self::Param>,
+        ) -> Result<Self::PreparedAsset, PrepareAssetError> {
+            match source_asset.empty {
+                true => Err(PrepareAssetError::Skip),
+                false => Ok(())
+            }
+        }
+    }
+
+    /// An app preparing `count` test assets with `budget`, and the handles keeping them alive.
+    /// 
+    /// Creation events are sent at the end of the first update, so preparation starts on the second.
+    fn budgeted_app(count: usize, budget: usize) -> (App, Vec<Handle<TestAsset>>) {
+        let mut app = App::new();
+        app.add_plugin(AssetPlugin::default())
+            .add_asset::<TestAsset>()
+            .add_plugin(RenderAssetPlugin::<TestAsset>::default().with_prepare_budget(budget));
+
+        let handles = (0..count)
+            .map(|_| app.world.resource_mut::<Assets<TestAsset>>().add(TestAsset { empty: false }))
+            .collect();
+        app.update();
+        (app, handles)
+    }
+
+    fn prepared_count(app: &App) -> usize {
+        app.world.resource::<RenderAssets<TestAsset>>().len()
+    }
+
+    #[test]
+    fn budget_spreads_preparation_across_updates() {
+        let (mut app, handles) = budgeted_app(100, 10);
+        assert_eq!(prepared_count(&app), 0);
+
+        for update in 1..=10 {
+            app.update();
+            assert_eq!(prepared_count(&app), update * 10);
+        }
+        assert_eq!(prepared_count(&app), handles.len());
+        assert!(app.world.resource::<PrepareAssetsQueue<TestAsset>>().is_empty());
+    }
+
+    #[test]
+    fn zero_budget_still_prepares() {
+        let (mut app, handles) = budgeted_app(3, 0);
+
+        for update in 1..=3 {
+            app.update();
+            assert_eq!(prepared_count(&app), update);
+        }
+        assert_eq!(prepared_count(&app), handles.len());
+    }
+
+    #[test]
+    fn skipped_assets_drop_their_preparation() {
+        let (mut app, handles) = budgeted_app(1, 1);
+        app.update();
+        assert!(app.world.resource::<RenderAssets<TestAsset>>().contains_key(&handles[0]));
+
+        // Modification events are sent at the end of the update, and handled on the next
+        app.world.resource_mut::<Assets<TestAsset>>().get_mut(&handles[0]).unwrap().empty = true;
+        app.update();
+        app.update();
+        assert_eq!(prepared_count(&app), 0);
+        assert!(app.world.resource::<PrepareAssetsQueue<TestAsset>>().is_empty());
+    }
+}
@@ -0,0 +1,63 @@
+use rust_shaders_shared::{glam::Vec3, ObjectData};
+
+
+
+/// Order in which objects of a render phase are drawn, by distance from the camera.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum SortOrder {
+    /// Nearest first, so opaque objects behind are rejected by early depth testing
+    #[default]
+    FrontToBack,
+    /// Farthest first, so transparent objects blend over what is behind them.
+    /// Unused until materials can be transparent, the renderer only draws opaque objects.
+    BackToFront,
+}
+
+/// Sort key of an object: its squared distance from `eye`.
+pub fn sort_key(object: &ObjectData, eye: Vec3) -> f32 {
+    object.model_matrix.w_axis.truncate().distance_squared(eye)
+}
+
+/// Sort `objects` in `order` as seen from `eye`.
+pub fn sort_objects(objects: &mut [ObjectData], eye: Vec3, order: SortOrder) {
+    objects.sort_by(|a, b| {
+        let ordering = sort_key(a, eye).total_cmp(&sort_key(b, eye));
+        match order {
+            SortOrder::FrontToBack => ordering,
+            SortOrder::BackToFront => ordering.reverse()
+        }
+    });
+}
+
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rust_shaders_shared::{glam::{vec3, Mat4}, ResourceHandle};
+
+    /// An object at `translation`, identified by its texture handle index
+    fn object(id: u32, translation: Vec3) -> ObjectData {
+        ObjectData::new(Mat4::from_translation(translation), ResourceHandle::new(id), ResourceHandle::new(0))
+    }
+
+    fn ids(objects: &[ObjectData]) -> Vec<u32> {
+        objects.iter().map(|object| object.texture_handle.index()).collect()
+    }
+
+    #[test]
+    fn transparent_objects_sort_back_to_front() {
+        let eye = vec3(0.0, 1.0, 0.0);
+        let mut objects = [
+            object(0, vec3(0.0, 1.0, -5.0)),
+            object(1, vec3(0.0, 1.0, -20.0)),
+            object(2, vec3(3.0, 1.0, -10.0)),
+        ];
+
+        sort_objects(&mut objects, eye, SortOrder::BackToFront);
+        assert_eq!(ids(&objects), [1, 2, 0]);
+
+        sort_objects(&mut objects, eye, SortOrder::FrontToBack);
+        assert_eq!(ids(&objects), [0, 2, 1]);
+    }
+}
@@ -0,0 +1,87 @@
+use super::ResourceManager;
+
+use anyhow::{bail, Context, Result};
+
+use paracosm_gpu::resource::buffer::*;
+use rust_shaders_shared::ResourceHandle;
+
+use std::{
+    marker::PhantomData,
+    mem::size_of
+};
+
+
+
+/// One bindless storage buffer of `T` per frame in flight, for data rewritten every frame.
+///
+/// Writing only the current frame's buffer avoids overwriting data still read by previous frames' commands.
+pub struct FrameRingBuffer<T> {
+    buffers: Vec<(Buffer, ResourceHandle)>,
+    capacity: usize,
+    phantom: PhantomData<fn() -> T>,
+}
+
+impl<T> FrameRingBuffer<T> {
+    /// Write `data` to the buffer for `frame_index`, returning the bindless handle to use for that frame.
+    pub fn write_current(&self, frame_index: usize, data: &Vec<T>) -> Result<ResourceHandle> {
+        if data.len() > self.capacity {
+            bail!("FrameRingBuffer capacity is {} elements, but {} were written", self.capacity, data.len());
+        }
+
+        let (buffer, handle) = &self.buffers[frame_index % self.buffers.len()];
+        buffer.write_buffer(data);
+
+        Ok(*handle)
+    }
+
+    /// Bindless handle of the buffer for `frame_index`.
+    pub fn handle(&self, frame_index: usize) -> ResourceHandle {
+        self.buffers[frame_index % self.buffers.len()].1
+    }
+
+    /// Maximum number of elements written each frame.
+    pub fn capacity(&self) -> usize {
+        self.capacity
+    }
+
+    /// Number of per-frame buffers.
+    pub fn frame_count(&self) -> usize {
+        self.buffers.len()
+    }
+}
+
+impl ResourceManager {
+    /// Create a [`FrameRingBuffer`] holding up to `capacity` elements for each of `frame_count` frames.
+    ///
+    /// `usage` is extended with `STORAGE_BUFFER` for bindless access.
+    pub fn create_frame_ring_buffer<T>(
+        &self,
+        name: &str,
+        frame_count: usize,
+        capacity: usize,
+        usage: BufferUsageFlags
+    ) -> Result<FrameRingBuffer<T>> {
+        if frame_count == 0 {
+            bail!("FrameRingBuffer \"{}\" needs at least one frame", name);
+        }
+
+        let mut buffers = Vec::with_capacity(frame_count);
+        for frame in 0..frame_count {
+            let info = BufferInfo::new(
+                size_of::<T>() * capacity,
+                usage | BufferUsageFlags::STORAGE_BUFFER,
+                MemoryLocation::CpuToGpu
+            );
+            let buffer = self.device.create_buffer(format!("{} (Frame {})", name, frame).as_str(), info, None)
+                .context(format!("FrameRingBuffer \"{}\" should create a buffer for frame {}", name, frame))?;
+            let handle = self.new_buffer_handle(&buffer);
+            buffers.push((buffer, handle));
+        }
+
+        Ok(FrameRingBuffer {
+            buffers,
+            capacity,
+            phantom: PhantomData
+        })
+    }
+}
@@ -1,10 +1,12 @@
+pub mod frame_ring_buffer;
 pub mod pipeline;
 pub mod shader;
 
 //----------------------------------------------//
 
 use anyhow::{Result, Context};
-use ash::vk;
+use ash::vk::{self, Handle};
+use bevy_log::prelude::*;
 
 use std::{
     collections::{
@@ -12,6 +14,7 @@ use std::{
         VecDeque
     },
     mem::size_of,
+    slice,
     sync::Mutex
 };
 
@@ -19,6 +22,7 @@ use paracosm_gpu::{
     device::Device,
     resource::{
         buffer::*,
+        buffer_arena::BufferRange,
         image::*,
         sampler::*
     }
@@ -35,31 +39,51 @@ use rust_shaders_shared::{
 
 
 
+/// Bindless binding a [`ResourceHandle`] indexes into
 #[derive(Copy, Clone, Eq, PartialEq, Hash, Debug, Default)]
-enum ResourceType {
+pub enum ResourceType {
     #[default] StorageBuffer,
     StorageImage,
     SampledImage,
     Sampler
 }
 
+impl ResourceType {
+    /// Bindless binding of handles of this type
+    pub fn binding(&self) -> u32 {
+        match self {
+            ResourceType::StorageBuffer => STORAGE_BUFFER_BINDING,
+            ResourceType::StorageImage => STORAGE_IMAGE_BINDING,
+            ResourceType::SampledImage => SAMPLED_IMAGE_BINDING,
+            ResourceType::Sampler => SAMPLER_BINDING
+        }
+    }
+}
+
 #[derive(Default)]
 struct ResourcePool {
     resource_type: ResourceType,
+    capacity: u32,
     pub(self) next_index: Mutex<u32>,
-    pub(self) recycled_handles: Mutex<VecDeque<ResourceHandle>>
+    pub(self) recycled_handles: Mutex<VecDeque<ResourceHandle>>,
+    /// Resource written to each bound slot
+    pub(self) occupants: Mutex<HashMap<u32, HandleOccupant>>
 }
 
 impl ResourcePool{
-    fn fetch_handle(&self) -> ResourceHandle {
-        self.recycled_handles
+    /// Fetch a free handle for `occupant`, and whether it was recycled, so its slot may still be read by earlier frames.
+    fn fetch_handle(&self, occupant: HandleOccupant) -> (ResourceHandle, bool) {
+        let (handle, recycled) = self.recycled_handles
             .lock()
             .unwrap()
             .pop_front()
             .map_or_else(
-                || ResourceHandle::new(self.increment_index()), 
-                |recycled_handle| recycled_handle
-            )
+                || (ResourceHandle::new(self.increment_index()), false), 
+                |recycled_handle| (recycled_handle, true)
+            );
+        self.occupants.lock().unwrap().insert(handle.index(), occupant);
+
+        (handle, recycled)
     }
 
     fn increment_index(&self) -> u32 {
@@ -69,38 +93,163 @@ impl ResourcePool{
 
         current_index
     }
+
+    /// Return `handle` for reuse. Returns `false` without recycling it if it isn't allocated from this pool,
+    /// or was already recycled.
+    fn recycle(&self, handle: ResourceHandle) -> bool {
+        if !self.is_bound(handle.index()) {
+            return false;
+        }
+
+        self.recycled_handles.lock().unwrap().push_back(handle);
+        self.occupants.lock().unwrap().remove(&handle.index());
+        true
+    }
+
+    /// Resource occupying slot `index`, if it is bound
+    fn occupant(&self, index: u32) -> Option<HandleOccupant> {
+        self.occupants.lock().unwrap().get(&index).copied()
+    }
+
+    /// Whether slot `index` holds a descriptor, allocated and not recycled
+    fn is_bound(&self, index: u32) -> bool {
+        index < *self.next_index.lock().unwrap()
+            && !self.recycled_handles.lock().unwrap().iter().any(|handle| handle.index() == index)
+    }
+
+    fn counts(&self) -> DescriptorCounts {
+        let allocated = *self.next_index.lock().unwrap();
+        let used = allocated - self.recycled_handles.lock().unwrap().len() as u32;
+
+        DescriptorCounts {
+            used,
+            free: self.capacity.saturating_sub(used)
+        }
+    }
+}
+
+/// Used and free descriptor counts for one bindless binding
+#[derive(Copy, Clone, Eq, PartialEq, Debug, Default)]
+pub struct DescriptorCounts {
+    pub used: u32,
+    pub free: u32
+}
+
+/// Descriptor slot of a [`ResourceHandle`] in one bindless binding, see [`ResourceManager::describe_handle`]
+#[derive(Copy, Clone, Eq, PartialEq, Debug)]
+pub struct HandleInfo {
+    pub resource_type: ResourceType,
+    pub index: u32,
+    /// Resource currently occupying the slot, `None` if it is unallocated or recycled
+    pub occupant: Option<HandleOccupant>
+}
+
+/// Resource written to a bindless descriptor slot
+#[derive(Copy, Clone, Eq, PartialEq, Debug)]
+pub enum HandleOccupant {
+    /// `range` bytes of `buffer` from `offset`, `vk::WHOLE_SIZE` for the rest of the buffer
+    Buffer {
+        buffer: vk::Buffer,
+        offset: u64,
+        range: u64
+    },
+    Image {
+        image: vk::Image,
+        image_view: vk::ImageView,
+        format: vk::Format
+    },
+    Sampler(vk::Sampler),
+}
+
+impl HandleOccupant {
+    fn image(image: &Image) -> Self {
+        Self::Image {
+            image: image.image,
+            image_view: image.image_view,
+            format: image.info.image_format
+        }
+    }
+}
+
+/// Snapshot of bindless descriptor usage, for spotting handle leaks during development
+#[derive(Copy, Clone, Eq, PartialEq, Debug, Default)]
+pub struct DescriptorStats {
+    pub storage_buffers: DescriptorCounts,
+    pub storage_images: DescriptorCounts,
+    pub sampled_images: DescriptorCounts,
+    pub samplers: DescriptorCounts
+}
+
+/// A descriptor update, owning its resource info so it can be deferred.
+#[derive(Copy, Clone)]
+enum DescriptorWrite {
+    Buffer {
+        binding: u32,
+        index: u32,
+        info: vk::DescriptorBufferInfo
+    },
+    Image {
+        binding: u32,
+        index: u32,
+        descriptor_type: vk::DescriptorType,
+        info: vk::DescriptorImageInfo
+    }
+}
+
+impl DescriptorWrite {
+    /// Binding and array index of the descriptor written
+    fn slot(&self) -> (u32, u32) {
+        match *self {
+            DescriptorWrite::Buffer { binding, index, .. } => (binding, index),
+            DescriptorWrite::Image { binding, index, .. } => (binding, index)
+        }
+    }
 }
 
 pub struct ResourceManager {
     pub device: Device,
     descriptor_pool: vk::DescriptorPool,
     descriptor_set_layout: vk::DescriptorSetLayout,
-    pub(crate) descriptor_set: vk::DescriptorSet,
+    pub(crate) descriptor_sets: Vec<vk::DescriptorSet>,
+    pending_writes: Mutex<Vec<Vec<DescriptorWrite>>>,
+    /// Fences of the frames that bound each descriptor set, which must signal before its pending writes are applied
+    set_fences: Mutex<Vec<Vec<vk::Fence>>>,
+    next_set: Mutex<usize>,
     pub pipeline_layouts: Vec<vk::PipelineLayout>,
     resource_pools: HashMap<ResourceType, ResourcePool>
 }
 
 impl ResourceManager {
+    /// Create a [`ResourceManager`] with a single bindless descriptor set, updated in place.
     pub(crate) fn new(device: &Device) -> Result<ResourceManager> {
+        Self::with_frame_sets(device, 1)
+    }
+
+    /// Create a [`ResourceManager`] with one bindless descriptor set per frame in flight.
+    /// 
+    /// Sets are bound in turn by [`ResourceManager::bind`]. Descriptor updates are deferred for each set until
+    /// the frames that last bound it have completed, so updates never modify a set still in use by their commands.
+    pub(crate) fn with_frame_sets(device: &Device, frame_count: usize) -> Result<ResourceManager> {
         let limits = device.limits();
+        let set_count = frame_count.max(1) as u32;
         
         // Create bindless descriptor pool
         let pool_sizes = vec![
             vk::DescriptorPoolSize {
                 ty: vk::DescriptorType::STORAGE_BUFFER,
-                descriptor_count: limits.max_descriptor_set_storage_buffers
+                descriptor_count: limits.max_descriptor_set_storage_buffers * set_count
             },
             vk::DescriptorPoolSize {
                 ty: vk::DescriptorType::STORAGE_IMAGE,
-                descriptor_count: limits.max_descriptor_set_storage_images
+                descriptor_count: limits.max_descriptor_set_storage_images * set_count
             },
             vk::DescriptorPoolSize {
                 ty: vk::DescriptorType::SAMPLED_IMAGE,
-                descriptor_count: limits.max_descriptor_set_sampled_images
+                descriptor_count: limits.max_descriptor_set_sampled_images * set_count
             },
             vk::DescriptorPoolSize {
                 ty: vk::DescriptorType::SAMPLER,
-                descriptor_count: limits.max_descriptor_set_samplers
+                descriptor_count: limits.max_descriptor_set_samplers * set_count
             },
         ];
 
@@ -159,12 +308,15 @@ impl ResourceManager {
 
         let descriptor_set_layouts = vec![descriptor_set_layout];
 
-        // Create descriptor set
-        let descriptor_set = unsafe { device.allocate_descriptor_sets(
+        // Create descriptor sets
+        let frame_set_layouts = vec![descriptor_set_layout; set_count as usize];
+        let descriptor_sets = unsafe { device.allocate_descriptor_sets(
             &vk::DescriptorSetAllocateInfo::builder()
                 .descriptor_pool(descriptor_pool)
-                .set_layouts(&descriptor_set_layouts)
-        ).context("Device should allocate descriptor sets from descriptor pool")?[0] };
+                .set_layouts(&frame_set_layouts)
+        ).context("Device should allocate descriptor sets from descriptor pool")? };
+        let pending_writes = Mutex::new(vec![vec![]; descriptor_sets.len()]);
+        let set_fences = Mutex::new(vec![vec![]; descriptor_sets.len()]);
 
         // Create pipeline layouts
         let push_constants = vec![
@@ -188,18 +340,22 @@ impl ResourceManager {
         let mut resource_pools = HashMap::new();
         resource_pools.insert(ResourceType::StorageBuffer, ResourcePool {
             resource_type: ResourceType::StorageBuffer,
+            capacity: limits.max_descriptor_set_storage_buffers,
             ..Default::default()
         });
         resource_pools.insert(ResourceType::StorageImage, ResourcePool {
             resource_type: ResourceType::StorageImage,
+            capacity: limits.max_descriptor_set_storage_images,
             ..Default::default()
         });
         resource_pools.insert(ResourceType::SampledImage, ResourcePool {
             resource_type: ResourceType::SampledImage,
+            capacity: limits.max_descriptor_set_sampled_images,
             ..Default::default()
         });
         resource_pools.insert(ResourceType::Sampler, ResourcePool {
             resource_type: ResourceType::Sampler,
+            capacity: limits.max_descriptor_set_samplers,
             ..Default::default()
         });
 
@@ -207,13 +363,22 @@ impl ResourceManager {
             device: device.clone(),
             descriptor_pool,
             descriptor_set_layout,
-            descriptor_set,
+            descriptor_sets,
+            pending_writes,
+            set_fences,
+            next_set: Mutex::new(0),
             pipeline_layouts,
             resource_pools,
         })
     }
 
-    pub fn bind(&self, command_buffer: vk::CommandBuffer) {
+    /// Bind the next descriptor set, first applying any updates deferred for it.
+    /// 
+    /// `frame_fence` signals when the frame recording `command_buffer` completes. It must have been waited on
+    /// before being reset for this frame, so earlier frames using it are complete.
+    pub fn bind(&self, command_buffer: vk::CommandBuffer, frame_fence: vk::Fence) {
+        let descriptor_set = self.next_descriptor_set(frame_fence);
+
         unsafe {
             // Bind global descriptor set
             self.device.cmd_bind_descriptor_sets(
@@ -221,7 +386,7 @@ impl ResourceManager {
                 vk::PipelineBindPoint::GRAPHICS,
                 self.pipeline_layouts[0],
                 0,
-                &[self.descriptor_set],
+                &[descriptor_set],
                 &[]
             );
             self.device.cmd_bind_descriptor_sets(
@@ -229,7 +394,7 @@ impl ResourceManager {
                 vk::PipelineBindPoint::COMPUTE,
                 self.pipeline_layouts[0],
                 0,
-                &[self.descriptor_set],
+                &[descriptor_set],
                 &[]
             );
             self.device.cmd_bind_descriptor_sets(
@@ -237,149 +402,366 @@ impl ResourceManager {
                 vk::PipelineBindPoint::RAY_TRACING_KHR,
                 self.pipeline_layouts[0],
                 0,
-                &[self.descriptor_set],
+                &[descriptor_set],
                 &[]
             );
         }
     }
 
-    pub(crate) fn recycle_handle(&self, handle: ResourceHandle) {
-        let handle_type = ResourceType::StorageBuffer; // TODO: Get handle type from handle itself. Until then recycle won't work properly.
+    /// Bind the next descriptor set for compute pipelines only, e.g. for commands recorded on a compute queue.
+    /// 
+    /// As with [`ResourceManager::bind`], `frame_fence` must signal once `command_buffer` completes.
+    /// Pass it to [`ResourceManager::forget_fences`] before destroying it.
+    pub(crate) fn bind_compute(&self, command_buffer: vk::CommandBuffer, frame_fence: vk::Fence) {
+        let descriptor_set = self.next_descriptor_set(frame_fence);
 
-        let resource_pool = self.resource_pools.get(&handle_type)
+        unsafe {
+            self.device.cmd_bind_descriptor_sets(
+                command_buffer,
+                vk::PipelineBindPoint::COMPUTE,
+                self.pipeline_layouts[0],
+                0,
+                &[descriptor_set],
+                &[]
+            );
+        }
+    }
+
+    /// Rotate to the next descriptor set, applying its deferred updates, and record `frame_fence` as using it.
+    fn next_descriptor_set(&self, frame_fence: vk::Fence) -> vk::DescriptorSet {
+        let set_index = {
+            let mut next_set = self.next_set.lock().unwrap();
+            let set_index = *next_set;
+            *next_set = (set_index + 1) % self.descriptor_sets.len();
+            set_index
+        };
+        let descriptor_set = self.descriptor_sets[set_index];
+
+        // Wait for other frames still using the set, then apply deferred updates
+        let mut set_fences = self.set_fences.lock().unwrap();
+        let mut fences = std::mem::take(&mut set_fences[set_index]);
+        fences.retain(|&fence| fence != frame_fence);
+        fences.sort_unstable_by_key(|fence| fence.as_raw());
+        fences.dedup();
+        let set_idle = fences.is_empty()
+            || unsafe { self.device.wait_for_fences(&fences, true, 1000000000) }.is_ok();
+        match set_idle {
+            true => {
+                let pending_writes = std::mem::take(&mut self.pending_writes.lock().unwrap()[set_index]);
+                self.write_descriptors(descriptor_set, &pending_writes);
+            },
+            // Keep the updates deferred until the set's frames complete
+            false => {
+                warn!("Descriptor set {} is still in use, deferring its descriptor updates", set_index);
+                set_fences[set_index] = fences;
+            }
+        }
+        set_fences[set_index].push(frame_fence);
+
+        descriptor_set
+    }
+
+    /// Return a handle created for `resource_type` for reuse once its resource is destroyed.
+    /// 
+    /// Handles don't record their binding, so `resource_type` must be the type the handle was created for.
+    pub(crate) fn recycle_handle(&self, resource_type: ResourceType, handle: ResourceHandle) {
+        let resource_pool = self.resource_pools.get(&resource_type)
             .expect("ResourceHandle should have a valid ResourceType");
 
-        resource_pool.recycled_handles
-            .lock()
-            .unwrap()
-            .push_back(handle);
+        self.discard_pending_writes(resource_type.binding(), handle.index());
+        if !resource_pool.recycle(handle) {
+            warn!("Ignoring recycle of {:?} handle {}, which is unallocated or already recycled", resource_type, handle.index());
+        }
     }
 
+    /// Create a storage buffer handle for `buffer`, which may have other usages such as `INDIRECT_BUFFER` as well.
     pub(crate) fn new_buffer_handle(&self, buffer: &Buffer) -> ResourceHandle {
+        assert!(
+            buffer.info.usage.contains(BufferUsageFlags::STORAGE_BUFFER),
+            "Buffer with usage {:?} must include STORAGE_BUFFER usage for bindless access",
+            buffer.info.usage
+        );
+
         let resource_pool = self.resource_pools.get(&ResourceType::StorageBuffer)
             .expect("StorageBuffer resource pool should exist");
-        let handle = resource_pool.fetch_handle();
+        let (handle, recycled) = resource_pool.fetch_handle(HandleOccupant::Buffer {
+            buffer: buffer.buffer,
+            offset: 0,
+            range: vk::WHOLE_SIZE
+        });
 
-        let buffer_info = [
-            vk::DescriptorBufferInfo::builder()
+        self.queue_write(DescriptorWrite::Buffer {
+            binding: STORAGE_BUFFER_BINDING,
+            index: handle.index(),
+            info: vk::DescriptorBufferInfo::builder()
                 .buffer(buffer.buffer)
                 .offset(0)
                 .range(vk::WHOLE_SIZE)
-                .build(),
-        ];
+                .build()
+        }, recycled);
 
-        let write = [
-            vk::WriteDescriptorSet::builder()
-                .dst_set(self.descriptor_set)
-                .dst_binding(STORAGE_BUFFER_BINDING)
-                .dst_array_element(handle.index())
-                .descriptor_type(vk::DescriptorType::STORAGE_BUFFER)
-                .buffer_info(&buffer_info)
-                .build(),
-        ];
+        handle
+    }
 
-        unsafe { self.device.update_descriptor_sets(&write, &[]); }
+    /// Create a storage buffer handle for a [`BufferArena`](paracosm_gpu::resource::buffer_arena::BufferArena) range.
+    pub fn new_buffer_range_handle(&self, range: &BufferRange) -> ResourceHandle {
+        assert!(
+            range.usage.contains(BufferUsageFlags::STORAGE_BUFFER),
+            "Buffer range with usage {:?} must include STORAGE_BUFFER usage for bindless access",
+            range.usage
+        );
+
+        let resource_pool = self.resource_pools.get(&ResourceType::StorageBuffer)
+            .expect("StorageBuffer resource pool should exist");
+        let (handle, recycled) = resource_pool.fetch_handle(HandleOccupant::Buffer {
+            buffer: range.buffer,
+            offset: range.offset,
+            range: range.size
+        });
+
+        self.queue_write(DescriptorWrite::Buffer {
+            binding: STORAGE_BUFFER_BINDING,
+            index: handle.index(),
+            info: vk::DescriptorBufferInfo::builder()
+                .buffer(range.buffer)
+                .offset(range.offset)
+                .range(range.size)
+                .build()
+        }, recycled);
 
         handle
     }
 
     pub(crate) fn new_storage_image_handle(&self, image: &Image) -> ResourceHandle {
         let resource_pool = self.resource_pools.get(&ResourceType::StorageImage)
-            .expect("StorageBuffer resource pool should exist");
-        let handle = resource_pool.fetch_handle();
-
-        let image_info = [
-            vk::DescriptorImageInfo::builder()
+            .expect("StorageImage resource pool should exist");
+        let (handle, recycled) = resource_pool.fetch_handle(HandleOccupant::image(image));
+
+        self.queue_write(DescriptorWrite::Image {
+            binding: STORAGE_IMAGE_BINDING,
+            index: handle.index(),
+            descriptor_type: vk::DescriptorType::STORAGE_IMAGE,
+            info: vk::DescriptorImageInfo::builder()
                 .image_layout(ImageLayout::GENERAL)
                 .image_view(image.image_view)
                 .sampler(vk::Sampler::null())
-                .build(),
-        ];
-
-        let write = [
-            vk::WriteDescriptorSet::builder()
-                .dst_set(self.descriptor_set)
-                .dst_binding(STORAGE_IMAGE_BINDING)
-                .dst_array_element(handle.index())
-                .descriptor_type(vk::DescriptorType::STORAGE_IMAGE)
-                .image_info(&image_info)
                 .build()
-        ];
-
-        unsafe { self.device.update_descriptor_sets(&write, &[]); }
+        }, recycled);
 
         handle
     }
 
     pub(crate) fn new_sampled_image_handle(&self, image: &Image) -> ResourceHandle {
         let resource_pool = self.resource_pools.get(&ResourceType::SampledImage)
-            .expect("StorageBuffer resource pool should exist");
-        let handle = resource_pool.fetch_handle();
-
-        let image_info = [
-            vk::DescriptorImageInfo::builder()
+            .expect("SampledImage resource pool should exist");
+        let (handle, recycled) = resource_pool.fetch_handle(HandleOccupant::image(image));
+
+        self.queue_write(DescriptorWrite::Image {
+            binding: SAMPLED_IMAGE_BINDING,
+            index: handle.index(),
+            descriptor_type: vk::DescriptorType::SAMPLED_IMAGE,
+            info: vk::DescriptorImageInfo::builder()
                 .image_layout(ImageLayout::READ_ONLY_OPTIMAL)
                 .image_view(image.image_view)
                 .sampler(vk::Sampler::null())
-                .build(),
-        ];
-
-        let write = [
-            vk::WriteDescriptorSet::builder()
-                .dst_set(self.descriptor_set)
-                .dst_binding(SAMPLED_IMAGE_BINDING)
-                .dst_array_element(handle.index())
-                .descriptor_type(vk::DescriptorType::SAMPLED_IMAGE)
-                .image_info(&image_info)
                 .build()
-        ];
-
-        unsafe { self.device.update_descriptor_sets(&write, &[]); }
+        }, recycled);
 
         handle
     }
 
     pub(crate) fn new_sampler_handle(&self, sampler: &Sampler) -> ResourceHandle {
         let resource_pool = self.resource_pools.get(&ResourceType::Sampler)
-            .expect("StorageBuffer resource pool should exist");
-        let handle = resource_pool.fetch_handle();
-
-        let sampler_info = [
-            vk::DescriptorImageInfo::builder()
+            .expect("Sampler resource pool should exist");
+        let (handle, recycled) = resource_pool.fetch_handle(HandleOccupant::Sampler(**sampler));
+
+        self.queue_write(DescriptorWrite::Image {
+            binding: SAMPLER_BINDING,
+            index: handle.index(),
+            descriptor_type: vk::DescriptorType::SAMPLER,
+            info: vk::DescriptorImageInfo::builder()
                 .image_layout(ImageLayout::UNDEFINED)
                 .image_view(vk::ImageView::null())
                 .sampler(**sampler)
-                .build(),
-        ];
-
-        let write = [
-            vk::WriteDescriptorSet::builder()
-                .dst_set(self.descriptor_set)
-                .dst_binding(SAMPLER_BINDING)
-                .dst_array_element(handle.index())
-                .descriptor_type(vk::DescriptorType::SAMPLER)
-                .image_info(&sampler_info)
                 .build()
-        ];
-
-        unsafe { self.device.update_descriptor_sets(&write, &[]); }
+        }, recycled);
 
         handle
     }
 
+    /// Stop tracking frame fences passed to [`ResourceManager::bind`] before they are destroyed,
+    /// e.g. when a surface is reconfigured. Their frames must be complete.
+    pub(crate) fn forget_fences(&self, fences: &[vk::Fence]) {
+        self.set_fences
+            .lock()
+            .unwrap()
+            .iter_mut()
+            .for_each(|set_fences| set_fences.retain(|fence| !fences.contains(fence)));
+    }
+
+    /// Drop deferred writes of a released descriptor slot, so sets applying them later never reference its destroyed resource.
+    fn discard_pending_writes(&self, binding: u32, index: u32) {
+        self.pending_writes
+            .lock()
+            .unwrap()
+            .iter_mut()
+            .for_each(|set_writes| set_writes.retain(|write| write.slot() != (binding, index)));
+    }
+
+    /// Get the current used and free descriptor counts of each bindless binding.
+    pub fn descriptor_stats(&self) -> DescriptorStats {
+        let counts = |resource_type| self.resource_pools
+            .get(&resource_type)
+            .map(ResourcePool::counts)
+            .unwrap_or_default();
+
+        DescriptorStats {
+            storage_buffers: counts(ResourceType::StorageBuffer),
+            storage_images: counts(ResourceType::StorageImage),
+            sampled_images: counts(ResourceType::SampledImage),
+            samplers: counts(ResourceType::Sampler)
+        }
+    }
+
+    /// Describe the resource occupying the slot `handle` indexes in the `resource_type` binding,
+    /// for debugging shaders reading the wrong resource.
+    pub fn describe_handle(&self, resource_type: ResourceType, handle: ResourceHandle) -> HandleInfo {
+        let resource_pool = self.resource_pools.get(&resource_type)
+            .expect("ResourceHandle should have a valid ResourceType");
+
+        HandleInfo {
+            resource_type,
+            index: handle.index(),
+            occupant: resource_pool.occupant(handle.index())
+        }
+    }
+
+    /// Write a descriptor immediately with a single descriptor set, otherwise defer it for each frame's set.
+    /// 
+    /// Slots that were never used are written immediately to every set, as no earlier frame can be reading them,
+    /// so new handles are usable in the current frame.
+    fn queue_write(&self, write: DescriptorWrite, recycled: bool) {
+        match self.descriptor_sets.len() == 1 || !recycled {
+            true => self.descriptor_sets
+                .iter()
+                .for_each(|&descriptor_set| self.write_descriptors(descriptor_set, slice::from_ref(&write))),
+            false => self.pending_writes
+                .lock()
+                .unwrap()
+                .iter_mut()
+                .for_each(|set_writes| set_writes.push(write))
+        }
+    }
+
+    fn write_descriptors(&self, descriptor_set: vk::DescriptorSet, writes: &[DescriptorWrite]) {
+        if writes.is_empty() {
+            return;
+        }
+
+        let writes: Vec<vk::WriteDescriptorSet> = writes
+            .iter()
+            .map(|write| match write {
+                DescriptorWrite::Buffer { binding, index, info } => vk::WriteDescriptorSet::builder()
+                    .dst_set(descriptor_set)
+                    .dst_binding(*binding)
+                    .dst_array_element(*index)
+                    .descriptor_type(vk::DescriptorType::STORAGE_BUFFER)
+                    .buffer_info(slice::from_ref(info))
+                    .build(),
+                DescriptorWrite::Image { binding, index, descriptor_type, info } => vk::WriteDescriptorSet::builder()
+                    .dst_set(descriptor_set)
+                    .dst_binding(*binding)
+                    .dst_array_element(*index)
+                    .descriptor_type(*descriptor_type)
+                    .image_info(slice::from_ref(info))
+                    .build()
+            })
+            .collect();
+
+        unsafe { self.device.update_descriptor_sets(&writes, &[]); }
+    }
+
 }
 
 impl Drop for ResourceManager {
     fn drop(&mut self) {
         unsafe {
-            for i in 0..self.pipeline_layouts.len() {
-                self.device.destroy_pipeline_layout(
-                    self.pipeline_layouts.remove(i), 
-                    None
-                );
+            for pipeline_layout in self.pipeline_layouts.drain(..) {
+                self.device.destroy_pipeline_layout(pipeline_layout, None);
             }
             self.device.destroy_descriptor_set_layout(self.descriptor_set_layout, None);
             self.device.destroy_descriptor_pool(self.descriptor_pool, None);
         }
     }
 }
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn pool(capacity: u32) -> ResourcePool {
+        ResourcePool {
+            resource_type: ResourceType::SampledImage,
+            capacity,
+            ..Default::default()
+        }
+    }
+
+    fn sampler(raw: u64) -> HandleOccupant {
+        HandleOccupant::Sampler(vk::Handle::from_raw(raw))
+    }
+
+    #[test]
+    fn counts_track_created_and_recycled_handles() {
+        let pool = pool(8);
+        let handles: Vec<_> = (0..3).map(|index| pool.fetch_handle(sampler(index + 1)).0).collect();
+        assert_eq!(pool.counts(), DescriptorCounts { used: 3, free: 5 });
+
+        assert!(pool.recycle(handles[1]));
+        assert_eq!(pool.counts(), DescriptorCounts { used: 2, free: 6 });
+        assert!(!pool.is_bound(handles[1].index()));
+
+        // Recycled slots are reused before new ones
+        assert_eq!(pool.fetch_handle(sampler(4)), (handles[1], true));
+        assert_eq!(pool.fetch_handle(sampler(5)), (ResourceHandle::new(3), false));
+        assert_eq!(pool.counts(), DescriptorCounts { used: 4, free: 4 });
+    }
+
+    #[test]
+    fn recycle_rejects_unallocated_and_repeated_handles() {
+        let pool = pool(8);
+        let (handle, _) = pool.fetch_handle(sampler(1));
+
+        assert!(!pool.recycle(ResourceHandle::new(5)));
+        assert!(pool.recycle(handle));
+        assert!(!pool.recycle(handle));
+        assert_eq!(pool.counts(), DescriptorCounts { used: 0, free: 8 });
+    }
+
+    #[test]
+    fn occupants_follow_slot_reuse() {
+        let pool = pool(8);
+        let (first, _) = pool.fetch_handle(sampler(1));
+        let (second, _) = pool.fetch_handle(sampler(2));
+        assert_eq!(pool.occupant(first.index()), Some(sampler(1)));
+        assert_eq!(pool.occupant(second.index()), Some(sampler(2)));
+        assert_eq!(pool.occupant(7), None);
+
+        // Recycled slots report no occupant until reused by a new resource
+        assert!(pool.recycle(first));
+        assert_eq!(pool.occupant(first.index()), None);
+        let (reused, _) = pool.fetch_handle(sampler(3));
+        assert_eq!(reused, first);
+        assert_eq!(pool.occupant(reused.index()), Some(sampler(3)));
+        assert_eq!(pool.occupant(second.index()), Some(sampler(2)));
+    }
+
+    #[test]
+    fn resource_type_bindings() {
+        assert_eq!(ResourceType::StorageBuffer.binding(), STORAGE_BUFFER_BINDING);
+        assert_eq!(ResourceType::StorageImage.binding(), STORAGE_IMAGE_BINDING);
+        assert_eq!(ResourceType::SampledImage.binding(), SAMPLED_IMAGE_BINDING);
+        assert_eq!(ResourceType::Sampler.binding(), SAMPLER_BINDING);
+    }
+}
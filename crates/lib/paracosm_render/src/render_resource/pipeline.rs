@@ -1,11 +1,17 @@
+use crate::camera::Camera;
 
-use anyhow::Result;
+use anyhow::{bail, Result};
+use ash::vk;
 
-use bevy_app::{App, Plugin};
-use bevy_asset::{AddAsset, Handle};
+use bevy_app::{App, CoreStage, Plugin};
+use bevy_asset::{AddAsset, Assets, Handle};
 use bevy_ecs::{prelude::*};
+use bevy_log::prelude::*;
 use bevy_reflect::{TypeUuid};
-use bevy_utils::{HashMap};
+use bevy_tasks::{AsyncComputeTaskPool, Task};
+use bevy_utils::{HashMap, HashSet};
+
+use futures_lite::future;
 
 use paracosm_gpu::{
     device::Device,
@@ -14,6 +20,9 @@ use paracosm_gpu::{
 
 
 
+/// Depth attachment format of pipelines created without a [`GraphicsPipelineDescriptor`] variant
+pub const DEFAULT_DEPTH_FORMAT: Format = Format::D24_UNORM_S8_UINT;
+
 #[derive(Clone, TypeUuid)]
 #[uuid = "22957743-5bc2-47f8-a6ff-a357c1e6dbe4"]
 pub enum Pipeline {
@@ -21,51 +30,423 @@ pub enum Pipeline {
     Compute(ComputePipeline)
 }
 
+/// Depth bias added to fragment depths, to resolve depth fighting between coplanar surfaces.
+/// 
+/// Depth is reversed, see [`Camera::DEPTH_COMPARE_OP`], so positive factors move fragments toward the camera.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct DepthBias {
+    pub constant_factor: f32,
+    /// Largest bias applied, requires the `depthBiasClamp` device feature if not `0.0`
+    pub clamp: f32,
+    pub slope_factor: f32,
+}
+
+impl DepthBias {
+    /// Push shadow casters away from the light, preventing shadow acne
+    pub fn shadow() -> Self {
+        Self {
+            constant_factor: -1.25,
+            clamp: 0.0,
+            slope_factor: -1.75,
+        }
+    }
+
+    /// Pull decals toward the camera, so they win against the surface they lie on
+    pub fn decal() -> Self {
+        Self {
+            constant_factor: 1.0,
+            clamp: 0.0,
+            slope_factor: 1.0,
+        }
+    }
+
+    /// Set the depth bias of following draws, for pipelines created with [`GraphicsPipelineOptions::dynamic_depth_bias`].
+    pub fn cmd_set(&self, device: &Device, command_buffer: vk::CommandBuffer) {
+        unsafe { device.cmd_set_depth_bias(command_buffer, self.constant_factor, self.clamp, self.slope_factor) };
+    }
+}
+
+/// Stencil test and writes of a graphics [`Pipeline`], e.g. to mask draws to a stenciled region or render outlines.
+#[derive(Clone, Copy, Debug)]
+pub struct StencilTestInfo {
+    pub front: StencilOpState,
+    pub back: StencilOpState,
+    /// Whether the reference value is set per draw with [`StencilTestInfo::cmd_set_reference`] instead of from the op states
+    pub dynamic_reference: bool,
+}
+
+impl StencilTestInfo {
+    /// Set the stencil reference of following draws, for pipelines created with [`StencilTestInfo::dynamic_reference`].
+    pub fn cmd_set_reference(device: &Device, command_buffer: vk::CommandBuffer, face_mask: StencilFaceFlags, reference: u32) {
+        unsafe { device.cmd_set_stencil_reference(command_buffer, face_mask, reference) };
+    }
+}
+
+/// Fixed function state of a graphics [`Pipeline`] that differs between the renderer's pipelines.
+#[derive(Clone, Debug)]
+pub struct GraphicsPipelineOptions {
+    pub topology: PrimitiveTopology,
+    /// Whether the maximum index value, e.g. `0xFFFFFFFF` for `u32` indices, restarts strip and fan topologies
+    pub primitive_restart: bool,
+    pub cull_mode: CullModeFlags,
+    /// Whether the pipeline renders with a depth attachment
+    pub depth_attachment: bool,
+    /// Whether fragments are tested against the depth attachment, disable to draw overlays on top of the scene
+    pub depth_test: bool,
+    /// Whether fragments write to the depth attachment
+    pub depth_write: bool,
+    /// `LINE` renders wireframes, and requires the `fillModeNonSolid` device feature
+    pub polygon_mode: PolygonMode,
+    /// Channels written to every color attachment, overriding the fragment stage's blend states.
+    /// `None` keeps the per-attachment masks of the blend states.
+    pub color_write_mask: Option<ColorComponentFlags>,
+    /// Bitwise logic op combining fragment and attachment colors, e.g. `XOR` for cursors, instead of blending.
+    /// The fragment stage's blend states must not enable blending.
+    pub logic_op: Option<LogicOp>,
+    pub depth_bias: Option<DepthBias>,
+    /// Sample count of the pipeline's attachments
+    pub samples: SampleCountFlags,
+    /// Shade at least this fraction of samples per pixel, from `0.0` to `1.0`, instead of once per pixel.
+    /// Anti-aliases high frequency shading such as alpha tested foliage with multisampling, and requires
+    /// the `sampleRateShading` device feature.
+    pub min_sample_shading: Option<f32>,
+    /// Stencil testing, which requires a depth attachment with a stencil aspect
+    pub stencil_test: Option<StencilTestInfo>,
+    /// Whether depth bias is set per draw with [`DepthBias::cmd_set`] instead of fixed at creation
+    pub dynamic_depth_bias: bool,
+}
+
+impl Default for GraphicsPipelineOptions {
+    fn default() -> Self {
+        Self {
+            topology: PrimitiveTopology::TRIANGLE_LIST,
+            primitive_restart: false,
+            cull_mode: CullModeFlags::BACK,
+            depth_attachment: true,
+            depth_test: true,
+            depth_write: true,
+            polygon_mode: PolygonMode::FILL,
+            color_write_mask: None,
+            logic_op: None,
+            depth_bias: None,
+            samples: SampleCountFlags::TYPE_1,
+            min_sample_shading: None,
+            stencil_test: None,
+            dynamic_depth_bias: false,
+        }
+    }
+}
+
+impl GraphicsPipelineOptions {
+    /// Options for overlays such as UI and gizmos, which render into the scene's depth attachment
+    /// without depth testing or writes, so they always draw on top.
+    pub fn overlay() -> Self {
+        Self {
+            depth_test: false,
+            depth_write: false,
+            ..Default::default()
+        }
+    }
+}
+
 impl Pipeline {
+    pub fn compute(device: Device, info: ComputePipelineInfo, pipeline_layout: PipelineLayout) -> Result<Self> {
+        Ok(Pipeline::Compute(device.create_compute_pipeline(info, pipeline_layout)?))
+    }
+
     pub fn graphics(
         device: Device,
         vertex_stage_info: VertexStageInfo,
         fragment_stage_info: FragmentStageInfo,
         pipeline_layout: PipelineLayout
     ) -> Result<Self> {
+        Self::graphics_with_options(device, vertex_stage_info, fragment_stage_info, pipeline_layout, GraphicsPipelineOptions::default())
+    }
+
+    pub fn graphics_with_options(
+        device: Device,
+        vertex_stage_info: VertexStageInfo,
+        fragment_stage_info: FragmentStageInfo,
+        pipeline_layout: PipelineLayout,
+        options: GraphicsPipelineOptions
+    ) -> Result<Self> {
+        Self::create_graphics(device, vertex_stage_info, Some(fragment_stage_info), pipeline_layout, options, DEFAULT_DEPTH_FORMAT)
+    }
+
+    /// Create a graphics pipeline along with its depth-only variant for a depth prepass.
+    /// 
+    /// Returns `(pipeline, depth_only_pipeline)`.
+    pub fn graphics_with_depth_prepass(
+        device: Device,
+        vertex_stage_info: VertexStageInfo,
+        fragment_stage_info: FragmentStageInfo,
+        pipeline_layout: PipelineLayout,
+        options: GraphicsPipelineOptions
+    ) -> Result<(Self, Self)> {
+        let depth_only = Self::depth_only(device.clone(), vertex_stage_info.clone(), pipeline_layout, options.clone())?;
+        let pipeline = Self::graphics_with_options(device, vertex_stage_info, fragment_stage_info, pipeline_layout, options)?;
+
+        Ok((pipeline, depth_only))
+    }
+
+    /// Create a depth-only graphics pipeline, with no fragment stage or color targets.
+    pub fn depth_only(
+        device: Device,
+        vertex_stage_info: VertexStageInfo,
+        pipeline_layout: PipelineLayout,
+        options: GraphicsPipelineOptions
+    ) -> Result<Self> {
+        if !options.depth_attachment {
+            bail!("A depth-only pipeline requires a depth attachment");
+        }
+
+        Self::create_graphics(device, vertex_stage_info, None, pipeline_layout, options, DEFAULT_DEPTH_FORMAT)
+    }
+
+    fn create_graphics(
+        device: Device,
+        vertex_stage_info: VertexStageInfo,
+        fragment_stage_info: Option<FragmentStageInfo>,
+        pipeline_layout: PipelineLayout,
+        options: GraphicsPipelineOptions,
+        depth_format: Format
+    ) -> Result<Self> {
+        // List topologies can't restart without the primitiveTopologyListRestart device feature
+        let list_topology = [PrimitiveTopology::POINT_LIST, PrimitiveTopology::LINE_LIST, PrimitiveTopology::TRIANGLE_LIST, PrimitiveTopology::PATCH_LIST]
+            .contains(&options.topology);
+        if options.primitive_restart && list_topology {
+            bail!("Primitive restart requires a strip or fan topology, not {:?}", options.topology);
+        }
+
+        if options.stencil_test.is_some() && !options.depth_attachment {
+            bail!("Stencil testing requires a depth stencil attachment");
+        }
+
+        let mut fragment_stage_info = fragment_stage_info;
+        if let (Some(fragment_stage_info), Some(color_write_mask)) = (fragment_stage_info.as_mut(), options.color_write_mask) {
+            fragment_stage_info.color_blend_states
+                .iter_mut()
+                .for_each(|blend_state| blend_state.color_write_mask = color_write_mask);
+        }
+
+        let depth_bias = options.depth_bias.unwrap_or(DepthBias {
+            constant_factor: 0.0,
+            clamp: 0.0,
+            slope_factor: 0.0,
+        });
+
         let pipeline_info = GraphicsPipelineInfo {
             vertex_stage_info,
             fragment_stage_info,
             input_assembly_state: PipelineInputAssemblyStateCreateInfo::builder()
-                .topology(PrimitiveTopology::TRIANGLE_LIST)
-                .primitive_restart_enable(false)
+                .topology(options.topology)
+                .primitive_restart_enable(options.primitive_restart)
                 .build(),
             rasterization_state: PipelineRasterizationStateCreateInfo::builder()
                 .depth_clamp_enable(false)
                 .rasterizer_discard_enable(false)
-                .polygon_mode(PolygonMode::FILL)
+                .polygon_mode(options.polygon_mode)
                 .line_width(1.0)
-                .cull_mode(CullModeFlags::BACK)
+                .cull_mode(options.cull_mode)
                 .front_face(FrontFace::COUNTER_CLOCKWISE)
-                .depth_bias_enable(false)
-                .depth_bias_constant_factor(0.0)
-                .depth_bias_clamp(0.0)
-                .depth_bias_slope_factor(0.0)
+                .depth_bias_enable(options.depth_bias.is_some() || options.dynamic_depth_bias)
+                .depth_bias_constant_factor(depth_bias.constant_factor)
+                .depth_bias_clamp(depth_bias.clamp)
+                .depth_bias_slope_factor(depth_bias.slope_factor)
                 .build(),
-            depth_stencil_state: Some(PipelineDepthStencilStateCreateInfo::builder()
-                .depth_test_enable(true)
-                .depth_write_enable(true)
-                .depth_compare_op(CompareOp::GREATER_OR_EQUAL)
+            depth_stencil_state: options.depth_attachment.then(|| PipelineDepthStencilStateCreateInfo::builder()
+                .depth_test_enable(options.depth_test)
+                .depth_write_enable(options.depth_write)
+                .depth_compare_op(Camera::DEPTH_COMPARE_OP)
                 .depth_bounds_test_enable(false)
-                .stencil_test_enable(false)
+                .stencil_test_enable(options.stencil_test.is_some())
+                .front(options.stencil_test.map_or_else(Default::default, |stencil_test| stencil_test.front))
+                .back(options.stencil_test.map_or_else(Default::default, |stencil_test| stencil_test.back))
                 .build()),
+            depth_format,
+            logic_op: options.logic_op,
             multisample_state: PipelineMultisampleStateCreateInfo::builder()
-                .rasterization_samples(SampleCountFlags::TYPE_1)
+                .rasterization_samples(options.samples)
+                .sample_shading_enable(options.min_sample_shading.is_some())
+                .min_sample_shading(options.min_sample_shading.unwrap_or_default())
                 .build(),
+            dynamic_states: [
+                (options.dynamic_depth_bias, DynamicState::DEPTH_BIAS),
+                (options.stencil_test.map_or(false, |stencil_test| stencil_test.dynamic_reference), DynamicState::STENCIL_REFERENCE)
+            ]
+                .into_iter()
+                .filter_map(|(enabled, state)| enabled.then_some(state))
+                .collect(),
         };
 
         Ok(Pipeline::Graphics(device.create_graphics_pipeline(pipeline_info, pipeline_layout)?))
     }
 }
 
-#[derive(Clone, Debug, Resource)]
+/// Shader stages and fixed function state of a graphics [`Pipeline`],
+/// kept to create variants of the pipeline for other attachment formats.
+#[derive(Clone)]
+pub struct GraphicsPipelineDescriptor {
+    pub vertex_stage_info: VertexStageInfo,
+    pub fragment_stage_info: FragmentStageInfo,
+    pub pipeline_layout: PipelineLayout,
+    pub options: GraphicsPipelineOptions,
+}
+
+impl GraphicsPipelineDescriptor {
+    /// Create the pipeline for the fragment stage's target formats and the [`DEFAULT_DEPTH_FORMAT`].
+    pub fn create(&self, device: Device) -> Result<Pipeline> {
+        Pipeline::graphics_with_options(
+            device,
+            self.vertex_stage_info.clone(),
+            self.fragment_stage_info.clone(),
+            self.pipeline_layout,
+            self.options.clone()
+        )
+    }
+
+    /// Create a variant of the pipeline rendering to `color_formats`, and `depth_format` or `UNDEFINED` without a depth attachment.
+    pub fn create_variant(&self, device: Device, color_formats: &[Format], depth_format: Format) -> Result<Pipeline> {
+        if color_formats.len() != self.fragment_stage_info.target_states.len() {
+            bail!(
+                "Pipeline variant has {} color formats, but the pipeline renders {} color targets",
+                color_formats.len(), self.fragment_stage_info.target_states.len()
+            );
+        }
+
+        let mut fragment_stage_info = self.fragment_stage_info.clone();
+        fragment_stage_info.target_states = color_formats.to_vec();
+        let options = GraphicsPipelineOptions {
+            depth_attachment: depth_format != Format::UNDEFINED,
+            ..self.options.clone()
+        };
+
+        Pipeline::create_graphics(device, self.vertex_stage_info.clone(), Some(fragment_stage_info), self.pipeline_layout, options, depth_format)
+    }
+}
+
+#[derive(Clone, Resource)]
 pub struct PipelineManager {
-    pub pipelines: HashMap<String, Handle<Pipeline>>
+    pub pipelines: HashMap<String, Handle<Pipeline>>,
+    /// Depth-only variants for a depth prepass, keyed by the label of their main pipeline
+    pub depth_prepass_pipelines: HashMap<String, Handle<Pipeline>>,
+    /// Descriptors of graphics pipelines by label, for creating variants for other attachment formats
+    pub descriptors: HashMap<String, GraphicsPipelineDescriptor>
+}
+
+impl PipelineManager {
+    /// Whether the pipeline for `label` has been created and can be bound.
+    pub fn is_ready(&self, label: &str) -> bool {
+        self.pipelines.contains_key(label)
+    }
+
+    /// Label of the variant of pipeline `label` for a set of attachment formats.
+    pub fn variant_label(label: &str, color_formats: &[Format], depth_format: Format) -> String {
+        format!("{} {:?} {:?}", label, color_formats, depth_format)
+    }
+
+    /// Queue creation of the variant of pipeline `label` for a set of attachment formats,
+    /// unless it already exists or is being created.
+    /// 
+    /// Returns `false` if `label` has no [`GraphicsPipelineDescriptor`] to create variants from,
+    /// or creating the variant already failed, see [`PipelineCompiler::has_failed`].
+    pub fn queue_variant(
+        &self,
+        compiler: &mut PipelineCompiler,
+        device: &Device,
+        label: &str,
+        color_formats: &[Format],
+        depth_format: Format
+    ) -> bool {
+        let Some(descriptor) = self.descriptors.get(label) else {
+            return false;
+        };
+
+        let variant_label = Self::variant_label(label, color_formats, depth_format);
+        if compiler.has_failed(&variant_label) {
+            return false;
+        }
+        if self.is_ready(&variant_label) || compiler.is_compiling(&variant_label) {
+            return true;
+        }
+
+        debug!("Queueing pipeline variant \"{}\"", variant_label);
+        let descriptor = descriptor.clone();
+        let device = device.clone();
+        let color_formats = color_formats.to_vec();
+        compiler.queue(&variant_label, move || descriptor.create_variant(device, &color_formats, depth_format));
+
+        true
+    }
+}
+
+/// The [`PipelineCompiler`] resource creates pipelines on the async compute task pool,
+/// so slow shader compilation doesn't stall the main thread.
+/// 
+/// Finished pipelines are added to the [`PipelineManager`] under their label at the start of an update.
+/// Until then [`PipelineManager::is_ready`] is false, and draws using the pipeline should be skipped.
+#[derive(Default, Resource)]
+pub struct PipelineCompiler {
+    tasks: Vec<(String, Task<Result<Pipeline>>)>,
+    /// Labels of pipelines that failed to be created, which aren't created again unless explicitly queued
+    failed: HashSet<String>
+}
+
+impl PipelineCompiler {
+    /// Queue creation of the pipeline for `label`, e.g. with [`Pipeline::graphics_with_options`].
+    /// Clears an earlier failure to create it, e.g. to retry after reloading its shaders.
+    pub fn queue<F>(&mut self, label: &str, create_pipeline: F)
+    where
+        F: FnOnce() -> Result<Pipeline> + Send + 'static
+    {
+        let task = AsyncComputeTaskPool::get().spawn(async move { create_pipeline() });
+        self.failed.remove(label);
+        self.tasks.push((label.to_string(), task));
+    }
+
+    /// Whether the pipeline for `label` is still being created.
+    pub fn is_compiling(&self, label: &str) -> bool {
+        self.tasks.iter().any(|(task_label, _)| task_label == label)
+    }
+
+    /// Whether creating the pipeline for `label` failed.
+    pub fn has_failed(&self, label: &str) -> bool {
+        self.failed.contains(label)
+    }
+
+    /// Take the pipelines that finished creation, by label, recording the labels of those that failed.
+    fn take_finished(&mut self) -> Vec<(String, Pipeline)> {
+        let mut finished = vec![];
+        let failed = &mut self.failed;
+        self.tasks.retain_mut(|(label, task)| {
+            let Some(result) = future::block_on(future::poll_once(task)) else {
+                return true;
+            };
+
+            match result {
+                Ok(pipeline) => finished.push((label.clone(), pipeline)),
+                Err(error) => {
+                    error!("Failed to create pipeline \"{}\": {}", label, error);
+                    failed.insert(label.clone());
+                }
+            }
+            false
+        });
+
+        finished
+    }
+}
+
+/// Add pipelines finished by the [`PipelineCompiler`] to the [`PipelineManager`]
+fn add_compiled_pipelines(
+    mut compiler: ResMut<PipelineCompiler>,
+    mut pipeline_manager: ResMut<PipelineManager>,
+    mut pipelines: ResMut<Assets<Pipeline>>
+) {
+    for (label, pipeline) in compiler.take_finished() {
+        let handle = pipelines.add(pipeline);
+        pipeline_manager.pipelines.insert(label, handle);
+    }
 }
 
 
@@ -74,10 +455,50 @@ pub struct PipelineManagerPlugin;
 impl Plugin for PipelineManagerPlugin {
     fn build(&self, app: &mut App) {
         app.add_asset::<Pipeline>()
-            .add_debug_asset::<Pipeline>();
+            .add_debug_asset::<Pipeline>()
+            .init_resource::<PipelineCompiler>()
+            .add_system_to_stage(CoreStage::PreUpdate, add_compiled_pipelines);
 
         app.world.insert_resource(PipelineManager {
-            pipelines: HashMap::new()
+            pipelines: HashMap::new(),
+            depth_prepass_pipelines: HashMap::new(),
+            descriptors: HashMap::new()
         })
     }
 }
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use bevy_tasks::TaskPool;
+
+    /// Take finished pipelines until the compiler has no tasks left
+    fn finish(compiler: &mut PipelineCompiler) -> Vec<(String, Pipeline)> {
+        let mut finished = vec![];
+        while !compiler.tasks.is_empty() {
+            finished.extend(compiler.take_finished());
+            std::thread::yield_now();
+        }
+        finished
+    }
+
+    #[test]
+    fn failed_pipelines_are_recorded_until_requeued() {
+        AsyncComputeTaskPool::init(TaskPool::default);
+        let mut compiler = PipelineCompiler::default();
+
+        compiler.queue("broken", || Err(anyhow::anyhow!("missing entry point")));
+        assert!(compiler.is_compiling("broken"));
+        assert!(finish(&mut compiler).is_empty());
+        assert!(!compiler.is_compiling("broken"));
+        assert!(compiler.has_failed("broken"));
+        assert!(!compiler.has_failed("other"));
+
+        // Explicitly queueing the pipeline again retries it
+        compiler.queue("broken", || Err(anyhow::anyhow!("still missing")));
+        assert!(!compiler.has_failed("broken"));
+        assert!(finish(&mut compiler).is_empty());
+        assert!(compiler.has_failed("broken"));
+    }
+}
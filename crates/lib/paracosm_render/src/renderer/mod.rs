@@ -1,22 +1,34 @@
 use crate::{
+    camera::Camera,
+    debug_draw::{draw_debug_geometry, DebugDraw},
+    debug_view::{self, DebugView},
     image::*, 
     mesh::*,
+    DEFAULT_DEPTH_FORMAT,
+    GraphicsPipelineDescriptor,
+    GraphicsPipelineOptions,
     Pipeline,
+    post_process::FXAA,
+    reduce::Reducer,
+    PipelineCompiler,
     PipelineManager,
     render_asset::RenderAssets,
-    render_resource::ResourceManager,
-    window::WindowSurfaces,
+    render_phase::{sort_objects, SortOrder},
+    render_resource::{frame_ring_buffer::FrameRingBuffer, ResourceManager},
+    window::{NonSendMarker, Presentation, WindowSurfaces},
     Shader, 
     ShaderManager,
+    view::ViewTarget,
 };
 
 use ash::vk;
 
-use bevy_asset::prelude::*;
-use bevy_ecs::prelude::*;
+use bevy_app::AppExit;
+use bevy_asset::{prelude::*, HandleId};
+use bevy_ecs::{prelude::*, system::SystemParam};
 use bevy_log::prelude::*;
 use bevy_time::prelude::*;
-use bevy_window::Windows;
+use bevy_window::{WindowId, Windows};
 
 use paracosm_gpu::{
     instance::Instance, 
@@ -26,58 +38,124 @@ use paracosm_gpu::{
         image as gpu_image,
         pipeline::*,
         sampler as gpu_sampler,
-    }
+        staging_pool::StagingPool,
+    },
+    surface::Surface,
 };
 
-use rust_shaders_shared::glam;
+use rust_shaders_shared::{glam, ResourceHandle};
 
 use std::{
     borrow::Cow,
+    cell::RefCell,
+    collections::HashMap,
     env,
+    marker::PhantomData,
     path::Path,
-    mem::size_of,
+    time::{Duration, Instant},
     slice
 };
 
 
 
+/// Bindless descriptor sets the renderer rotates between, one per frame in flight of a triple buffered swapchain
+const FRAMES_IN_FLIGHT: usize = 3;
+
 /// The [`RenderContext`] resource provides access to the renderer's GPU resources
 #[derive(Resource)]
 pub struct RenderContext {
     pub device: Device,
     pub resource_manager: ResourceManager,
+    /// Color target format of the window surfaces, used by internal pipelines
+    pub surface_format: Format,
+    /// Staging buffers shared by asset uploads
+    pub staging_pool: StagingPool,
+}
+
+/// CPU time spent by the [`render_system`] in the last frame, to tell whether rendering is CPU or GPU bound
+#[derive(Resource, Default, Debug)]
+pub struct FrameTimings {
+    /// Time recording and submitting each window's commands, from beginning rendering until submission
+    pub recording: HashMap<WindowId, Duration>,
+}
+
+impl FrameTimings {
+    /// Total recording time of all windows
+    pub fn total_recording(&self) -> Duration {
+        self.recording.values().sum()
+    }
+}
+
+/// Render asset handles and resources used by the [`render_system`]
+#[derive(SystemParam)]
+pub struct SceneAssets<'w, 's> {
+    mesh_handles: Res<'w, MeshManager>,
+    meshes: Res<'w, RenderAssets<Mesh>>,
+    image_handles: Res<'w, ImageManager>,
+    images: Res<'w, RenderAssets<Image>>,
+    sampler_handles: Res<'w, SamplerManager>,
+    samplers: Res<'w, RenderAssets<Sampler>>,
+    #[system_param(ignore)]
+    _marker: PhantomData<&'s ()>,
 }
 
 // TODO: Properly implement scene object management
-#[derive(Default, Resource)]
+#[derive(Resource)]
 pub struct SceneData {
-    object_buffers: Vec<(Buffer, ResourceHandle)>,
+    object_buffer: Option<FrameRingBuffer<rust_shaders_shared::ObjectData>>,
+    /// Label in the [`SamplerManager`] of the sampler for textured objects, e.g. "Nearest" for pixel art
+    pub sampler: String,
+}
+
+impl Default for SceneData {
+    fn default() -> Self {
+        Self {
+            object_buffer: None,
+            sampler: "Linear".to_string(),
+        }
+    }
 }
 
 
 
 pub fn initialize_renderer(
+    _marker: NonSend<NonSendMarker>,
     windows: Res<Windows>,
     instance: Res<Instance>,
+    mut window_surfaces: NonSendMut<WindowSurfaces>,
     mut commands: Commands
 ) {
     // Create Device
     let Some(window) = windows.get_primary() else {
         return error!("No windows found for application!");
     };
-    let window_handle = window.raw_handle();
+    let Some(window_handle) = window.raw_handle() else {
+        return error!("Failed to get primary window handle!");
+    };
 
-    let device = Device::primary(instance.clone(), window_handle)
+    let device = Device::primary(instance.clone(), Some(window_handle.clone()))
         .expect("Vulkan should find a Device with required support");
 
     // Create resource manager
-    let resource_manager = ResourceManager::new(&device)
+    let resource_manager = ResourceManager::with_frame_sets(&device, FRAMES_IN_FLIGHT)
         .expect("A ResourceManager should be created for the Device");
 
+    // Create primary window surface to select the color target format for pipelines
+    let mut surface = Surface::new(device.clone(), &window_handle)
+        .expect("Primary window surface should be created");
+    let surface_format = surface.select_format()
+        .expect("Primary window surface should support presentation")
+        .format;
+    surface.set_preferred_format(surface_format);
+    window_surfaces.surfaces.insert(window.id(), surface);
+
     // Insert RenderContext
+    let staging_pool = device.create_staging_pool();
     let render_context = RenderContext {
         device,
         resource_manager,
+        surface_format,
+        staging_pool,
     };
     
     initialize_internal_assets(&render_context, &mut commands);
@@ -94,39 +172,63 @@ pub fn render_system(
     mut window_surfaces: NonSendMut<WindowSurfaces>,
     pipeline_handles: Res<PipelineManager>,
     pipeline_assets: Res<Assets<Pipeline>>,
-    mesh_handles: Res<MeshManager>,
-    meshes: Res<RenderAssets<Mesh>>,
-    image_handles: Res<ImageManager>,
-    images: Res<RenderAssets<Image>>,
-    sampler_handles: Res<SamplerManager>,
-    samplers: Res<RenderAssets<Sampler>>,
+    mut pipeline_compiler: ResMut<PipelineCompiler>,
+    assets: SceneAssets,
+    presentation: Res<Presentation>,
     mut scene_data: ResMut<SceneData>,  // TODO: properly implement scene object management
+    mut debug_draw: ResMut<DebugDraw>,
+    debug_view: Res<DebugView>,
+    mut view_target: Option<ResMut<ViewTarget>>,
+    mut frame_timings: ResMut<FrameTimings>,
+    cameras: Query<&Camera>,
     time: NonSend<Time>
 ) {
     let device = &render_context.device;
     let resource_manager = &render_context.resource_manager;
     let pipeline_layout = resource_manager.pipeline_layouts[0];
+    let window_surfaces = &mut *window_surfaces;
 
     //let _span = info_span!("present_frames").entered();
 
     // TODO: convert window iteration to Views and simultaneous presentation
     // Render for each active window surface
+    frame_timings.recording.clear();
     for window in windows.iter() {
         // Check window is configured
         if !window_surfaces.configured_windows.contains(&window.id()) {
             continue;
         }
 
+        // Skip window while its last frame is held for manual present
+        if window_surfaces.pending_present.contains(&window.id()) {
+            continue;
+        }
+
         // Get surface for window
         let Some(surface) = window_surfaces.surfaces.get_mut(&window.id()) else {
             continue;
         };
-        let Ok(extent) = surface.extent() else {
+        let Ok(surface_extent) = surface.extent() else {
             continue;
         };
 
-        // Begin rendering
-        let command_buffer = match surface.begin_rendering() {
+        // Pipelines target the primary surface format, so skip surfaces that couldn't match it
+        match surface.format() {
+            Ok(format) if format == render_context.surface_format => (),
+            Ok(format) => {
+                error!("Renderer::render_system: Surface format {:?} does not match pipeline format {:?}", format, render_context.surface_format);
+                continue;
+            },
+            Err(_) => continue
+        }
+
+        // Begin rendering, offscreen to the view target if one is used
+        let recording_start = Instant::now();
+        let begin_result = match view_target.as_mut() {
+            Some(view_target) => view_target.begin_rendering(device, resource_manager, surface, window.id(), render_context.surface_format),
+            None => surface.begin_rendering().map(|command_buffer| (command_buffer, surface_extent))
+        };
+        let (command_buffer, extent) = match begin_result {
             Ok(result) => result,
             Err(error) => {
                 error!("Renderer::render_system: {}", error);
@@ -134,125 +236,313 @@ pub fn render_system(
             }
         };
 
-        resource_manager.bind(command_buffer);
+        resource_manager.bind(command_buffer, surface.frame_fence());
+
 
 
+        let mesh_asset = match assets.mesh_handles.meshes.get("square") {
+            Some(value) => assets.meshes.get(value),
+            None => None
+        };
+
+        let test_image = match assets.image_handles.images.get("statue") {
+            Some(value) => assets.images.get(value),
+            None => None
+        };
+
+        let object_sampler = match assets.sampler_handles.samplers.get(&scene_data.sampler) {
+            Some(value) => assets.samplers.get(value),
+            None => None
+        };
 
         // TODO: properly implement scene object management
         // Init per-frame object buffers if necessary
-        let object_buffers = &mut scene_data.object_buffers;
-        if object_buffers.is_empty() {
-            for frame in 0..surface.frame_count() {
-                let info = BufferInfo::new(
-                    size_of::<rust_shaders_shared::ObjectData>() * 10000,
-                    BufferUsageFlags::INDIRECT_BUFFER | BufferUsageFlags::STORAGE_BUFFER,
-                    MemoryLocation::CpuToGpu
-                );
-                let object_buffer = device.create_buffer(format!("Object Buffer (Frame {})", frame).as_str(), info, None);
-                let handle = resource_manager.new_buffer_handle(&object_buffer);
-                object_buffers.push((object_buffer, handle));
-            }
+        if scene_data.object_buffer.is_none() {
+            let object_buffer = resource_manager.create_frame_ring_buffer(
+                "Object Buffer",
+                surface.frame_count(),
+                10000,
+                BufferUsageFlags::INDIRECT_BUFFER
+            ).expect("ResourceManager should create a frame ring buffer.");
+            scene_data.object_buffer = Some(object_buffer);
         }
-        let object_buffer = &object_buffers[0];
+
+        // Select each instance's bindless texture and sampler
+        let texture_handle = test_image.map_or(ResourceHandle::new(0), |image| image.handle);
+        let sampler_handle = object_sampler.map_or(ResourceHandle::new(0), |sampler| sampler.handle);
+
+        let camera = cameras
+            .iter()
+            .next()
+            .cloned()
+            .unwrap_or_default();
 
         let mut object_data = Vec::with_capacity(10000);
         for i in 0..100 {
             for j in 0..100 {
-                object_data.push(rust_shaders_shared::ObjectData{
-                    model_matrix: glam::Mat4::from_scale_rotation_translation(
+                object_data.push(rust_shaders_shared::ObjectData::new(
+                    glam::Mat4::from_scale_rotation_translation(
                         glam::Vec3::ONE, 
                         glam::Quat::from_axis_angle(glam::Vec3::Y, time.elapsed_seconds() * (45_f32 + j as f32).to_radians()), 
                         glam::vec3((i * 2) as f32, 0f32, (j * 2) as f32)
                     ),
-                })
+                    texture_handle,
+                    sampler_handle
+                ))
             }
         }
-        object_buffer.0.write_buffer(&object_data);
-        let object_buffers = &scene_data.object_buffers;
-
-        let mesh_asset = match mesh_handles.meshes.get("square") {
-            Some(value) => meshes.get(value),
-            None => None
+        // Draw opaque objects nearest first for early depth rejection
+        sort_objects(&mut object_data, camera.eye, SortOrder::FrontToBack);
+        let object_buffer_handle = match scene_data.object_buffer.as_ref().unwrap().write_current(surface.frame_index(), &object_data) {
+            Ok(handle) => handle,
+            Err(error) => {
+                error!("Renderer::render_system: {}", error);
+                continue;
+            }
         };
 
-        let test_image = match image_handles.images.get("statue") {
-            Some(value) => images.get(value),
+        let post_process_sampler = match assets.sampler_handles.samplers.get("Linear Clamp") {
+            Some(value) => assets.samplers.get(value),
             None => None
         };
 
-        let linear_sampler = match  sampler_handles.samplers.get("Linear") {
-            Some(value) => samplers.get(value),
-            None => None
-        };
+        // Set frame state shared by all pipelines
+        unsafe {
+            let viewports = [
+                vk::Viewport::builder()
+                    .width(extent.width as f32)
+                    .height(extent.height as f32)
+                    .min_depth(1.0)
+                    .max_depth(0.0)
+                    .build()
+            ];
+            let scissors = [extent.into()];
+            device.cmd_set_viewport(command_buffer, 0, &viewports);
+            device.cmd_set_scissor(command_buffer, 0, &scissors);
 
-        // Do rendering tasks
-        if let Some(Pipeline::Graphics(pipeline)) = match pipeline_handles.pipelines.get("textured_lit_mesh") {
-            Some(value) => pipeline_assets.get(value),
-            None => None
-        } {
+            // Camera
+            let camera_matrix = camera.view_projection(surface_extent.width as f32 / surface_extent.height as f32);
+
+            let push_constant = [rust_shaders_shared::ShaderConstants {
+                camera_matrix,
+                object_buffer_handle,
+            }];
+            let (_, push_constant_bytes, _) = push_constant.align_to::<u8>();
+
+            device.cmd_push_constants(command_buffer, pipeline_layout, vk::ShaderStageFlags::ALL, 0, push_constant_bytes);
+        }
+
+        // Look up graphics pipelines, using variants for the attachment formats the pass renders to if they differ
+        // Missing variants are skipped this frame, and queued for creation after rendering
+        let color_formats = [render_context.surface_format];
+        let depth_format = view_target.as_ref().map_or(DEFAULT_DEPTH_FORMAT, |view_target| view_target.depth_format);
+        let get_pipeline = |label: &str| match pipeline_handles.pipelines.get(label).and_then(|handle| pipeline_assets.get(handle)) {
+            Some(Pipeline::Graphics(pipeline)) => Some(pipeline),
+            _ => None
+        };
+        let missing_variants = RefCell::new(vec![]);
+        let pipeline_for_pass = |label: &str, depth_format: Format| {
+            let pipeline = get_pipeline(label)?;
+            let Err(error) = pipeline.validate_attachments(&color_formats, depth_format) else {
+                return Some(pipeline.pipeline);
+            };
+
+            match get_pipeline(&PipelineManager::variant_label(label, &color_formats, depth_format)) {
+                Some(variant) => Some(variant.pipeline),
+                None => {
+                    missing_variants.borrow_mut().push((label.to_string(), depth_format, error));
+                    None
+                }
+            }
+        };
+        let graphics_pipeline = |label: &str| pipeline_for_pass(label, depth_format);
+        let post_process_pipeline = |label: &str| pipeline_for_pass(label, Format::UNDEFINED);
+
+        // Do rendering tasks, with shading replaced or overlaid by the debug view
+        // Pipeline variants match the mesh's vertex format
+        let vertex_format = mesh_asset.map_or(VertexFormat::default(), |mesh| mesh.vertex_format);
+        let mesh_pipeline = debug_view.replacement_pipeline().unwrap_or("textured_lit_mesh");
+        let mesh_pipelines = [Some(mesh_pipeline), debug_view.overlay_pipeline()];
+        let mesh_pipelines = mesh_pipelines
+            .into_iter()
+            .flatten()
+            .filter_map(|label| graphics_pipeline(&vertex_format.pipeline_label(label)));
+        for pipeline in mesh_pipelines {
             unsafe {
-                let viewports = [
-                    vk::Viewport::builder()
-                        .width(extent.width as f32)
-                        .height(extent.height as f32)
-                        .min_depth(1.0)
-                        .max_depth(0.0)
-                        .build()
-                ];
-                let scissors = [extent.into()];
-                device.cmd_set_viewport(command_buffer, 0, &viewports);
-                device.cmd_set_scissor(command_buffer, 0, &scissors);
-                device.cmd_bind_pipeline(command_buffer, vk::PipelineBindPoint::GRAPHICS, pipeline.pipeline);
-
-                // Camera
-                let mut proj = glam::Mat4::perspective_infinite_rh(
-                    45_f32.to_radians(), 
-                    extent.width as f32 / extent.height as f32, 
-                    0.1, 
-                );
-                proj.y_axis *= -1.0;
-                let view = glam::Mat4::look_at_rh(
-                    glam::vec3(-5.0, 2.0, -5.0), 
-                    glam::Vec3::ZERO,
-                    glam::Vec3::Y
-                );
-                let camera_matrix = proj * view;
-
-                let push_constant = [rust_shaders_shared::ShaderConstants {
-                    camera_matrix,
-                    object_buffer_handle: object_buffers[0].1,
-                }];
-                let (_, push_constant_bytes, _) = push_constant.align_to::<u8>();
-
-                device.cmd_push_constants(command_buffer, pipeline_layout, vk::ShaderStageFlags::ALL, 0, push_constant_bytes);
+                device.cmd_bind_pipeline(command_buffer, vk::PipelineBindPoint::GRAPHICS, pipeline);
 
                 if let Some(mesh) = mesh_asset {
                     let vertex_buffer = mesh.vertex_buffer.buffer;
                     let index_buffer = mesh.index_buffer.buffer;
+                    let index_type = mesh.index_format.index_type();
                     
                     device.cmd_bind_vertex_buffers(command_buffer, 0, slice::from_ref(&vertex_buffer), &[0]);
-                    device.cmd_bind_index_buffer(command_buffer, index_buffer, 0, vk::IndexType::UINT32);
+                    device.cmd_bind_index_buffer(command_buffer, index_buffer, 0, index_type);
 
-                    device.cmd_draw_indexed(command_buffer, mesh.index_count as u32, 10000, 0, 0, 0);
+                    // TODO: bind each submesh's material once materials are supported
+                    for submesh in mesh.submeshes.iter() {
+                        device.cmd_draw_indexed(command_buffer, submesh.index_count, 10000, submesh.first_index, 0, 0);
+                    }
                 }
             }
         }
 
-        // End rendering
-        if let Err(error) = surface.end_rendering() {
+        // Draw immediate mode geometry last
+        draw_debug_geometry(
+            device,
+            command_buffer,
+            &mut debug_draw,
+            window.id(),
+            surface.frame_index(),
+            graphics_pipeline("debug_lines"),
+            graphics_pipeline("debug_triangles")
+        );
+
+        // End rendering, blitting the view target to the surface if one is used
+        let end_result = match view_target.as_ref() {
+            Some(view_target) => view_target.end_rendering(
+                resource_manager,
+                surface,
+                command_buffer,
+                window.id(),
+                post_process_sampler.map(|sampler| sampler.handle),
+                post_process_pipeline
+            ).and_then(|_| surface.end_frame()),
+            None => surface.end_rendering()
+        };
+
+        // Queue creation of missing pipeline variants for following frames. Failed variants were logged when they failed
+        for (label, depth_format, error) in missing_variants.into_inner() {
+            let queued = pipeline_handles.queue_variant(&mut pipeline_compiler, device, &label, &color_formats, depth_format);
+            let failed = pipeline_compiler.has_failed(&PipelineManager::variant_label(&label, &color_formats, depth_format));
+            if !queued && !failed {
+                error!("Renderer::render_system: Skipping pipeline \"{}\": {}", label, error);
+            }
+        }
+
+        if let Err(error) = end_result {
             error!("Renderer::render_system: {}", error);
             continue;
         };
+        frame_timings.recording.insert(window.id(), recording_start.elapsed());
 
-        // Present rendered image to surface
-        if let Err(error) = surface.queue_present() {
-            error!("Renderer::render_system: {}", error);
+        // Hold rendered image until requested when presenting manually
+        if presentation.manual {
+            window_surfaces.pending_present.insert(window.id());
             continue;
+        }
+
+        // Present rendered image to surface
+        match surface.queue_present() {
+            Ok(status) => window_surfaces.update_status(window.id(), status),
+            Err(error) => error!("Renderer::render_system: {}", error)
         };
     }
+
+    debug_draw.clear();
 }
 
 
+/// Longest wait for the device to finish submitted work at shutdown
+const SHUTDOWN_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// Destroy renderer resources in dependency order once the app is exiting.
+/// 
+/// Waits for the device to idle, then frees pipelines, then buffers and images, then window surfaces,
+/// and finally the [`RenderContext`] with its descriptor sets and pipeline layouts.
+/// If the device doesn't idle in time, resources are leaked instead, see [`leak_renderer_resources`].
+pub fn shutdown_renderer(world: &mut World) {
+    if world.resource::<Events<AppExit>>().is_empty() {
+        return;
+    }
+    let Some(render_context) = world.get_resource::<RenderContext>() else {
+        return;
+    };
+
+    info!("Shutting down renderer");
+    match render_context.device.wait_idle_timeout(SHUTDOWN_TIMEOUT) {
+        Ok(true) => (),
+        Ok(false) => {
+            error!("Device did not idle within {:?}; leaking renderer resources it may still be using", SHUTDOWN_TIMEOUT);
+            leak_renderer_resources(world);
+            return;
+        },
+        Err(error) => {
+            error!("Failed to wait for the device to idle: {:?}; leaking renderer resources it may still be using", error);
+            leak_renderer_resources(world);
+            return;
+        }
+    }
+
+    // Destroy pipelines
+    world.remove_resource::<PipelineManager>();
+    if let Some(mut pipelines) = world.get_resource_mut::<Assets<Pipeline>>() {
+        let ids: Vec<HandleId> = pipelines.ids().collect();
+        for id in ids.iter() {
+            pipelines.remove(*id);
+        }
+        info!("Destroyed {} pipelines", ids.len());
+    }
+
+    // Destroy buffers and images
+    if let Some(meshes) = world.remove_resource::<RenderAssets<Mesh>>() {
+        info!("Destroyed {} meshes", meshes.len());
+    }
+    if let Some(images) = world.remove_resource::<RenderAssets<Image>>() {
+        info!("Destroyed {} images", images.len());
+    }
+    if let Some(samplers) = world.remove_resource::<RenderAssets<Sampler>>() {
+        info!("Destroyed {} samplers", samplers.len());
+    }
+    if let Some(scene_data) = world.remove_resource::<SceneData>() {
+        let frame_count = scene_data.object_buffer.map_or(0, |object_buffer| object_buffer.frame_count());
+        info!("Destroyed {} object buffers", frame_count);
+    }
+    if world.remove_resource::<DebugDraw>().is_some() {
+        info!("Destroyed debug draw buffers");
+    }
+    if world.remove_resource::<ViewTarget>().is_some() {
+        info!("Destroyed view targets");
+    }
+    if world.remove_resource::<Reducer>().is_some() {
+        info!("Destroyed reduction pipelines");
+    }
+
+    // Destroy window surfaces
+    if let Some(window_surfaces) = world.remove_non_send_resource::<WindowSurfaces>() {
+        info!("Destroyed {} window surfaces", window_surfaces.surfaces.len());
+    }
+
+    // Destroy descriptor sets and pipeline layouts, the Device is destroyed with its last reference
+    world.remove_resource::<RenderContext>();
+    info!("Renderer shut down");
+}
+
+/// Leak renderer resources instead of destroying them while a hung device may still use them.
+/// 
+/// Their drops would also wait for the device to idle without a timeout, hanging the exit.
+fn leak_renderer_resources(world: &mut World) {
+    fn leak<R: Resource>(world: &mut World) {
+        if let Some(resource) = world.remove_resource::<R>() {
+            std::mem::forget(resource);
+        }
+    }
+
+    leak::<PipelineManager>(world);
+    leak::<Assets<Pipeline>>(world);
+    leak::<RenderAssets<Mesh>>(world);
+    leak::<RenderAssets<Image>>(world);
+    leak::<RenderAssets<Sampler>>(world);
+    leak::<SceneData>(world);
+    leak::<DebugDraw>(world);
+    leak::<ViewTarget>(world);
+    leak::<Reducer>(world);
+    if let Some(window_surfaces) = world.remove_non_send_resource::<WindowSurfaces>() {
+        std::mem::forget(window_surfaces);
+    }
+    leak::<RenderContext>(world);
+}
+
 fn initialize_internal_assets(render_context: &RenderContext, commands: &mut Commands) {
     let device = &render_context.device;
     let resource_manager = &render_context.resource_manager;
@@ -270,24 +560,59 @@ fn initialize_internal_assets(render_context: &RenderContext, commands: &mut Com
         entry_point: Cow::from("frag::unlit::main\0")
     };
     let textured_lit_frag = Shader {
-        module,
+        module: module.clone(),
         entry_point: Cow::from("frag::textured_lit::main\0")
     };
+    let cutout_frag = Shader {
+        module: module.clone(),
+        entry_point: Cow::from("frag::cutout::main\0")
+    };
+    let immediate_vert = Shader {
+        module: module.clone(),
+        entry_point: Cow::from("vert::immediate::main\0")
+    };
+    let mesh_normals_vert = Shader {
+        module: module.clone(),
+        entry_point: Cow::from("vert::mesh_normals::main\0")
+    };
+    let uv_checker_frag = Shader {
+        module: module.clone(),
+        entry_point: Cow::from("frag::uv_checker::main\0")
+    };
+    let fullscreen_vert = Shader {
+        module: module.clone(),
+        entry_point: Cow::from("vert::fullscreen::main\0")
+    };
+    let fxaa_frag = Shader {
+        module: module.clone(),
+        entry_point: Cow::from("frag::fxaa::main\0")
+    };
+
+    // Create built-in compute pipelines
+    let reducer = Reducer::new(device, &module, pipeline_layout)
+        .expect("Reduction pipelines should be created");
 
-    // Create mesh pipeline
-    let unlit_pipeline = Pipeline::graphics(
-        device.clone(), 
-        VertexStageInfo {
+    // Create mesh pipelines, with variants for each vertex format
+    let vertex_input = |vertex_format: VertexFormat| match vertex_format {
+        VertexFormat::Full => VertexInputDescription {
+            binding_description: Vertex::binding_description(),
+            attribute_descriptions: Vertex::attribute_descriptions().to_vec()
+        },
+        VertexFormat::PackedColor => VertexInputDescription {
+            binding_description: PackedVertex::binding_description(),
+            attribute_descriptions: PackedVertex::attribute_descriptions().to_vec()
+        }
+    };
+    // Descriptors are kept in the PipelineManager to create variants for other attachment formats
+    let mesh_pipeline = |frag: &Shader, vertex_format: VertexFormat| GraphicsPipelineDescriptor {
+        vertex_stage_info: VertexStageInfo {
             shader: mesh_vert.module.clone(),
             entry_point: mesh_vert.entry_point.clone(),
-            vertex_input_desc: VertexInputDescription {
-                binding_description: Vertex::binding_description(),
-                attribute_descriptions: Vertex::attribute_descriptions().to_vec()
-            }
+            vertex_input_desc: vertex_input(vertex_format)
         },
-        FragmentStageInfo {
-            shader: unlit_frag.module.clone(),
-            entry_point: unlit_frag.entry_point.clone(),
+        fragment_stage_info: FragmentStageInfo {
+            shader: frag.module.clone(),
+            entry_point: frag.entry_point.clone(),
             color_blend_states: vec![
                 PipelineColorBlendAttachmentState::builder()
                     .blend_enable(false)
@@ -301,43 +626,168 @@ fn initialize_internal_assets(render_context: &RenderContext, commands: &mut Com
                     .build()
             ],
             target_states: vec![
-                Format::B8G8R8A8_UNORM
+                render_context.surface_format
             ]
         },
-        pipeline_layout
-    ).expect("Graphics pipeline should be created");
-
-    let textured_lit_pipeline = Pipeline::graphics(
-        device.clone(), 
-        VertexStageInfo {
-            shader: mesh_vert.module.clone(),
-            entry_point: mesh_vert.entry_point.clone(),
+        pipeline_layout,
+        options: GraphicsPipelineOptions::default()
+    };
+    let vertex_formats = [VertexFormat::Full, VertexFormat::PackedColor];
+    let mesh_descriptors: Vec<_> = vertex_formats
+        .iter()
+        .flat_map(|&vertex_format| [
+            (vertex_format.pipeline_label("unlit_mesh"), mesh_pipeline(&unlit_frag, vertex_format)),
+            (vertex_format.pipeline_label("textured_lit_mesh"), mesh_pipeline(&textured_lit_frag, vertex_format))
+        ])
+        .collect();
+    let mesh_pipelines: Vec<_> = mesh_descriptors
+        .iter()
+        .map(|(label, descriptor)| {
+            let pipeline = descriptor.create(device.clone())
+                .expect("Graphics pipeline should be created");
+            let depth_pipeline = Pipeline::depth_only(device.clone(), descriptor.vertex_stage_info.clone(), pipeline_layout, descriptor.options.clone())
+                .expect("Graphics pipeline should be created");
+            (label.clone(), (pipeline, depth_pipeline))
+        })
+        .collect();
+
+    // Create alpha cutout pipelines. The cutout forces early fragment tests, which write depth before it can discard,
+    // so it draws without depth writes and has no depth prepass
+    let cutout_descriptors: Vec<_> = vertex_formats
+        .iter()
+        .map(|&vertex_format| (vertex_format.pipeline_label("cutout_mesh"), GraphicsPipelineDescriptor {
+            options: GraphicsPipelineOptions {
+                depth_write: false,
+                ..Default::default()
+            },
+            ..mesh_pipeline(&cutout_frag, vertex_format)
+        }))
+        .collect();
+
+    // Create immediate geometry pipelines
+    let debug_pipeline = |topology: PrimitiveTopology| GraphicsPipelineDescriptor {
+        vertex_stage_info: VertexStageInfo {
+            shader: immediate_vert.module.clone(),
+            entry_point: immediate_vert.entry_point.clone(),
             vertex_input_desc: VertexInputDescription {
                 binding_description: Vertex::binding_description(),
                 attribute_descriptions: Vertex::attribute_descriptions().to_vec()
             }
         },
-        FragmentStageInfo {
-            shader: textured_lit_frag.module.clone(),
-            entry_point: textured_lit_frag.entry_point.clone(),
+        fragment_stage_info: FragmentStageInfo {
+            shader: unlit_frag.module.clone(),
+            entry_point: unlit_frag.entry_point.clone(),
+            color_blend_states: vec![
+                PipelineColorBlendAttachmentState::builder()
+                    .blend_enable(false)
+                    .color_write_mask(ColorComponentFlags::RGBA)
+                    .build()
+            ],
+            target_states: vec![
+                render_context.surface_format
+            ]
+        },
+        pipeline_layout,
+        options: GraphicsPipelineOptions {
+            topology,
+            cull_mode: CullModeFlags::NONE,
+            ..Default::default()
+        }
+    };
+    let debug_descriptors = vec![
+        ("debug_lines".to_string(), debug_pipeline(PrimitiveTopology::LINE_LIST)),
+        ("debug_triangles".to_string(), debug_pipeline(PrimitiveTopology::TRIANGLE_LIST))
+    ];
+
+    // Create debug view pipelines, with variants for each vertex format
+    let debug_view_pipeline = |vert: &Shader, frag: &Shader, vertex_format: VertexFormat, options: GraphicsPipelineOptions| GraphicsPipelineDescriptor {
+        vertex_stage_info: VertexStageInfo {
+            shader: vert.module.clone(),
+            entry_point: vert.entry_point.clone(),
+            vertex_input_desc: vertex_input(vertex_format)
+        },
+        fragment_stage_info: FragmentStageInfo {
+            shader: frag.module.clone(),
+            entry_point: frag.entry_point.clone(),
+            color_blend_states: vec![
+                PipelineColorBlendAttachmentState::builder()
+                    .blend_enable(false)
+                    .color_write_mask(ColorComponentFlags::RGBA)
+                    .build()
+            ],
+            target_states: vec![
+                render_context.surface_format
+            ]
+        },
+        pipeline_layout,
+        options
+    };
+    // Wireframes need non-solid polygon modes, so without them the wireframe debug view draws no overlay
+    let wireframe_supported = device.capabilities().fill_mode_non_solid;
+    if !wireframe_supported {
+        warn!("Device doesn't support fillModeNonSolid, the wireframe debug view is unavailable");
+    }
+    let debug_view_descriptors: Vec<_> = vertex_formats
+        .iter()
+        .flat_map(|&vertex_format| [
+            wireframe_supported.then(|| (vertex_format.pipeline_label(debug_view::WIREFRAME), debug_view_pipeline(&mesh_vert, &unlit_frag, vertex_format, GraphicsPipelineOptions {
+                cull_mode: CullModeFlags::NONE,
+                polygon_mode: PolygonMode::LINE,
+                ..Default::default()
+            }))),
+            Some((vertex_format.pipeline_label(debug_view::NORMALS), debug_view_pipeline(&mesh_normals_vert, &unlit_frag, vertex_format, GraphicsPipelineOptions::default()))),
+            Some((vertex_format.pipeline_label(debug_view::UV_CHECKER), debug_view_pipeline(&mesh_vert, &uv_checker_frag, vertex_format, GraphicsPipelineOptions::default())))
+        ])
+        .flatten()
+        .collect();
+
+    // Create post-process pipelines, drawing a full-screen triangle without vertex buffers
+    let fxaa_pipeline = GraphicsPipelineDescriptor {
+        vertex_stage_info: VertexStageInfo {
+            shader: fullscreen_vert.module.clone(),
+            entry_point: fullscreen_vert.entry_point.clone(),
+            vertex_input_desc: VertexInputDescription {
+                binding_description: Vertex::binding_description(),
+                attribute_descriptions: vec![]
+            }
+        },
+        fragment_stage_info: FragmentStageInfo {
+            shader: fxaa_frag.module.clone(),
+            entry_point: fxaa_frag.entry_point.clone(),
             color_blend_states: vec![
                 PipelineColorBlendAttachmentState::builder()
                     .blend_enable(false)
-                    .src_color_blend_factor(BlendFactor::SRC_COLOR)
-                    .dst_color_blend_factor(BlendFactor::ONE_MINUS_DST_COLOR)
-                    .color_blend_op(BlendOp::ADD)
-                    .src_alpha_blend_factor(BlendFactor::ZERO)
-                    .dst_alpha_blend_factor(BlendFactor::ZERO)
-                    .alpha_blend_op(BlendOp::ADD)
                     .color_write_mask(ColorComponentFlags::RGBA)
                     .build()
             ],
             target_states: vec![
-                Format::B8G8R8A8_UNORM
+                render_context.surface_format
             ]
         },
-        pipeline_layout
-    ).expect("Graphics pipeline should be created");
+        pipeline_layout,
+        options: GraphicsPipelineOptions {
+            cull_mode: CullModeFlags::NONE,
+            depth_attachment: false,
+            ..Default::default()
+        }
+    };
+    let post_process_descriptors = vec![(FXAA.to_string(), fxaa_pipeline)];
+
+    // Create pipelines from descriptors for the surface format
+    let pipelines: Vec<_> = cutout_descriptors
+        .iter()
+        .chain(debug_descriptors.iter())
+        .chain(debug_view_descriptors.iter())
+        .chain(post_process_descriptors.iter())
+        .map(|(label, descriptor)| (label.clone(), descriptor.create(device.clone()).expect("Graphics pipeline should be created")))
+        .collect();
+    let descriptors: Vec<_> = mesh_descriptors
+        .into_iter()
+        .chain(cutout_descriptors)
+        .chain(debug_descriptors)
+        .chain(debug_view_descriptors)
+        .chain(post_process_descriptors)
+        .collect();
 
     // Create linear image sampler
     let sampler = Sampler::new(
@@ -351,6 +801,30 @@ fn initialize_internal_assets(render_context: &RenderContext, commands: &mut Com
         (0.0, 0.0, 0.0)
     );
 
+    // Create nearest image sampler, for hard texel edges
+    let nearest_sampler = Sampler::new(
+        (gpu_sampler::Filter::NEAREST, gpu_sampler::Filter::NEAREST),
+        (gpu_sampler::SamplerAddressMode::REPEAT, gpu_sampler::SamplerAddressMode::REPEAT, gpu_sampler::SamplerAddressMode::REPEAT),
+        None,
+        gpu_sampler::BorderColor::INT_OPAQUE_BLACK,
+        false,
+        None,
+        gpu_sampler::SamplerMipmapMode::NEAREST,
+        (0.0, 0.0, 0.0)
+    );
+
+    // Create clamped linear sampler for post-processing
+    let clamp_sampler = Sampler::new(
+        (gpu_sampler::Filter::LINEAR, gpu_sampler::Filter::LINEAR),
+        (gpu_sampler::SamplerAddressMode::CLAMP_TO_EDGE, gpu_sampler::SamplerAddressMode::CLAMP_TO_EDGE, gpu_sampler::SamplerAddressMode::CLAMP_TO_EDGE),
+        None,
+        gpu_sampler::BorderColor::INT_OPAQUE_BLACK,
+        false,
+        None,
+        gpu_sampler::SamplerMipmapMode::NEAREST,
+        (0.0, 0.0, 0.0)
+    );
+
     // Add internal assets to world
     commands.add(|world: &mut World| {
         // Add shader assets
@@ -358,26 +832,56 @@ fn initialize_internal_assets(render_context: &RenderContext, commands: &mut Com
         let mesh_vert_handle = shader_assets.add(mesh_vert);
         let unlit_frag_handle = shader_assets.add(unlit_frag);
         let textured_lit_frag_handle = shader_assets.add(textured_lit_frag);
+        let immediate_vert_handle = shader_assets.add(immediate_vert);
+        let mesh_normals_vert_handle = shader_assets.add(mesh_normals_vert);
+        let uv_checker_frag_handle = shader_assets.add(uv_checker_frag);
+        let fullscreen_vert_handle = shader_assets.add(fullscreen_vert);
+        let fxaa_frag_handle = shader_assets.add(fxaa_frag);
+        let cutout_frag_handle = shader_assets.add(cutout_frag);
 
         let mut shader_manager = world.resource_mut::<ShaderManager>();
         shader_manager.shaders.insert("mesh_vert".to_string(), mesh_vert_handle);
         shader_manager.shaders.insert("unlit_frag".to_string(), unlit_frag_handle);
         shader_manager.shaders.insert("textured_lit_frag".to_string(), textured_lit_frag_handle);
+        shader_manager.shaders.insert("immediate_vert".to_string(), immediate_vert_handle);
+        shader_manager.shaders.insert("mesh_normals_vert".to_string(), mesh_normals_vert_handle);
+        shader_manager.shaders.insert("uv_checker_frag".to_string(), uv_checker_frag_handle);
+        shader_manager.shaders.insert("fullscreen_vert".to_string(), fullscreen_vert_handle);
+        shader_manager.shaders.insert("fxaa_frag".to_string(), fxaa_frag_handle);
+        shader_manager.shaders.insert("cutout_frag".to_string(), cutout_frag_handle);
 
         // Add pipeline assets
         let mut pipeline_assets = world.resource_mut::<Assets<Pipeline>>();
-        let unlit_pipeline_handle = pipeline_assets.add(unlit_pipeline);
-        let textured_lit_pipeline_handle = pipeline_assets.add(textured_lit_pipeline);
+        let mesh_pipeline_handles: Vec<_> = mesh_pipelines
+            .into_iter()
+            .map(|(label, (pipeline, depth_pipeline))| (label, pipeline_assets.add(pipeline), pipeline_assets.add(depth_pipeline)))
+            .collect();
+        let pipeline_handles: Vec<_> = pipelines
+            .into_iter()
+            .map(|(label, pipeline)| (label, pipeline_assets.add(pipeline)))
+            .collect();
 
         let mut pipeline_manager = world.resource_mut::<PipelineManager>();
-        pipeline_manager.pipelines.insert("unlit_mesh".to_string(), unlit_pipeline_handle);
-        pipeline_manager.pipelines.insert("textured_lit_mesh".to_string(), textured_lit_pipeline_handle);
+        for (label, pipeline_handle, depth_pipeline_handle) in mesh_pipeline_handles {
+            pipeline_manager.pipelines.insert(label.clone(), pipeline_handle);
+            pipeline_manager.depth_prepass_pipelines.insert(label, depth_pipeline_handle);
+        }
+        for (label, pipeline_handle) in pipeline_handles {
+            pipeline_manager.pipelines.insert(label, pipeline_handle);
+        }
+        pipeline_manager.descriptors.extend(descriptors);
 
         // Add sampler assets
         let mut sampler_assets = world.resource_mut::<Assets<Sampler>>();
         let asset_handle = sampler_assets.add(sampler);
+        let nearest_asset_handle = sampler_assets.add(nearest_sampler);
+        let clamp_asset_handle = sampler_assets.add(clamp_sampler);
 
         let mut sampler_manager = world.resource_mut::<SamplerManager>();
         sampler_manager.samplers.insert("Linear".to_string(), asset_handle);
+        sampler_manager.samplers.insert("Nearest".to_string(), nearest_asset_handle);
+        sampler_manager.samplers.insert("Linear Clamp".to_string(), clamp_asset_handle);
+
+        world.insert_resource(reducer);
     });
 }
\ No newline at end of file
@@ -0,0 +1,344 @@
+use crate::{
+    camera::Camera,
+    post_process::PostProcessChain,
+    render_resource::ResourceManager,
+};
+
+use anyhow::{bail, Result};
+use ash::vk;
+
+use bevy_ecs::prelude::*;
+use bevy_log::prelude::*;
+use bevy_window::WindowId;
+
+use paracosm_gpu::{
+    device::Device,
+    resource::image::*,
+    surface::Surface,
+};
+
+use rust_shaders_shared::ResourceHandle;
+
+use std::{
+    collections::HashMap,
+    slice
+};
+
+
+
+/// Resolution of a [`ViewTarget`], relative to its window surface.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum ViewResolution {
+    /// Match the window surface extent
+    Window,
+    /// Scale the window surface extent, e.g. 0.5 to render at half resolution
+    Scale(f32),
+    /// Use a fixed extent, regardless of window size
+    Fixed(u32, u32)
+}
+
+impl ViewResolution {
+    pub fn extent(&self, surface_extent: vk::Extent2D) -> vk::Extent2D {
+        let extent = match *self {
+            ViewResolution::Window => surface_extent,
+            ViewResolution::Scale(scale) => vk::Extent2D {
+                width: (surface_extent.width as f32 * scale).round() as u32,
+                height: (surface_extent.height as f32 * scale).round() as u32
+            },
+            ViewResolution::Fixed(width, height) => vk::Extent2D { width, height }
+        };
+
+        vk::Extent2D {
+            width: extent.width.max(1),
+            height: extent.height.max(1)
+        }
+    }
+}
+
+/// Offscreen color and depth attachments for a single frame in flight.
+pub struct ViewTargetImages {
+    pub color: Image,
+    pub depth: Image,
+}
+
+/// The [`ViewTarget`] resource holds offscreen render targets for each window,
+/// which are rendered into and then blitted to the window's swapchain image.
+///
+/// Decouples the render resolution from the window, and runs its [`PostProcessChain`] before blitting.
+#[derive(Resource)]
+pub struct ViewTarget {
+    pub resolution: ViewResolution,
+    pub depth_format: vk::Format,
+    pub post_process: PostProcessChain,
+    targets: HashMap<WindowId, (vk::Extent2D, Vec<ViewTargetImages>)>,
+}
+
+impl ViewTarget {
+    pub fn new(resolution: ViewResolution) -> Self {
+        Self {
+            resolution,
+            depth_format: vk::Format::D24_UNORM_S8_UINT,
+            post_process: PostProcessChain::default(),
+            targets: HashMap::new(),
+        }
+    }
+
+    /// Get the render extent of the targets for `window_id`, if created.
+    pub fn extent(&self, window_id: WindowId) -> Option<vk::Extent2D> {
+        self.targets.get(&window_id).map(|(extent, _)| *extent)
+    }
+
+    /// Get the current frame's target images for `window_id`, if created.
+    pub fn images(&self, window_id: WindowId, frame_index: usize) -> Option<&ViewTargetImages> {
+        self.targets.get(&window_id).and_then(|(_, images)| images.get(frame_index))
+    }
+
+    /// Begin the surface's frame and begin rendering to the current frame's view target,
+    /// (re)creating the target images if the render extent, color format, or frame count changed.
+    ///
+    /// Returns the frame's command buffer and the render extent.
+    pub fn begin_rendering(
+        &mut self,
+        device: &Device,
+        resource_manager: &ResourceManager,
+        surface: &mut Surface,
+        window_id: WindowId,
+        color_format: vk::Format
+    ) -> Result<(vk::CommandBuffer, vk::Extent2D)> {
+        let extent = self.resolution.extent(surface.extent()?);
+
+        // Create target images if necessary
+        let needs_images = match self.targets.get(&window_id) {
+            Some((target_extent, images)) => *target_extent != extent
+                || images.len() != surface.frame_count()
+                || images.iter().any(|target| target.color.info.image_format != color_format),
+            None => true
+        };
+        if needs_images {
+            debug!("Creating view targets of {}x{} for window {:?}", extent.width, extent.height, window_id);
+            let mut images = Vec::with_capacity(surface.frame_count());
+            for frame in 0..surface.frame_count() {
+                images.push(self.create_images(device, extent, color_format, frame)?);
+            }
+            self.targets.insert(window_id, (extent, images));
+        }
+
+        // Create post-process targets matching the view targets
+        if !self.post_process.is_empty() && (needs_images || !self.post_process.is_prepared(window_id)) {
+            let view_colors: Vec<&Image> = self.targets[&window_id].1
+                .iter()
+                .map(|target| &target.color)
+                .collect();
+            self.post_process.prepare(device, resource_manager, window_id, &view_colors)?;
+        }
+
+        let command_buffer = surface.begin_frame()?;
+        let target = &self.targets[&window_id].1[surface.frame_index()];
+
+        unsafe {
+            // Transition attachments layouts to optimal, with separate depth and stencil layouts
+            device.transition_image_layout(
+                command_buffer,
+                &target.color,
+                vk::ImageLayout::UNDEFINED,
+                vk::ImageLayout::COLOR_ATTACHMENT_OPTIMAL
+            );
+            device.transition_image_aspect_layout(
+                command_buffer,
+                &target.depth,
+                vk::ImageAspectFlags::DEPTH,
+                vk::ImageLayout::UNDEFINED,
+                vk::ImageLayout::DEPTH_ATTACHMENT_OPTIMAL
+            );
+            let has_stencil = format_has_stencil(self.depth_format);
+            if has_stencil {
+                device.transition_image_aspect_layout(
+                    command_buffer,
+                    &target.depth,
+                    vk::ImageAspectFlags::STENCIL,
+                    vk::ImageLayout::UNDEFINED,
+                    vk::ImageLayout::STENCIL_ATTACHMENT_OPTIMAL
+                );
+            }
+
+            // Begin rendering
+            let color_attachment_info = vk::RenderingAttachmentInfo::builder()
+                .image_view(target.color.image_view)
+                .image_layout(vk::ImageLayout::ATTACHMENT_OPTIMAL)
+                .load_op(vk::AttachmentLoadOp::CLEAR)
+                .store_op(vk::AttachmentStoreOp::STORE)
+                .clear_value(vk::ClearValue {
+                    color: vk::ClearColorValue { float32: [0.0, 0.0, 0.0, 1.0] }
+                });
+            let depth_attachment_info = vk::RenderingAttachmentInfo::builder()
+                .image_view(target.depth.image_view)
+                .image_layout(vk::ImageLayout::DEPTH_ATTACHMENT_OPTIMAL)
+                .load_op(vk::AttachmentLoadOp::CLEAR)
+                .store_op(vk::AttachmentStoreOp::DONT_CARE)
+                .clear_value(vk::ClearValue {
+                    depth_stencil: vk::ClearDepthStencilValue { depth: Camera::DEPTH_CLEAR_VALUE, stencil: 0 }
+                });
+            let stencil_attachment_info = vk::RenderingAttachmentInfo::builder()
+                .image_view(target.depth.image_view)
+                .image_layout(vk::ImageLayout::STENCIL_ATTACHMENT_OPTIMAL)
+                .load_op(vk::AttachmentLoadOp::CLEAR)
+                .store_op(vk::AttachmentStoreOp::DONT_CARE)
+                .clear_value(vk::ClearValue {
+                    depth_stencil: vk::ClearDepthStencilValue { depth: Camera::DEPTH_CLEAR_VALUE, stencil: 0 }
+                });
+            let mut rendering_info = vk::RenderingInfo::builder()
+                .render_area(vk::Rect2D::builder()
+                    .extent(extent)
+                    .build()
+                )
+                .layer_count(1)
+                .color_attachments(slice::from_ref(&color_attachment_info))
+                .depth_attachment(&depth_attachment_info);
+            if has_stencil {
+                rendering_info = rendering_info.stencil_attachment(&stencil_attachment_info);
+            }
+
+            device.cmd_begin_rendering(command_buffer, &rendering_info);
+        }
+
+        Ok((command_buffer, extent))
+    }
+
+    /// End rendering to the current frame's view target, run the post-process chain when a sampler is given,
+    /// and blit the result to the surface's swapchain image.
+    ///
+    /// `effect_pipeline` looks up post-process pipelines by label.
+    /// The frame must still be ended with [`Surface::end_frame`].
+    pub fn end_rendering(
+        &self,
+        resource_manager: &ResourceManager,
+        surface: &Surface,
+        command_buffer: vk::CommandBuffer,
+        window_id: WindowId,
+        sampler_handle: Option<ResourceHandle>,
+        effect_pipeline: impl Fn(&str) -> Option<vk::Pipeline>
+    ) -> Result<()> {
+        let device = &resource_manager.device;
+        let Some(target) = self.images(window_id, surface.frame_index()) else {
+            bail!("No view target exists for window {:?}", window_id);
+        };
+
+        unsafe { device.cmd_end_rendering(command_buffer) };
+
+        let post_processed = sampler_handle.and_then(|sampler_handle| self.post_process.run(
+            resource_manager,
+            command_buffer,
+            &target.color,
+            sampler_handle,
+            effect_pipeline
+        ));
+        let output = match post_processed {
+            Some(output) => {
+                device.transition_image_layout(
+                    command_buffer,
+                    output,
+                    vk::ImageLayout::SHADER_READ_ONLY_OPTIMAL,
+                    vk::ImageLayout::TRANSFER_SRC_OPTIMAL
+                );
+                output
+            },
+            None => {
+                device.transition_image_layout(
+                    command_buffer,
+                    &target.color,
+                    vk::ImageLayout::COLOR_ATTACHMENT_OPTIMAL,
+                    vk::ImageLayout::TRANSFER_SRC_OPTIMAL
+                );
+                &target.color
+            }
+        };
+
+        surface.blit_to_swapchain(command_buffer, output)
+    }
+
+    /// Remove the targets for `window_id`, e.g. when its window is closed.
+    pub fn remove(&mut self, resource_manager: &ResourceManager, window_id: WindowId) {
+        self.post_process.remove(resource_manager, window_id);
+        self.targets.remove(&window_id);
+    }
+
+    fn create_images(&self, device: &Device, extent: vk::Extent2D, color_format: vk::Format, frame: usize) -> Result<ViewTargetImages> {
+        let image_extent = Extent3D { width: extent.width, height: extent.height, depth: 1 };
+
+        let info = ImageInfo {
+            image_type: ImageType::TYPE_2D,
+            image_format: color_format,
+            image_extent,
+            mip_levels: MipLevels::Count(1),
+            array_layers: 1,
+            samples: SampleCountFlags::TYPE_1,
+            tiling: ImageTiling::OPTIMAL,
+            usage: ImageUsageFlags::COLOR_ATTACHMENT | ImageUsageFlags::TRANSFER_SRC | ImageUsageFlags::SAMPLED,
+            aspect: ImageAspectFlags::COLOR,
+            memory_location: MemoryLocation::GpuOnly,
+            transient: false,
+            view_formats: vec![],
+            sharing: Sharing::Exclusive
+        };
+        let color = device.create_image(format!("View Target Color {}", frame).as_str(), info, None)?;
+
+        let info = ImageInfo {
+            image_type: ImageType::TYPE_2D,
+            image_format: self.depth_format,
+            image_extent,
+            mip_levels: MipLevels::Count(1),
+            array_layers: 1,
+            samples: SampleCountFlags::TYPE_1,
+            tiling: ImageTiling::OPTIMAL,
+            usage: ImageUsageFlags::DEPTH_STENCIL_ATTACHMENT,
+            aspect: match format_has_stencil(self.depth_format) {
+                true => ImageAspectFlags::DEPTH | ImageAspectFlags::STENCIL,
+                false => ImageAspectFlags::DEPTH
+            },
+            memory_location: MemoryLocation::GpuOnly,
+            // Depth is cleared each frame and never stored
+            transient: true,
+            view_formats: vec![],
+            sharing: Sharing::Exclusive
+        };
+        let depth = device.create_image(format!("View Target Depth {}", frame).as_str(), info, None)?;
+
+        Ok(ViewTargetImages {
+            color,
+            depth
+        })
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SURFACE_EXTENT: vk::Extent2D = vk::Extent2D { width: 1280, height: 720 };
+
+    #[test]
+    fn window_resolution_matches_surface() {
+        assert_eq!(ViewResolution::Window.extent(SURFACE_EXTENT), SURFACE_EXTENT);
+    }
+
+    #[test]
+    fn scaled_resolution_rounds() {
+        assert_eq!(ViewResolution::Scale(0.5).extent(SURFACE_EXTENT), vk::Extent2D { width: 640, height: 360 });
+        assert_eq!(
+            ViewResolution::Scale(1.0 / 3.0).extent(vk::Extent2D { width: 100, height: 50 }),
+            vk::Extent2D { width: 33, height: 17 }
+        );
+    }
+
+    #[test]
+    fn fixed_resolution_ignores_surface() {
+        assert_eq!(ViewResolution::Fixed(320, 240).extent(SURFACE_EXTENT), vk::Extent2D { width: 320, height: 240 });
+    }
+
+    #[test]
+    fn resolution_is_at_least_one_texel() {
+        assert_eq!(ViewResolution::Scale(0.0).extent(SURFACE_EXTENT), vk::Extent2D { width: 1, height: 1 });
+        assert_eq!(ViewResolution::Fixed(0, 0).extent(SURFACE_EXTENT), vk::Extent2D { width: 1, height: 1 });
+    }
+}
@@ -1,13 +1,16 @@
-use crate::RenderContext;
+use crate::{
+    debug_draw::DebugDraw,
+    RenderContext
+};
 
 use ash::vk::Extent2D;
 
-use bevy_app::{App, Plugin};
+use bevy_app::{App, CoreStage, Plugin};
 use bevy_ecs::prelude::*;
 use bevy_log::prelude::*;
-use bevy_window::{WindowClosed, WindowId, WindowResized, Windows};
+use bevy_window::{PresentMode, WindowClosed, WindowId, WindowResized, Windows};
 
-use paracosm_gpu::surface::Surface;
+use paracosm_gpu::surface::{is_presentable_extent, Surface, SwapchainStatus};
 
 use std::collections::{HashMap, HashSet};
 
@@ -24,14 +27,75 @@ impl Plugin for WindowRenderPlugin {
     fn build(&self, app: &mut App) {
         app.init_non_send_resource::<WindowSurfaces>()
             .init_resource::<NonSendMarker>()
-            .add_system(process_windows);
+            .init_resource::<Presentation>()
+            .add_system(process_windows)
+            .add_system_to_stage(CoreStage::Last, present_windows);
     }
 }
 
 #[derive(Default)]
 pub struct WindowSurfaces {
     pub surfaces: HashMap<WindowId, Surface>,
-    pub configured_windows: HashSet<WindowId>
+    pub configured_windows: HashSet<WindowId>,
+    /// Windows with a rendered frame waiting for a manual present
+    pub pending_present: HashSet<WindowId>,
+    /// Present mode each window's surface was configured with, to reconfigure when it changes, e.g. toggling vsync
+    present_modes: HashMap<WindowId, PresentMode>,
+    /// Windows whose swapchain was reported suboptimal or out of date, to reconfigure before their next frame
+    pub stale_windows: HashSet<WindowId>,
+}
+
+impl WindowSurfaces {
+    /// Handle a swapchain status of `window_id`'s surface, marking it stale unless optimal.
+    pub fn update_status(&mut self, window_id: WindowId, status: SwapchainStatus) {
+        if status != SwapchainStatus::Optimal {
+            debug!("Window {} swapchain is {:?}, reconfiguring", window_id, status);
+            self.stale_windows.insert(window_id);
+        }
+    }
+
+    /// Resize `window_id`'s configured surface to `extent`, keeping its present mode and discarding any held frame.
+    /// 
+    /// Zero extents of minimized windows are skipped, keeping the current swapchain and its frames.
+    fn resize_surface(&mut self, render_context: &RenderContext, window_id: WindowId, extent: Extent2D) {
+        if !is_presentable_extent(extent) || !self.configured_windows.contains(&window_id) {
+            return;
+        }
+        let Some(surface) = self.surfaces.get_mut(&window_id) else {
+            return;
+        };
+
+        self.pending_present.remove(&window_id);
+        render_context.resource_manager.forget_fences(&surface.frame_fences());
+        if let Err(error) = surface.resize(extent) {
+            // Fully reconfigure next update instead
+            error!("Failed to resize surface for window {:?}: {:?}", window_id, error);
+            self.configured_windows.remove(&window_id);
+        }
+    }
+}
+
+/// The [`Presentation`] resource controls when rendered frames are presented to their windows.
+/// 
+/// By default frames are presented as soon as they are rendered. In manual mode, a rendered frame is held
+/// until [`Presentation::present`] is called for its window, and is presented at the end of that update.
+/// No new frame is rendered for a window while its frame is held.
+#[derive(Default, Resource)]
+pub struct Presentation {
+    pub manual: bool,
+    requests: HashSet<WindowId>
+}
+
+impl Presentation {
+    /// Request presentation of the held frame for `window_id`.
+    pub fn present(&mut self, window_id: WindowId) {
+        self.requests.insert(window_id);
+    }
+
+    /// Request presentation of the held frames for all windows, e.g. to present multiple windows together.
+    pub fn present_all(&mut self, windows: &Windows) {
+        self.requests.extend(windows.iter().map(|window| window.id()));
+    }
 }
 
 // Window Systems
@@ -43,24 +107,33 @@ pub fn process_windows(
     render_context: Res<RenderContext>,
     windows: Res<Windows>,
     mut window_surfaces: NonSendMut<WindowSurfaces>,
+    mut debug_draw: ResMut<DebugDraw>,
     mut resized: EventReader<WindowResized>,
     mut closed: EventReader<WindowClosed>,
 ) {
     let device = &render_context.device;
 
-    // Check for resized windows
+    // Resize surfaces of resized windows
     resized.iter().for_each(|resized_window| {
         debug!("Window {} resized to {} x {}", resized_window.id, resized_window.width, resized_window.height);
 
-        // Ensure surface will be reconfigured
-        window_surfaces.configured_windows.remove(&resized_window.id);
+        if let Some(window) = windows.get(resized_window.id) {
+            let extent = Extent2D { width: window.physical_width(), height: window.physical_height() };
+            window_surfaces.resize_surface(&render_context, resized_window.id, extent);
+        }
     });
 
     // Process closed windows
     let closed_windows: HashSet<WindowId> = closed.iter().map(|closed_window| {
         // Drop surface for closed window
-        window_surfaces.surfaces.remove(&closed_window.id);
+        if let Some(surface) = window_surfaces.surfaces.remove(&closed_window.id) {
+            render_context.resource_manager.forget_fences(&surface.frame_fences());
+        }
         window_surfaces.configured_windows.remove(&closed_window.id);
+        window_surfaces.pending_present.remove(&closed_window.id);
+        window_surfaces.present_modes.remove(&closed_window.id);
+        window_surfaces.stale_windows.remove(&closed_window.id);
+        debug_draw.remove_window(closed_window.id);
 
         closed_window.id
     })
@@ -74,28 +147,91 @@ pub fn process_windows(
         }
 
         let extent = Extent2D {
-            width: window.physical_width().max(1), 
-            height: window.physical_height().max(1)
+            width: window.physical_width(), 
+            height: window.physical_height()
         };
 
-        // Create window surface if needed
-        window_surfaces.surfaces
-            .entry(window.id())
-            .or_insert_with(|| {
-                Surface::new(device.clone(), &window.raw_handle().unwrap())
-            });
+        // Create window surface if needed, skipping the window if it can't be presented to
+        if !window_surfaces.surfaces.contains_key(&window.id()) {
+            match Surface::new(device.clone(), &window.raw_handle().unwrap()) {
+                Ok(mut surface) => {
+                    surface.set_preferred_format(render_context.surface_format);
+                    window_surfaces.surfaces.insert(window.id(), surface);
+                },
+                Err(error) => {
+                    error!("Failed to create surface for window {:?}: {}", window.id(), error);
+                    return
+                }
+            }
+        }
 
-        // Configure window surface if needed
+        // Resize stale swapchains, then configure window surface if needed, discarding any held frame.
+        // Surfaces keep their requested present mode, e.g. from `Surface::set_vsync`, until the window's present mode changes
+        if window_surfaces.stale_windows.remove(&window.id()) {
+            window_surfaces.resize_surface(&render_context, window.id(), extent);
+        }
+        let present_mode_changed = window_surfaces.present_modes.get(&window.id()) != Some(&window.present_mode());
+        if present_mode_changed {
+            window_surfaces.configured_windows.remove(&window.id());
+        }
+        // Minimized windows are configured once restored
+        if !window_surfaces.configured_windows.contains(&window.id()) && !is_presentable_extent(extent) {
+            return
+        }
         if window_surfaces.configured_windows.insert(window.id()) {
+            window_surfaces.pending_present.remove(&window.id());
+            window_surfaces.present_modes.insert(window.id(), window.present_mode());
             if let Some(surface) = window_surfaces.surfaces.get_mut(&window.id()) {
-                surface.configure(window.present_mode(), extent);
+                render_context.resource_manager.forget_fences(&surface.frame_fences());
+                let present_mode = match present_mode_changed {
+                    true => window.present_mode(),
+                    false => surface.requested_present_mode()
+                };
+                if let Err(error) = surface.configure(present_mode, extent) {
+                    // Retry next update, e.g. once the window can be presented to again
+                    error!("Failed to configure surface for window {:?}: {:?}", window.id(), error);
+                    window_surfaces.configured_windows.remove(&window.id());
+                    return
+                }
             }
         }
 
+        // Keep the acquired image of a frame held for manual present
+        if window_surfaces.pending_present.contains(&window.id()) {
+            return
+        }
+
         // TODO: consider moving swapchain image acquisition closer to surface present
         let surface = window_surfaces.surfaces.get_mut(&window.id()).unwrap();
-        if let Err(error) = surface.acquire_next_image(1000000000) {
-            error!("process_windows: {}", error.to_string());
+        match surface.acquire_next_image(1000000000) {
+            // No image was acquired, so skip rendering until the surface is reconfigured next update
+            Ok(SwapchainStatus::OutOfDate) => {
+                window_surfaces.configured_windows.remove(&window.id());
+            },
+            Ok(status) => window_surfaces.update_status(window.id(), status),
+            Err(error) => error!("process_windows: {}", error.to_string())
         }
     });
 }
+
+/// Present held frames for windows requested through [`Presentation::present`]
+pub fn present_windows(
+    _marker: NonSend<NonSendMarker>,
+    mut presentation: ResMut<Presentation>,
+    mut window_surfaces: NonSendMut<WindowSurfaces>,
+) {
+    let window_surfaces = &mut *window_surfaces;
+    for window_id in presentation.requests.drain() {
+        if !window_surfaces.pending_present.remove(&window_id) {
+            continue;
+        }
+
+        let Some(surface) = window_surfaces.surfaces.get_mut(&window_id) else {
+            continue;
+        };
+        match surface.queue_present() {
+            Ok(status) => window_surfaces.update_status(window_id, status),
+            Err(error) => error!("present_windows: {}", error.to_string())
+        }
+    }
+}
@@ -0,0 +1,100 @@
+use crate::typed_buffer::TypedBuffer;
+
+use glam::{IVec2, UVec3, Vec3, Vec4};
+use spirv_std::{
+    arch::{atomic_i_add, atomic_u_max, atomic_u_min},
+    glam,
+    image::*,
+    memory::{Scope, Semantics},
+    spirv,
+    RuntimeArray
+};
+
+use rust_shaders_shared::{
+    ImageReduceConstants,
+    ReduceConstants,
+    REDUCE_OP_MAX,
+    REDUCE_OP_MIN,
+    REDUCE_OP_SUM,
+    REDUCE_WORKGROUP_SIZE
+};
+
+const SCOPE: u32 = Scope::Device as u32;
+const SEMANTICS: u32 = Semantics::NONE.bits();
+
+fn identity(op: u32) -> u32 {
+    match op {
+        REDUCE_OP_MIN => u32::MAX,
+        _ => 0
+    }
+}
+
+fn combine(op: u32, a: u32, b: u32) -> u32 {
+    match op {
+        REDUCE_OP_SUM => a.wrapping_add(b),
+        REDUCE_OP_MIN => a.min(b),
+        _ => a.max(b)
+    }
+}
+
+/// Atomically combine `value` into `target`
+fn atomic_combine(op: u32, target: &mut u32, value: u32) {
+    unsafe {
+        match op {
+            REDUCE_OP_SUM => atomic_i_add::<u32, SCOPE, SEMANTICS>(target, value),
+            REDUCE_OP_MIN => atomic_u_min::<u32, SCOPE, SEMANTICS>(target, value),
+            REDUCE_OP_MAX => atomic_u_max::<u32, SCOPE, SEMANTICS>(target, value),
+            _ => 0
+        };
+    }
+}
+
+/// Reduce `count` values of the source buffer into the first value of the result buffer.
+///
+/// Each invocation reduces a strided share of the values, then combines it with the result atomically.
+#[spirv(compute(threads(64)))]
+pub fn main(
+    #[spirv(push_constant)] constants: &ReduceConstants,
+    #[spirv(global_invocation_id)] global_id: UVec3,
+    #[spirv(num_workgroups)] workgroup_count: UVec3,
+    #[spirv(descriptor_set = 0, binding = 0, storage_buffer)] storage_buffers: &mut RuntimeArray<TypedBuffer<[u32]>>
+) {
+    let op = constants.op;
+    let stride = workgroup_count.x * REDUCE_WORKGROUP_SIZE;
+
+    let source = unsafe { storage_buffers.index(constants.source_buffer_handle.index() as usize) };
+    let mut value = identity(op);
+    let mut index = global_id.x;
+    while index < constants.count {
+        value = combine(op, value, source[index as usize]);
+        index += stride;
+    }
+
+    let result = unsafe { storage_buffers.index_mut(constants.result_buffer_handle.index() as usize) };
+    atomic_combine(op, &mut result[0], value);
+}
+
+/// Reduce the luminance of each texel of the source image to its maximum, stored as `f32` bits in the result buffer.
+#[spirv(compute(threads(8, 8)))]
+pub fn max_luminance(
+    #[spirv(push_constant)] constants: &ImageReduceConstants,
+    #[spirv(global_invocation_id)] global_id: UVec3,
+    #[spirv(descriptor_set = 0, binding = 0, storage_buffer)] storage_buffers: &mut RuntimeArray<TypedBuffer<[u32]>>,
+    #[spirv(descriptor_set = 0, binding = 2)] sampled_images: &RuntimeArray<Image!(
+        2D,
+        format = rgba32f,
+        sampled
+    )>
+) {
+    if global_id.x >= constants.width || global_id.y >= constants.height {
+        return;
+    }
+
+    let image = unsafe { sampled_images.index(constants.source_image_handle.index() as usize) };
+    let color: Vec4 = image.fetch(IVec2::new(global_id.x as i32, global_id.y as i32));
+    let luminance = color.truncate().dot(Vec3::new(0.2126, 0.7152, 0.0722)).max(0.0);
+
+    // Non-negative floats order the same as their bits
+    let result = unsafe { storage_buffers.index_mut(constants.result_buffer_handle.index() as usize) };
+    atomic_combine(REDUCE_OP_MAX, &mut result[0], luminance.to_bits());
+}
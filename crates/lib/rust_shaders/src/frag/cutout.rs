@@ -0,0 +1,30 @@
+use glam::{Vec2, Vec4};
+use spirv_std::{
+    arch::kill,
+    glam,
+    spirv,
+};
+
+use rust_shaders_shared::{
+    ShaderConstants,
+};
+
+const ALPHA_CUTOFF: f32 = 0.5;
+
+/// Unlit alpha cutout, discarding fragments below [`ALPHA_CUTOFF`] with forced early fragment tests.
+/// 
+/// Depth is tested and written before the shader runs, so discarded fragments still occlude.
+/// Draw it with depth writes disabled for correct occlusion.
+#[spirv(fragment(early_fragment_tests))]
+pub fn main(
+    #[spirv(push_constant)] _constants: &ShaderConstants,
+    frag_color: Vec4,
+    _frag_tex_coord: Vec2,
+    out_color: &mut Vec4,
+) {
+    if frag_color.w < ALPHA_CUTOFF {
+        kill();
+    }
+
+    *out_color = frag_color;
+}
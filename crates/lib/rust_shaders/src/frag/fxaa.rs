@@ -0,0 +1,69 @@
+use glam::{Vec2, Vec3, Vec4};
+use spirv_std::{
+    glam,
+    spirv,
+    image::*,
+    Sampler,
+    RuntimeArray
+};
+
+use rust_shaders_shared::PostProcessConstants;
+
+const SPAN_MAX: f32 = 8.0;
+const REDUCE_MUL: f32 = 1.0 / 8.0;
+const REDUCE_MIN: f32 = 1.0 / 128.0;
+
+fn luma(color: Vec4) -> f32 {
+    color.truncate().dot(Vec3::new(0.299, 0.587, 0.114))
+}
+
+/// Fast approximate anti-aliasing post-process pass
+#[spirv(fragment)]
+pub fn main(
+    #[spirv(push_constant)] constants: &PostProcessConstants,
+    _frag_color: Vec4,
+    frag_tex_coord: Vec2,
+    out_color: &mut Vec4,
+    #[spirv(descriptor_set = 0, binding = 2)] sampled_images: &RuntimeArray<Image!(
+        2D,
+        format = rgba32f,
+        sampled
+    )>,
+    #[spirv(descriptor_set = 0, binding = 3)] samplers: &RuntimeArray<Sampler>
+) {
+    let image = unsafe { sampled_images.index(constants.source_image_handle.index() as usize) };
+    let sampler = unsafe { *samplers.index(constants.sampler_handle.index() as usize) };
+    let texel = constants.texel_size;
+    let sample = |offset: Vec2| -> Vec4 { image.sample_by_lod(sampler, frag_tex_coord + offset, 0.0) };
+
+    // Sample neighbourhood luma
+    let color_m = sample(Vec2::ZERO);
+    let luma_nw = luma(sample(Vec2::new(-1.0, -1.0) * texel));
+    let luma_ne = luma(sample(Vec2::new(1.0, -1.0) * texel));
+    let luma_sw = luma(sample(Vec2::new(-1.0, 1.0) * texel));
+    let luma_se = luma(sample(Vec2::new(1.0, 1.0) * texel));
+    let luma_m = luma(color_m);
+
+    let luma_min = luma_m.min(luma_nw.min(luma_ne).min(luma_sw.min(luma_se)));
+    let luma_max = luma_m.max(luma_nw.max(luma_ne).max(luma_sw.max(luma_se)));
+
+    // Find edge direction
+    let direction = Vec2::new(
+        (luma_sw + luma_se) - (luma_nw + luma_ne),
+        (luma_nw + luma_sw) - (luma_ne + luma_se)
+    );
+    let direction_reduce = ((luma_nw + luma_ne + luma_sw + luma_se) * 0.25 * REDUCE_MUL).max(REDUCE_MIN);
+    let inverse_direction_min = 1.0 / (direction.abs().min_element() + direction_reduce);
+    let direction = (direction * inverse_direction_min).clamp(Vec2::splat(-SPAN_MAX), Vec2::splat(SPAN_MAX)) * texel;
+
+    // Blend along the edge
+    let color_a = (sample(direction * (1.0 / 3.0 - 0.5)) + sample(direction * (2.0 / 3.0 - 0.5))) * 0.5;
+    let color_b = color_a * 0.5 + (sample(direction * -0.5) + sample(direction * 0.5)) * 0.25;
+    let luma_b = luma(color_b);
+
+    let color = match luma_b < luma_min || luma_b > luma_max {
+        true => color_a,
+        false => color_b
+    };
+    *out_color = color.truncate().extend(color_m.w);
+}
@@ -1,3 +1,6 @@
+pub mod cutout;
+pub mod fxaa;
 pub mod unlit;
+pub mod uv_checker;
 
 pub mod textured_lit;
\ No newline at end of file
@@ -23,6 +23,8 @@ pub fn main(
     #[spirv(push_constant)] constants: &ShaderConstants,
     frag_color: Vec4,
     frag_tex_coord: Vec2,
+    #[spirv(flat)] frag_texture_index: u32,
+    #[spirv(flat)] frag_sampler_index: u32,
     out_color: &mut Vec4,
     #[spirv(descriptor_set = 0, binding = 2)] sampled_images: &RuntimeArray<Image!(
         2D,
@@ -32,10 +34,10 @@ pub fn main(
     #[spirv(descriptor_set = 0, binding = 3)] samplers: &RuntimeArray<Sampler>
 ) {
     let sampler = unsafe {
-        samplers.index(0)
+        samplers.index(frag_sampler_index as usize)
     };
     let color: Vec4 = unsafe {
-        sampled_images.index(0).sample(*sampler, frag_tex_coord)
+        sampled_images.index(frag_texture_index as usize).sample(*sampler, frag_tex_coord)
     };
     *out_color = color; 
     //*out_color = Vec4::from((frag_tex_coord, 0.5, 0.0));
@@ -0,0 +1,30 @@
+use glam::{Vec2, Vec4};
+use spirv_std::{
+    glam,
+    spirv,
+};
+
+use rust_shaders_shared::{
+    ShaderConstants,
+};
+
+const CHECKER_COUNT: f32 = 8.0;
+
+/// Checkerboard of the mesh's texture coordinates, for debug visualization
+#[spirv(fragment)]
+pub fn main(
+    #[spirv(push_constant)] _constants: &ShaderConstants,
+    _frag_color: Vec4,
+    frag_tex_coord: Vec2,
+    out_color: &mut Vec4,
+) {
+    let cell = (frag_tex_coord * CHECKER_COUNT).floor();
+    let checker = (cell.x + cell.y) % 2.0;
+    let shade = match checker.abs() < 0.5 {
+        true => 0.2,
+        false => 0.8
+    };
+
+    // Tint by coordinate so UV direction is visible
+    *out_color = Vec4::new(shade * (0.5 + frag_tex_coord.x * 0.5), shade, shade * (0.5 + frag_tex_coord.y * 0.5), 1.0);
+}
@@ -3,5 +3,6 @@
 
 mod typed_buffer;
 
+pub mod comp;
 pub mod vert;
 pub mod frag;
\ No newline at end of file
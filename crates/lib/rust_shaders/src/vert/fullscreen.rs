@@ -0,0 +1,21 @@
+use glam::{Vec2, Vec4};
+use spirv_std::{
+    glam,
+    spirv,
+};
+
+/// Vertex shader for a full-screen triangle, drawn with 3 vertices and no vertex buffer
+#[spirv(vertex)]
+pub fn main(
+    // Input Parameters
+    #[spirv(vertex_index)] vertex_index: i32,
+    // Output Parameters
+    #[spirv(position)] out_pos: &mut Vec4,
+    out_color: &mut Vec4,
+    out_tex_coord: &mut Vec2
+) {
+    let tex_coord = Vec2::new(((vertex_index << 1) & 2) as f32, (vertex_index & 2) as f32);
+    *out_pos = Vec4::new(tex_coord.x * 2.0 - 1.0, tex_coord.y * 2.0 - 1.0, 0.0, 1.0);
+    *out_color = Vec4::ONE;
+    *out_tex_coord = tex_coord;
+}
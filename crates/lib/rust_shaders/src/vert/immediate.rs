@@ -0,0 +1,28 @@
+use glam::{Vec2, Vec3, Vec4};
+use spirv_std::{
+    glam,
+    spirv,
+};
+
+use rust_shaders_shared::{
+    ShaderConstants,
+};
+
+/// Vertex shader for immediate geometry already in world space
+#[spirv(vertex)]
+pub fn main(
+    // Input Parameters
+    #[spirv(push_constant)] constants: &ShaderConstants,
+    in_position: Vec3,
+    _in_normal: Vec3,
+    in_color: Vec3,
+    in_tex_coord: Vec2,
+    // Output Parameters
+    #[spirv(position)] out_pos: &mut Vec4,
+    out_color: &mut Vec4,
+    out_tex_coord: &mut Vec2
+) {
+    *out_pos = constants.camera_matrix * Vec4::from((in_position, 1.0));
+    *out_color = Vec4::from((in_color, 1.0));
+    *out_tex_coord = in_tex_coord;
+}
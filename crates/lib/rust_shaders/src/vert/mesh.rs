@@ -25,10 +25,14 @@ pub fn main(
     // Output Parameters
     #[spirv(position)] out_pos: &mut Vec4,
     out_color: &mut Vec4,
-    out_tex_coord: &mut Vec2
+    out_tex_coord: &mut Vec2,
+    #[spirv(flat)] out_texture_index: &mut u32,
+    #[spirv(flat)] out_sampler_index: &mut u32
 ) {
-    let model_matrix = unsafe { storage_buffers.index(constants.object_buffer_handle.index() as usize)[instance_index as usize].model_matrix };
-    *out_pos = constants.camera_matrix * model_matrix * Vec4::from((in_position, 1.0));
+    let object_data = unsafe { storage_buffers.index(constants.object_buffer_handle.index() as usize)[instance_index as usize] };
+    *out_pos = constants.camera_matrix * object_data.model_matrix * Vec4::from((in_position, 1.0));
     *out_color = Vec4::from((in_color, 0.0));
     *out_tex_coord = in_tex_coord;
+    *out_texture_index = object_data.texture_handle.index();
+    *out_sampler_index = object_data.sampler_handle.index();
 }
\ No newline at end of file
@@ -1 +1,4 @@
-pub mod mesh;
\ No newline at end of file
+pub mod fullscreen;
+pub mod immediate;
+pub mod mesh;
+pub mod mesh_normals;
\ No newline at end of file
@@ -23,6 +23,64 @@ impl ResourceHandle {
     }
 }
 
+/// A [`DevicePtr`] carries the device address of a buffer of `T`, so push constants and buffers can reference
+/// whole buffers by address instead of descriptor index.
+/// 
+/// Shaders can't dereference it yet, as rust-gpu doesn't support physical storage buffer pointers.
+/// 
+/// `Copy`, `Clone`, `PartialEq`, `Eq`, `Hash` and `Debug` only compare or copy the address, so they hold for any `T`,
+/// and the marker keeps it `Send` and `Sync` like the `u64` it is.
+#[repr(transparent)]
+pub struct DevicePtr<T> {
+    address: u64,
+    _marker: core::marker::PhantomData<fn() -> T>,
+}
+
+impl<T> Clone for DevicePtr<T> {
+    fn clone(&self) -> Self {
+        *self
+    }
+}
+
+impl<T> Copy for DevicePtr<T> {}
+
+impl<T> PartialEq for DevicePtr<T> {
+    fn eq(&self, other: &Self) -> bool {
+        self.address == other.address
+    }
+}
+
+impl<T> Eq for DevicePtr<T> {}
+
+impl<T> core::hash::Hash for DevicePtr<T> {
+    fn hash<H: core::hash::Hasher>(&self, state: &mut H) {
+        self.address.hash(state);
+    }
+}
+
+impl<T> core::fmt::Debug for DevicePtr<T> {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        write!(f, "DevicePtr({:#x})", self.address)
+    }
+}
+
+impl<T> DevicePtr<T> {
+    pub fn new(address: u64) -> Self {
+        Self {
+            address,
+            _marker: core::marker::PhantomData
+        }
+    }
+
+    pub fn address(&self) -> u64 {
+        self.address
+    }
+
+    pub fn is_null(&self) -> bool {
+        self.address == 0
+    }
+}
+
 /// Global push constants for all shaders
 #[derive(Copy, Clone, PartialEq)]
 #[repr(C)]
@@ -32,11 +90,59 @@ pub struct ShaderConstants {
     // pub test_image_handle: ResourceHandle
 }
 
+/// Push constants for full-screen post-process passes, sharing the global push constant range
+#[derive(Copy, Clone, PartialEq)]
+#[repr(C)]
+pub struct PostProcessConstants {
+    pub source_image_handle: ResourceHandle,
+    pub sampler_handle: ResourceHandle,
+    pub texel_size: Vec2
+}
+
+/// Workgroup size of the reduction compute shaders
+pub const REDUCE_WORKGROUP_SIZE: u32 = 64;
+/// Side length of the square workgroups of image reduction compute shaders
+pub const REDUCE_IMAGE_WORKGROUP_SIZE: u32 = 8;
+
+/// Operations of [`ReduceConstants`]
+pub const REDUCE_OP_SUM: u32 = 0;
+pub const REDUCE_OP_MIN: u32 = 1;
+pub const REDUCE_OP_MAX: u32 = 2;
+
+/// Push constants for reducing a buffer of `u32` values, sharing the global push constant range
+/// 
+/// The result buffer's first value must hold the operation's identity before the reduction.
+#[derive(Copy, Clone, PartialEq)]
+#[repr(C)]
+pub struct ReduceConstants {
+    pub source_buffer_handle: ResourceHandle,
+    pub result_buffer_handle: ResourceHandle,
+    /// Number of values reduced from the start of the source buffer
+    pub count: u32,
+    /// One of the `REDUCE_OP_*` constants
+    pub op: u32
+}
+
+/// Push constants for reducing the texels of a sampled image, sharing the global push constant range
+#[derive(Copy, Clone, PartialEq)]
+#[repr(C)]
+pub struct ImageReduceConstants {
+    pub source_image_handle: ResourceHandle,
+    pub result_buffer_handle: ResourceHandle,
+    pub width: u32,
+    pub height: u32
+}
+
 /// Object data for instanced rendering
+/// 
+/// Each instance selects its own bindless texture and sampler, so differently textured instances can share a draw.
 #[derive(Copy, Clone, PartialEq)]
 #[repr(C)]
 pub struct ObjectData {
-    pub model_matrix: Mat4
+    pub model_matrix: Mat4,
+    pub texture_handle: ResourceHandle,
+    pub sampler_handle: ResourceHandle,
+    _padding: [u32; 2]
 }
 
 #[derive(Copy, Clone, PartialEq)]
@@ -49,6 +155,17 @@ pub struct Vertex {
 }
 
 
+/// A [`Vertex`] with color packed into 8 bit RGBA, read by the same shaders as `R8G8B8A8_UNORM`
+#[derive(Copy, Clone, PartialEq)]
+#[repr(C)]
+pub struct PackedVertex {
+    pub position: Vec3,
+    pub normal: Vec3,
+    pub color: u32,
+    pub uv: Vec2
+}
+
+
 
 // Rust only source
 #[cfg(not(target_arch = "spirv"))] use std::mem::size_of;
@@ -63,6 +180,18 @@ impl ResourceHandle {
     }
 }
 
+#[cfg(not(target_arch = "spirv"))]
+impl ObjectData {
+    pub fn new(model_matrix: Mat4, texture_handle: ResourceHandle, sampler_handle: ResourceHandle) -> Self {
+        Self {
+            model_matrix,
+            texture_handle,
+            sampler_handle,
+            _padding: [0; 2]
+        }
+    }
+}
+
 #[cfg(not(target_arch = "spirv"))]
 impl Vertex {
     pub fn new(position: Vec3, normal: Vec3, color: Vec3, uv: Vec2) -> Self {
@@ -111,3 +240,108 @@ impl Vertex {
         [position, normal, color, uv]
     }
 }
+
+#[cfg(not(target_arch = "spirv"))]
+impl PackedVertex {
+    /// Pack `vertex` color to 8 bits per channel, with opaque alpha.
+    pub fn from_vertex(vertex: &Vertex) -> Self {
+        let [r, g, b] = (vertex.color.clamp(Vec3::ZERO, Vec3::ONE) * 255.0).round().to_array();
+        Self {
+            position: vertex.position,
+            normal: vertex.normal,
+            color: u32::from_le_bytes([r as u8, g as u8, b as u8, u8::MAX]),
+            uv: vertex.uv
+        }
+    }
+
+    pub fn binding_description() -> vk::VertexInputBindingDescription {
+        vk::VertexInputBindingDescription::builder()
+            .binding(0)
+            .stride(size_of::<Self>() as u32)
+            .input_rate(vk::VertexInputRate::VERTEX)
+            .build()
+    }
+
+    pub fn attribute_descriptions() -> [vk::VertexInputAttributeDescription; 4] {
+        let position = vk::VertexInputAttributeDescription::builder()
+            .binding(0)
+            .location(0)
+            .format(vk::Format::R32G32B32_SFLOAT)
+            .offset(0)
+            .build();
+        let normal = vk::VertexInputAttributeDescription::builder()
+            .binding(0)
+            .location(1)
+            .format(vk::Format::R32G32B32_SFLOAT)
+            .offset(size_of::<Vec3>() as u32)
+            .build();
+        let color = vk::VertexInputAttributeDescription::builder()
+            .binding(0)
+            .location(2)
+            .format(vk::Format::R8G8B8A8_UNORM)
+            .offset(2 * size_of::<Vec3>() as u32)
+            .build();
+        let uv = vk::VertexInputAttributeDescription::builder()
+            .binding(0)
+            .location(3)
+            .format(vk::Format::R32G32_SFLOAT)
+            .offset(2 * size_of::<Vec3>() as u32 + size_of::<u32>() as u32)
+            .build();
+
+        [position, normal, color, uv]
+    }
+}
+
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Byte offset of a field, from a pointer to it within `vertex`
+    fn offset_of<T, F>(vertex: &T, field: &F) -> u32 {
+        (field as *const F as usize - vertex as *const T as usize) as u32
+    }
+
+    #[test]
+    fn packed_vertex_layout() {
+        assert_eq!(size_of::<PackedVertex>(), 36);
+        assert_eq!(size_of::<Vertex>(), 44);
+
+        let vertex = PackedVertex::from_vertex(&Vertex::new(Vec3::ZERO, Vec3::Z, Vec3::ONE, Vec2::ZERO));
+        let offsets = [
+            offset_of(&vertex, &vertex.position),
+            offset_of(&vertex, &vertex.normal),
+            offset_of(&vertex, &vertex.color),
+            offset_of(&vertex, &vertex.uv)
+        ];
+        let attribute_offsets = PackedVertex::attribute_descriptions().map(|attribute| attribute.offset);
+        assert_eq!(attribute_offsets, offsets);
+        assert_eq!(attribute_offsets, [0, 12, 24, 28]);
+        assert_eq!(PackedVertex::binding_description().stride, 36);
+    }
+
+    #[test]
+    fn device_ptr_traits_ignore_pointee() {
+        // Neither Copy nor Debug, but pointers to it are both
+        struct Pointee;
+
+        let ptr = DevicePtr::<Pointee>::new(0x1000);
+        let copy = ptr;
+        assert_eq!(ptr, copy);
+        assert_ne!(ptr, DevicePtr::new(0x2000));
+        assert!(DevicePtr::<Pointee>::new(0).is_null());
+        assert_eq!(format!("{:?}", ptr), "DevicePtr(0x1000)");
+        assert_eq!(size_of::<DevicePtr<Pointee>>(), size_of::<u64>());
+
+        fn send_sync<T: Send + Sync>() {}
+        send_sync::<DevicePtr<*const u32>>();
+    }
+
+    #[test]
+    fn packed_vertex_color_bytes() {
+        let vertex = Vertex::new(Vec3::ZERO, Vec3::Z, Vec3::new(1.0, 0.5, -1.0), Vec2::ZERO);
+        let packed = PackedVertex::from_vertex(&vertex);
+        assert_eq!(packed.color.to_le_bytes(), [255, 128, 0, 255]);
+    }
+}